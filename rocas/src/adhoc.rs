@@ -0,0 +1,104 @@
+//! `rocas watch`: watches directories with a single rule given on the
+//! command line, like `rocas watch ~/Scans --pattern '*.pdf' --to
+//! ~/Documents/Scans`.
+//!
+//! The directories and the rule are written to a config in `watch` in the
+//! rocas config directory, which only the user running rocas can enter. It
+//! is loaded like any other and removed when rocas exits or starts again.
+//! `rocas.toml` is neither read nor changed. Every other setting keeps its
+//! default, except that rocas does not look for updates.
+
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use clap::ArgMatches;
+use toml::{Table, Value};
+
+use crate::AppError;
+use crate::config;
+use crate::edit::NewRule;
+use crate::format::Format;
+
+/// The config written by [`write`].
+static CONFIG: OnceLock<PathBuf> = OnceLock::new();
+
+/// Writes the config described by the arguments of `rocas watch` and
+/// returns its path.
+///
+/// # Errors
+///
+/// Returns an I/O error if the config cannot be written.
+pub fn write(watch: &ArgMatches) -> Result<PathBuf, AppError> {
+    let strings = |id: &str| {
+        watch
+            .get_many::<String>(id)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let rule = NewRule {
+        name: Some("watch".to_string()),
+        patterns: strings("patterns"),
+        destination: watch
+            .get_one::<String>("to")
+            .cloned()
+            .unwrap_or_default(),
+        action: watch
+            .get_one::<String>("action")
+            .cloned(),
+    };
+    let watch_paths = watch
+        .get_many::<PathBuf>("paths")
+        .into_iter()
+        .flatten()
+        .map(|path| {
+            std::path::absolute(path).map(|path| Value::String(path.to_string_lossy().into_owned()))
+        })
+        .collect::<Result<_, _>>()?;
+    let watcher = Table::from_iter([
+        ("watch_paths".to_string(), Value::Array(watch_paths)),
+        ("recursive".to_string(), Value::Boolean(watch.get_flag("recursive"))),
+    ]);
+    let rule: Table = rule
+        .fields()
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+    let misc = Table::from_iter([("check_for_updates".to_string(), Value::Boolean(false))]);
+    let config = Table::from_iter([
+        ("watcher".to_string(), Value::Table(watcher)),
+        ("rules".to_string(), Value::Array(vec![Value::Table(rule)])),
+        ("misc".to_string(), Value::Table(misc)),
+    ]);
+
+    let dir = config::rocas_dir().join("watch");
+    config::create_private_dir(&dir)?;
+    let path = dir.join(format!("{}.toml", std::process::id()));
+    // Left behind by a rocas with the same process ID that did not exit.
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+        _ => {},
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        options.mode(0o600);
+    }
+    options
+        .open(&path)?
+        .write_all(Format::Toml.render(&config).as_bytes())?;
+    let _ = CONFIG.set(path.clone());
+    Ok(path)
+}
+
+/// Removes the config written by [`write`], if any, before rocas exits or
+/// starts again.
+pub fn stop() {
+    if let Some(path) = CONFIG.get() {
+        let _ = std::fs::remove_file(path);
+    }
+}
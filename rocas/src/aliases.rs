@@ -0,0 +1,116 @@
+//! The `[patterns]` section: named pattern lists that rules share.
+//!
+//! ```toml
+//! [patterns]
+//! images = ["*.jpg", "*.jpeg", "*.png", "*.webp"]
+//! media = ["@images", "*.mp4"]
+//!
+//! [[rules]]
+//! patterns = ["@images", "*.svg"]
+//! destination = "~/Pictures"
+//! ```
+//!
+//! A pattern that is `@` followed by a group's name is replaced by the
+//! group's patterns, which may reference other groups. Other patterns
+//! starting with `@`, like `@*.png`, are globs as before.
+
+use toml::{Table, Value};
+
+pub const PATTERNS_KEY: &str = "patterns";
+
+/// Error returned for a malformed `[patterns]` section or an unknown group.
+#[derive(Debug, thiserror::Error)]
+pub enum AliasError {
+    #[error("'patterns' must be a table of pattern lists")]
+    NotATable,
+
+    #[error("pattern group '{0}' must be a list of patterns")]
+    InvalidGroup(String),
+
+    #[error("unknown pattern group '@{0}'")]
+    UnknownGroup(String),
+
+    #[error("pattern group '{0}' includes itself")]
+    Cycle(String),
+}
+
+/// Replaces the group references in the patterns of every rule of `config`
+/// and removes the `[patterns]` section.
+///
+/// # Errors
+///
+/// Returns [`AliasError`] if the section is malformed, or a rule or group
+/// references a group that does not exist or includes itself.
+pub fn apply(config: &mut Table) -> Result<(), AliasError> {
+    let groups = match config.remove(PATTERNS_KEY) {
+        None => Table::new(),
+        Some(Value::Table(groups)) => groups,
+        Some(_) => return Err(AliasError::NotATable),
+    };
+
+    let Some(Value::Array(rules)) = config.get_mut("rules") else {
+        return Ok(());
+    };
+    for rule in rules {
+        let Some(Value::Array(patterns)) = rule
+            .as_table_mut()
+            .and_then(|rule| rule.get_mut("patterns"))
+        else {
+            continue;
+        };
+        let mut expanded = Vec::with_capacity(patterns.len());
+        for pattern in patterns.drain(..) {
+            expand(&groups, pattern, &mut Vec::new(), &mut expanded)?;
+        }
+        *patterns = expanded;
+    }
+
+    Ok(())
+}
+
+/// Appends `pattern` to `out`, or the patterns of the group it references.
+/// `stack` holds the groups being expanded, to detect cycles.
+fn expand<'g>(
+    groups: &'g Table,
+    pattern: Value,
+    stack: &mut Vec<&'g str>,
+    out: &mut Vec<Value>,
+) -> Result<(), AliasError> {
+    let Some(name) = pattern.as_str().and_then(reference) else {
+        out.push(pattern);
+        return Ok(());
+    };
+
+    let Some((name, group)) = groups.get_key_value(name) else {
+        return Err(AliasError::UnknownGroup(name.to_string()));
+    };
+    if stack.contains(&name.as_str()) {
+        return Err(AliasError::Cycle(name.clone()));
+    }
+    let Value::Array(group) = group else {
+        return Err(AliasError::InvalidGroup(name.clone()));
+    };
+
+    stack.push(name);
+    for pattern in group {
+        if !pattern.is_str() {
+            return Err(AliasError::InvalidGroup(name.clone()));
+        }
+        expand(groups, pattern.clone(), stack, out)?;
+    }
+    stack.pop();
+
+    Ok(())
+}
+
+/// Returns the group `pattern` references, if it is `@` and a name.
+fn reference(pattern: &str) -> Option<&str> {
+    pattern
+        .strip_prefix('@')
+        .filter(|name| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        })
+}
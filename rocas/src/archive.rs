@@ -0,0 +1,243 @@
+//! Zip-archive destinations.
+//!
+//! A rule whose `destination` starts with `zip:` adds matched files to a zip
+//! archive instead of moving them into a directory, e.g.
+//! `zip:/home/chiko/Archive/receipts-{year}-{month}.zip` for a rolling
+//! monthly archive. The archive is created on first use, directories are
+//! added with their contents, and the source is deleted once the archive has
+//! been written.
+//!
+//! Every append writes the updated archive under a temporary name and
+//! renames it into place, so an interrupted append leaves the previous
+//! archive intact. Appends are serialized, so workers filing into the same
+//! archive never lose each other's entries.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write as _};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+use crate::config::{Action, ConflictStrategy, RuleConfig};
+use crate::{clock, transfer};
+
+pub const PREFIX: &str = "zip:";
+
+/// Held while an archive is rewritten.
+static APPEND_LOCK: Mutex<()> = Mutex::new(());
+
+/// Error returned by zip-archive destinations.
+#[derive(Debug, thiserror::Error)]
+pub enum ArchiveError {
+    #[error("{0} is not supported for zip destinations")]
+    Unsupported(&'static str),
+
+    #[error("zip archive error: {0}")]
+    Zip(#[from] ZipError),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Returns the archive path of a `zip:` destination, or `None` for any other
+/// destination.
+pub fn path(destination: &str) -> Option<&str> {
+    destination.strip_prefix(PREFIX)
+}
+
+/// Returns `true` if `destination` is a zip archive.
+pub fn is_archive(destination: &str) -> bool {
+    path(destination).is_some()
+}
+
+/// Rejects rule options that zip destinations do not support.
+///
+/// # Errors
+///
+/// Returns [`ArchiveError::Unsupported`] for hard links, `backup_dir`,
+/// `on_conflict = "versions"` and a `{counter}` in the rename template.
+pub fn validate(config: &RuleConfig, has_counter: bool) -> Result<(), ArchiveError> {
+    if config.action() == Action::Hardlink {
+        return Err(ArchiveError::Unsupported("action = \"hardlink\""));
+    }
+    if config.backup_dir.is_some() {
+        return Err(ArchiveError::Unsupported("backup_dir"));
+    }
+    if config.on_conflict() == ConflictStrategy::Versions {
+        return Err(ArchiveError::Unsupported("on_conflict = \"versions\""));
+    }
+    // A counter needs to list the destination directory.
+    if has_counter {
+        return Err(ArchiveError::Unsupported("{counter} in rename"));
+    }
+    Ok(())
+}
+
+/// Adds the file or directory at `source` to `archive` as `name`, creating
+/// the archive if it does not exist yet.
+///
+/// An entry of the same name is handled with the rule's `on_conflict`:
+/// renamed with `conflict_suffix`, skipped, or replaced (kept as
+/// `<name>.bak` with `backup`). Returns the entry name used, or `None` if
+/// the entry was skipped.
+pub fn append(
+    archive: &Path,
+    source: &Path,
+    name: &str,
+    config: &RuleConfig,
+) -> Result<Option<String>, ArchiveError> {
+    let _guard = APPEND_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+
+    let mut existing = if archive.exists() {
+        Some(ZipArchive::new(BufReader::new(File::open(archive)?))?)
+    } else {
+        None
+    };
+    let names: HashSet<String> = existing
+        .iter()
+        .flat_map(ZipArchive::file_names)
+        .map(str::to_owned)
+        .collect();
+
+    let is_dir = source.is_dir();
+    let mut entry = name.to_owned();
+    // Entries replaced by the new one, and the name to keep them under.
+    let mut replaced = None;
+    if contains(&names, name, is_dir) {
+        match config.on_conflict() {
+            ConflictStrategy::Rename => {
+                entry = free_name(&names, name, &config.conflict_suffix, is_dir);
+            },
+            ConflictStrategy::Skip => return Ok(None),
+            // Rejected by `validate`.
+            ConflictStrategy::Overwrite | ConflictStrategy::Versions => {
+                replaced = Some(
+                    config
+                        .backup
+                        .then(|| format!("{name}.bak")),
+                );
+            },
+        }
+    }
+
+    let temp = transfer::temp_path(archive);
+    let written = (|| -> Result<(), ArchiveError> {
+        let mut writer = ZipWriter::new(BufWriter::new(File::create(&temp)?));
+
+        if let Some(existing) = &mut existing {
+            for idx in 0..existing.len() {
+                let file = existing.by_index_raw(idx)?;
+                let file_name = file.name().to_owned();
+                match &replaced {
+                    Some(backup) if is_entry(&file_name, name) => {
+                        if let Some(backup) = backup {
+                            let renamed = format!("{backup}{}", &file_name[name.len()..]);
+                            writer.raw_copy_file_rename(file, renamed)?;
+                        }
+                    },
+                    // The previous backup is replaced by the new one.
+                    Some(Some(backup)) if is_entry(&file_name, backup) => {},
+                    _ => writer.raw_copy_file(file)?,
+                }
+            }
+        }
+
+        add(&mut writer, source, &entry)?;
+        let mut file = writer.finish()?;
+        file.flush()?;
+        file.get_ref().sync_all()?;
+        Ok(())
+    })();
+
+    if let Err(e) = written.and_then(|()| Ok(std::fs::rename(&temp, archive)?)) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    Ok(Some(entry))
+}
+
+/// Writes `path` to the archive as `name`, recursing into directories.
+fn add<W: io::Write + io::Seek>(
+    writer: &mut ZipWriter<W>,
+    path: &Path,
+    name: &str,
+) -> Result<(), ArchiveError> {
+    let meta = std::fs::metadata(path)?;
+    let modified = meta
+        .modified()
+        .ok()
+        .map(clock::at)
+        .and_then(|t| {
+            DateTime::from_date_and_time(t.year, t.month, t.day, t.hour, t.minute, t.second).ok()
+        })
+        .unwrap_or_default();
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .last_modified_time(modified)
+        .large_file(meta.len() >= u64::from(u32::MAX));
+
+    if meta.is_dir() {
+        writer.add_directory(format!("{name}/"), options)?;
+
+        let mut children: Vec<_> = std::fs::read_dir(path)?.collect::<Result<_, _>>()?;
+        children.sort_by_key(std::fs::DirEntry::file_name);
+        for child in children {
+            let child_name = format!("{name}/{}", child.file_name().to_string_lossy());
+            add(writer, &child.path(), &child_name)?;
+        }
+        return Ok(());
+    }
+
+    writer.start_file(name, options)?;
+    io::copy(&mut File::open(path)?, writer)?;
+    Ok(())
+}
+
+/// Returns `true` if `entry` is `name` itself or, for a directory, lies
+/// inside it.
+fn is_entry(entry: &str, name: &str) -> bool {
+    entry
+        .strip_prefix(name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Returns `true` if the archive already holds `name`.
+fn contains(names: &HashSet<String>, name: &str, is_dir: bool) -> bool {
+    if is_dir {
+        names
+            .iter()
+            .any(|entry| is_entry(entry, name))
+    } else {
+        names.contains(name)
+    }
+}
+
+/// Returns the first name not in the archive with `suffix` appended to
+/// `name`, `{n}` counting up from 1: `report.pdf` becomes `report (1).pdf`.
+/// A directory's name is suffixed as a whole.
+fn free_name(names: &HashSet<String>, name: &str, suffix: &str, is_dir: bool) -> String {
+    let (stem, ext) = match Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+    {
+        Some(ext) if !is_dir => (&name[..name.len() - ext.len() - 1], format!(".{ext}")),
+        _ => (name, String::new()),
+    };
+
+    for n in 1_u64.. {
+        let suffix = suffix.replace("{n}", &n.to_string());
+        let candidate = format!("{stem}{suffix}{ext}");
+        if !contains(names, &candidate, is_dir) {
+            return candidate;
+        }
+    }
+
+    unreachable!("ran out of conflict suffix numbers")
+}
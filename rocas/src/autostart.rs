@@ -0,0 +1,1772 @@
+//! How rocas starts with the system, for `rocas setup` and `rocas unsetup`.
+//!
+//! By default rocas starts when the user logs in: from the `Run` registry
+//! key on Windows, a launch agent on macOS and a systemd user unit on Linux.
+//! On Windows it can instead be started at login by a Task Scheduler task,
+//! which can run it with the highest privileges available to the user, or
+//! be installed as a Windows service, which starts on boot and which the
+//! service control manager restarts when it crashes. Installing a service
+//! needs an elevated prompt. Where policy keeps the `Run` key from being
+//! written, rocas starts at login from a shortcut in the user's Startup
+//! folder instead.
+//!
+//! Linux systems without systemd get a service instead, which starts on boot
+//! and runs rocas as the user who set it up: an OpenRC script in
+//! `/etc/init.d` or a runit service directory in `/etc/sv`, linked into the
+//! directory runit supervises. Setting it up needs root.
+//!
+//! On Linux desktops without systemd, rocas starts with the desktop session
+//! from an XDG autostart entry, `~/.config/autostart/rocas.desktop`, which
+//! needs no root. `--mode desktop` asks for it anywhere on Linux.
+//!
+//! FreeBSD gets an rc.d script in `/usr/local/etc/rc.d`, enabled in
+//! `rc.conf` with `sysrc`, which also starts rocas on boot as the user who
+//! set it up. Setting it up needs root too.
+//!
+//! Each of these is a [`ServiceManager`], and [`managers`] lists the ones of
+//! this platform; [`crate::service`] drives them. Only one is set up at a
+//! time, so rocas never starts twice. A task or service is given the config
+//! in use when it is set up, as the account it runs as may look for its
+//! config elsewhere. `--config`, `--env` and arguments after `--` given to
+//! `rocas setup` are passed on to the rocas it starts, in every mode.
+//!
+//! The service runs `rocas run --service`, which reports to the service
+//! control manager that rocas is running and stops rocas when the service
+//! is stopped or Windows shuts down.
+//!
+//! `rocas start`, `rocas stop` and `rocas restart` drive whatever is set up
+//! through its own manager: the service control manager, Task Scheduler,
+//! launchd, systemd, OpenRC, runit or FreeBSD's rc. The `Run` registry key,
+//! the Startup folder and XDG autostart entries have no manager, so they do
+//! not work with them.
+//!
+//! `rocas setup --status` reads back what is set up: the `Run` registry
+//! value or Startup shortcut, launch agent or systemd user unit, task or service, the command it
+//! starts rocas with and whether that is this rocas binary, and whether
+//! rocas is running.
+
+use std::ffi::OsStr;
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+use std::io::ErrorKind;
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use crate::service::ServiceManager;
+#[cfg(not(windows))]
+use crate::status;
+use crate::{AppError, config};
+
+/// The name of the Task Scheduler task and of the Windows service.
+pub const NAME: &str = "Rocas";
+
+/// Error returned when the task or service cannot be set up or removed.
+#[derive(Debug, thiserror::Error)]
+pub enum AutostartError {
+    #[error("{0} is not available on this platform")]
+    Unsupported(&'static str),
+
+    #[error(
+        "--mode service needs OpenRC or runit on Linux; with systemd, `rocas setup` sets up a \
+         user unit"
+    )]
+    NoServiceManager,
+
+    #[error("cannot write '{}': run `rocas setup` as root", .0.display())]
+    NeedsRoot(PathBuf),
+
+    #[error("rocas does not start with the system; run `rocas setup` first")]
+    NotSetUp,
+
+    #[error("rocas is set up to start more than once; run `rocas setup` to keep only one")]
+    SetUpTwice,
+
+    #[error("rocas starts from {0}, which cannot {1} it")]
+    Unmanaged(&'static str, &'static str),
+
+    #[error("{command} failed ({status}): {output}")]
+    Failed {
+        command: &'static str,
+        status: ExitStatus,
+        output: String,
+    },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// How rocas starts with the system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// At login, from the platform's login items.
+    Login,
+    /// At login, from a Task Scheduler task (Windows).
+    Task,
+    /// On boot, as a Windows service, an OpenRC or runit service (Linux) or
+    /// an rc.d service (FreeBSD).
+    Service,
+    /// With the desktop session, from an XDG autostart entry (Linux).
+    Desktop,
+}
+
+impl Mode {
+    /// Parses the value of `rocas setup --mode`. Defaults to `Login`.
+    pub fn parse(name: &str) -> Self {
+        match name {
+            "task" => Self::Task,
+            "service" => Self::Service,
+            "desktop" => Self::Desktop,
+            _ => Self::Login,
+        }
+    }
+
+    /// Returns the mode `rocas setup` uses when none is given: an autostart
+    /// entry in Linux desktop sessions without systemd, a service on FreeBSD
+    /// and on other Linux systems without systemd, and the login items
+    /// elsewhere.
+    pub fn preferred() -> Self {
+        if linux::desktop_session() {
+            Self::Desktop
+        } else if cfg!(target_os = "freebsd") || linux::init().is_some() {
+            Self::Service
+        } else {
+            Self::Login
+        }
+    }
+
+    /// Returns the value of `rocas setup --mode` for this mode.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::Task => "task",
+            Self::Service => "service",
+            Self::Desktop => "desktop",
+        }
+    }
+
+    /// Returns the `rocas setup` option that asks for this mode.
+    pub const fn option(self) -> &'static str {
+        match self {
+            Self::Login => "--mode login",
+            Self::Task => "--mode task",
+            Self::Service => "--mode service",
+            Self::Desktop => "--mode desktop",
+        }
+    }
+
+    pub const fn describe(self) -> &'static str {
+        match self {
+            Self::Login => "starts at login",
+            Self::Task => "starts at login from a scheduled task",
+            Self::Service => "starts on boot as a service",
+            Self::Desktop => "starts with the desktop session",
+        }
+    }
+}
+
+/// What rocas is started with, besides what its mode needs.
+#[derive(Debug, Default, Clone)]
+pub struct Launch {
+    /// The config to load instead of the default one.
+    pub config: Option<PathBuf>,
+    /// Environment variables to set, for `--env`.
+    pub env: Vec<(String, String)>,
+    /// More arguments for `rocas run`.
+    pub args: Vec<String>,
+}
+
+impl Launch {
+    /// Returns the arguments to start rocas with: `config`, the environment
+    /// variables, then `run` with `run_args` and the extra arguments.
+    fn line(&self, config: Option<&Path>, run_args: &[&str]) -> Vec<String> {
+        let mut line = Vec::new();
+        if let Some(config) = config {
+            line.push("--config".to_string());
+            line.push(quote(&config.to_string_lossy()));
+        }
+        for (key, value) in &self.env {
+            line.push("--env".to_string());
+            line.push(quote(&format!("{key}={value}")));
+        }
+        line.push("run".to_string());
+        line.extend(
+            run_args
+                .iter()
+                .map(|arg| (*arg).to_string()),
+        );
+        line.extend(self.args.iter().map(|arg| quote(arg)));
+        line
+    }
+}
+
+/// A mode rocas is set up to start in.
+#[derive(Debug)]
+pub struct Installed {
+    pub mode: Mode,
+    /// Where the mode is kept: a registry value, a file, a task or a service.
+    pub location: String,
+    /// The command it starts rocas with, if it can be read.
+    pub command: Option<String>,
+    /// Whether rocas is running, if it can be told.
+    pub running: Option<bool>,
+}
+
+/// Returns the ways rocas can start with the system on this platform, the
+/// login items first. With `highest`, a task runs with the highest
+/// privileges available to the user.
+pub fn managers(highest: bool) -> Vec<Box<dyn ServiceManager>> {
+    let mut managers = login_items();
+    managers.extend(windows::managers(highest));
+    managers.extend(linux::managers());
+    managers.extend(freebsd::managers());
+    managers
+}
+
+/// Reports to the service control manager that rocas is running, for
+/// `rocas run --service`. From then on, stopping the service stops rocas.
+///
+/// # Errors
+///
+/// Returns [`AutostartError`] if rocas was not started as a service.
+pub fn serve() -> Result<(), AutostartError> {
+    windows::serve()
+}
+
+/// Returns `true` if rocas runs as a Windows service.
+pub fn is_service() -> bool {
+    windows::is_service()
+}
+
+/// Returns the login items. Windows has its own, the `Run` registry keys,
+/// among its managers.
+#[cfg(not(windows))]
+fn login_items() -> Vec<Box<dyn ServiceManager>> {
+    vec![Box::new(LoginItems)]
+}
+
+#[cfg(windows)]
+fn login_items() -> Vec<Box<dyn ServiceManager>> {
+    Vec::new()
+}
+
+/// The platform's login items: a launch agent on macOS and a systemd user
+/// unit elsewhere.
+#[cfg(not(windows))]
+struct LoginItems;
+
+#[cfg(not(windows))]
+impl ServiceManager for LoginItems {
+    fn mode(&self) -> Mode {
+        Mode::Login
+    }
+
+    fn install(&self, launch: &Launch) -> Result<String, AppError> {
+        config::enable_startup(&launch.line(launch.config.as_deref(), &[]))?;
+        Ok("rocas will now start when you log in.".to_string())
+    }
+
+    fn uninstall(&self) -> Result<(), AppError> {
+        config::disable_startup()
+    }
+
+    fn start(&self) -> Result<(), AppError> {
+        Ok(control_login("start")?)
+    }
+
+    fn stop(&self) -> Result<(), AppError> {
+        Ok(control_login("stop")?)
+    }
+
+    fn status(&self) -> Result<Option<Installed>, AppError> {
+        if !config::startup_enabled()? {
+            return Ok(None);
+        }
+        let record = login_record();
+        Ok(Some(Installed {
+            mode: Mode::Login,
+            location: record
+                .as_ref()
+                .map_or_else(|| "the login items".to_string(), |(location, _)| location.clone()),
+            command: record.map(|(_, command)| command),
+            running: Some(status::is_running()),
+        }))
+    }
+}
+
+/// Quotes `arg` for a command line if it holds spaces. Launch agents keep
+/// each argument apart, so there it is left as it is.
+fn quote(arg: &str) -> String {
+    if arg.contains(' ') && !cfg!(target_os = "macos") {
+        format!("\"{arg}\"")
+    } else {
+        arg.to_string()
+    }
+}
+
+/// Returns where the login item is kept and the command it runs.
+#[cfg(target_os = "macos")]
+fn login_record() -> Option<(String, String)> {
+    let path = find_record(&dirs::home_dir()?.join("Library/LaunchAgents"), "plist")?;
+    let plist = std::fs::read_to_string(&path).ok()?;
+    let arguments = plist
+        .split_once("<key>ProgramArguments</key>")?
+        .1
+        .split_once("</array>")?
+        .0;
+    let command: Vec<&str> = arguments
+        .split("<string>")
+        .skip(1)
+        .filter_map(|part| part.split_once("</string>"))
+        .map(|(argument, _)| argument)
+        .collect();
+    Some((path.display().to_string(), command.join(" ")))
+}
+
+/// Returns where the login item is kept and the command it runs.
+#[cfg(not(any(windows, target_os = "macos")))]
+fn login_record() -> Option<(String, String)> {
+    let path = find_record(&dirs::config_dir()?.join("systemd/user"), "service")?;
+    let unit = std::fs::read_to_string(&path).ok()?;
+    let command = unit
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("ExecStart="))?;
+    Some((path.display().to_string(), command.trim().to_string()))
+}
+
+/// Has launchd start or stop the launch agent, `verb` being `start` or
+/// `stop`.
+#[cfg(target_os = "macos")]
+fn control_login(verb: &'static str) -> Result<(), AutostartError> {
+    let path = find_record(
+        &dirs::home_dir()
+            .ok_or(AutostartError::NotSetUp)?
+            .join("Library/LaunchAgents"),
+        "plist",
+    )
+    .ok_or(AutostartError::NotSetUp)?;
+    // The agent's label is the name of its file.
+    let label = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    run("launchctl", [verb, label.as_str()])
+}
+
+/// Has systemd start or stop the user unit, `verb` being `start` or `stop`.
+#[cfg(not(any(windows, target_os = "macos")))]
+fn control_login(verb: &'static str) -> Result<(), AutostartError> {
+    let path = find_record(
+        &dirs::config_dir()
+            .ok_or(AutostartError::NotSetUp)?
+            .join("systemd/user"),
+        "service",
+    )
+    .ok_or(AutostartError::NotSetUp)?;
+    let unit = path.file_name().unwrap_or_default();
+    run("systemctl", [OsStr::new("--user"), OsStr::new(verb), unit])
+}
+
+/// Finds the file named after rocas with `extension` in `dir`.
+#[cfg(not(windows))]
+fn find_record(dir: &Path, extension: &str) -> Option<PathBuf> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == extension)
+                && path
+                    .file_stem()
+                    .is_some_and(|stem| stem.eq_ignore_ascii_case(NAME))
+        })
+}
+
+/// Returns the rocas executable and the arguments to start it with. The
+/// config in use is passed on if `launch` names none and it is a file.
+#[cfg(any(windows, target_os = "linux", target_os = "freebsd"))]
+fn command_line(
+    launch: &Launch,
+    run_args: &[&str],
+) -> Result<(PathBuf, Vec<String>), AutostartError> {
+    let exe = std::env::current_exe()?;
+    let config = match &launch.config {
+        Some(config) => Some(config.clone()),
+        None => Some(PathBuf::from(config::config_path()))
+            .filter(|config| config.is_file())
+            .map(std::path::absolute)
+            .transpose()?,
+    };
+    Ok((exe, launch.line(config.as_deref(), run_args)))
+}
+
+/// Returns what `command` prints, if it succeeds.
+#[cfg(any(windows, target_os = "linux", target_os = "freebsd"))]
+fn output<const N: usize>(command: &str, args: [&str; N]) -> Option<String> {
+    let output = Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn run<I, S>(command: &'static str, args: I) -> Result<(), AutostartError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // The service managers explain what went wrong on either stream.
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Err(AutostartError::Failed {
+        command,
+        status: output.status,
+        output: text.trim().to_string(),
+    })
+}
+
+#[cfg(any(windows, target_os = "linux", target_os = "freebsd"))]
+fn exists<I, S>(command: &str, args: I) -> bool
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Returns the user running `rocas setup`, unless that is root.
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn user() -> Option<String> {
+    ["SUDO_USER", "DOAS_USER", "USER"]
+        .into_iter()
+        .find_map(|var| std::env::var(var).ok())
+        .filter(|user| !user.is_empty() && user != "root")
+}
+
+/// Returns the home directory of `user` from `/etc/passwd`.
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn home_of(user: &str) -> Option<PathBuf> {
+    let passwd = std::fs::read_to_string("/etc/passwd").ok()?;
+    passwd.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        (fields.first() == Some(&user))
+            .then(|| fields.get(5).map(PathBuf::from))
+            .flatten()
+    })
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn write_executable(path: &Path, contents: &str) -> Result<(), AutostartError> {
+    std::fs::write(path, contents).map_err(|e| needs_root(e, path))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn needs_root(e: std::io::Error, path: &Path) -> AutostartError {
+    match e.kind() {
+        ErrorKind::PermissionDenied => AutostartError::NeedsRoot(path.to_path_buf()),
+        _ => AutostartError::Io(e),
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsStr;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use windows_registry::{CURRENT_USER, Key, LOCAL_MACHINE};
+
+    pub use self::scm::{is_service, serve};
+    use super::{
+        AutostartError,
+        Installed,
+        Launch,
+        Mode,
+        NAME,
+        command_line,
+        exists,
+        output,
+        quote,
+        run,
+    };
+    use crate::service::ServiceManager;
+    use crate::{AppError, status};
+
+    /// The key login items start from, under `HKEY_CURRENT_USER` or
+    /// `HKEY_LOCAL_MACHINE`.
+    const RUN: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+
+    /// Where Explorer keeps the login items disabled in Task Manager, under
+    /// the same root as [`RUN`].
+    const STARTUP_APPROVED: &str =
+        r"Software\Microsoft\Windows\CurrentVersion\Explorer\StartupApproved\Run";
+
+    /// Returns the command of the rocas value in the key `path` of `root`,
+    /// if there is one.
+    fn run_value(root: &Key, path: &str) -> Option<String> {
+        root.open(path)
+            .ok()?
+            .get_string(NAME)
+            .ok()
+    }
+
+    /// Removes the rocas value from the key `path` of `root`, if it has one.
+    /// The key is only opened for writing then, so a `HKEY_LOCAL_MACHINE`
+    /// key without it needs no elevated prompt.
+    fn remove_value(root: &Key, path: &str) -> Result<(), AutostartError> {
+        let present = root
+            .open(path)
+            .is_ok_and(|key| key.get_value(NAME).is_ok());
+        if present {
+            root.create(path)
+                .and_then(|key| key.remove_value(NAME))
+                .map_err(std::io::Error::from)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the `Run` keys, the Startup folder, the task and the service.
+    pub fn managers(highest: bool) -> Vec<Box<dyn ServiceManager>> {
+        vec![
+            Box::new(RunKey::new("HKEY_CURRENT_USER", CURRENT_USER, RUN, STARTUP_APPROVED)),
+            Box::new(RunKey::new("HKEY_LOCAL_MACHINE", LOCAL_MACHINE, RUN, STARTUP_APPROVED)),
+            Box::new(StartupFolder { shortcut: shortcut_path() }),
+            Box::new(Task { highest }),
+            Box::new(Service),
+        ]
+    }
+
+    /// The rocas value in a `Run` key, which starts rocas when the user logs
+    /// in: under `HKEY_CURRENT_USER` for them, or under `HKEY_LOCAL_MACHINE`
+    /// for everyone, which needs an elevated prompt.
+    struct RunKey<'a> {
+        /// The name of `root`, for the user.
+        hive: &'static str,
+        root: &'a Key,
+        /// The `Run` key under `root`.
+        run: &'a str,
+        /// The `StartupApproved` key under `root`.
+        approved: &'a str,
+    }
+
+    impl<'a> RunKey<'a> {
+        const fn new(hive: &'static str, root: &'a Key, run: &'a str, approved: &'a str) -> Self {
+            Self { hive, root, run, approved }
+        }
+    }
+
+    impl ServiceManager for RunKey<'_> {
+        fn mode(&self) -> Mode {
+            Mode::Login
+        }
+
+        fn install(&self, launch: &Launch) -> Result<String, AppError> {
+            let exe = std::env::current_exe()?;
+            let command = format!(
+                "{} {}",
+                quote(&exe.to_string_lossy()),
+                launch
+                    .line(launch.config.as_deref(), &[])
+                    .join(" ")
+            );
+            self.root
+                .create(self.run)
+                .and_then(|key| key.set_string(NAME, &command))
+                .map_err(std::io::Error::from)?;
+            // A login item disabled in Task Manager stays disabled until
+            // Explorer forgets about it.
+            remove_value(self.root, self.approved)?;
+            Ok("rocas will now start when you log in.".to_string())
+        }
+
+        fn uninstall(&self) -> Result<(), AppError> {
+            remove_value(self.root, self.run)?;
+            remove_value(self.root, self.approved)?;
+            Ok(())
+        }
+
+        fn start(&self) -> Result<(), AppError> {
+            Err(AutostartError::Unmanaged("the Run registry key", "start").into())
+        }
+
+        fn stop(&self) -> Result<(), AppError> {
+            Err(AutostartError::Unmanaged("the Run registry key", "stop").into())
+        }
+
+        fn status(&self) -> Result<Option<Installed>, AppError> {
+            Ok(run_value(self.root, self.run).map(|command| Installed {
+                mode: Mode::Login,
+                location: format!(r"{}\{}\{NAME}", self.hive, self.run),
+                command: Some(command),
+                running: Some(status::is_running()),
+            }))
+        }
+    }
+
+    /// A shortcut in the user's Startup folder that starts rocas when they
+    /// log in, for when the `Run` key cannot be written.
+    struct StartupFolder {
+        /// Where the shortcut goes, if the Startup folder can be found.
+        shortcut: Option<PathBuf>,
+    }
+
+    impl ServiceManager for StartupFolder {
+        fn mode(&self) -> Mode {
+            Mode::Login
+        }
+
+        fn install(&self, launch: &Launch) -> Result<String, AppError> {
+            let path = self
+                .shortcut
+                .as_ref()
+                .ok_or(AutostartError::Unsupported("the Startup folder"))?;
+            let exe = std::env::current_exe()?;
+            let args = launch
+                .line(launch.config.as_deref(), &[])
+                .join(" ");
+            // Only the shell can write a shortcut, through its COM object.
+            let script = format!(
+                "$s = (New-Object -ComObject WScript.Shell).CreateShortcut('{}'); $s.TargetPath = \
+                 '{}'; $s.Arguments = '{}'; $s.Save()",
+                powershell(&path.to_string_lossy()),
+                powershell(&exe.to_string_lossy()),
+                powershell(&args),
+            );
+            run("powershell", ["-NoProfile", "-NonInteractive", "-Command", &script])?;
+            Ok("rocas will now start when you log in, from a shortcut in your Startup folder."
+                .to_string())
+        }
+
+        fn uninstall(&self) -> Result<(), AppError> {
+            if let Some(path) = self
+                .shortcut
+                .as_ref()
+                .filter(|path| path.is_file())
+            {
+                std::fs::remove_file(path)?;
+            }
+            Ok(())
+        }
+
+        fn start(&self) -> Result<(), AppError> {
+            Err(AutostartError::Unmanaged("the Startup folder", "start").into())
+        }
+
+        fn stop(&self) -> Result<(), AppError> {
+            Err(AutostartError::Unmanaged("the Startup folder", "stop").into())
+        }
+
+        fn status(&self) -> Result<Option<Installed>, AppError> {
+            let Some(path) = self
+                .shortcut
+                .as_ref()
+                .filter(|path| path.is_file())
+            else {
+                return Ok(None);
+            };
+            let script = format!(
+                "$s = (New-Object -ComObject WScript.Shell).CreateShortcut('{}'); $s.TargetPath; \
+                 $s.Arguments",
+                powershell(&path.to_string_lossy()),
+            );
+            let command =
+                output("powershell", ["-NoProfile", "-NonInteractive", "-Command", &script])
+                    .and_then(|text| {
+                        let mut lines = text.lines();
+                        let target = lines.next()?.trim();
+                        let arguments = lines.next().unwrap_or_default().trim();
+                        Some(
+                            format!("{} {arguments}", quote(target))
+                                .trim_end()
+                                .to_string(),
+                        )
+                    });
+            Ok(Some(Installed {
+                mode: Mode::Login,
+                location: path.display().to_string(),
+                command,
+                running: Some(status::is_running()),
+            }))
+        }
+    }
+
+    fn shortcut_path() -> Option<PathBuf> {
+        Some(
+            dirs::config_dir()?
+                .join(format!(r"Microsoft\Windows\Start Menu\Programs\Startup\{NAME}.lnk")),
+        )
+    }
+
+    /// Escapes `text` for a single-quoted PowerShell string.
+    fn powershell(text: &str) -> String {
+        text.replace('\'', "''")
+    }
+
+    /// A Task Scheduler task that starts rocas when the user logs in, with
+    /// the highest privileges available to them if `highest` is set.
+    struct Task {
+        highest: bool,
+    }
+
+    impl ServiceManager for Task {
+        fn mode(&self) -> Mode {
+            Mode::Task
+        }
+
+        fn install(&self, launch: &Launch) -> Result<String, AppError> {
+            let (exe, args) = command_line(launch, &[])?;
+            let user = match (std::env::var("USERDOMAIN"), std::env::var("USERNAME")) {
+                (Ok(domain), Ok(name)) => format!("{domain}\\{name}"),
+                (_, Ok(name)) => name,
+                _ => String::new(),
+            };
+            let run_level = if self.highest { "HighestAvailable" } else { "LeastPrivilege" };
+            let xml = format!(
+                r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Description>Organizes files as they arrive in the watched directories</Description>
+  </RegistrationInfo>
+  <Triggers>
+    <LogonTrigger>
+      <Enabled>true</Enabled>
+      <UserId>{user}</UserId>
+    </LogonTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="Author">
+      <UserId>{user}</UserId>
+      <LogonType>InteractiveToken</LogonType>
+      <RunLevel>{run_level}</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>
+    <RestartOnFailure>
+      <Interval>PT1M</Interval>
+      <Count>999</Count>
+    </RestartOnFailure>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>{command}</Command>
+      <Arguments>{arguments}</Arguments>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+                user = escape(&user),
+                command = escape(&exe.to_string_lossy()),
+                arguments = escape(&args.join(" ")),
+            );
+
+            // schtasks reads task definitions as UTF-16 with a byte order
+            // mark.
+            let path = std::env::temp_dir().join(format!("rocas-task-{}.xml", std::process::id()));
+            let bytes: Vec<u8> = std::iter::once(0xFEFF)
+                .chain(xml.encode_utf16())
+                .flat_map(u16::to_le_bytes)
+                .collect();
+            std::fs::write(&path, bytes)?;
+            let created = run(
+                "schtasks",
+                [
+                    OsStr::new("/Create"),
+                    OsStr::new("/TN"),
+                    OsStr::new(NAME),
+                    OsStr::new("/XML"),
+                    path.as_os_str(),
+                    OsStr::new("/F"),
+                ],
+            );
+            let _ = std::fs::remove_file(&path);
+            created?;
+            Ok(format!("rocas will now start when you log in, from the '{NAME}' scheduled task."))
+        }
+
+        fn uninstall(&self) -> Result<(), AppError> {
+            if exists("schtasks", ["/Query", "/TN", NAME]) {
+                run("schtasks", ["/Delete", "/TN", NAME, "/F"])?;
+            }
+            Ok(())
+        }
+
+        fn start(&self) -> Result<(), AppError> {
+            Ok(run("schtasks", ["/Run", "/TN", NAME])?)
+        }
+
+        fn stop(&self) -> Result<(), AppError> {
+            Ok(run("schtasks", ["/End", "/TN", NAME])?)
+        }
+
+        fn restart(&self) -> Result<(), AppError> {
+            // It may not be running.
+            let _ = exists("schtasks", ["/End", "/TN", NAME]);
+            self.start()
+        }
+
+        fn status(&self) -> Result<Option<Installed>, AppError> {
+            let Some(xml) = output("schtasks", ["/Query", "/TN", NAME, "/XML"]) else {
+                return Ok(None);
+            };
+            let command = between(&xml, "<Command>", "</Command>").map(|program| {
+                let arguments = between(&xml, "<Arguments>", "</Arguments>").unwrap_or_default();
+                format!("{} {}", quote(&unescape(program)), unescape(arguments))
+                    .trim_end()
+                    .to_string()
+            });
+            Ok(Some(Installed {
+                mode: Mode::Task,
+                location: format!(r"the Task Scheduler task \{NAME}"),
+                command,
+                running: Some(status::is_running()),
+            }))
+        }
+    }
+
+    /// A Windows service that starts rocas on boot and is restarted when it
+    /// crashes.
+    struct Service;
+
+    impl ServiceManager for Service {
+        fn mode(&self) -> Mode {
+            Mode::Service
+        }
+
+        fn install(&self, launch: &Launch) -> Result<String, AppError> {
+            let (exe, args) = command_line(launch, &["--service"])?;
+            let bin_path = format!("{} {}", quote(&exe.to_string_lossy()), args.join(" "));
+
+            let verb = if exists("sc.exe", ["query", NAME]) { "config" } else { "create" };
+            run(
+                "sc.exe",
+                [verb, NAME, "binPath=", &bin_path, "start=", "auto", "DisplayName=", NAME],
+            )?;
+            run(
+                "sc.exe",
+                ["description", NAME, "Organizes files as they arrive in the watched directories"],
+            )?;
+            run(
+                "sc.exe",
+                [
+                    "failure",
+                    NAME,
+                    "reset=",
+                    "86400",
+                    "actions=",
+                    "restart/5000/restart/60000/restart/60000",
+                ],
+            )?;
+            Ok(format!(
+                "rocas will now start on boot as the '{NAME}' service. Start it now with `rocas \
+                 start`."
+            ))
+        }
+
+        fn uninstall(&self) -> Result<(), AppError> {
+            if exists("sc.exe", ["query", NAME]) {
+                // It may not be running.
+                let _ = exists("sc.exe", ["stop", NAME]);
+                run("sc.exe", ["delete", NAME])?;
+            }
+            Ok(())
+        }
+
+        fn start(&self) -> Result<(), AppError> {
+            Ok(run("sc.exe", ["start", NAME])?)
+        }
+
+        /// sc.exe only asks the service to stop, so this waits until it has,
+        /// for a restart to start it again.
+        fn stop(&self) -> Result<(), AppError> {
+            if stopped() {
+                return Ok(());
+            }
+            run("sc.exe", ["stop", NAME])?;
+            for _ in 0..60 {
+                if stopped() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(500));
+            }
+            Ok(())
+        }
+
+        /// It runs as another account, so whether it runs is asked of the
+        /// service control manager.
+        fn status(&self) -> Result<Option<Installed>, AppError> {
+            let Some(config) = output("sc.exe", ["qc", NAME]) else {
+                return Ok(None);
+            };
+            let command = config.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                (key.trim() == "BINARY_PATH_NAME").then(|| value.trim().to_string())
+            });
+            let running = output("sc.exe", ["query", NAME]).map(|state| {
+                state
+                    .lines()
+                    .any(|line| line.trim_start().starts_with("STATE") && line.contains("RUNNING"))
+            });
+            Ok(Some(Installed {
+                mode: Mode::Service,
+                location: format!("the {NAME} service"),
+                command,
+                running,
+            }))
+        }
+    }
+
+    fn stopped() -> bool {
+        output("sc.exe", ["query", NAME]).is_some_and(|state| {
+            state
+                .lines()
+                .any(|line| line.trim_start().starts_with("STATE") && line.contains("STOPPED"))
+        })
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    fn unescape(text: &str) -> String {
+        text.replace("&quot;", "\"")
+            .replace("&gt;", ">")
+            .replace("&lt;", "<")
+            .replace("&amp;", "&")
+    }
+
+    fn between<'a>(text: &'a str, start: &str, end: &str) -> Option<&'a str> {
+        text.split_once(start)?
+            .1
+            .split_once(end)
+            .map(|(inside, _)| inside)
+    }
+
+    /// The side of the service that talks to the service control manager.
+    mod scm {
+        use std::ffi::c_void;
+        use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+        use std::sync::mpsc::{self, Sender};
+        use std::sync::{Mutex, PoisonError};
+        use std::{io, ptr};
+
+        use windows_sys::Win32::Foundation::{ERROR_CALL_NOT_IMPLEMENTED, NO_ERROR};
+        use windows_sys::Win32::System::Services::{
+            RegisterServiceCtrlHandlerExW,
+            SERVICE_ACCEPT_SHUTDOWN,
+            SERVICE_ACCEPT_STOP,
+            SERVICE_CONTROL_INTERROGATE,
+            SERVICE_CONTROL_SHUTDOWN,
+            SERVICE_CONTROL_STOP,
+            SERVICE_RUNNING,
+            SERVICE_STATUS,
+            SERVICE_STOP_PENDING,
+            SERVICE_STOPPED,
+            SERVICE_TABLE_ENTRYW,
+            SERVICE_WIN32_OWN_PROCESS,
+            SetServiceStatus,
+            StartServiceCtrlDispatcherW,
+        };
+        use windows_sys::core::PWSTR;
+
+        use super::super::{AutostartError, NAME};
+
+        static HANDLE: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+        static SERVING: AtomicBool = AtomicBool::new(false);
+        /// Where `service_main` reports whether the service started.
+        static STARTED: Mutex<Option<Sender<io::Result<()>>>> = Mutex::new(None);
+
+        pub fn serve() -> Result<(), AutostartError> {
+            let (tx, rx) = mpsc::channel();
+            *STARTED
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(tx.clone());
+
+            // The dispatcher only returns once the service has stopped.
+            std::thread::spawn(move || {
+                let mut name = wide(NAME);
+                let table = [
+                    SERVICE_TABLE_ENTRYW {
+                        lpServiceName: name.as_mut_ptr(),
+                        lpServiceProc: Some(service_main),
+                    },
+                    SERVICE_TABLE_ENTRYW {
+                        lpServiceName: ptr::null_mut(),
+                        lpServiceProc: None,
+                    },
+                ];
+                // SAFETY: the table ends with an empty entry and outlives the
+                // call, and `name` is NUL-terminated.
+                if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+                    let _ = tx.send(Err(io::Error::last_os_error()));
+                }
+            });
+
+            rx.recv()
+                .unwrap_or_else(|_| Err(io::Error::other("the service dispatcher stopped")))?;
+            SERVING.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        pub fn is_service() -> bool {
+            SERVING.load(Ordering::SeqCst)
+        }
+
+        unsafe extern "system" fn service_main(_argc: u32, _argv: *mut PWSTR) {
+            let name = wide(NAME);
+            // SAFETY: `name` is NUL-terminated and the handler takes no
+            // context.
+            let handle =
+                unsafe { RegisterServiceCtrlHandlerExW(name.as_ptr(), Some(handler), ptr::null()) };
+            let started = if handle.is_null() {
+                Err(io::Error::last_os_error())
+            } else {
+                HANDLE.store(handle, Ordering::SeqCst);
+                set_state(SERVICE_RUNNING);
+                Ok(())
+            };
+            if let Some(tx) = STARTED
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take()
+            {
+                let _ = tx.send(started);
+            }
+        }
+
+        unsafe extern "system" fn handler(
+            control: u32,
+            _event_type: u32,
+            _event_data: *mut c_void,
+            _context: *mut c_void,
+        ) -> u32 {
+            match control {
+                SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+                    set_state(SERVICE_STOP_PENDING);
+                    info!("Stopping, as the service was stopped");
+                    crate::status::stop();
+                    crate::daemon::stop();
+                    set_state(SERVICE_STOPPED);
+                    std::process::exit(0);
+                },
+                SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+                _ => ERROR_CALL_NOT_IMPLEMENTED,
+            }
+        }
+
+        fn set_state(state: u32) {
+            let status = SERVICE_STATUS {
+                dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+                dwCurrentState: state,
+                dwControlsAccepted: if state == SERVICE_RUNNING {
+                    SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN
+                } else {
+                    0
+                },
+                dwWin32ExitCode: NO_ERROR,
+                dwServiceSpecificExitCode: 0,
+                dwCheckPoint: 0,
+                dwWaitHint: if state == SERVICE_STOP_PENDING { 5000 } else { 0 },
+            };
+            // SAFETY: the handle came from `RegisterServiceCtrlHandlerExW`
+            // and is never closed.
+            unsafe { SetServiceStatus(HANDLE.load(Ordering::SeqCst), &raw const status) };
+        }
+
+        fn wide(text: &str) -> Vec<u16> {
+            text.encode_utf16()
+                .chain(std::iter::once(0))
+                .collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::path::PathBuf;
+
+        use windows_registry::{CURRENT_USER, Key};
+
+        use super::{RunKey, StartupFolder, run_value};
+        use crate::autostart::{Launch, Mode, NAME, quote};
+        use crate::service::{self, ServiceManager};
+
+        /// A throwaway key under `HKEY_CURRENT_USER\Software`, deleted with
+        /// everything in it when dropped.
+        struct TestKey(String);
+
+        impl TestKey {
+            fn new(test: &str) -> Self {
+                let path = format!(r"Software\rocas-test-{}-{test}", std::process::id());
+                CURRENT_USER
+                    .create(&path)
+                    .expect("HKEY_CURRENT_USER can be written");
+                Self(path)
+            }
+
+            /// Opens the key, for writing unless `read_only`.
+            fn open(&self, read_only: bool) -> Key {
+                let key = if read_only {
+                    CURRENT_USER.open(&self.0)
+                } else {
+                    CURRENT_USER.create(&self.0)
+                };
+                key.expect("the key can be opened")
+            }
+        }
+
+        impl Drop for TestKey {
+            fn drop(&mut self) {
+                let _ = CURRENT_USER.remove_tree(&self.0);
+            }
+        }
+
+        fn run_key(root: &Key) -> RunKey<'_> {
+            RunKey::new("HKEY_CURRENT_USER", root, "Run", "StartupApproved")
+        }
+
+        fn launch() -> Launch {
+            Launch {
+                config: Some(PathBuf::from(r"C:\rocas config\rocas.toml")),
+                ..Launch::default()
+            }
+        }
+
+        fn command() -> String {
+            let exe = std::env::current_exe().expect("the test binary has a path");
+            format!(
+                r#"{} --config "C:\rocas config\rocas.toml" run"#,
+                quote(&exe.to_string_lossy())
+            )
+        }
+
+        #[test]
+        fn installs_the_run_value() {
+            let key = TestKey::new("install");
+            let root = key.open(false);
+            run_key(&root)
+                .install(&launch())
+                .expect("the Run key can be written");
+
+            assert_eq!(run_value(&root, "Run"), Some(command()));
+            let installed = run_key(&root)
+                .status()
+                .expect("the Run key can be read")
+                .expect("rocas is set up");
+            assert_eq!(installed.mode, Mode::Login);
+            assert_eq!(installed.location, format!(r"HKEY_CURRENT_USER\Run\{NAME}"));
+            assert_eq!(installed.command, Some(command()));
+        }
+
+        #[test]
+        fn rewrites_the_run_value() {
+            let key = TestKey::new("rewrite");
+            let root = key.open(false);
+            root.create("Run")
+                .and_then(|run| run.set_string(NAME, r"C:\old\rocas.exe run"))
+                .expect("the value can be written");
+
+            run_key(&root)
+                .install(&launch())
+                .expect("the Run key can be written");
+            assert_eq!(run_value(&root, "Run"), Some(command()));
+        }
+
+        #[test]
+        fn enables_a_login_item_disabled_in_task_manager() {
+            let key = TestKey::new("approved");
+            let root = key.open(false);
+            root.create("StartupApproved")
+                .and_then(|approved| approved.set_u32(NAME, 3))
+                .expect("the value can be written");
+
+            run_key(&root)
+                .install(&launch())
+                .expect("the Run key can be written");
+            let approved = root
+                .open("StartupApproved")
+                .expect("the key is still there");
+            assert!(approved.get_value(NAME).is_err());
+        }
+
+        #[test]
+        fn removes_the_run_value() {
+            let key = TestKey::new("remove");
+            let root = key.open(false);
+            run_key(&root)
+                .install(&launch())
+                .expect("the Run key can be written");
+
+            run_key(&root)
+                .uninstall()
+                .expect("the value can be removed");
+            assert_eq!(run_value(&root, "Run"), None);
+            assert!(
+                run_key(&root)
+                    .status()
+                    .expect("the Run key can be read")
+                    .is_none()
+            );
+            // Removing it again, or from a key that was never written, is
+            // not an error.
+            run_key(&root)
+                .uninstall()
+                .expect("nothing is left to remove");
+        }
+
+        #[test]
+        fn falls_back_to_the_startup_folder() {
+            let key = TestKey::new("fallback");
+            let root = key.open(true);
+            let startup = tempfile::tempdir().expect("a temporary directory");
+            let shortcut = startup
+                .path()
+                .join(format!("{NAME}.lnk"));
+            let managers: Vec<Box<dyn ServiceManager + '_>> = vec![
+                Box::new(run_key(&root)),
+                Box::new(StartupFolder { shortcut: Some(shortcut.clone()) }),
+            ];
+
+            let done = service::set_up(&managers, Mode::Login, &launch())
+                .expect("the Startup folder can be written");
+            assert!(done.contains("Startup folder"), "{done}");
+            assert!(done.contains("could not be set up"), "{done}");
+            assert!(shortcut.is_file());
+            assert_eq!(run_value(&root, "Run"), None);
+
+            let installed = managers[1]
+                .status()
+                .expect("the shortcut can be read")
+                .expect("rocas is set up");
+            assert_eq!(installed.location, shortcut.display().to_string());
+
+            managers[1]
+                .uninstall()
+                .expect("the shortcut can be removed");
+            assert!(!shortcut.exists());
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod windows {
+    use super::AutostartError;
+    use crate::service::ServiceManager;
+
+    pub fn managers(_highest: bool) -> Vec<Box<dyn ServiceManager>> {
+        Vec::new()
+    }
+
+    pub fn serve() -> Result<(), AutostartError> {
+        Err(AutostartError::Unsupported("--service"))
+    }
+
+    pub const fn is_service() -> bool {
+        false
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::os::unix::fs::symlink;
+    use std::path::{Path, PathBuf};
+
+    use super::{
+        AutostartError,
+        Installed,
+        Launch,
+        Mode,
+        NAME,
+        command_line,
+        exists,
+        home_of,
+        needs_root,
+        output,
+        quote,
+        run,
+        user,
+        write_executable,
+    };
+    use crate::service::ServiceManager;
+    use crate::{AppError, status};
+
+    /// The name of the service. Services are named in lower case on Linux.
+    const SERVICE: &str = "rocas";
+    const OPENRC_SCRIPT: &str = "/etc/init.d/rocas";
+    const RUNIT_DIR: &str = "/etc/sv/rocas";
+    /// Where runit looks for the services to supervise, which differs
+    /// between distributions.
+    const RUNIT_SERVICES: [&str; 3] = ["/var/service", "/etc/service", "/service"];
+
+    /// An init system rocas sets up a service with.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Init {
+        OpenRc,
+        Runit,
+    }
+
+    /// Returns the OpenRC or runit service and the XDG autostart entry.
+    pub fn managers() -> Vec<Box<dyn ServiceManager>> {
+        vec![Box::new(InitService), Box::new(AutostartEntry)]
+    }
+
+    /// Returns the init system to set up a service with, or `None` if
+    /// systemd runs, whose user units the login items use.
+    pub fn init() -> Option<Init> {
+        if Path::new("/run/systemd/system").is_dir() {
+            None
+        } else if Path::new("/run/openrc").is_dir() || Path::new("/sbin/openrc-run").is_file() {
+            Some(Init::OpenRc)
+        } else if Path::new("/run/runit").is_dir() || runit_services().is_some() {
+            Some(Init::Runit)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` in a desktop session without systemd, unless rocas
+    /// runs through sudo or doas, where the entry would be root's.
+    pub fn desktop_session() -> bool {
+        let set = |var| std::env::var_os(var).is_some_and(|value| !value.is_empty());
+        !Path::new("/run/systemd/system").is_dir()
+            && ["XDG_CURRENT_DESKTOP", "WAYLAND_DISPLAY", "DISPLAY"]
+                .into_iter()
+                .any(set)
+            && !["SUDO_USER", "DOAS_USER"]
+                .into_iter()
+                .any(set)
+    }
+
+    /// An XDG autostart entry that starts rocas with the desktop session.
+    struct AutostartEntry;
+
+    impl ServiceManager for AutostartEntry {
+        fn mode(&self) -> Mode {
+            Mode::Desktop
+        }
+
+        fn install(&self, launch: &Launch) -> Result<String, AppError> {
+            let path = desktop_path().ok_or(AutostartError::Unsupported("--mode desktop"))?;
+            let exe = quote(&std::env::current_exe()?.to_string_lossy());
+            let args = launch
+                .line(launch.config.as_deref(), &[])
+                .join(" ");
+            let entry = [
+                "[Desktop Entry]".to_string(),
+                "Type=Application".to_string(),
+                format!("Name={NAME}"),
+                "Comment=Organizes files as they arrive in the watched directories".to_string(),
+                format!("Exec={exe} {args}"),
+                "Terminal=false".to_string(),
+                "NoDisplay=true".to_string(),
+                "X-GNOME-Autostart-enabled=true\n".to_string(),
+            ]
+            .join("\n");
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&path, entry)?;
+            Ok(format!(
+                "rocas will now start when you log in to your desktop, from {}.",
+                path.display()
+            ))
+        }
+
+        fn uninstall(&self) -> Result<(), AppError> {
+            if let Some(path) = desktop_path().filter(|path| path.is_file()) {
+                std::fs::remove_file(path)?;
+            }
+            Ok(())
+        }
+
+        fn start(&self) -> Result<(), AppError> {
+            Err(AutostartError::Unmanaged("an XDG autostart entry", "start").into())
+        }
+
+        fn stop(&self) -> Result<(), AppError> {
+            Err(AutostartError::Unmanaged("an XDG autostart entry", "stop").into())
+        }
+
+        fn status(&self) -> Result<Option<Installed>, AppError> {
+            let Some(path) = desktop_path() else {
+                return Ok(None);
+            };
+            let Ok(entry) = std::fs::read_to_string(&path) else {
+                return Ok(None);
+            };
+            let command = entry
+                .lines()
+                .find_map(|line| line.strip_prefix("Exec="))
+                .map(|command| command.trim().to_string());
+            Ok(Some(Installed {
+                mode: Mode::Desktop,
+                location: path.display().to_string(),
+                command,
+                running: Some(status::is_running()),
+            }))
+        }
+    }
+
+    fn desktop_path() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("autostart/rocas.desktop"))
+    }
+
+    /// An OpenRC or runit service that starts rocas on boot as the user
+    /// running `rocas setup`, even through sudo or doas.
+    struct InitService;
+
+    impl InitService {
+        /// Runs `rc-service` with `openrc`, or `sv` with `runit`, whichever
+        /// the service was set up with. runit brings services up and down.
+        fn control(openrc: &str, runit: &str) -> Result<(), AppError> {
+            if Path::new(OPENRC_SCRIPT).is_file() {
+                Ok(run("rc-service", [SERVICE, openrc])?)
+            } else {
+                Ok(run("sv", [runit, RUNIT_DIR])?)
+            }
+        }
+    }
+
+    impl ServiceManager for InitService {
+        fn mode(&self) -> Mode {
+            Mode::Service
+        }
+
+        fn install(&self, launch: &Launch) -> Result<String, AppError> {
+            let init = init().ok_or(AutostartError::NoServiceManager)?;
+            let user = user();
+            let mut launch = launch.clone();
+            if let Some(home) = user.as_deref().and_then(home_of) {
+                // The service starts without the user's environment.
+                launch
+                    .env
+                    .insert(0, ("HOME".to_string(), home.display().to_string()));
+            }
+            let (exe, args) = command_line(&launch, &[])?;
+            let exe = quote(&exe.to_string_lossy());
+            let args = args.join(" ");
+
+            match init {
+                Init::OpenRc => {
+                    let command_user = user
+                        .map(|user| format!("command_user=\"{user}\"\n"))
+                        .unwrap_or_default();
+                    let script = [
+                        "#!/sbin/openrc-run\n".to_string(),
+                        "description=\"Organizes files as they arrive in the watched directories\""
+                            .to_string(),
+                        format!("command={exe}"),
+                        format!("command_args='{args}'"),
+                        format!("{command_user}command_background=true"),
+                        "pidfile=\"/run/${RC_SVCNAME}.pid\"\n".to_string(),
+                        "depend() {\n\tneed localmount\n\tafter net\n}\n".to_string(),
+                    ]
+                    .join("\n");
+                    write_executable(Path::new(OPENRC_SCRIPT), &script)?;
+                    run("rc-update", ["add", SERVICE, "default"])?;
+                    Ok(format!(
+                        "rocas will now start on boot as the OpenRC service '{SERVICE}'. Start it \
+                         now with `rc-service {SERVICE} start`."
+                    ))
+                },
+                Init::Runit => {
+                    let services = runit_services().ok_or(AutostartError::NoServiceManager)?;
+                    let chpst = user
+                        .map(|user| format!("chpst -u {user} "))
+                        .unwrap_or_default();
+                    let script = format!("#!/bin/sh\nexec 2>&1\nexec {chpst}{exe} {args}\n");
+                    let dir = Path::new(RUNIT_DIR);
+                    std::fs::create_dir_all(dir).map_err(|e| needs_root(e, dir))?;
+                    write_executable(&dir.join("run"), &script)?;
+                    let link = services.join(SERVICE);
+                    if link.symlink_metadata().is_err() {
+                        symlink(dir, &link).map_err(|e| needs_root(e, &link))?;
+                    }
+                    Ok(format!(
+                        "rocas will now start on boot as the runit service '{SERVICE}'. runit \
+                         starts it within a few seconds."
+                    ))
+                },
+            }
+        }
+
+        fn uninstall(&self) -> Result<(), AppError> {
+            let script = Path::new(OPENRC_SCRIPT);
+            if script.is_file() {
+                // It may not be running or enabled.
+                let _ = exists("rc-service", [SERVICE, "stop"]);
+                let _ = exists("rc-update", ["del", SERVICE, "default"]);
+                std::fs::remove_file(script).map_err(|e| needs_root(e, script))?;
+            }
+
+            // runit stops the service once its link is gone.
+            for services in RUNIT_SERVICES {
+                let link = Path::new(services).join(SERVICE);
+                if link.symlink_metadata().is_ok() {
+                    std::fs::remove_file(&link).map_err(|e| needs_root(e, &link))?;
+                }
+            }
+            let dir = Path::new(RUNIT_DIR);
+            if dir.is_dir() {
+                std::fs::remove_dir_all(dir).map_err(|e| needs_root(e, dir))?;
+            }
+            Ok(())
+        }
+
+        fn start(&self) -> Result<(), AppError> {
+            Self::control("start", "up")
+        }
+
+        fn stop(&self) -> Result<(), AppError> {
+            Self::control("stop", "down")
+        }
+
+        fn restart(&self) -> Result<(), AppError> {
+            Self::control("restart", "restart")
+        }
+
+        fn status(&self) -> Result<Option<Installed>, AppError> {
+            if let Ok(script) = std::fs::read_to_string(OPENRC_SCRIPT) {
+                let value = |key: &str| {
+                    script.lines().find_map(|line| {
+                        line.strip_prefix(key)?
+                            .strip_prefix('=')
+                            .map(|value| value.trim_matches('\''))
+                    })
+                };
+                let command = value("command").map(|exe| {
+                    format!("{exe} {}", value("command_args").unwrap_or_default())
+                        .trim_end()
+                        .to_string()
+                });
+                return Ok(Some(Installed {
+                    mode: Mode::Service,
+                    location: format!("the OpenRC service {OPENRC_SCRIPT}"),
+                    command,
+                    running: Some(exists("rc-service", [SERVICE, "status"])),
+                }));
+            }
+
+            let Ok(run_script) = std::fs::read_to_string(Path::new(RUNIT_DIR).join("run")) else {
+                return Ok(None);
+            };
+            let command = run_script
+                .lines()
+                .filter_map(|line| line.strip_prefix("exec "))
+                .find(|line| !line.starts_with("2>"))
+                .map(|line| match line.strip_prefix("chpst -u ") {
+                    Some(rest) => rest
+                        .split_once(' ')
+                        .map_or("", |(_, command)| command),
+                    None => line,
+                })
+                .map(str::to_string);
+            Ok(Some(Installed {
+                mode: Mode::Service,
+                location: format!("the runit service {RUNIT_DIR}"),
+                command,
+                running: output("sv", ["status", RUNIT_DIR]).map(|state| state.starts_with("run:")),
+            }))
+        }
+    }
+
+    fn runit_services() -> Option<PathBuf> {
+        RUNIT_SERVICES
+            .iter()
+            .map(PathBuf::from)
+            .find(|dir| dir.is_dir())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod linux {
+    use crate::service::ServiceManager;
+
+    /// An init system rocas sets up a service with.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Init {}
+
+    pub fn managers() -> Vec<Box<dyn ServiceManager>> {
+        Vec::new()
+    }
+
+    pub const fn init() -> Option<Init> {
+        None
+    }
+
+    pub const fn desktop_session() -> bool {
+        false
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+mod freebsd {
+    use std::path::Path;
+
+    use super::{
+        AutostartError,
+        Installed,
+        Launch,
+        Mode,
+        command_line,
+        exists,
+        home_of,
+        needs_root,
+        quote,
+        run,
+        user,
+        write_executable,
+    };
+    use crate::AppError;
+    use crate::service::ServiceManager;
+
+    /// The name of the service, and of its rc.conf variables.
+    const SERVICE: &str = "rocas";
+    const SCRIPT: &str = "/usr/local/etc/rc.d/rocas";
+    const PIDFILE: &str = "/var/run/rocas.pid";
+
+    /// Returns the rc.d service.
+    pub fn managers() -> Vec<Box<dyn ServiceManager>> {
+        vec![Box::new(RcService)]
+    }
+
+    /// An rc.d script that starts rocas on boot as the user running `rocas
+    /// setup`, enabled in rc.conf with `sysrc`.
+    struct RcService;
+
+    impl ServiceManager for RcService {
+        fn mode(&self) -> Mode {
+            Mode::Service
+        }
+
+        fn install(&self, launch: &Launch) -> Result<String, AppError> {
+            let user = user();
+            let mut launch = launch.clone();
+            if let Some(home) = user.as_deref().and_then(home_of) {
+                // The service starts without the user's environment.
+                launch
+                    .env
+                    .insert(0, ("HOME".to_string(), home.display().to_string()));
+            }
+            let (exe, args) = command_line(&launch, &[])?;
+            let exe = quote(&exe.to_string_lossy());
+            let args = args.join(" ");
+            // daemon(8) writes the pid file before it drops to the user.
+            let daemon_user = user
+                .map(|user| format!("-u {user} "))
+                .unwrap_or_default();
+
+            let script = [
+                "#!/bin/sh\n".to_string(),
+                format!("# PROVIDE: {SERVICE}"),
+                "# REQUIRE: LOGIN".to_string(),
+                "# KEYWORD: shutdown\n".to_string(),
+                ". /etc/rc.subr\n".to_string(),
+                format!("name=\"{SERVICE}\""),
+                format!("rcvar=\"{SERVICE}_enable\"\n"),
+                format!("pidfile=\"{PIDFILE}\""),
+                "command=\"/usr/sbin/daemon\"".to_string(),
+                format!("command_args='-f -p {PIDFILE} {daemon_user}{exe} {args}'\n"),
+                "load_rc_config $name".to_string(),
+                format!(": ${{{SERVICE}_enable:=\"NO\"}}\n"),
+                "run_rc_command \"$1\"\n".to_string(),
+            ]
+            .join("\n");
+            write_executable(Path::new(SCRIPT), &script)?;
+            run("sysrc", [format!("{SERVICE}_enable=YES")])?;
+            Ok(format!(
+                "rocas will now start on boot as the rc.d service '{SERVICE}'. Start it now with \
+                 `service {SERVICE} start`."
+            ))
+        }
+
+        fn uninstall(&self) -> Result<(), AppError> {
+            let script = Path::new(SCRIPT);
+            if script.is_file() {
+                // It may not be running or enabled.
+                let _ = exists("service", [SERVICE, "onestop"]);
+                let _ = exists("sysrc", ["-x", format!("{SERVICE}_enable").as_str()]);
+                std::fs::remove_file(script).map_err(|e| needs_root(e, script))?;
+            }
+            Ok(())
+        }
+
+        fn start(&self) -> Result<(), AppError> {
+            Ok(run("service", [SERVICE, "start"])?)
+        }
+
+        fn stop(&self) -> Result<(), AppError> {
+            Ok(run("service", [SERVICE, "stop"])?)
+        }
+
+        fn restart(&self) -> Result<(), AppError> {
+            Ok(run("service", [SERVICE, "restart"])?)
+        }
+
+        fn status(&self) -> Result<Option<Installed>, AppError> {
+            let Ok(script) = std::fs::read_to_string(SCRIPT) else {
+                return Ok(None);
+            };
+            // The command is what daemon(8) runs after its own options.
+            let command = script
+                .lines()
+                .find_map(|line| line.strip_prefix("command_args="))
+                .map(|args| {
+                    let mut words = args.trim_matches('\'').split(' ');
+                    let mut command = Vec::new();
+                    while let Some(word) = words.next() {
+                        match word {
+                            "-f" if command.is_empty() => {},
+                            "-p" | "-u" if command.is_empty() => {
+                                words.next();
+                            },
+                            _ => command.push(word),
+                        }
+                    }
+                    command.join(" ")
+                });
+            Ok(Some(Installed {
+                mode: Mode::Service,
+                location: format!("the rc.d service {SCRIPT}"),
+                command,
+                running: Some(exists("service", [SERVICE, "onestatus"])),
+            }))
+        }
+    }
+}
+
+#[cfg(not(target_os = "freebsd"))]
+mod freebsd {
+    use crate::service::ServiceManager;
+
+    pub fn managers() -> Vec<Box<dyn ServiceManager>> {
+        Vec::new()
+    }
+}
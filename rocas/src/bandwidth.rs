@@ -0,0 +1,116 @@
+//! Bandwidth limit for copy-based moves.
+//!
+//! Moves across filesystems (e.g. onto a network share) can saturate a slow
+//! link. A [`BandwidthLimit`] is shared by all workers and paces the chunked
+//! copy so the combined rate stays under the configured bytes per second,
+//! optionally only during certain hours of the day.
+
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::clock;
+use crate::config::WatcherConfig;
+
+#[derive(Debug, Error)]
+pub enum BandwidthError {
+    #[error("bandwidth_hours '{0}' must look like HH:MM-HH:MM")]
+    InvalidHours(String),
+}
+
+/// A daily time range in minutes since local midnight. The range wraps past
+/// midnight when `end` is before `start`, e.g. `22:00-06:00`.
+#[derive(Debug, Clone, Copy)]
+struct Hours {
+    start: u32,
+    end: u32,
+}
+
+impl Hours {
+    fn parse(raw: &str) -> Result<Self, BandwidthError> {
+        let invalid = || BandwidthError::InvalidHours(raw.to_owned());
+        let (start, end) = raw
+            .split_once('-')
+            .ok_or_else(invalid)?;
+
+        Ok(Self {
+            start: parse_time(start).ok_or_else(invalid)?,
+            end: parse_time(end).ok_or_else(invalid)?,
+        })
+    }
+
+    fn contains(self, minute: u32) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// Parses `HH:MM` into minutes since midnight.
+fn parse_time(raw: &str) -> Option<u32> {
+    let (hours, minutes) = raw.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// Caps the combined rate of all running copies.
+pub struct BandwidthLimit {
+    bytes_per_sec: u64,
+    hours: Option<Hours>,
+    /// When the bytes handed out so far will have been "sent" at the limit.
+    next_free: Mutex<Instant>,
+}
+
+impl BandwidthLimit {
+    /// Builds the limit from `bandwidth_limit_kb` and `bandwidth_hours`, or
+    /// returns `None` when no limit is configured.
+    pub fn new(settings: &WatcherConfig) -> Result<Option<Self>, BandwidthError> {
+        let Some(kb) = settings
+            .bandwidth_limit_kb
+            .filter(|&kb| kb > 0)
+        else {
+            return Ok(None);
+        };
+
+        let hours = settings
+            .bandwidth_hours
+            .as_deref()
+            .map(Hours::parse)
+            .transpose()?;
+
+        Ok(Some(Self {
+            bytes_per_sec: kb * 1024,
+            hours,
+            next_free: Mutex::new(Instant::now()),
+        }))
+    }
+
+    /// Accounts for `bytes` just written and sleeps as long as needed to keep
+    /// the combined rate under the limit. Does nothing outside the configured
+    /// hours.
+    pub fn consume(&self, bytes: u64) {
+        if self
+            .hours
+            .is_some_and(|hours| !hours.contains(clock::now().minute_of_day()))
+        {
+            return;
+        }
+
+        let cost = Duration::from_nanos(bytes.saturating_mul(1_000_000_000) / self.bytes_per_sec);
+        let until = {
+            let mut next_free = self
+                .next_free
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            // Idle time does not build up credit for a later burst.
+            *next_free = (*next_free).max(Instant::now()) + cost;
+            *next_free
+        };
+
+        std::thread::sleep(until.saturating_duration_since(Instant::now()));
+    }
+}
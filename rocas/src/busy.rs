@@ -0,0 +1,101 @@
+//! Detection of files that another process still has open.
+//!
+//! Moving a file while its writer holds it open either fails half-way
+//! (Windows) or leaves the writer appending to a file that has already been
+//! filed away. Matches on such files are deferred by the event loop and
+//! retried once the file is free.
+
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// A match deferred because the file was open in another process.
+pub struct OpenFile {
+    pub path: PathBuf,
+    /// When the file was first found open.
+    pub since: Instant,
+    /// When to check the file again.
+    pub retry_at: Instant,
+}
+
+/// Returns `true` if another process has `path` open.
+///
+/// Errors while probing (e.g. the file vanished) count as "not open"; the
+/// move itself will report them.
+#[cfg(windows)]
+pub fn is_open(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const ERROR_LOCK_VIOLATION: i32 = 33;
+
+    // Asking for exclusive access (no sharing) fails while any other handle
+    // to the file is open.
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .share_mode(0)
+        .open(path)
+    {
+        Ok(_) => false,
+        Err(e) => matches!(e.raw_os_error(), Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)),
+    }
+}
+
+/// Returns `true` if another process has `path` open.
+///
+/// Errors while probing (e.g. the file vanished) count as "not open"; the
+/// move itself will report them.
+#[cfg(unix)]
+pub fn is_open(path: &Path) -> bool {
+    is_locked(path) || has_open_handle(path)
+}
+
+/// Returns `true` if another process has `path` open.
+#[cfg(not(any(unix, windows)))]
+pub fn is_open(_path: &Path) -> bool {
+    false
+}
+
+/// Returns `true` if another process holds an advisory `flock` on `path`.
+#[cfg(unix)]
+fn is_locked(path: &Path) -> bool {
+    use std::os::fd::AsRawFd;
+
+    let Ok(file) = std::fs::File::open(path) else {
+        return false;
+    };
+
+    // SAFETY: the fd stays valid for the lifetime of `file`. The probe lock,
+    // if acquired, is released when `file` is closed.
+    let rc = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    rc != 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EWOULDBLOCK)
+}
+
+/// Returns `true` if any other process has a file descriptor open on `path`,
+/// found by scanning `/proc/<pid>/fd` like `lsof` does. Only processes whose
+/// descriptors we may read are considered.
+#[cfg(target_os = "linux")]
+fn has_open_handle(path: &Path) -> bool {
+    let Ok(target) = std::fs::canonicalize(path) else {
+        return false;
+    };
+    let Ok(procs) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+    let own_pid = std::process::id().to_string();
+
+    procs
+        .filter_map(Result::ok)
+        .filter(|proc| {
+            let name = proc.file_name();
+            let name = name.to_string_lossy();
+            name != own_pid && name.bytes().all(|b| b.is_ascii_digit())
+        })
+        .filter_map(|proc| std::fs::read_dir(proc.path().join("fd")).ok())
+        .flat_map(|fds| fds.filter_map(Result::ok))
+        .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|link| link == target))
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn has_open_handle(_path: &Path) -> bool {
+    false
+}
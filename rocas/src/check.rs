@@ -0,0 +1,340 @@
+//! `rocas check`: validates the config without starting to watch.
+//!
+//! Besides what loading the config already enforces, this looks for
+//! mistakes that would otherwise only show up at runtime or not at all:
+//! misspelled keys, patterns that do not compile, watch paths that do not
+//! exist, watchers that apply rules that do not exist, destinations inside a
+//! watched directory and rules that can never match because an earlier rule
+//! takes their files.
+
+use std::path::Path;
+
+use serde_json::{Value, json};
+
+use crate::config::{self, Config, MiscConfig, RuleConfig, WatcherConfig};
+use crate::rule::CompiledRule;
+use crate::{AppError, archive, engine, include, migrate, schema, webdav};
+
+/// Counts and prints diagnostics. With `--json`, they are collected and
+/// printed together by [`Diagnostics::finish`].
+#[derive(Default)]
+struct Diagnostics {
+    errors: usize,
+    warnings: usize,
+    json: Option<Vec<Value>>,
+}
+
+impl Diagnostics {
+    fn new(json: bool) -> Self {
+        Self {
+            json: json.then(Vec::new),
+            ..Self::default()
+        }
+    }
+
+    fn error(&mut self, msg: &str) {
+        self.errors += 1;
+        self.report("error", msg);
+    }
+
+    fn warning(&mut self, msg: &str) {
+        self.warnings += 1;
+        self.report("warning", msg);
+    }
+
+    fn report(&mut self, severity: &str, msg: &str) {
+        match &mut self.json {
+            Some(problems) => problems.push(json!({ "severity": severity, "message": msg })),
+            None => println!("{severity}: {msg}"),
+        }
+    }
+
+    /// Prints the summary for the config at `path`. Returns `true` if there
+    /// were no errors.
+    fn finish(self, path: &str) -> bool {
+        if let Some(problems) = self.json {
+            let summary = json!({
+                "config": path,
+                "valid": self.errors == 0,
+                "errors": self.errors,
+                "warnings": self.warnings,
+                "problems": problems,
+            });
+            println!("{summary:#}");
+        } else if self.errors + self.warnings == 0 {
+            println!("{path}: no problems found");
+        } else {
+            println!("{path}: {} error(s), {} warning(s)", self.errors, self.warnings);
+        }
+        self.errors == 0
+    }
+}
+
+/// Checks `config` and prints what is wrong with it, as JSON if `json` is
+/// set. Returns `true` if no errors were found; warnings do not count.
+pub fn run(config: &Config, json: bool) -> bool {
+    let path = config::config_path();
+    let mut diag = Diagnostics::new(json);
+
+    check_keys(Path::new(&path), &mut diag);
+    check_watchers(config, &mut diag);
+    check_rules(config, &mut diag);
+
+    diag.finish(&path)
+}
+
+/// Prints why the config could not be loaded, as JSON if `json` is set.
+pub fn load_failed(error: &AppError, json: bool) {
+    if json {
+        let mut diag = Diagnostics::new(true);
+        diag.error(&error.to_string());
+        diag.finish(&config::config_path());
+    } else {
+        println!("error: {error}");
+    }
+}
+
+/// Reports keys the config file sets that rocas does not know, which are
+/// otherwise silently ignored. Included files are checked too.
+fn check_keys(path: &Path, diag: &mut Diagnostics) {
+    if !path.exists() {
+        diag.warning(&format!(
+            "no config file at '{}'; using the defaults (run `rocas init` to create one)",
+            path.display()
+        ));
+        return;
+    }
+    // Rules from included files are numbered after their position in the
+    // merged list, as everywhere else.
+    let table = match include::resolve(path) {
+        Ok(table) => table,
+        Err(e) => {
+            diag.error(&e.to_string());
+            return;
+        },
+    };
+
+    if !table.contains_key(migrate::VERSION_KEY) {
+        diag.warning("the config does not say which format version it is for; run `rocas migrate`");
+    }
+
+    for (section, value) in &table {
+        match (section.as_str(), value) {
+            ("watcher", toml::Value::Table(keys)) => {
+                unknown_keys("[watcher]", keys, &schema::names(WatcherConfig::FIELDS), diag);
+            },
+            ("watcher", toml::Value::Array(watchers)) => {
+                for (idx, watcher) in watchers.iter().enumerate() {
+                    if let toml::Value::Table(keys) = watcher {
+                        unknown_keys(
+                            &format!("watcher {}", idx + 1),
+                            keys,
+                            &schema::names(WatcherConfig::FIELDS),
+                            diag,
+                        );
+                    }
+                }
+            },
+            ("misc", toml::Value::Table(keys)) => {
+                unknown_keys("[misc]", keys, &schema::names(MiscConfig::FIELDS), diag);
+            },
+            ("rules", toml::Value::Array(rules)) => {
+                for (idx, rule) in rules.iter().enumerate() {
+                    if let toml::Value::Table(keys) = rule {
+                        unknown_keys(
+                            &format!("rule {}", idx + 1),
+                            keys,
+                            &schema::names(RuleConfig::FIELDS),
+                            diag,
+                        );
+                    }
+                }
+            },
+            ("version", toml::Value::Integer(version)) => {
+                if *version < i64::from(migrate::CURRENT_VERSION) {
+                    diag.warning(&format!(
+                        "the config is for version {version} of the format; run `rocas migrate` \
+                         to upgrade it"
+                    ));
+                }
+            },
+            ("watcher" | "misc" | "rules" | "version", _) => {
+                diag.error(&format!("'{section}' has the wrong type"));
+            },
+            _ => diag.error(&format!(
+                "unknown section '{section}'{}",
+                suggestion(section, &["version", "watcher", "misc", "rules"])
+            )),
+        }
+    }
+}
+
+fn unknown_keys(location: &str, keys: &toml::Table, known: &[&str], diag: &mut Diagnostics) {
+    for key in keys.keys() {
+        if !known.contains(&key.as_str()) {
+            diag.error(&format!("unknown key '{key}' in {location}{}", suggestion(key, known)));
+        }
+    }
+}
+
+/// Returns a "did you mean" hint for the known name closest to `name`, if
+/// one is close enough to be a likely typo.
+fn suggestion(name: &str, known: &[&str]) -> String {
+    known
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2.max(name.len() / 3))
+        .min()
+        .map(|(_, candidate)| format!("; did you mean '{candidate}'?"))
+        .unwrap_or_default()
+}
+
+/// Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute
+                .min(row[j] + 1)
+                .min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+fn check_watchers(config: &Config, diag: &mut Diagnostics) {
+    let watchers = config.watchers();
+
+    for (idx, watcher) in watchers.iter().enumerate() {
+        if watchers[..idx]
+            .iter()
+            .any(|earlier| earlier.name == watcher.name)
+        {
+            diag.error(&format!("there is more than one watcher named '{}'", watcher.name));
+        }
+        if let Err(e) = config.rules_of(watcher) {
+            diag.error(&e.to_string());
+        }
+
+        for path in watcher.effective_paths() {
+            match std::fs::metadata(path) {
+                Ok(meta) if meta.is_dir() => {},
+                Ok(_) => diag.error(&format!("watch path '{path}' is not a directory")),
+                Err(e) => diag.error(&format!("watch path '{path}' cannot be watched: {e}")),
+            }
+        }
+    }
+}
+
+fn check_rules(config: &Config, diag: &mut Diagnostics) {
+    for (idx, rule) in config.rules.iter().enumerate() {
+        let name = format!("rule '{}'", rule.name);
+        // Only the directories of the watchers that apply the rule matter.
+        let roots: Vec<_> = config
+            .watchers()
+            .into_iter()
+            .filter(|watcher| watcher.rules.is_empty() || watcher.rules.contains(&rule.name))
+            .flat_map(WatcherConfig::effective_paths)
+            .filter_map(|root| std::path::absolute(root).ok())
+            .collect();
+
+        if rule.patterns.is_empty() {
+            diag.error(&format!("{name} has no patterns and never matches"));
+        }
+        if rule.destination.is_empty() {
+            diag.error(&format!("{name} has no destination"));
+        }
+        if let Err(e) = CompiledRule::new(rule) {
+            diag.error(&format!("{name}: {e}"));
+        }
+
+        check_destination(&name, rule, &roots, diag);
+        check_duplicates(config, idx, &name, diag);
+    }
+}
+
+/// Warns about a local destination inside a watched directory: rocas
+/// ignores files in it, and one equal to the watched directory matches its
+/// own moves again.
+fn check_destination(
+    name: &str,
+    rule: &RuleConfig,
+    roots: &[std::path::PathBuf],
+    diag: &mut Diagnostics,
+) {
+    // The engine does not exclude remote destinations and archives either.
+    if webdav::is_remote(&rule.destination) || archive::is_archive(&rule.destination) {
+        return;
+    }
+
+    let Ok(dest) = std::path::absolute(engine::static_dir(&rule.destination)) else {
+        return;
+    };
+
+    for root in roots {
+        if dest == *root {
+            diag.warning(&format!(
+                "{name}: destination '{}' is the watched directory; moved files would be matched \
+                 again",
+                rule.destination
+            ));
+        } else if dest.starts_with(root) {
+            diag.warning(&format!(
+                "{name}: destination '{}' is inside the watched directory '{}'; files in it are \
+                 ignored",
+                rule.destination,
+                root.display()
+            ));
+        }
+    }
+}
+
+/// Warns about rules that repeat an earlier one or its name, and about
+/// patterns an earlier rule always takes first when only the first matching
+/// rule runs.
+fn check_duplicates(config: &Config, idx: usize, name: &str, diag: &mut Diagnostics) {
+    let rule = &config.rules[idx];
+
+    for (earlier_idx, earlier) in config.rules[..idx].iter().enumerate() {
+        if rule.name == earlier.name {
+            diag.warning(&format!("{name} has the same name as rule {}", earlier_idx + 1));
+        }
+
+        let mut patterns = rule.patterns.clone();
+        let mut earlier_patterns = earlier.patterns.clone();
+        patterns.sort();
+        earlier_patterns.sort();
+
+        if patterns == earlier_patterns && rule.destination == earlier.destination {
+            diag.warning(&format!("{name} duplicates rule '{}'", earlier.name));
+            continue;
+        }
+
+        // A `contains` condition lets files fall through to later rules, as
+        // does applying every matching rule.
+        if config
+            .watchers()
+            .iter()
+            .any(|watcher| watcher.match_all())
+            || earlier.contains.is_some()
+        {
+            continue;
+        }
+        for pattern in &rule.patterns {
+            if earlier.patterns.contains(pattern) {
+                diag.warning(&format!(
+                    "{name}: pattern '{pattern}' is already matched by rule '{}', so this rule \
+                     never sees those files",
+                    earlier.name
+                ));
+            }
+        }
+    }
+}
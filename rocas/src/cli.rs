@@ -1,4 +1,6 @@
 // use self_update::cargo_crate_version;
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
@@ -6,11 +8,216 @@ use clap::{Parser, Subcommand};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+
+    /// Organize the files already in the watched directories on startup
+    #[arg(long, global = true)]
+    pub existing: bool,
+
+    /// Only log what the rules would do, regardless of `dry_run` in the
+    /// config
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Print the output of status, test, check and setup --status as JSON
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Load the config from this file instead of the default locations
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Set an environment variable before loading the config; repeatable
+    #[arg(
+        long,
+        global = true,
+        value_name = "KEY=VALUE",
+        value_parser = crate::interpolate::parse_assignment
+    )]
+    pub env: Vec<(String, String)>,
 }
 
 #[derive(Subcommand, Debug, PartialEq)]
 pub enum Commands {
-    /// Configure rocas to start on boot
-    #[command(visible_alias = "b")]
-    Boot,
+    /// Watch the configured directories and organize new files (the default)
+    Run {
+        /// Detach from the terminal and keep running in the background
+        #[arg(long)]
+        daemon: bool,
+
+        /// Where to write the process id of the daemon
+        #[arg(long, value_name = "PATH", requires = "daemon")]
+        pid_file: Option<PathBuf>,
+
+        /// Run as a Windows service, as `rocas setup --mode service` sets up
+        #[arg(long, hide = true, conflicts_with = "daemon")]
+        service: bool,
+    },
+
+    /// Start rocas on boot
+    Setup {
+        /// Show how rocas is set up to start instead, and whether it runs
+        #[arg(long, conflicts_with_all = ["mode", "highest"])]
+        status: bool,
+
+        /// How to start rocas: at login (the default), from a Task Scheduler
+        /// task at login, as a service on boot (Windows, FreeBSD, and Linux
+        /// with OpenRC or runit; the default on the last two), or from an XDG
+        /// autostart entry (Linux; the default on desktops without systemd)
+        #[arg(long, value_parser = ["login", "task", "service", "desktop"])]
+        mode: Option<String>,
+
+        /// Run the task with the highest privileges available to the user
+        #[arg(long)]
+        highest: bool,
+
+        /// More arguments to start `rocas run` with, after `--`
+        #[arg(last = true, value_name = "ARGS")]
+        args: Vec<String>,
+    },
+
+    /// Stop starting rocas on boot, however it was set up
+    Unsetup,
+
+    /// Start rocas through whatever `rocas setup` set up: the service,
+    /// task, launch agent or systemd user unit
+    Start,
+
+    /// Stop the rocas that `rocas setup` set up to start
+    Stop,
+
+    /// Restart the rocas that `rocas setup` set up to start, e.g. to load
+    /// a new binary
+    Restart,
+
+    /// Validate the config and report problems without starting to watch
+    Check,
+
+    /// Move the files of recent moves back to where they were
+    Undo {
+        /// Undo the latest N moves (1 by default)
+        #[arg(long, value_name = "N", conflicts_with = "since")]
+        last: Option<usize>,
+
+        /// Undo the moves made within this long, e.g. 10m, 2h or 1d
+        #[arg(long, value_name = "AGE", value_parser = crate::history::parse_age)]
+        since: Option<std::time::Duration>,
+    },
+
+    /// Stop starting rocas on boot and remove its state and logs, and
+    /// optionally its config and the rocas binary
+    Uninstall {
+        /// Also remove the config and the rocas binary, without asking
+        #[arg(long)]
+        purge: bool,
+    },
+
+    /// Watch directories with a single rule given here, without reading
+    /// the config file
+    Watch {
+        /// Directories to watch
+        #[arg(required = true, value_name = "DIR")]
+        paths: Vec<PathBuf>,
+
+        /// Files to match, e.g. '*.pdf'; repeat for several patterns
+        #[arg(long = "pattern", required = true, value_name = "PATTERN")]
+        patterns: Vec<String>,
+
+        /// Where matching files go
+        #[arg(long, value_name = "PATH")]
+        to: String,
+
+        /// What to do with matching files
+        #[arg(long, value_parser = ["move", "hardlink"])]
+        action: Option<String>,
+
+        /// Also watch subdirectories
+        #[arg(long)]
+        recursive: bool,
+    },
+
+    /// Write a commented starter config to the config directory
+    Init {
+        /// Overwrite an existing config
+        #[arg(long)]
+        force: bool,
+
+        /// Ask which folder to watch, which example rules to keep and
+        /// whether to start on boot
+        #[arg(long, short)]
+        interactive: bool,
+    },
+
+    /// Print the log of rocas from its log file, or from the journal when
+    /// it runs as a systemd service
+    Logs {
+        /// Keep printing new lines as they are logged
+        #[arg(long, short)]
+        follow: bool,
+
+        /// Number of lines to print from the end of the log
+        #[arg(long, short = 'n', default_value_t = 50, value_name = "N")]
+        lines: usize,
+    },
+
+    /// Rewrite the config in the current format, keeping a backup
+    Migrate,
+
+    /// Remove the empty directories left under the watched directories
+    PruneEmpty {
+        /// Directory to prune instead of the watched directories
+        path: Option<PathBuf>,
+
+        /// Directories to keep with all they contain, e.g. '.git' or
+        /// 're:^keep-.*'; repeat for several patterns
+        #[arg(long, value_name = "PATTERN")]
+        ignore: Vec<String>,
+    },
+
+    /// Edit the rules in the config file
+    Rule {
+        #[command(subcommand)]
+        command: RuleCommand,
+    },
+
+    /// Print a JSON Schema of the config file for editor validation
+    Schema,
+
+    /// Show what the running rocas is doing: uptime, watched directories,
+    /// rules, files moved, the last event and pending retries
+    Status,
+
+    /// Show how every rule evaluates a file, pattern by pattern and
+    /// condition by condition, to find out why it is or is not picked up
+    Explain {
+        /// The file to explain
+        path: PathBuf,
+    },
+
+    /// Show which rule would handle each path, without touching it
+    Test {
+        /// Paths to test; read one per line from stdin if there are none
+        paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum RuleCommand {
+    /// Append a rule to the config file, after the existing ones
+    Add {
+        /// Files to match, e.g. '*.pdf'; repeat for several patterns
+        #[arg(long = "pattern", required = true, value_name = "PATTERN")]
+        patterns: Vec<String>,
+
+        /// Where matching files go
+        #[arg(long, value_name = "PATH")]
+        dest: String,
+
+        /// What to do with matching files
+        #[arg(long, value_parser = ["move", "hardlink"])]
+        action: Option<String>,
+
+        /// Name of the rule in logs
+        #[arg(long)]
+        name: Option<String>,
+    },
 }
@@ -0,0 +1,127 @@
+//! Local wall-clock time, for date placeholders and time-of-day settings.
+//!
+//! Only the calendar fields rocas needs are exposed, read from the operating
+//! system so its time zone rules apply.
+
+use std::time::SystemTime;
+
+/// A point in time broken down in the local time zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: u16,
+    /// 1–12.
+    pub month: u8,
+    /// 1–31.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl LocalTime {
+    /// Minutes since local midnight.
+    pub fn minute_of_day(self) -> u32 {
+        u32::from(self.hour) * 60 + u32::from(self.minute)
+    }
+}
+
+/// Returns the current local time.
+pub fn now() -> LocalTime {
+    at(SystemTime::now())
+}
+
+/// Returns `time` in the local time zone.
+#[cfg(unix)]
+pub fn at(time: SystemTime) -> LocalTime {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let secs = libc::time_t::try_from(secs).unwrap_or(libc::time_t::MAX);
+
+    // SAFETY: `localtime_r` only reads `secs` and writes to `tm`.
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&raw const secs, &raw mut tm);
+        tm
+    };
+
+    let field = |value: libc::c_int| u8::try_from(value).unwrap_or(0);
+    LocalTime {
+        year: u16::try_from(tm.tm_year + 1900).unwrap_or(1970),
+        month: field(tm.tm_mon + 1),
+        day: field(tm.tm_mday),
+        hour: field(tm.tm_hour),
+        minute: field(tm.tm_min),
+        second: field(tm.tm_sec),
+    }
+}
+
+/// Returns `time` in the local time zone.
+#[cfg(windows)]
+pub fn at(time: SystemTime) -> LocalTime {
+    use windows_sys::Win32::Foundation::{FILETIME, SYSTEMTIME};
+    use windows_sys::Win32::System::Time::{FileTimeToSystemTime, SystemTimeToTzSpecificLocalTime};
+
+    /// 100 ns intervals between 1601-01-01 (the `FILETIME` epoch) and
+    /// 1970-01-01.
+    const UNIX_EPOCH_TICKS: u64 = 116_444_736_000_000_000;
+
+    let ticks = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_nanos() / 100).unwrap_or(u64::MAX))
+        .saturating_add(UNIX_EPOCH_TICKS);
+    let file_time = FILETIME {
+        dwLowDateTime: (ticks & 0xFFFF_FFFF) as u32,
+        dwHighDateTime: (ticks >> 32) as u32,
+    };
+
+    // SAFETY: both calls only read and write the structs passed to them; a
+    // null time zone means the current one.
+    let local = unsafe {
+        let mut utc: SYSTEMTIME = std::mem::zeroed();
+        let mut local: SYSTEMTIME = std::mem::zeroed();
+        FileTimeToSystemTime(&raw const file_time, &raw mut utc);
+        SystemTimeToTzSpecificLocalTime(std::ptr::null(), &raw const utc, &raw mut local);
+        local
+    };
+
+    let field = |value: u16| u8::try_from(value).unwrap_or(0);
+    LocalTime {
+        year: local.wYear,
+        month: field(local.wMonth),
+        day: field(local.wDay),
+        hour: field(local.wHour),
+        minute: field(local.wMinute),
+        second: field(local.wSecond),
+    }
+}
+
+/// Returns `time` in UTC; local time zones are not supported here.
+#[cfg(not(any(unix, windows)))]
+pub fn at(time: SystemTime) -> LocalTime {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let days = secs / 86_400;
+
+    // Gregorian calendar calculation (algorithm by Howard Hinnant).
+    let shifted = days + 719_468;
+    let era = shifted / 146_097;
+    let doe = shifted - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    let field = |value: u64| u8::try_from(value).unwrap_or(0);
+    LocalTime {
+        year: u16::try_from(year).unwrap_or(u16::MAX),
+        month: field(month),
+        day: field(day),
+        hour: field(secs / 3_600 % 24),
+        minute: field(secs / 60 % 60),
+        second: field(secs % 60),
+    }
+}
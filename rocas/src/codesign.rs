@@ -0,0 +1,168 @@
+//! Checks of the operating system's code signature on a binary, for
+//! `misc.update_signer`.
+//!
+//! On Windows the Authenticode signature is checked with PowerShell's
+//! `Get-AuthenticodeSignature`, which asks `WinVerifyTrust` whether the
+//! signature is valid and chains to a trusted root. The signer matches if it
+//! is the subject of the signing certificate, like `CN=Example, O=Example,
+//! C=US`, or just its common name.
+//!
+//! On macOS `codesign --verify --strict` checks the signature, and the signer
+//! matches if it is the Team ID or one of the authorities `codesign
+//! --display` lists, like `Developer ID Application: Example (ABCDE12345)`.
+//!
+//! Linux binaries carry no code signature; there the release key signature
+//! checked by the updater is all there is.
+
+use std::path::Path;
+use std::process::ExitStatus;
+#[cfg(any(windows, target_os = "macos"))]
+use std::process::{Command, Stdio};
+
+/// Error returned when a binary is not signed by the expected signer.
+#[derive(Debug, thiserror::Error)]
+pub enum CodesignError {
+    #[error("the {0} command was not found")]
+    NotInstalled(&'static str),
+
+    #[error("it has no valid code signature ({0})")]
+    Invalid(String),
+
+    #[error("checking its code signature failed ({status}): {stderr}")]
+    Failed { status: ExitStatus, stderr: String },
+
+    #[error("it is signed by {actual}, not by {expected}")]
+    WrongSigner { expected: String, actual: String },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Returns `true` if binaries on this platform carry a code signature that
+/// [`verify`] can check.
+pub const fn supported() -> bool {
+    cfg!(any(windows, target_os = "macos"))
+}
+
+/// Checks that the binary at `path` has a valid code signature made by
+/// `signer`. Does nothing where [`supported`] is `false`.
+#[cfg(windows)]
+pub fn verify(path: &Path, signer: &str) -> Result<(), CodesignError> {
+    // The path goes through the environment, so it needs no quoting.
+    let output = Command::new("powershell")
+        .args([
+            "-NoProfile",
+            "-NonInteractive",
+            "-Command",
+            "$s = Get-AuthenticodeSignature -LiteralPath $env:ROCAS_SIGNED_FILE; $s.Status; \
+             $s.SignerCertificate.Subject",
+        ])
+        .env("ROCAS_SIGNED_FILE", path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| not_installed(e, "powershell"))?;
+    if !output.status.success() {
+        return Err(failed(output.status, &output.stderr));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().map(str::trim);
+    let status = lines.next().unwrap_or_default();
+    if status != "Valid" {
+        return Err(CodesignError::Invalid(status.to_string()));
+    }
+    let subject = lines.next().unwrap_or_default();
+    let common_name = subject
+        .split(',')
+        .map(str::trim)
+        .find_map(|part| part.strip_prefix("CN="));
+    if subject == signer || common_name == Some(signer) {
+        Ok(())
+    } else {
+        Err(wrong_signer(signer, subject))
+    }
+}
+
+/// Checks that the binary at `path` has a valid code signature made by
+/// `signer`. Does nothing where [`supported`] is `false`.
+#[cfg(target_os = "macos")]
+pub fn verify(path: &Path, signer: &str) -> Result<(), CodesignError> {
+    let checked = Command::new("codesign")
+        .args(["--verify", "--strict"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| not_installed(e, "codesign"))?;
+    if !checked.status.success() {
+        let reason = String::from_utf8_lossy(&checked.stderr);
+        return Err(CodesignError::Invalid(
+            reason
+                .lines()
+                .last()
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+        ));
+    }
+
+    // `codesign --display` writes what it finds to stderr.
+    let shown = Command::new("codesign")
+        .args(["--display", "--verbose=2"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| not_installed(e, "codesign"))?;
+    if !shown.status.success() {
+        return Err(failed(shown.status, &shown.stderr));
+    }
+    let details = String::from_utf8_lossy(&shown.stderr);
+    let signers: Vec<&str> = details
+        .lines()
+        .filter_map(|line| {
+            line.strip_prefix("Authority=")
+                .or_else(|| line.strip_prefix("TeamIdentifier="))
+        })
+        .map(str::trim)
+        .collect();
+    if signers.contains(&signer) {
+        Ok(())
+    } else if signers.is_empty() {
+        Err(wrong_signer(signer, "no one (an ad-hoc signature)"))
+    } else {
+        Err(wrong_signer(signer, &signers.join(", ")))
+    }
+}
+
+/// Checks that the binary at `path` has a valid code signature made by
+/// `signer`. Does nothing where [`supported`] is `false`.
+#[cfg(not(any(windows, target_os = "macos")))]
+#[expect(clippy::unnecessary_wraps, reason = "fallible on Windows and macOS")]
+pub fn verify(_path: &Path, _signer: &str) -> Result<(), CodesignError> {
+    Ok(())
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn not_installed(e: std::io::Error, command: &'static str) -> CodesignError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => CodesignError::NotInstalled(command),
+        _ => CodesignError::Io(e),
+    }
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn failed(status: ExitStatus, stderr: &[u8]) -> CodesignError {
+    CodesignError::Failed {
+        status,
+        stderr: String::from_utf8_lossy(stderr)
+            .trim()
+            .to_owned(),
+    }
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+fn wrong_signer(expected: &str, actual: &str) -> CodesignError {
+    CodesignError::WrongSigner {
+        expected: expected.to_string(),
+        actual: actual.to_string(),
+    }
+}
@@ -0,0 +1,66 @@
+//! Content-based rule conditions, evaluated after a rule's patterns match.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use regex::bytes::Regex;
+
+use crate::pattern::{PatternError, REGEX_PREFIX};
+
+/// What a [`Contains`] condition searches for.
+#[derive(Debug, Clone)]
+enum Needle {
+    Literal(Vec<u8>),
+    Regex(Regex),
+}
+
+/// Matches files whose content contains a string or, with the `re:` prefix, a
+/// regular expression.
+///
+/// Only the first `max_bytes` of the file are read, so huge files cannot stall
+/// the event loop.
+#[derive(Debug, Clone)]
+pub struct Contains {
+    needle: Needle,
+    max_bytes: u64,
+}
+
+impl Contains {
+    /// Compiles a `contains` condition.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PatternError`] if a `re:` needle is not a valid regular
+    /// expression.
+    pub fn new(raw: &str, max_bytes: u64) -> Result<Self, PatternError> {
+        let needle = match raw.strip_prefix(REGEX_PREFIX) {
+            Some(expr) => Needle::Regex(
+                Regex::new(expr).map_err(|source| PatternError { raw: raw.to_string(), source })?,
+            ),
+            None => Needle::Literal(raw.as_bytes().to_vec()),
+        };
+
+        Ok(Self { needle, max_bytes })
+    }
+
+    /// Returns `true` if the first `max_bytes` of `path` contain the needle.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`std::io::Error`] if the file cannot be opened or read.
+    pub fn matches(&self, path: &Path) -> std::io::Result<bool> {
+        let mut buf = Vec::new();
+        File::open(path)?
+            .take(self.max_bytes)
+            .read_to_end(&mut buf)?;
+
+        Ok(match &self.needle {
+            Needle::Regex(regex) => regex.is_match(&buf),
+            Needle::Literal(needle) if needle.is_empty() => true,
+            Needle::Literal(needle) => buf
+                .windows(needle.len())
+                .any(|w| w == needle.as_slice()),
+        })
+    }
+}
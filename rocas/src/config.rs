@@ -1,12 +1,16 @@
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-use auto_launch::AutoLaunchBuilder;
-use clap::Command;
+#[cfg(not(windows))]
+use auto_launch::{AutoLaunch, AutoLaunchBuilder};
+use clap::ArgMatches;
 use forgeconf::forgeconf;
 use self_update::cargo_crate_version;
 
-use crate::pattern::Pattern;
-use crate::{AppError, art, logger};
+use crate::interpolate::{Unset, UnsetVariable, expand_in_place};
+use crate::pattern::{Pattern, PatternError};
+use crate::{AppError, art, destination, include, logger, schema, tilde};
 
 pub fn downloads_path() -> String {
     let dir = dirs::download_dir();
@@ -35,22 +39,112 @@ pub fn rocas_dir() -> PathBuf {
         .join("rocas")
 }
 
-#[allow(dead_code)]
+/// Creates `dir` and its parents so that only the current user can enter
+/// it, and takes away the access of others if it already existed.
+#[cfg(unix)]
+pub fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+}
+
+/// Creates `dir` and its parents. The directories rocas keeps its files in
+/// are in the user's profile, which only that user can enter.
+#[cfg(not(unix))]
+pub fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)
+}
+
+const CONFIG_NAME: &str = "rocas.toml";
+
+/// The file names looked for in each config directory, in order. See
+/// [`crate::format`] for the formats besides TOML.
+const CONFIG_NAMES: &[&str] = &[CONFIG_NAME, "rocas.yaml", "rocas.yml", "rocas.json"];
+
+/// The file passed with `--config`, which replaces the search of
+/// [`config_candidates`].
+static CONFIG_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Makes [`config_path`] return `path` instead of searching for a config.
+/// Only the first call has an effect.
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_OVERRIDE.set(path);
+}
+
+/// Returns the config file to load: the `--config` path if one was given,
+/// otherwise the first of [`config_candidates`] that exists. When none does,
+/// this is the first candidate, which is where `rocas init` writes.
 pub fn config_path() -> String {
-    let config_name = "rocas.toml";
+    let path = CONFIG_OVERRIDE
+        .get()
+        .cloned()
+        .unwrap_or_else(|| {
+            let candidates = config_candidates();
+            candidates
+                .iter()
+                .find(|path| path.is_file())
+                .unwrap_or(&candidates[0])
+                .clone()
+        });
 
-    rocas_dir()
-        .join(config_name)
-        .to_str()
-        .unwrap_or(config_name)
+    path.to_str()
+        .unwrap_or(CONFIG_NAME)
         .to_string()
 }
 
-#[forgeconf(config(path = config_path()))]
+/// Returns where to look for the config file, in order of preference:
+/// `$XDG_CONFIG_HOME/rocas`, the platform config directory (`~/.config` on
+/// Linux, `~/Library/Application Support` on macOS, `%APPDATA%` on
+/// Windows), `~/.config/rocas` for dotfile setups on macOS and Windows, and
+/// finally the current directory. Each directory is searched for every one
+/// of [`CONFIG_NAMES`].
+fn config_candidates() -> Vec<PathBuf> {
+    let xdg = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute());
+    let dotfiles = dirs::home_dir().map(|home| home.join(".config"));
+
+    let mut dirs: Vec<PathBuf> = [xdg, dirs::config_dir(), dotfiles]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("rocas"))
+        .collect();
+    dirs.push(PathBuf::new());
+    dirs.dedup();
+
+    dirs.iter()
+        .flat_map(|dir| {
+            CONFIG_NAMES
+                .iter()
+                .map(|name| dir.join(name))
+        })
+        .collect()
+}
+
+/// The key [`include::prepare`] moves a list of `[[watcher]]` entries to,
+/// as forgeconf reads `watcher` as a single table.
+pub const WATCHERS_KEY: &str = "watchers";
+
+#[forgeconf(config(path = include::load_path()))]
 pub struct Config {
+    /// The format version the config was written for. See
+    /// [`crate::migrate`].
+    #[field(default = 0, help = "Config format version; upgrade with rocas migrate")]
+    pub version: u32,
+
     #[field(name = "watcher", nested)]
     pub watcher: WatcherConfig,
 
+    /// The `[[watcher]]` entries of a config with several watchers, which
+    /// [`include::prepare`] moves here. When there are any, `watcher` is
+    /// unused.
+    #[field(name = "watchers", nested)]
+    pub watchers: Vec<WatcherConfig>,
+
     #[field(name = "rules", nested)]
     pub rules: Vec<RuleConfig>,
 
@@ -58,57 +152,226 @@ pub struct Config {
     pub misc: MiscConfig,
 }
 
-/// Configuration for the filesystem watcher.
-#[forgeconf]
-pub struct WatcherConfig {
-    /// Single directory to watch. Used when `watch_paths` is empty.
-    /// Defaults to the OS downloads directory.
-    #[field(
-        default = downloads_path(),
-        help = "Specify the directory where rocas should look for new files"
-    )]
-    pub watch_path: String,
-
-    /// Multiple directories to watch simultaneously. When non-empty this takes
-    /// precedence over `watch_path`. All directories share the same
-    /// `recursive`, `max_depth`, and timing settings.
-    #[field(
-        default = Vec::new(),
-        help = "Multiple directories to watch simultaneously"
-    )]
-    pub watch_paths: Vec<String>,
-
-    #[field(default = false, help = "Should rocas look into directories?")]
-    pub recursive: bool,
-
-    #[field(default = 1000, help = "How often should rocas should check for new files?")]
-    pub interval_millis: u64,
-
-    #[field(
-        default = None, 
-        help = "How much is rocas supposed to look into directories? Only avaiable if recursive is enabled."
-    )]
-    pub max_depth: Option<usize>,
-
-    /// Events within this window (in milliseconds) for the same path are
-    /// collapsed into one. Increase on slow network drives or when batch
-    /// copy tools fire many rapid events.
-    #[field(
-        default = 50,
-        help = "Collapse events within this window (ms); increase for slow/network drives"
-    )]
-    pub debounce_ms: u64,
-
-    /// How long to wait (in milliseconds) for a rename "To" counterpart before
-    /// treating the "From" as a plain delete.
-    #[field(
-        default = 50,
-        help = "Wait this long for a rename pair before treating From as a delete (ms)"
-    )]
-    pub rename_timeout_ms: u64,
+schema::with_fields! {
+    /// Configuration for the filesystem watcher.
+    #[forgeconf]
+    #[expect(clippy::struct_excessive_bools, reason = "independent on/off config switches")]
+    pub struct WatcherConfig {
+        /// Name of the watcher in logs. `[[watcher]]` entries without one are
+        /// named after their position, e.g. `watcher-2`.
+        #[field(default = String::new(), help = "Name of this watcher in logs")]
+        pub name: String,
+
+        /// Single directory to watch. Used when `watch_paths` is empty.
+        /// Defaults to the OS downloads directory.
+        #[field(
+            default = downloads_path(),
+            help = "Specify the directory where rocas should look for new files"
+        )]
+        pub watch_path: String,
+
+        /// Multiple directories to watch simultaneously. When non-empty this takes
+        /// precedence over `watch_path`. All directories share the same
+        /// `recursive`, `max_depth`, and timing settings.
+        #[field(
+            default = Vec::new(),
+            help = "Multiple directories to watch simultaneously"
+        )]
+        pub watch_paths: Vec<String>,
+
+        /// Names of the rules this watcher applies, in config order. Empty
+        /// applies every rule.
+        #[field(
+            default = Vec::new(),
+            help = "Only apply the rules with these names; every rule if empty"
+        )]
+        pub rules: Vec<String>,
+
+        #[field(default = false, help = "Should rocas look into directories?")]
+        pub recursive: bool,
+
+        #[field(default = 1000, help = "How often should rocas should check for new files?")]
+        pub interval_millis: u64,
+
+        #[field(
+            default = None, 
+            help = "How much is rocas supposed to look into directories? Only avaiable if recursive is enabled."
+        )]
+        pub max_depth: Option<usize>,
+
+        /// Events within this window (in milliseconds) for the same path are
+        /// collapsed into one. Increase on slow network drives or when batch
+        /// copy tools fire many rapid events.
+        #[field(
+            default = 50,
+            help = "Collapse events within this window (ms); increase for slow/network drives"
+        )]
+        pub debounce_ms: u64,
+
+        /// How long to wait (in milliseconds) for a rename "To" counterpart before
+        /// treating the "From" as a plain delete.
+        #[field(
+            default = 50,
+            help = "Wait this long for a rename pair before treating From as a delete (ms)"
+        )]
+        pub rename_timeout_ms: u64,
+
+        /// Run every file already in the watched directories through the rules on
+        /// startup. Can also be enabled with the `--existing` flag.
+        #[field(
+            default = false,
+            help = "Organize files already in the watched directories on startup"
+        )]
+        pub process_existing: bool,
+
+        /// Log what each matching rule would do instead of doing it. Can also be
+        /// enabled with the `--dry-run` flag.
+        #[field(
+            default = false,
+            help = "Log what rules would do without touching any file"
+        )]
+        pub dry_run: bool,
+
+        /// How long (in milliseconds) a file's size and modification time must
+        /// stay unchanged before rocas acts on it.
+        #[field(
+            default = 2000,
+            help = "Wait until a file has been unchanged this long before acting on it (ms)"
+        )]
+        pub stable_quiet_ms: u64,
+
+        /// Events arriving within this window (in milliseconds) of the first one
+        /// are processed together, with one summary log line per burst. `0`
+        /// processes every event on its own.
+        #[field(
+            default = 250,
+            help = "Process events arriving within this window as one batch (ms)"
+        )]
+        pub batch_window_ms: u64,
+
+        /// Number of threads that move files concurrently. Events for the same
+        /// file are always handled in order by the same thread.
+        #[field(default = 4, help = "Number of threads that move files concurrently")]
+        pub worker_threads: usize,
+
+        /// How the copy made when moving across filesystems is checked before the
+        /// source is deleted: `none`, `size`, or `hash` (SHA-256, reads both
+        /// files).
+        #[field(
+            default = "size".to_string(),
+            validate = forgeconf::validators::one_of([
+                "none".to_string(),
+                "size".to_string(),
+                "hash".to_string(),
+            ]),
+            help = "Check cross-filesystem copies before deleting the source: none | size | hash"
+        )]
+        pub verify_copies: String,
+
+        /// Keep modification times, permissions and extended attributes when a
+        /// move has to copy the file to another filesystem.
+        #[field(
+            default = true,
+            help = "Keep timestamps, permissions and extended attributes on cross-filesystem moves"
+        )]
+        pub preserve_metadata: bool,
+
+        /// Log progress and speed while copying files at least this large (in
+        /// megabytes) to another filesystem. `0` disables progress reporting.
+        #[field(default = 100, help = "Report copy progress for files at least this large (MB)")]
+        pub progress_threshold_mb: u64,
+
+        /// Free space (in megabytes) to leave on a destination filesystem. A move
+        /// to another filesystem is skipped when the file would not fit with this
+        /// much to spare.
+        #[field(default = 0, help = "Free space to keep on destination filesystems (MB)")]
+        pub min_free_space_mb: u64,
+
+        /// Cap on the combined rate of copies to other filesystems, in kilobytes
+        /// per second. Renames within a filesystem are not limited.
+        #[field(default = None, help = "Limit cross-filesystem copies to this many KB/s")]
+        pub bandwidth_limit_kb: Option<u64>,
+
+        /// Only apply `bandwidth_limit_kb` during this local time range, e.g.
+        /// `09:00-18:00` or `22:00-06:00`. Always applies when unset.
+        #[field(default = None, help = "Only limit bandwidth during this time range (HH:MM-HH:MM)")]
+        pub bandwidth_hours: Option<String>,
+
+        /// How many times to retry a move that failed (e.g. because the file was
+        /// briefly locked). `0` gives up after the first failure.
+        #[field(default = 5, help = "Retry a failed move this many times")]
+        pub retry_attempts: u32,
+
+        /// Delay before the first retry of a failed move, in milliseconds. It
+        /// doubles with every further failure.
+        #[field(
+            default = 1000,
+            help = "Delay before retrying a failed move; doubles each time (ms)"
+        )]
+        pub retry_backoff_ms: u64,
+
+        /// Defer files that another process still has open (e.g. a download
+        /// being written by a browser) until they are closed.
+        #[field(
+            default = true,
+            help = "Wait until no other process has a file open before moving it"
+        )]
+        pub skip_open_files: bool,
+
+        /// How often (in seconds) to check whether a deferred open file has been
+        /// closed.
+        #[field(default = 5, help = "Re-check files held open by another process this often (s)")]
+        pub open_file_retry_secs: u64,
+
+        /// How events are matched against rules: `first` applies only the first
+        /// matching rule, `all` applies every matching rule in order until one
+        /// with `stop = true` matches.
+        #[field(
+            default = "first".to_string(),
+            validate = forgeconf::validators::one_of(["first".to_string(), "all".to_string()]),
+            help = "Apply only the first matching rule (first) or every matching rule (all)"
+        )]
+        pub match_mode: String,
+
+        /// Extensions of temporary files written by browsers while downloading.
+        /// Such files are never matched, and neither is `file.pdf` while
+        /// `file.pdf.<ext>` exists. Set to an empty list to disable.
+        #[field(
+            default = vec![
+                "crdownload".to_string(),
+                "part".to_string(),
+                "partial".to_string(),
+                "download".to_string(),
+                "opdownload".to_string(),
+            ],
+            help = "Extensions of in-progress download files to ignore"
+        )]
+        pub partial_download_extensions: Vec<String>,
+    }
 }
 
 impl WatcherConfig {
+    /// Parses the `verify_copies` string into a [`VerifyMode`].
+    /// Defaults to `Size` for any unrecognised value.
+    pub fn verify_copies(&self) -> VerifyMode {
+        match self
+            .verify_copies
+            .to_lowercase()
+            .as_str()
+        {
+            "none" => VerifyMode::None,
+            "hash" => VerifyMode::Hash,
+            _ => VerifyMode::Size,
+        }
+    }
+
+    /// Returns `true` when every matching rule should be applied rather than
+    /// only the first one.
+    pub fn match_all(&self) -> bool {
+        self.match_mode
+            .eq_ignore_ascii_case("all")
+    }
+
     /// Returns the effective list of directories to watch. If `watch_paths` is
     /// non-empty it is used as-is; otherwise the single `watch_path` is
     /// returned as a one-element list.
@@ -124,57 +387,178 @@ impl WatcherConfig {
     }
 }
 
-/// Miscellaneous runtime configuration (logging, update checks).
-#[forgeconf]
-pub struct MiscConfig {
-    #[field(
-        default = true,
-        help = "Should I look for updates on startup?"
-    )]
-    pub check_for_updates: bool,
-
-    #[field(
-        default = false,
-        help = "Should I auto update myself?"
-    )]
-    pub auto_update: bool,
-
-    #[field(
-        default = "info".to_string(),
-        validate = forgeconf::validators::one_of(
-            ["trace".to_string(), "debug".to_string(), "info".to_string(), "warn".to_string(), "error".to_string()]
-        ),
-        help = "Rocas log level trace | info | warn | error",
-    )]
-    pub log_level: String,
-
-    /// Path to the log file. Omit to use the OS default data directory.
-    /// Linux:   ~/.local/share/rocas/rocas.log
-    /// macOS:   ~/Library/Application Support/rocas/rocas.log
-    /// Windows: %APPDATA%\rocas\rocas.log
-    #[field(
-        default = Some(logs_path()),
-        help = "Where should rocas store its log files"
-    )]
-    pub log_file: Option<String>,
-
-    /// Rotate the log file when it exceeds this size in megabytes.
-    #[field(
-        default = 10,
-        help = "Max limit of a log file size in megabytes"
-    )]
-    pub log_max_size_mb: u64,
-
-    /// Number of rotated log files to keep alongside the active log
-    /// (rocas.log.1, rocas.log.2, …).
-    #[field(
-        default = 3,
-        help = "Number of log files to keep"
-    )]
-    pub log_keep_files: u32,
+schema::with_fields! {
+    /// Miscellaneous runtime configuration (logging, update checks).
+    #[forgeconf]
+    pub struct MiscConfig {
+        #[field(
+            default = true,
+            help = "Should I look for updates on startup?"
+        )]
+        pub check_for_updates: bool,
+
+        #[field(
+            default = false,
+            help = "Should I auto update myself?"
+        )]
+        pub auto_update: bool,
+
+        /// Only install updates signed with the release key. Builds made
+        /// without the key have to turn this off to update themselves.
+        #[field(
+            default = true,
+            help = "Should I only install updates signed with the release key?"
+        )]
+        pub verify_update_signature: bool,
+
+        /// Which releases to update to: `stable` ones only, also `beta` and
+        /// other pre-releases like `1.5.0-rc.1`, or also `nightly` builds.
+        #[field(
+            default = "stable".to_string(),
+            validate = forgeconf::validators::one_of([
+                "stable".to_string(),
+                "beta".to_string(),
+                "nightly".to_string(),
+            ]),
+            help = "Which releases to update to: stable | beta | nightly"
+        )]
+        pub update_channel: String,
+
+        /// Only install updates whose binary carries a valid code signature by
+        /// this signer: the certificate subject or its common name on Windows,
+        /// the Team ID or a signing authority on macOS. Has no effect on Linux.
+        #[field(
+            default = None,
+            help = "Code signer that updated binaries must be signed by (Windows and macOS)"
+        )]
+        pub update_signer: Option<String>,
+
+        /// Proxy for looking for and downloading updates, like
+        /// `http://proxy.example.com:3128`. Without it, the `HTTPS_PROXY`,
+        /// `HTTP_PROXY` and `NO_PROXY` environment variables apply.
+        #[field(
+            default = None,
+            help = "Proxy URL for looking for and downloading updates"
+        )]
+        pub update_proxy: Option<String>,
+
+        /// GitHub token to look for and download updates with. Requests
+        /// without a token share a limit of 60 an hour per IP address. Falls
+        /// back to the `GITHUB_TOKEN` environment variable.
+        #[field(
+            default = None,
+            help = "GitHub token for looking for and downloading updates"
+        )]
+        pub update_token: Option<String>,
+
+        #[field(
+            default = "info".to_string(),
+            validate = forgeconf::validators::one_of(
+                ["trace".to_string(), "debug".to_string(), "info".to_string(), "warn".to_string(), "error".to_string()]
+            ),
+            help = "Rocas log level trace | info | warn | error",
+        )]
+        pub log_level: String,
+
+        /// How log lines are written: `text` for people, or `json`, one object
+        /// per line with the rule, source, destination, size and error of the
+        /// file as keys of their own, for log collectors like Loki.
+        #[field(
+            default = "text".to_string(),
+            validate = forgeconf::validators::one_of([
+                "text".to_string(),
+                "json".to_string(),
+            ]),
+            help = "How log lines are written: text | json"
+        )]
+        pub log_format: String,
+
+        /// Path to the log file. Omit to use `rocas.log` in the rocas config
+        /// directory.
+        /// Linux:   ~/.config/rocas/rocas.log
+        /// macOS:   ~/Library/Application Support/rocas/rocas.log
+        /// Windows: %APPDATA%\rocas\rocas.log
+        #[field(
+            default = Some(logs_path()),
+            help = "Where should rocas store its log files"
+        )]
+        pub log_file: Option<String>,
+
+        /// Rotate the log file when it exceeds this size in megabytes.
+        #[field(
+            default = 10,
+            help = "Max limit of a log file size in megabytes"
+        )]
+        pub log_max_size_mb: u64,
+
+        /// Number of rotated log files to keep alongside the active log
+        /// (rocas.log.1, rocas.log.2, …).
+        #[field(
+            default = 3,
+            help = "Number of log files to keep"
+        )]
+        pub log_keep_files: u32,
+
+        /// Also rotate the log file on the first record of every day or week,
+        /// whatever its size.
+        #[field(
+            default = "never".to_string(),
+            validate = forgeconf::validators::one_of([
+                "never".to_string(),
+                "daily".to_string(),
+                "weekly".to_string(),
+            ]),
+            help = "Also rotate the log file every day or week: never | daily | weekly"
+        )]
+        pub log_rotate: String,
+
+        /// Delete rotated log files last written more than this many days ago.
+        /// `0` keeps them until `log_keep_files` pushes them out.
+        #[field(
+            default = 0,
+            help = "Delete rotated log files older than this many days; 0 keeps them"
+        )]
+        pub log_max_age_days: u64,
+
+        /// Which desktop notifications rocas shows: `off`, only when a move
+        /// fails (`errors`), or also for every file it moves (`all`). Rules can
+        /// change this for themselves with their own `notify`.
+        #[field(
+            default = "off".to_string(),
+            validate = forgeconf::validators::one_of([
+                "off".to_string(),
+                "errors".to_string(),
+                "all".to_string(),
+            ]),
+            help = "Desktop notifications: off | errors | all"
+        )]
+        pub notify: String,
+
+        /// Show a tray icon, or menu bar icon on macOS, while watching. Only
+        /// builds with the `tray` feature have one.
+        #[field(
+            default = true,
+            help = "Show a tray icon while watching (builds with the tray feature)"
+        )]
+        pub tray: bool,
+    }
 }
 
 impl MiscConfig {
+    /// Parses the `update_channel` string into a [`Channel`].
+    /// Defaults to `Stable` for any unrecognised value.
+    pub fn update_channel(&self) -> Channel {
+        match self
+            .update_channel
+            .to_lowercase()
+            .as_str()
+        {
+            "beta" => Channel::Beta,
+            "nightly" => Channel::Nightly,
+            _ => Channel::Stable,
+        }
+    }
+
     /// Parses the `log_level` string into a [`log::LevelFilter`].
     /// Defaults to `Info` for any unrecognised value.
     pub fn log_level(&self) -> log::LevelFilter {
@@ -186,14 +570,327 @@ impl MiscConfig {
             _ => log::LevelFilter::Info,
         }
     }
+
+    /// Parses the `log_rotate` string into a [`logger::Rotation`].
+    /// Defaults to `Never` for any unrecognised value.
+    pub fn log_rotate(&self) -> logger::Rotation {
+        match self.log_rotate.to_lowercase().as_str() {
+            "daily" => logger::Rotation::Daily,
+            "weekly" => logger::Rotation::Weekly,
+            _ => logger::Rotation::Never,
+        }
+    }
+
+    /// Parses the `log_format` string into a [`logger::Format`].
+    /// Defaults to `Text` for any unrecognised value.
+    pub fn log_format(&self) -> logger::Format {
+        match self.log_format.to_lowercase().as_str() {
+            "json" => logger::Format::Json,
+            _ => logger::Format::Text,
+        }
+    }
+
+    /// Parses the `notify` string into a [`Notify`].
+    /// Defaults to `Off` for any unrecognised value.
+    pub fn notify(&self) -> Notify {
+        Notify::parse(&self.notify).unwrap_or(Notify::Off)
+    }
+}
+
+schema::with_fields! {
+    /// A single file-routing rule: files matching any `pattern` are moved to
+    /// `destination`.
+    ///
+    /// When the matching pattern is a `re:` regex, `destination` may reference its
+    /// capture groups (`$1`, `${2}`, `${name}`). Patterns ending in `/` match
+    /// directories, which are moved as a whole. A `webdav://` destination uploads
+    /// to a WebDAV server instead.
+    #[forgeconf]
+    #[expect(clippy::struct_excessive_bools, reason = "independent on/off rule options")]
+    pub struct RuleConfig {
+        #[field(help = "Glob or re: patterns matched against new files")]
+        pub patterns: Vec<String>,
+
+        #[field(help = "Directory, zip: archive or webdav:// URL to put matched files in")]
+        pub destination: String,
+
+        /// Identifies the rule in logs and listings. Unnamed rules are called
+        /// `rule-<n>` after their position.
+        #[field(default = String::new(), help = "Name shown in logs; defaults to rule-<n>")]
+        pub name: String,
+
+        #[field(default = None, help = "What the rule is for")]
+        pub description: Option<String>,
+
+        /// Only match files whose content contains this string. Prefix with `re:`
+        /// to search for a regular expression instead.
+        #[field(
+            default = None,
+            help = "Only match files whose content contains this text (prefix with re: for a regex)"
+        )]
+        pub contains: Option<String>,
+
+        /// How much of each file (in kilobytes) `contains` searches.
+        #[field(
+            default = 1024,
+            help = "Search at most this many kilobytes of a file for `contains`"
+        )]
+        pub contains_max_size_kb: u64,
+
+        /// Rename matched files using a template such as `scan-{counter:4}.{ext}`.
+        /// See [`crate::template`] for the available placeholders.
+        #[field(
+            default = None,
+            help = "Rename matched files, e.g. scan-{counter:4}.{ext}"
+        )]
+        pub rename: Option<String>,
+
+        /// Move at most this many files per `rate_limit_window_secs`. Excess
+        /// matches are queued until budget becomes available.
+        #[field(
+            default = None,
+            help = "Move at most this many files per rate limit window"
+        )]
+        pub rate_limit_files: Option<u64>,
+
+        /// Move at most this many megabytes per `rate_limit_window_secs`.
+        #[field(
+            default = None,
+            help = "Move at most this many megabytes per rate limit window"
+        )]
+        pub rate_limit_mb: Option<u64>,
+
+        /// Length of the sliding window used by `rate_limit_files` and
+        /// `rate_limit_mb`, in seconds.
+        #[field(
+            default = 3600,
+            help = "Length of the rate limit window in seconds"
+        )]
+        pub rate_limit_window_secs: u64,
+
+        /// What to do with matched files: `move` them into `destination`, or
+        /// `hardlink` them there while the original stays in place.
+        #[field(
+            default = "move".to_string(),
+            validate = forgeconf::validators::one_of([
+                "move".to_string(),
+                "hardlink".to_string(),
+            ]),
+            help = "What to do with matched files: move | hardlink"
+        )]
+        pub action: String,
+
+        /// What `action = "hardlink"` does when the destination is on another
+        /// filesystem, where hard links are impossible: `copy` the file instead
+        /// (the original still stays in place) or `skip` it.
+        #[field(
+            default = "copy".to_string(),
+            validate = forgeconf::validators::one_of([
+                "copy".to_string(),
+                "skip".to_string(),
+            ]),
+            help = "What hardlink does across filesystems: copy | skip"
+        )]
+        pub link_fallback: String,
+
+        /// What to do when the destination file already exists: `rename` the new
+        /// file by appending `conflict_suffix`, `overwrite` the existing one,
+        /// `skip` the move and leave the source in place, or keep `versions_keep`
+        /// previous `versions` as `<name>.1`, `<name>.2`, ….
+        #[field(
+            default = "rename".to_string(),
+            validate = forgeconf::validators::one_of([
+                "rename".to_string(),
+                "overwrite".to_string(),
+                "skip".to_string(),
+                "versions".to_string(),
+            ]),
+            help = "What to do when the destination exists: rename | overwrite | skip | versions"
+        )]
+        pub on_conflict: String,
+
+        /// Appended to the file name (before the extension) by `on_conflict =
+        /// "rename"`. `{n}` is replaced by the lowest free number, starting at 1.
+        #[field(
+            default = " ({n})".to_string(),
+            help = "Suffix for renamed conflicting files; {n} is the number"
+        )]
+        pub conflict_suffix: String,
+
+        /// How many previous copies `on_conflict = "versions"` keeps; older ones
+        /// are deleted.
+        #[field(default = 5, help = "Previous versions kept by on_conflict = versions")]
+        pub versions_keep: u32,
+
+        /// Keep the file displaced by `on_conflict = "overwrite"` as `<name>.bak`.
+        #[field(default = false, help = "Keep an overwritten file as <name>.bak")]
+        pub backup: bool,
+
+        /// Move files displaced by `on_conflict = "overwrite"` into this directory
+        /// instead of keeping them as `<name>.bak`. Implies `backup`.
+        #[field(
+            default = None,
+            help = "Directory to keep overwritten files in (implies backup)"
+        )]
+        pub backup_dir: Option<String>,
+
+        /// Stop processing the event once this rule matches, so later rules never
+        /// see it. Only meaningful with `match_mode = "all"`; in `first` mode
+        /// every rule stops processing.
+        #[field(default = false, help = "Stop evaluating later rules once this rule matches")]
+        pub stop: bool,
+
+        /// Hold matches whose destination is out of space until enough space is
+        /// available, instead of skipping them.
+        #[field(
+            default = false,
+            help = "Wait for free space on a full destination instead of skipping"
+        )]
+        pub wait_for_space: bool,
+
+        /// Queue matches while the destination is unreachable (an unmounted
+        /// network share, a WebDAV server that is down) and move them once it is
+        /// back, instead of failing. The destination up to its first placeholder
+        /// must already exist for rocas to tell that it is mounted.
+        #[field(
+            default = false,
+            help = "Queue matches while the destination is unreachable instead of failing"
+        )]
+        pub wait_for_destination: bool,
+
+        /// Make destination filenames valid on Windows-compatible filesystems
+        /// (exFAT, NTFS, SMB): replace illegal characters, drop trailing dots
+        /// and spaces and rename reserved names like `CON`.
+        #[field(default = false, help = "Make destination filenames valid on exFAT/NTFS/SMB")]
+        pub sanitize: bool,
+
+        /// Replacement for illegal characters when `sanitize` is on.
+        #[field(
+            default = "_".to_string(),
+            help = "Replacement for illegal filename characters"
+        )]
+        pub sanitize_replacement: String,
+
+        /// Per-character replacements for `sanitize`, as `"<char>=<replacement>"`
+        /// entries (e.g. `":= -"`). Unlisted illegal characters use
+        /// `sanitize_replacement`.
+        #[field(
+            default = Vec::new(),
+            help = "Per-character filename replacements, e.g. \":= -\""
+        )]
+        pub sanitize_map: Vec<String>,
+
+        /// User name for a `webdav://` destination (HTTP basic auth).
+        #[field(default = None, help = "User name for a WebDAV destination")]
+        pub webdav_user: Option<String>,
+
+        /// Password (or app password) for `webdav_user`.
+        #[field(default = None, help = "Password for a WebDAV destination")]
+        pub webdav_password: Option<String>,
+
+        /// Bearer token for a `webdav://` destination, used instead of
+        /// `webdav_user`/`webdav_password`.
+        #[field(default = None, help = "Bearer token for a WebDAV destination")]
+        pub webdav_token: Option<String>,
+
+        /// Encrypt matched files with `age` for `encrypt_recipients` instead of
+        /// moving them as they are. The plaintext source is deleted once the
+        /// encrypted file is in place; for zip and WebDAV destinations, once the
+        /// file encrypted into a temporary file is in the archive or uploaded.
+        #[field(default = false, help = "Encrypt matched files with age")]
+        pub encrypt: bool,
+
+        /// Public keys (`age1…` or `ssh-…`) that can decrypt files of an
+        /// `encrypt` rule.
+        #[field(default = Vec::new(), help = "age recipients for encrypt")]
+        pub encrypt_recipients: Vec<String>,
+
+        /// Desktop notifications for this rule's files, like `misc.notify`.
+        /// `default` keeps the one of `misc.notify`.
+        #[field(
+            default = "default".to_string(),
+            validate = forgeconf::validators::one_of([
+                "default".to_string(),
+                "off".to_string(),
+                "errors".to_string(),
+                "all".to_string(),
+            ]),
+            help = "Desktop notifications for this rule: default | off | errors | all"
+        )]
+        pub notify: String,
+    }
+}
+
+/// Which releases the updater follows. Each channel also takes the releases
+/// of the more stable ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Channel {
+    Stable,
+    /// Pre-releases like `1.5.0-beta.1` or `1.5.0-rc.1`.
+    Beta,
+    /// Pre-releases tagged like `1.5.0-nightly.20260101`.
+    Nightly,
+}
+
+/// How the copy made by a cross-filesystem move is checked before the source
+/// is deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyMode {
+    None,
+    /// Compare the sizes of source and copy.
+    Size,
+    /// Compare sizes and SHA-256 checksums.
+    Hash,
+}
+
+/// What a rule does with the files it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Move,
+    /// Hard-link the file into the destination and leave it in place.
+    Hardlink,
+}
+
+/// What a `hardlink` rule does when the destination is on another
+/// filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkFallback {
+    /// Copy the file, leaving the original in place.
+    Copy,
+    Skip,
+}
+
+/// Which desktop notifications rocas shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Notify {
+    Off,
+    /// Only when a file could not be moved.
+    Errors,
+    /// Also for every file moved.
+    All,
+}
+
+impl Notify {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "errors" => Some(Self::Errors),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
 }
 
-/// A single file-routing rule: files matching any `pattern` are moved to
-/// `destination`.
-#[forgeconf]
-pub struct RuleConfig {
-    pub patterns: Vec<String>,
-    pub destination: String,
+/// How a rule handles a destination file that already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Keep both files by numbering the new one, e.g. `report (1).pdf`.
+    Rename,
+    Overwrite,
+    Skip,
+    /// Shift the existing file to `<name>.1` (and older versions further up),
+    /// keeping at most `versions_keep` of them.
+    Versions,
 }
 
 impl RuleConfig {
@@ -201,13 +898,56 @@ impl RuleConfig {
     ///
     /// Callers that match many files should call this once and retain the
     /// result rather than re-compiling on every match attempt.
-    pub fn compiled_patterns(&self) -> Vec<Pattern> {
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PatternError`] for the first pattern that fails to compile.
+    pub fn compiled_patterns(&self) -> Result<Vec<Pattern>, PatternError> {
         self.patterns
             .iter()
             .map(|p| Pattern::new(p))
             .collect()
     }
 
+    /// Parses the `action` string into an [`Action`].
+    /// Defaults to `Move` for any unrecognised value.
+    pub fn action(&self) -> Action {
+        match self.action.to_lowercase().as_str() {
+            "hardlink" => Action::Hardlink,
+            _ => Action::Move,
+        }
+    }
+
+    /// Parses the `link_fallback` string into a [`LinkFallback`].
+    /// Defaults to `Copy` for any unrecognised value.
+    pub fn link_fallback(&self) -> LinkFallback {
+        match self
+            .link_fallback
+            .to_lowercase()
+            .as_str()
+        {
+            "skip" => LinkFallback::Skip,
+            _ => LinkFallback::Copy,
+        }
+    }
+
+    /// Parses the `notify` string into a [`Notify`], or `None` to use
+    /// `misc.notify`.
+    pub fn notify(&self) -> Option<Notify> {
+        Notify::parse(&self.notify)
+    }
+
+    /// Parses the `on_conflict` string into a [`ConflictStrategy`].
+    /// Defaults to `Rename` for any unrecognised value.
+    pub fn on_conflict(&self) -> ConflictStrategy {
+        match self.on_conflict.to_lowercase().as_str() {
+            "overwrite" => ConflictStrategy::Overwrite,
+            "skip" => ConflictStrategy::Skip,
+            "versions" => ConflictStrategy::Versions,
+            _ => ConflictStrategy::Rename,
+        }
+    }
+
     /// Returns `true` if any rule pattern matches `path`.
     ///
     /// Compiles patterns inline on each call; prefer [`compiled_patterns`] once
@@ -218,30 +958,177 @@ impl RuleConfig {
     #[expect(dead_code, reason = "utility method kept for external callers and tests")]
     pub fn matches(&self, path: &str) -> bool {
         // Iterate directly over raw strings to avoid the intermediate Vec.
+        // Invalid patterns never match.
         self.patterns
             .iter()
-            .any(|p| Pattern::new(p).matches(path))
+            .any(|p| Pattern::new(p).is_ok_and(|p| p.matches(path)))
     }
 }
 
 impl Config {
-    pub fn load(klap: Command) -> Result<Self, forgeconf::ConfigError> {
-        let matches = klap.get_matches();
-
-        if let Some(("boot", _)) = matches.subcommand() {
-            statup_toggle().expect("startup_toggle");
+    pub fn load(matches: &ArgMatches) -> Result<Self, AppError> {
+        // A missing config file means the defaults, unless it was asked for.
+        if let Some(path) = CONFIG_OVERRIDE.get()
+            && !path.is_file()
+        {
+            return Err(AppError::ConfigNotFound(path.clone()));
         }
+        include::prepare(Path::new(&config_path()))?;
 
-        let res = Self::loader()
-            .add_source(Self::from_clap(&matches))
+        let mut res = Self::loader()
+            .add_source(Self::from_clap(matches))
             .load()?;
 
+        if matches.get_flag("existing") {
+            res.watcher.process_existing = true;
+            for watcher in &mut res.watchers {
+                watcher.process_existing = true;
+            }
+        }
+        if matches.get_flag("dry_run") {
+            res.watcher.dry_run = true;
+            for watcher in &mut res.watchers {
+                watcher.dry_run = true;
+            }
+        }
+        res.expand_env()?;
+        res.expand_tilde();
+        res.name_rules();
+        destination::check(&res.rules)?;
+
         Ok(res)
     }
 
+    /// Returns the watchers to run: the `[[watcher]]` entries if there are
+    /// any, otherwise the `[watcher]` section.
+    pub fn watchers(&self) -> Vec<&WatcherConfig> {
+        if self.watchers.is_empty() { vec![&self.watcher] } else { self.watchers.iter().collect() }
+    }
+
+    /// Returns the rules `watcher` applies, in config order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::UnknownRule`] if `watcher` names a rule that does
+    /// not exist.
+    pub fn rules_of(&self, watcher: &WatcherConfig) -> Result<Vec<&RuleConfig>, AppError> {
+        if let Some(name) = watcher.rules.iter().find(|name| {
+            !self
+                .rules
+                .iter()
+                .any(|rule| rule.name == **name)
+        }) {
+            return Err(AppError::UnknownRule {
+                watcher: watcher.name.clone(),
+                rule: name.clone(),
+            });
+        }
+
+        Ok(self
+            .rules
+            .iter()
+            .filter(|rule| watcher.rules.is_empty() || watcher.rules.contains(&rule.name))
+            .collect())
+    }
+
+    fn watchers_mut(&mut self) -> impl Iterator<Item = &mut WatcherConfig> {
+        std::iter::once(&mut self.watcher).chain(&mut self.watchers)
+    }
+
+    /// Expands `${VAR}` references in the values that name paths or
+    /// credentials. See [`crate::interpolate`] for the syntax.
+    fn expand_env(&mut self) -> Result<(), UnsetVariable> {
+        for watcher in self.watchers_mut() {
+            expand_in_place(&mut watcher.watch_path, Unset::Error)?;
+            for path in &mut watcher.watch_paths {
+                expand_in_place(path, Unset::Error)?;
+            }
+        }
+        for value in
+            [&mut self.misc.log_file, &mut self.misc.update_proxy, &mut self.misc.update_token]
+                .into_iter()
+                .flatten()
+        {
+            expand_in_place(value, Unset::Error)?;
+        }
+
+        for rule in &mut self.rules {
+            // An unset reference may be a named capture group. Invalid
+            // patterns are reported when the rule is compiled.
+            let captures: Vec<String> = rule
+                .compiled_patterns()
+                .unwrap_or_default()
+                .iter()
+                .flat_map(Pattern::capture_names)
+                .map(str::to_owned)
+                .collect();
+            expand_in_place(&mut rule.destination, Unset::Keep(&captures))?;
+            for value in [
+                &mut rule.backup_dir,
+                &mut rule.webdav_user,
+                &mut rule.webdav_password,
+                &mut rule.webdav_token,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                expand_in_place(value, Unset::Error)?;
+            }
+            for recipient in &mut rule.encrypt_recipients {
+                expand_in_place(recipient, Unset::Error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Expands `~` and `~user` at the start of every path. Destinations are
+    /// expanded again after their templates are filled in.
+    fn expand_tilde(&mut self) {
+        for watcher in self.watchers_mut() {
+            tilde::expand_in_place(&mut watcher.watch_path);
+            watcher
+                .watch_paths
+                .iter_mut()
+                .for_each(tilde::expand_in_place);
+        }
+        if let Some(log_file) = &mut self.misc.log_file {
+            tilde::expand_in_place(log_file);
+        }
+
+        for rule in &mut self.rules {
+            if let Cow::Owned(destination) = tilde::expand_destination(&rule.destination) {
+                rule.destination = destination;
+            }
+            if let Some(backup_dir) = &mut rule.backup_dir {
+                tilde::expand_in_place(backup_dir);
+            }
+        }
+    }
+
+    /// Names every unnamed rule and `[[watcher]]` entry after its position,
+    /// e.g. `rule-3`.
+    fn name_rules(&mut self) {
+        for (idx, rule) in self.rules.iter_mut().enumerate() {
+            if rule.name.trim().is_empty() {
+                rule.name = format!("rule-{}", idx + 1);
+            }
+        }
+        for (idx, watcher) in self.watchers.iter_mut().enumerate() {
+            if watcher.name.trim().is_empty() {
+                watcher.name = format!("watcher-{}", idx + 1);
+            }
+        }
+    }
+
     /// Builds and prints the startup ASCII art banner with configuration
     /// summary.
-    pub fn print_startup_banner(&self, watch_paths: &[&str]) {
+    pub fn print_startup_banner(&self) {
+        // The banner would come between the JSON lines on stderr.
+        if self.misc.log_format() == logger::Format::Json {
+            return;
+        }
+
         // We format messages the same way as the logger so the output is consistent.
         let secs = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -255,30 +1142,56 @@ impl Config {
             |s: &str| -> String { if tty { format!("\x1b[2m{s}\x1b[0m") } else { s.to_string() } };
         let info = |msg: &str| logger::format_line(&ts, log::Level::Info, "rocas", msg);
 
+        let watchers = self.watchers();
+        let watch_paths: usize = watchers
+            .iter()
+            .map(|watcher| watcher.effective_paths().len())
+            .sum();
         msgs.push(dim("  watching"));
         msgs.push(info(&format!(
             "  {} director{} (v{})",
-            watch_paths.len(),
-            if watch_paths.len() == 1 { "y" } else { "ies" },
+            watch_paths,
+            if watch_paths == 1 { "y" } else { "ies" },
             cargo_crate_version!()
         )));
-        for path in watch_paths {
-            msgs.push(info(&format!("    {path}")));
+        for watcher in &watchers {
+            for path in watcher.effective_paths() {
+                if watcher.name.is_empty() {
+                    msgs.push(info(&format!("    {path}")));
+                } else {
+                    msgs.push(info(&format!("    {path} ({})", watcher.name)));
+                }
+            }
         }
 
-        msgs.push(String::new());
-        msgs.push(dim("  watcher"));
-        msgs.push(info(&format!(
-            "  recursive={}  interval={}ms  debounce={}ms  rename_timeout={}ms{}",
-            self.watcher.recursive,
-            self.watcher.interval_millis,
-            self.watcher.debounce_ms,
-            self.watcher.rename_timeout_ms,
-            match self.watcher.max_depth {
-                Some(d) => format!("  max_depth={d}"),
-                None => String::new(),
+        for watcher in &watchers {
+            msgs.push(String::new());
+            if watcher.name.is_empty() {
+                msgs.push(dim("  watcher"));
+            } else {
+                msgs.push(dim(&format!("  watcher {}", watcher.name)));
             }
-        )));
+            msgs.push(info(&format!(
+                "  recursive={}  interval={}ms  debounce={}ms  rename_timeout={}ms  \
+                 stable_quiet={}ms  match_mode={}{}",
+                watcher.recursive,
+                watcher.interval_millis,
+                watcher.debounce_ms,
+                watcher.rename_timeout_ms,
+                watcher.stable_quiet_ms,
+                watcher.match_mode,
+                match watcher.max_depth {
+                    Some(d) => format!("  max_depth={d}"),
+                    None => String::new(),
+                }
+            )));
+            if !watcher.rules.is_empty() {
+                msgs.push(info(&format!("  rules={}", watcher.rules.join(", "))));
+            }
+            if watcher.dry_run {
+                msgs.push(info("  dry run: files are only logged, never touched"));
+            }
+        }
 
         msgs.push(String::new());
         msgs.push(dim("  rules"));
@@ -286,7 +1199,15 @@ impl Config {
             msgs.push(info("  (none)"));
         } else {
             for rule in &self.rules {
-                msgs.push(info(&format!("  {} → {}", rule.patterns.join(", "), rule.destination)));
+                msgs.push(info(&format!(
+                    "  {}: {} → {}",
+                    rule.name,
+                    rule.patterns.join(", "),
+                    rule.destination
+                )));
+                if let Some(description) = &rule.description {
+                    msgs.push(info(&format!("    {description}")));
+                }
             }
         }
 
@@ -297,13 +1218,14 @@ impl Config {
             self.misc.log_level, self.misc.check_for_updates, self.misc.auto_update,
         )));
         msgs.push(info(&format!(
-            "  log_file={}  max_size={}MB  keep={}",
+            "  log_file={}  max_size={}MB  keep={}  rotate={}",
             self.misc
                 .log_file
                 .as_deref()
                 .unwrap_or("(default)"),
             self.misc.log_max_size_mb,
             self.misc.log_keep_files,
+            self.misc.log_rotate,
         )));
 
         let msg_refs: Vec<&str> = msgs
@@ -314,26 +1236,43 @@ impl Config {
     }
 }
 
-pub fn statup_toggle() -> Result<(), AppError> {
-    let conf = AutoLaunchBuilder::new()
+#[cfg(not(windows))]
+fn auto_launch(args: &[String]) -> Result<AutoLaunch, AppError> {
+    Ok(AutoLaunchBuilder::new()
         .set_app_name("Rocas")
         .set_app_path(&rocas_path()?)
+        .set_args(args)
         .set_macos_launch_mode(auto_launch::MacOSLaunchMode::LaunchAgent)
-        .set_windows_enable_mode(auto_launch::WindowsEnableMode::Dynamic)
         .set_linux_launch_mode(auto_launch::LinuxLaunchMode::Systemd)
-        .build()?;
+        .build()?)
+}
 
+/// Makes rocas start on boot with `args`. A login item left by another
+/// rocas binary is pointed at this one. Windows writes its `Run` key itself,
+/// in [`crate::autostart`].
+#[cfg(not(windows))]
+pub fn enable_startup(args: &[String]) -> Result<(), AppError> {
+    auto_launch(args)?.enable()?;
+    Ok(())
+}
+
+/// Returns `true` if rocas starts on boot.
+#[cfg(not(windows))]
+pub fn startup_enabled() -> Result<bool, AppError> {
+    Ok(auto_launch(&[])?.is_enabled()?)
+}
+
+/// Stops rocas from starting on boot, if it does.
+#[cfg(not(windows))]
+pub fn disable_startup() -> Result<(), AppError> {
+    let conf = auto_launch(&[])?;
     if conf.is_enabled()? {
         conf.disable()?;
-        info!("Fine, I didn't want to organize your shitty ass files anyway..");
-    } else {
-        conf.enable()?;
-        info!("Gotchu boss, I'll be taking care of your files now.");
     }
-
     Ok(())
 }
 
+#[cfg(not(windows))]
 fn rocas_path() -> Result<String, AppError> {
     let path = std::env::current_exe()?;
     // current_exe always returns a valid UTF-8 path on supported platforms;
@@ -0,0 +1,328 @@
+//! The control channel of the running rocas, for commands that need to ask
+//! it something rather than read the files it leaves behind.
+//!
+//! While watching, rocas listens on a Unix domain socket, `rocas.sock` in
+//! `$XDG_RUNTIME_DIR/rocas` (or in `run` in the rocas config directory
+//! without one), and on Windows on the named pipe `\\.\pipe\rocas-<user>`.
+//! A client sends one request and reads one response, each a JSON document
+//! preceded by its length as a 4-byte big-endian number. A request names a
+//! `command`; the response has `ok` and either the command's `result` or an
+//! `error`. Each client is answered on a thread of its own, so one that is
+//! slow to send its request holds up no other.
+//!
+//! Only the user running rocas can send requests. The socket is bound in a
+//! directory only its owner can enter, so no other user can reach it even
+//! before its own permissions are set. The pipe refuses remote clients and
+//! lets other local users only read from it, which is not enough to send a
+//! request.
+//!
+//! `status` is the only command so far: it returns the same snapshot rocas
+//! writes to `status.json`, as it is at that moment.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+use crate::status;
+
+/// The longest request or response accepted, so a bad length cannot make
+/// rocas allocate without bound.
+const MAX_MESSAGE: u32 = 1 << 20;
+
+/// How long either side waits for the other to send or read a message.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Error returned when a request cannot be sent or is refused.
+#[derive(Debug, thiserror::Error)]
+pub enum ControlError {
+    #[error("rocas is not running")]
+    NotRunning,
+
+    #[error("message of {0} bytes is too large")]
+    TooLarge(u64),
+
+    #[error("invalid message: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("rocas refused the request: {0}")]
+    Refused(String),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Sends `command` to the running rocas and returns its result.
+///
+/// # Errors
+///
+/// Returns [`ControlError::NotRunning`] if no rocas listens, or another
+/// [`ControlError`] if the request fails or rocas refuses it.
+pub fn request(command: &str) -> Result<Value, ControlError> {
+    let mut stream = platform::connect().map_err(|e| match e.kind() {
+        ErrorKind::NotFound | ErrorKind::ConnectionRefused => ControlError::NotRunning,
+        _ => ControlError::Io(e),
+    })?;
+    send(&mut stream, &json!({ "command": command }))?;
+    let mut response = receive(&mut stream)?;
+    if response
+        .get("ok")
+        .and_then(Value::as_bool)
+        == Some(true)
+    {
+        return Ok(response
+            .get_mut("result")
+            .map(Value::take)
+            .unwrap_or_default());
+    }
+    Err(ControlError::Refused(
+        response
+            .get("error")
+            .and_then(Value::as_str)
+            .unwrap_or("no reason given")
+            .to_string(),
+    ))
+}
+
+/// Starts answering requests in the background until the process exits.
+/// Another rocas already listening keeps the channel, and this one goes
+/// without.
+pub fn start() {
+    let listener = match platform::listen() {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Could not open the control channel: {e}");
+            return;
+        },
+    };
+    std::thread::spawn(move || platform::serve(listener, answer));
+}
+
+/// Closes the control channel, for when rocas stops watching.
+pub fn stop() {
+    platform::remove();
+}
+
+/// Reads one request from `stream` and writes its response.
+fn answer(stream: &mut (impl Read + Write)) {
+    let response = match receive(stream) {
+        Ok(request) => respond(&request),
+        Err(e) => json!({ "ok": false, "error": e.to_string() }),
+    };
+    if let Err(e) = send(stream, &response) {
+        debug!("Could not answer a control request: {e}");
+    }
+}
+
+fn respond(request: &Value) -> Value {
+    match request
+        .get("command")
+        .and_then(Value::as_str)
+    {
+        Some("status") => json!({ "ok": true, "result": status::current() }),
+        Some(command) => json!({ "ok": false, "error": format!("unknown command '{command}'") }),
+        None => json!({ "ok": false, "error": "the request names no command" }),
+    }
+}
+
+fn send(stream: &mut impl Write, message: &Value) -> Result<(), ControlError> {
+    let bytes = serde_json::to_vec(message)?;
+    let len = u32::try_from(bytes.len())
+        .ok()
+        .filter(|len| *len <= MAX_MESSAGE)
+        .ok_or(ControlError::TooLarge(bytes.len() as u64))?;
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(&bytes)?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn receive(stream: &mut impl Read) -> Result<Value, ControlError> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len);
+    if len > MAX_MESSAGE {
+        return Err(ControlError::TooLarge(len.into()));
+    }
+    let mut bytes = vec![0; len as usize];
+    stream.read_exact(&mut bytes)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::io;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+
+    use super::TIMEOUT;
+    use crate::config;
+
+    const SOCKET_NAME: &str = "rocas.sock";
+
+    pub fn connect() -> io::Result<UnixStream> {
+        let stream = UnixStream::connect(path())?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        Ok(stream)
+    }
+
+    /// Binds the socket, replacing one left behind by a rocas that is gone.
+    pub fn listen() -> io::Result<UnixListener> {
+        let path = path();
+        if UnixStream::connect(&path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another rocas listens on '{}'", path.display()),
+            ));
+        }
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {},
+        }
+        // Private before the socket in it is bound with the permissions of
+        // the umask.
+        config::create_private_dir(&dir())?;
+        let listener = UnixListener::bind(&path)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        Ok(listener)
+    }
+
+    /// Answers each client on a thread of its own.
+    pub fn serve(listener: UnixListener, answer: fn(&mut UnixStream)) {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            if stream
+                .set_read_timeout(Some(TIMEOUT))
+                .is_ok()
+                && stream
+                    .set_write_timeout(Some(TIMEOUT))
+                    .is_ok()
+            {
+                std::thread::spawn(move || answer(&mut stream));
+            }
+        }
+    }
+
+    pub fn remove() {
+        let _ = std::fs::remove_file(path());
+    }
+
+    fn path() -> PathBuf {
+        dir().join(SOCKET_NAME)
+    }
+
+    /// The directory of the socket, which only its owner can enter.
+    fn dir() -> PathBuf {
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(|| config::rocas_dir().join("run"), |dir| PathBuf::from(dir).join("rocas"))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle};
+
+    use windows_sys::Win32::Foundation::{ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_FIRST_PIPE_INSTANCE,
+        PIPE_ACCESS_DUPLEX,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe,
+        CreateNamedPipeW,
+        PIPE_READMODE_BYTE,
+        PIPE_REJECT_REMOTE_CLIENTS,
+        PIPE_TYPE_BYTE,
+        PIPE_UNLIMITED_INSTANCES,
+        PIPE_WAIT,
+    };
+
+    /// The first instance of the pipe, created when rocas starts so that a
+    /// second rocas finds the name taken.
+    pub struct Listener(File);
+
+    pub fn connect() -> io::Result<File> {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(name())
+    }
+
+    pub fn listen() -> io::Result<Listener> {
+        create(true).map(Listener)
+    }
+
+    /// Answers on the first instance of the pipe, then on a new instance
+    /// for each client after it, each client on a thread of its own.
+    pub fn serve(listener: Listener, answer: fn(&mut File)) {
+        let mut pipe = listener.0;
+        loop {
+            // SAFETY: the handle is a pipe instance owned by `pipe`.
+            let connected = unsafe { ConnectNamedPipe(pipe.as_raw_handle(), std::ptr::null_mut()) };
+            let already = || {
+                io::Error::last_os_error()
+                    .raw_os_error()
+                    .and_then(|code| u32::try_from(code).ok())
+                    == Some(ERROR_PIPE_CONNECTED)
+            };
+            let connected = connected != 0 || already();
+            let next = match create(false) {
+                Ok(next) => next,
+                Err(e) => {
+                    warn!("The control channel closed: {e}");
+                    return;
+                },
+            };
+            let mut client = std::mem::replace(&mut pipe, next);
+            if connected {
+                std::thread::spawn(move || {
+                    answer(&mut client);
+                    // Closing the pipe drops what the client has not read yet.
+                    let _ = client.sync_all();
+                });
+            }
+        }
+    }
+
+    /// Pipes go away with their last handle.
+    pub const fn remove() {}
+
+    fn create(first: bool) -> io::Result<File> {
+        let name: Vec<u16> = std::ffi::OsStr::new(&name())
+            .encode_wide()
+            .chain(std::iter::once(0))
+            .collect();
+        let open_mode = PIPE_ACCESS_DUPLEX | if first { FILE_FLAG_FIRST_PIPE_INSTANCE } else { 0 };
+        // SAFETY: `name` is a NUL-terminated UTF-16 string, and a null
+        // security attributes pointer gives the pipe the default security
+        // descriptor.
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                4096,
+                4096,
+                0,
+                std::ptr::null(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `handle` is a valid pipe handle that nothing else owns.
+        Ok(unsafe { File::from_raw_handle(handle) })
+    }
+
+    fn name() -> String {
+        let user = std::env::var("USERNAME").unwrap_or_default();
+        format!(r"\\.\pipe\rocas-{user}")
+    }
+}
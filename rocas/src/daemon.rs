@@ -0,0 +1,186 @@
+//! `rocas run --daemon`: keeps watching in the background.
+//!
+//! On Unix, rocas forks twice and starts a new session, so the daemon has no
+//! controlling terminal and is not a child of the shell. On Windows, rocas
+//! starts itself again as a detached process without `--daemon`. Either way,
+//! the daemon's process id is written to a PID file, and the command returns
+//! once the daemon is started. The daemon logs to its log file only.
+//!
+//! A daemon that starts itself again, to reload its config, does so without
+//! `--daemon` and `--pid-file`, so the new process does not detach again and
+//! stays the process its service manager or shell follows. It takes over
+//! the PID file instead, which it is passed in `ROCAS_PID_FILE`.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::{AppError, config};
+
+const PID_NAME: &str = "rocas.pid";
+
+/// The environment variable a daemon passes its PID file in to the process
+/// it starts in its place.
+const PID_FILE_VAR: &str = "ROCAS_PID_FILE";
+
+/// The PID file of this process, if it is the daemon.
+static PID_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Returns the PID file used when none is given with `--pid-file`.
+pub fn default_pid_file() -> PathBuf {
+    config::rocas_dir().join(PID_NAME)
+}
+
+/// Starts the daemon and exits this process, or, in the daemon, returns to
+/// carry on running.
+///
+/// # Errors
+///
+/// Returns an I/O error if the process cannot be forked or started, or the
+/// PID file cannot be written.
+pub fn start(pid_file: &Path) -> Result<(), AppError> {
+    if let Some(dir) = pid_file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    detach(pid_file)?;
+    let _ = PID_FILE.set(pid_file.to_path_buf());
+    Ok(())
+}
+
+/// Takes over the PID file of the daemon that started this process in its
+/// place, if any, writing the process id of this one to it.
+///
+/// # Errors
+///
+/// Returns an I/O error if the PID file cannot be written.
+pub fn resume() -> Result<(), AppError> {
+    let Some(pid_file) = std::env::var_os(PID_FILE_VAR).map(PathBuf::from) else {
+        return Ok(());
+    };
+    std::fs::write(&pid_file, format!("{}\n", std::process::id()))?;
+    let _ = PID_FILE.set(pid_file);
+    Ok(())
+}
+
+/// Returns the arguments this process was started with, without `--daemon`
+/// and `--pid-file`, and the environment variables to set, for starting it
+/// again in its place.
+pub fn restart_args() -> (Vec<OsString>, Vec<(&'static str, OsString)>) {
+    let mut args = Vec::new();
+    let mut pid_file_value = false;
+    for arg in std::env::args_os().skip(1) {
+        if std::mem::take(&mut pid_file_value) {
+            continue;
+        }
+        if arg == "--pid-file" {
+            pid_file_value = true;
+        } else if arg != "--daemon"
+            && !arg
+                .to_str()
+                .is_some_and(|arg| arg.starts_with("--pid-file="))
+        {
+            args.push(arg);
+        }
+    }
+    let env = PID_FILE
+        .get()
+        .map(|pid_file| (PID_FILE_VAR, pid_file.clone().into_os_string()))
+        .into_iter()
+        .collect();
+    (args, env)
+}
+
+/// Removes the PID file when the daemon stops.
+pub fn stop() {
+    if let Some(pid_file) = PID_FILE.get() {
+        let _ = std::fs::remove_file(pid_file);
+    }
+}
+
+/// Prints where the daemon runs and exits.
+fn started(pid: u32, pid_file: &Path) -> ! {
+    println!("rocas is running in the background (pid {pid}, written to {})", pid_file.display());
+    std::process::exit(0);
+}
+
+#[cfg(unix)]
+fn detach(pid_file: &Path) -> Result<(), AppError> {
+    use std::io;
+
+    // SAFETY: rocas has not started any threads yet, so the child does not
+    // inherit locks held by threads that do not exist in it.
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error().into()),
+        0 => {},
+        child => {
+            // The first child writes the PID file before it exits.
+            let mut status = 0;
+            // SAFETY: `child` is a child of this process.
+            unsafe { libc::waitpid(child, &raw mut status, 0) };
+            let pid = std::fs::read_to_string(pid_file)
+                .ok()
+                .and_then(|pid| pid.trim().parse().ok())
+                .filter(|_| libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0)
+                .ok_or_else(|| {
+                    io::Error::other(format!(
+                        "could not write the PID file '{}'",
+                        pid_file.display()
+                    ))
+                })?;
+            started(pid, pid_file);
+        },
+    }
+
+    // SAFETY: called in the child, which is not a process group leader.
+    if unsafe { libc::setsid() } == -1 {
+        // SAFETY: leaves the child without running the parent's cleanup.
+        unsafe { libc::_exit(1) };
+    }
+    // Without a session leader, the daemon can never acquire a terminal.
+    // SAFETY: as above, there is only one thread.
+    match unsafe { libc::fork() } {
+        0 => {},
+        daemon => {
+            let code =
+                i32::from(daemon == -1 || std::fs::write(pid_file, format!("{daemon}\n")).is_err());
+            // SAFETY: leaves the first child without running the parent's
+            // cleanup.
+            unsafe { libc::_exit(code) };
+        },
+    }
+
+    // Nobody reads the terminal output of a daemon.
+    let null = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        use std::os::fd::AsRawFd;
+
+        // SAFETY: both file descriptors are open.
+        unsafe { libc::dup2(null.as_raw_fd(), fd) };
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn detach(pid_file: &Path) -> Result<(), AppError> {
+    use std::os::windows::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    const DETACHED_PROCESS: u32 = 0x0000_0008;
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+    let (args, _) = restart_args();
+    let child = Command::new(std::env::current_exe()?)
+        .args(args)
+        .env(PID_FILE_VAR, pid_file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP)
+        .spawn()?;
+
+    std::fs::write(pid_file, format!("{}\n", child.id()))?;
+    started(child.id(), pid_file);
+}
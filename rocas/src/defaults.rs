@@ -0,0 +1,71 @@
+//! The `[defaults]` section: rule settings shared by every rule.
+//!
+//! ```toml
+//! [defaults]
+//! on_conflict = "skip"
+//! wait_for_space = true
+//! ```
+//!
+//! Each key is copied into every rule that does not set it itself, so
+//! per-rule values always win. `patterns` cannot have a default.
+
+use toml::{Table, Value};
+
+use crate::config::RuleConfig;
+
+pub const DEFAULTS_KEY: &str = "defaults";
+
+/// Error returned for a malformed `[defaults]` section.
+#[derive(Debug, thiserror::Error)]
+pub enum DefaultsError {
+    #[error("'defaults' must be a table")]
+    NotATable,
+
+    #[error("unknown key '{0}' in [defaults]")]
+    UnknownKey(String),
+
+    #[error("'patterns' cannot be set in [defaults]")]
+    Patterns,
+}
+
+/// Copies the `[defaults]` of `config` into its rules and removes the
+/// section.
+///
+/// # Errors
+///
+/// Returns [`DefaultsError`] if the section is not a table or sets a key
+/// rules do not have.
+pub fn apply(config: &mut Table) -> Result<(), DefaultsError> {
+    let defaults = match config.remove(DEFAULTS_KEY) {
+        None => return Ok(()),
+        Some(Value::Table(defaults)) => defaults,
+        Some(_) => return Err(DefaultsError::NotATable),
+    };
+
+    for key in defaults.keys() {
+        if key == "patterns" {
+            return Err(DefaultsError::Patterns);
+        }
+        if !RuleConfig::FIELDS
+            .iter()
+            .any(|field| field.name == key)
+        {
+            return Err(DefaultsError::UnknownKey(key.clone()));
+        }
+    }
+
+    if let Some(Value::Array(rules)) = config.get_mut("rules") {
+        for rule in rules {
+            let Value::Table(rule) = rule else {
+                continue;
+            };
+            for (key, value) in &defaults {
+                if !rule.contains_key(key) {
+                    rule.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Checks that rule destinations can be written to when the config is
+//! loaded.
+//!
+//! A typo in a destination, or one on a read-only mount, would otherwise
+//! only show up when the first file fails to move. Destinations that do not
+//! exist yet are fine as long as rocas can create them: the closest existing
+//! directory must be writable. Remote destinations and rules that wait for
+//! their destination are not checked, as those may be away at startup.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::RuleConfig;
+use crate::{archive, engine, space, webdav};
+
+/// A destination that cannot be written to.
+#[derive(Debug)]
+pub struct Problem {
+    pub rule: String,
+    pub destination: String,
+    pub error: io::Error,
+}
+
+/// Error returned when one or more destinations cannot be written to.
+#[derive(Debug, thiserror::Error)]
+#[error("{}", format_problems(.0))]
+pub struct UnwritableDestinations(pub Vec<Problem>);
+
+fn format_problems(problems: &[Problem]) -> String {
+    let lines: Vec<String> = problems
+        .iter()
+        .map(|problem| {
+            format!(
+                "rule '{}': cannot write to '{}': {}",
+                problem.rule, problem.destination, problem.error
+            )
+        })
+        .collect();
+    if let [line] = lines.as_slice() {
+        line.clone()
+    } else {
+        format!("{} destinations cannot be written to:\n  {}", lines.len(), lines.join("\n  "))
+    }
+}
+
+/// Checks the destination of every rule in `rules`.
+///
+/// # Errors
+///
+/// Returns [`UnwritableDestinations`] listing every rule whose destination
+/// cannot be created or written to.
+pub fn check(rules: &[RuleConfig]) -> Result<(), UnwritableDestinations> {
+    let problems: Vec<Problem> = rules
+        .iter()
+        .filter(|rule| !rule.wait_for_destination && !webdav::is_remote(&rule.destination))
+        .filter_map(|rule| {
+            let error = writable(&directory(&rule.destination)).err()?;
+            Some(Problem {
+                rule: rule.name.clone(),
+                destination: rule.destination.clone(),
+                error,
+            })
+        })
+        .collect();
+
+    if problems.is_empty() { Ok(()) } else { Err(UnwritableDestinations(problems)) }
+}
+
+/// Returns the directory a destination writes into, up to its first
+/// template placeholder. For a zip archive, this is the directory that holds
+/// it.
+fn directory(destination: &str) -> PathBuf {
+    let dir = match archive::path(destination) {
+        Some(archive) if engine::static_dir(archive) == archive => Path::new(archive)
+            .parent()
+            .unwrap_or(Path::new("")),
+        Some(archive) => Path::new(engine::static_dir(archive)),
+        None => Path::new(engine::static_dir(destination)),
+    };
+
+    if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir.to_path_buf() }
+}
+
+/// Returns an error if `dir` cannot be created or written to.
+fn writable(dir: &Path) -> io::Result<()> {
+    let existing = space::existing_ancestor(dir).map_err(|_| {
+        io::Error::new(io::ErrorKind::NotFound, "the drive or volume does not exist")
+    })?;
+    if !existing.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            format!("'{}' is not a directory", existing.display()),
+        ));
+    }
+    access(&existing)
+}
+
+#[cfg(unix)]
+fn access(dir: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    // SAFETY: `path` is NUL-terminated.
+    if unsafe { libc::access(path.as_ptr(), libc::W_OK | libc::X_OK) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Windows ignores the read-only attribute of directories, so only the
+/// existence of the volume is checked.
+#[cfg(not(unix))]
+fn access(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! `rocas rule add`: appends a rule to the config file.
+//!
+//! A TOML config gets a `[[rules]]` table appended to its text, so its
+//! formatting and comments stay exactly as they were. YAML and JSON configs
+//! are parsed and written back, which loses the comments of a YAML file.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use crate::format::{Format, ParseError};
+use crate::pattern::{Pattern, PatternError};
+
+/// A rule to add, as given on the command line.
+pub struct NewRule {
+    pub name: Option<String>,
+    pub patterns: Vec<String>,
+    pub destination: String,
+    pub action: Option<String>,
+}
+
+/// Error returned when a rule cannot be added to the config.
+#[derive(Debug, thiserror::Error)]
+pub enum EditError {
+    #[error("cannot read '{}': {source}", path.display())]
+    Io { path: PathBuf, source: std::io::Error },
+
+    #[error("cannot parse '{}': {source}", path.display())]
+    Parse { path: PathBuf, source: ParseError },
+
+    #[error(
+        "cannot add a rule to '{}': its 'rules' is not a list of [[rules]] tables",
+        .0.display()
+    )]
+    NotAppendable(PathBuf),
+
+    #[error(transparent)]
+    Pattern(#[from] PatternError),
+}
+
+impl NewRule {
+    /// The rule's keys in the order they are written.
+    pub fn fields(&self) -> Vec<(&'static str, Value)> {
+        let mut fields = Vec::new();
+        if let Some(name) = &self.name {
+            fields.push(("name", Value::String(name.clone())));
+        }
+        fields.push((
+            "patterns",
+            Value::Array(
+                self.patterns
+                    .iter()
+                    .cloned()
+                    .map(Value::String)
+                    .collect(),
+            ),
+        ));
+        fields.push(("destination", Value::String(self.destination.clone())));
+        if let Some(action) = &self.action {
+            fields.push(("action", Value::String(action.clone())));
+        }
+        fields
+    }
+}
+
+/// Appends `rule` to the rules of the config at `path`, after its existing
+/// ones.
+///
+/// # Errors
+///
+/// Returns [`EditError`] if a pattern does not compile, or the config cannot
+/// be read, parsed or written, or defines its rules in a way a table cannot
+/// be appended to.
+pub fn add_rule(path: &Path, rule: &NewRule) -> Result<(), EditError> {
+    for pattern in &rule.patterns {
+        Pattern::new(pattern)?;
+    }
+
+    let io_error = |source| EditError::Io { path: path.to_path_buf(), source };
+    let parse_error = |source| EditError::Parse { path: path.to_path_buf(), source };
+
+    let text = std::fs::read_to_string(path).map_err(io_error)?;
+    let format = Format::of(path);
+    let mut config = format
+        .parse(&text)
+        .map_err(parse_error)?;
+
+    let contents = if format == Format::Toml {
+        let mut contents = text;
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+        contents.push_str("[[rules]]\n");
+        for (key, value) in rule.fields() {
+            let _ = writeln!(contents, "{key} = {value}");
+        }
+
+        // `rules = [...]` written inline cannot be extended by a table.
+        if Format::Toml.parse(&contents).is_err() {
+            return Err(EditError::NotAppendable(path.to_path_buf()));
+        }
+        contents
+    } else {
+        let table: Table = rule
+            .fields()
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+        match config
+            .entry("rules")
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            Value::Array(rules) => rules.push(Value::Table(table)),
+            _ => return Err(EditError::NotAppendable(path.to_path_buf())),
+        }
+        format.render(&config)
+    };
+
+    std::fs::write(path, contents).map_err(io_error)
+}
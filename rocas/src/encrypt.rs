@@ -0,0 +1,149 @@
+//! Encryption at rest with [age](https://age-encryption.org).
+//!
+//! A rule with `encrypt = true` runs each matched file through the `age`
+//! command-line tool for the rule's `encrypt_recipients` instead of moving
+//! it. The ciphertext is written under a temporary name in the destination
+//! and renamed into place when `age` has finished, and only then is the
+//! plaintext source deleted. Without a `rename` template the destination
+//! name gets an `.age` extension.
+//!
+//! Zip archives and WebDAV servers take a finished file, so for those
+//! destinations the file is encrypted into a temporary file first, in a
+//! private directory with a random name, which is added to the archive or
+//! uploaded and then deleted. The plaintext source
+//! is only deleted once the ciphertext is in the archive or on the server.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use tempfile::TempDir;
+
+use crate::config::{Action, RuleConfig};
+use crate::transfer;
+
+/// Error returned by rules that encrypt their files.
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptError {
+    #[error("encrypt needs at least one entry in encrypt_recipients")]
+    NoRecipients,
+
+    #[error("'{0}' is not an age recipient (expected age1… or ssh-…)")]
+    InvalidRecipient(String),
+
+    #[error("{0} is not supported with encrypt")]
+    Unsupported(&'static str),
+
+    #[error("the age command was not found; install age to use encrypt")]
+    NotInstalled,
+
+    #[error("age failed ({status}): {stderr}")]
+    Failed { status: ExitStatus, stderr: String },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Encrypts files for a fixed set of age recipients.
+pub struct Encryptor {
+    recipients: Vec<String>,
+}
+
+impl Encryptor {
+    /// Creates the encryptor for `config`, or returns `None` when the rule
+    /// does not encrypt.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EncryptError`] if the recipients are missing or malformed,
+    /// or the rule uses an option encryption does not support.
+    pub fn new(config: &RuleConfig) -> Result<Option<Self>, EncryptError> {
+        if !config.encrypt {
+            return Ok(None);
+        }
+
+        if config.action() == Action::Hardlink {
+            return Err(EncryptError::Unsupported("action = \"hardlink\""));
+        }
+
+        if config.encrypt_recipients.is_empty() {
+            return Err(EncryptError::NoRecipients);
+        }
+        if let Some(invalid) = config
+            .encrypt_recipients
+            .iter()
+            .find(|r| !r.starts_with("age1") && !r.starts_with("ssh-"))
+        {
+            return Err(EncryptError::InvalidRecipient(invalid.clone()));
+        }
+
+        Ok(Some(Self {
+            recipients: config.encrypt_recipients.clone(),
+        }))
+    }
+
+    /// Writes `from` encrypted to `dest`, replacing a file already there.
+    /// `from` is left in place.
+    pub fn encrypt(&self, from: &Path, dest: &Path) -> Result<(), EncryptError> {
+        let temp = transfer::temp_path(dest);
+
+        let mut command = Command::new("age");
+        command.arg("--encrypt");
+        for recipient in &self.recipients {
+            command
+                .arg("--recipient")
+                .arg(recipient);
+        }
+        let output = command
+            .arg("--output")
+            .arg(&temp)
+            .arg(from)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => EncryptError::NotInstalled,
+                _ => EncryptError::Io(e),
+            })?;
+
+        if !output.status.success() {
+            let _ = std::fs::remove_file(&temp);
+            return Err(EncryptError::Failed {
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr)
+                    .trim()
+                    .to_owned(),
+            });
+        }
+
+        if let Err(e) = std::fs::rename(&temp, dest) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Writes `from` encrypted to a temporary file, for the destinations
+    /// that take a finished file. The file is in a directory with a random
+    /// name that only this user can enter, so no one else can plant or swap
+    /// it. `from` is left in place; the temporary file is deleted when the
+    /// returned [`Ciphertext`] is dropped.
+    pub fn encrypt_to_temp(&self, from: &Path) -> Result<Ciphertext, EncryptError> {
+        let dir = tempfile::Builder::new()
+            .prefix("rocas-encrypt-")
+            .tempdir()?;
+        let path = dir.path().join("file.age");
+        self.encrypt(from, &path)?;
+        Ok(Ciphertext { _dir: dir, path })
+    }
+}
+
+/// A file encrypted into a temporary directory, deleted when dropped.
+pub struct Ciphertext {
+    _dir: TempDir,
+    path: PathBuf,
+}
+
+impl Ciphertext {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
@@ -0,0 +1,1775 @@
+//! Rule evaluation and file actions for filesystem events.
+//!
+//! The [`Engine`] runs on the event loop thread: it filters incoming events
+//! and keeps the queues of deferred matches. The actual work (waiting for a
+//! file to settle, evaluating rules and moving it) is handed to a
+//! [`WorkerPool`] together with the shared [`Context`], and the results come
+//! back as [`Completion`]s.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::Scope;
+use std::time::{Duration, Instant, SystemTime};
+
+use crossbeam_channel::{Receiver, Sender, unbounded};
+use watcher::{FileEvent, long_path};
+
+use crate::busy::{self, OpenFile};
+use crate::config::{Action, ConflictStrategy, VerifyMode, WatcherConfig};
+use crate::encrypt::{Ciphertext, Encryptor};
+use crate::offline::{self, Journal, WaitingForDestination};
+use crate::pool::WorkerPool;
+use crate::retry::{self, FailedMove};
+use crate::rule::CompiledRule;
+use crate::space::{self, WaitingForSpace};
+use crate::template::TemplateContext;
+use crate::throttle::Throttled;
+use crate::transfer::Placed;
+use crate::webdav::{self, WebDav};
+use crate::{AppError, archive, clock, history, logger, notify, tilde, transfer};
+
+/// How often to probe file size and mtime while waiting for a download to
+/// finish.
+const STABLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Maximum time to wait for a file to stop growing before moving it anyway.
+const STABLE_MAX_WAIT: Duration = Duration::from_secs(300);
+
+/// Fallback interval for re-checking throttled matches when no rate limiter
+/// reports an expiry.
+const THROTTLE_RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to keep retrying a file that another process holds open before
+/// giving up on it.
+const OPEN_FILE_MAX_WAIT: Duration = Duration::from_hours(1);
+
+/// How often to check whether a full destination has regained space.
+const SPACE_RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often to check whether an unreachable destination is back.
+const OFFLINE_RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long events for a path rocas itself wrote are ignored.
+const OWN_WRITE_GRACE: Duration = Duration::from_secs(10);
+
+/// What happened to a single filesystem event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// No rule applied (no match, deleted file, in-progress download, …).
+    Ignored,
+    Moved,
+    /// The destination already existed and the rule skips conflicts.
+    Skipped,
+    /// Deferred by a rule's rate limit, because the file was open, the
+    /// destination was full or unreachable, or to retry a failed move.
+    Queued,
+    Failed,
+}
+
+/// Outcome counts for a batch of events.
+#[derive(Debug, Clone, Copy, Default)]
+struct BatchSummary {
+    events: usize,
+    moved: usize,
+    skipped: usize,
+    queued: usize,
+    failed: usize,
+}
+
+impl BatchSummary {
+    fn record(&mut self, outcome: Outcome) {
+        self.events += 1;
+        match outcome {
+            Outcome::Ignored => {},
+            Outcome::Moved => self.moved += 1,
+            Outcome::Skipped => self.skipped += 1,
+            Outcome::Queued => self.queued += 1,
+            Outcome::Failed => self.failed += 1,
+        }
+    }
+
+    /// Logs the summary, unless every event in the batch was ignored.
+    fn log(&self) {
+        if self.moved + self.skipped + self.queued + self.failed == 0 {
+            return;
+        }
+
+        info!(
+            "Processed {} events: {} moved, {} skipped, {} queued, {} failed",
+            self.events, self.moved, self.skipped, self.queued, self.failed
+        );
+    }
+}
+
+/// A batch whose files are still being processed by the workers.
+#[derive(Default)]
+struct Batch {
+    summary: BatchSummary,
+    pending: usize,
+}
+
+/// Paths rocas recently wrote (move destinations, backups, reserved names),
+/// so the events they cause are not treated as new files.
+#[derive(Default)]
+struct OwnWrites {
+    written: Mutex<HashMap<PathBuf, Instant>>,
+}
+
+impl OwnWrites {
+    fn record(&self, path: &Path) {
+        self.written
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(absolute(path), Instant::now());
+    }
+
+    /// Returns `true` if rocas wrote `path` (or a directory containing it)
+    /// within [`OWN_WRITE_GRACE`].
+    fn contains(&self, path: &Path) -> bool {
+        let mut written = self
+            .written
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if written.is_empty() {
+            return false;
+        }
+
+        written.retain(|_, at| at.elapsed() < OWN_WRITE_GRACE);
+        absolute(path)
+            .ancestors()
+            .any(|p| written.contains_key(p))
+    }
+}
+
+/// Result of processing one file on a worker.
+enum Processed {
+    Done(Outcome),
+    /// The matching rule was out of rate-limit budget.
+    Throttled(Throttled),
+    /// Another process had the file open.
+    Open(PathBuf),
+    /// The destination was out of space and the rule waits for space.
+    NoSpace(Throttled),
+    /// The destination was unreachable and the rule waits for it.
+    Offline(Throttled),
+    /// The file could not be moved.
+    Failed {
+        path: PathBuf,
+        /// Index of the rule that failed to move it.
+        rule: usize,
+        error: String,
+    },
+}
+
+/// A finished job, sent from a worker back to the [`Engine`].
+pub struct Completion {
+    batch: Option<u64>,
+    /// Number of earlier attempts that failed for this file.
+    attempt: u32,
+    processed: Processed,
+}
+
+/// Everything a worker needs to process a file: the compiled rules, the
+/// watcher settings and the paths rocas recently wrote.
+pub struct Context<'a> {
+    rules: &'a [CompiledRule<'a>],
+    settings: &'a WatcherConfig,
+    /// Rule destinations inside a watched directory. Files in them were put
+    /// there by rocas and are never matched again, which would otherwise loop.
+    excluded: Vec<PathBuf>,
+    /// Absolute watched directories.
+    roots: Vec<PathBuf>,
+    /// Whether any rule has a directory pattern.
+    dir_rules: bool,
+    own_writes: OwnWrites,
+    transfer: transfer::Options,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(
+        rules: &'a [CompiledRule<'a>],
+        settings: &'a WatcherConfig,
+    ) -> Result<Self, AppError> {
+        Ok(Self {
+            rules,
+            settings,
+            excluded: nested_destinations(rules, settings),
+            roots: settings
+                .effective_paths()
+                .iter()
+                .map(|root| absolute(Path::new(root)))
+                .collect(),
+            dir_rules: rules
+                .iter()
+                .any(CompiledRule::matches_dirs),
+            own_writes: OwnWrites::default(),
+            transfer: transfer::Options::new(settings)?,
+        })
+    }
+
+    /// Returns the path an event should be processed for, or `None` if the
+    /// event can be ignored without touching the file.
+    ///
+    /// In-progress browser downloads are ignored, as is the final file while
+    /// its temporary sibling (e.g. `file.pdf.crdownload`) still exists; the
+    /// browser's closing rename produces a fresh event for it. Entries inside
+    /// a directory matched by a directory pattern are moved along with it.
+    fn filter(&self, event: &FileEvent) -> Option<PathBuf> {
+        let path = match event {
+            FileEvent::Created(p) | FileEvent::Modified(p) => p.clone(),
+            FileEvent::Deleted(_) => return None,
+            FileEvent::Renamed { to, .. } => to.clone(),
+        };
+
+        if self.is_excluded(&path) {
+            trace!("Ignoring '{}' inside a rule destination", path.display());
+            return None;
+        }
+        if self.own_writes.contains(&path) || transfer::is_temp(&path) {
+            trace!("Ignoring '{}' written by rocas", path.display());
+            return None;
+        }
+        if !path.exists() {
+            trace!("Ignoring '{}', which no longer exists", path.display());
+            return None;
+        }
+        if self.in_matched_dir(&path) {
+            trace!("Ignoring '{}' inside a directory matched by a rule", path.display());
+            return None;
+        }
+        if self.is_partial_download(&path) {
+            debug!("Ignoring in-progress download '{}'", path.display());
+            return None;
+        }
+        if self.has_partial_sibling(&path) {
+            debug!("Ignoring '{}' until its download finishes", path.display());
+            return None;
+        }
+
+        Some(path)
+    }
+
+    /// Applies the matching rules to `path`. Runs on a worker thread.
+    ///
+    /// Only the first matching rule is applied unless `match_mode = "all"`,
+    /// in which case every matching rule is applied in order until one has
+    /// `stop = true`. A rule that is out of rate-limit budget, or a file that
+    /// another process still has open, ends processing and is handed back to
+    /// the engine to retry later.
+    ///
+    /// Directories are only matched by directory patterns (ending in `/`) and
+    /// are moved as a whole.
+    fn process(&self, mut path: PathBuf, log_level: log::Level) -> Processed {
+        // An earlier job for the same path may have moved it already.
+        if !path.exists() {
+            return Processed::Done(Outcome::Ignored);
+        }
+
+        let is_dir = path.is_dir();
+        let (mut full, mut filename) = match_subjects(&path);
+        let mut stable = false;
+        let mut outcome = Outcome::Ignored;
+
+        for (idx, rule) in self.rules.iter().enumerate() {
+            if rule
+                .match_pattern(&full, &filename, is_dir)
+                .is_none()
+            {
+                continue;
+            }
+
+            // Wait for the file to be fully written before inspecting its
+            // content or moving it. Without this, a download that triggers a
+            // Created/Modified event early can be moved while the writer still
+            // has it open, resulting in a 0-byte destination.
+            if !stable {
+                let quiet = Duration::from_millis(self.settings.stable_quiet_ms);
+                if let Err(e) = wait_until_stable(&path, quiet) {
+                    return Processed::Failed { path, rule: idx, error: e.to_string() };
+                }
+                stable = true;
+
+                if self.settings.skip_open_files && !is_dir && busy::is_open(&path) {
+                    info!("'{}' is open in another process; retrying later.", path.display());
+                    return Processed::Open(path);
+                }
+            }
+
+            if let Some(contains) = &rule.contains {
+                // Content conditions only apply to files.
+                if is_dir {
+                    continue;
+                }
+                match contains.matches(&path) {
+                    Ok(true) => {},
+                    Ok(false) => continue,
+                    Err(e) => {
+                        warn!("Could not read '{}' to check its content: {e}", path.display());
+                        continue;
+                    },
+                }
+            }
+
+            // A dry run only logs what the rule would do.
+            if self.settings.dry_run {
+                simulate(rule, &path, log_level);
+                outcome = Outcome::Skipped;
+                if !self.settings.match_all() || rule.config.stop {
+                    break;
+                }
+                continue;
+            }
+
+            if !acquire_budget(rule, &path) {
+                info!(
+                    "Rate limit of {} reached; queued '{}' for later.",
+                    rule.config.name,
+                    path.display()
+                );
+                return Processed::Throttled(Throttled { path, rule: idx });
+            }
+
+            match self.apply_rule(rule, &path) {
+                Ok(None) => outcome = Outcome::Skipped,
+                Ok(Some((dest, placed))) => {
+                    log_placed(log_level, rule, placed, &path, &dest);
+                    notify::placed(rule.config, placed, &path, &dest);
+                    outcome = Outcome::Moved;
+                    // Later rules (in `all` mode) see a moved file at its new
+                    // location. An uploaded or archived file is gone.
+                    match placed {
+                        Placed::Moved => {
+                            path = dest;
+                            (full, filename) = match_subjects(&path);
+                        },
+                        Placed::Uploaded | Placed::Archived | Placed::Encrypted => break,
+                        Placed::Linked | Placed::Copied => {},
+                    }
+                },
+                Err(e @ AppError::InsufficientSpace { .. }) => {
+                    return self.out_of_space(idx, path, &e);
+                },
+                Err(e) if rule.config.wait_for_destination && offline::is_unreachable(&e) => {
+                    warn!("Cannot move '{}' yet: {e}; queued until it is back.", path.display());
+                    return Processed::Offline(Throttled { path, rule: idx });
+                },
+                Err(e) => return Processed::Failed { path, rule: idx, error: e.to_string() },
+            }
+
+            // Without `match_all`, stop after the first matching rule: a second
+            // rule would otherwise move the file again.
+            if !self.settings.match_all() || rule.config.stop {
+                break;
+            }
+        }
+
+        Processed::Done(outcome)
+    }
+
+    /// Applies a deferred match whose rule has regained budget or whose
+    /// destination may have regained space or come back. Runs on a worker
+    /// thread.
+    fn apply_throttled(&self, item: Throttled) -> Processed {
+        let rule = &self.rules[item.rule];
+        match self.apply_rule(rule, &item.path) {
+            Ok(Some((dest, placed))) => {
+                log_placed(log::Level::Info, rule, placed, &item.path, &dest);
+                notify::placed(rule.config, placed, &item.path, &dest);
+                Processed::Done(Outcome::Moved)
+            },
+            Ok(None) => Processed::Done(Outcome::Skipped),
+            Err(AppError::InsufficientSpace { .. })
+                if self.rules[item.rule]
+                    .config
+                    .wait_for_space =>
+            {
+                Processed::NoSpace(item)
+            },
+            Err(e) if rule.config.wait_for_destination && offline::is_unreachable(&e) => {
+                debug!("Destination for '{}' is still unreachable: {e}", item.path.display());
+                Processed::Offline(item)
+            },
+            Err(e) => Processed::Failed {
+                path: item.path,
+                rule: item.rule,
+                error: e.to_string(),
+            },
+        }
+    }
+
+    /// Skips a match whose destination is out of space, or hands it back to
+    /// the engine to wait for space if the rule asks for that.
+    fn out_of_space(&self, rule: usize, path: PathBuf, error: &AppError) -> Processed {
+        if self.rules[rule].config.wait_for_space {
+            warn!("Cannot move '{}' yet: {error}; waiting for space.", path.display());
+            Processed::NoSpace(Throttled { path, rule })
+        } else {
+            warn!("Skipping '{}': {error}", path.display());
+            Processed::Done(Outcome::Skipped)
+        }
+    }
+
+    /// Moves (or links) `path` as configured by `rule` and returns where it
+    /// was placed and how, or `None` if it was skipped because of a conflict
+    /// or, for `hardlink`, because it is already in place.
+    ///
+    /// Every path written along the way is recorded as an own write.
+    fn apply_rule(
+        &self,
+        rule: &CompiledRule,
+        path: &Path,
+    ) -> Result<Option<(PathBuf, Placed)>, AppError> {
+        let (full, filename) = match_subjects(path);
+        let (pattern, subject) = rule
+            .match_pattern(&full, &filename, path.is_dir())
+            .ok_or_else(|| AppError::Other(format!("'{}' no longer matches", path.display())))?;
+
+        if rule.config.wait_for_destination
+            && let Some(base) = mount_point(rule)
+            && !base.is_dir()
+        {
+            return Err(AppError::DestinationOffline(base.to_path_buf()));
+        }
+
+        let ctx = TemplateContext { path, pattern, subject };
+        if let Some(remote) = &rule.remote {
+            return self.upload(rule, remote, &ctx);
+        }
+        if rule.archive {
+            return self.add_to_archive(rule, &ctx);
+        }
+
+        let dest = resolve_destination(rule, &ctx)?;
+        self.check_space(path, &dest)?;
+
+        let action = rule.config.action();
+        // A file kept in place would otherwise be linked again on every
+        // restart with `process_existing`.
+        if action == Action::Hardlink
+            && !rule.reserves_destination()
+            && transfer::already_placed(path, &dest)
+        {
+            debug!("'{}' is already in '{}'; skipping.", path.display(), dest.display());
+            return Ok(None);
+        }
+
+        // A `{counter}` rename reserved a fresh name, so there is nothing to
+        // conflict with.
+        let (dest, reserved) = if rule.reserves_destination() {
+            (dest, true)
+        } else {
+            match self.prepare_destination(rule, dest, path.is_dir())? {
+                Some(prepared) => prepared,
+                None => return Ok(None),
+            }
+        };
+        self.own_writes.record(&dest);
+
+        let placed = match (action, &rule.encryptor) {
+            (Action::Move, Some(encryptor)) => encrypt(encryptor, path, &dest),
+            (Action::Move, None) => transfer::move_path(path, &dest, &self.transfer)
+                .map(|()| Some(Placed::Moved))
+                .map_err(AppError::from),
+            (Action::Hardlink, _) => {
+                // Linking changes the source's link count, which shows up as
+                // an event for it.
+                self.own_writes.record(path);
+                transfer::link_path(path, &dest, rule.config.link_fallback(), &self.transfer)
+                    .map_err(AppError::from)
+            },
+        };
+
+        match placed {
+            Ok(Some(placed)) => {
+                if placed == Placed::Moved {
+                    history::record(path, &dest);
+                }
+                Ok(Some((dest, placed)))
+            },
+            result => {
+                // Release the reserved name.
+                if reserved {
+                    let _ = std::fs::remove_file(&dest);
+                }
+                result?;
+                Ok(None)
+            },
+        }
+    }
+
+    /// Uploads `ctx.path` to the rule's WebDAV destination and deletes it
+    /// locally, handling conflicts like [`prepare_destination`] does. The
+    /// returned destination is the URL of the upload.
+    ///
+    /// [`prepare_destination`]: Self::prepare_destination
+    fn upload(
+        &self,
+        rule: &CompiledRule,
+        remote: &WebDav,
+        ctx: &TemplateContext,
+    ) -> Result<Option<(PathBuf, Placed)>, AppError> {
+        let path = ctx.path;
+        let is_dir = path.is_dir();
+        let collection = webdav::collection_url(&expand_destination(rule, ctx))?;
+        let name = target_name(rule, ctx)?;
+
+        remote.ensure_collection(&collection)?;
+        let mut url = webdav::join(&collection, &name);
+
+        if remote.exists(&url)? {
+            match rule.config.on_conflict() {
+                ConflictStrategy::Rename => {
+                    url = remote.free_name(
+                        &collection,
+                        &name,
+                        &rule.config.conflict_suffix,
+                        is_dir,
+                    )?;
+                    debug!("'{}' already exists; using '{url}'.", webdav::join(&collection, &name));
+                },
+                ConflictStrategy::Skip => {
+                    info!("'{url}' already exists; skipping.");
+                    return Ok(None);
+                },
+                ConflictStrategy::Overwrite => {
+                    if rule.config.backup {
+                        let backup = format!("{url}.bak");
+                        remote.rename(&url, &backup)?;
+                        info!("Backed up {url} → {backup}");
+                    } else if is_dir {
+                        remote.delete(&url)?;
+                    }
+                },
+                ConflictStrategy::Versions => {
+                    let keep = rule.config.versions_keep;
+                    if keep == 0 {
+                        if is_dir {
+                            remote.delete(&url)?;
+                        }
+                    } else {
+                        // MOVE replaces the oldest version kept.
+                        for n in (1..keep).rev() {
+                            let version = format!("{url}.{n}");
+                            if remote.exists(&version)? {
+                                remote.rename(&version, &format!("{url}.{}", n + 1))?;
+                            }
+                        }
+                        remote.rename(&url, &format!("{url}.1"))?;
+                    }
+                },
+            }
+        }
+
+        let verify = self.transfer.verify != VerifyMode::None;
+        match &rule.encryptor {
+            Some(encryptor) => {
+                let ciphertext = encryptor.encrypt_to_temp(path)?;
+                remote.upload(ciphertext.path(), &url, verify)?;
+            },
+            None => remote.upload(path, &url, verify)?,
+        }
+        transfer::remove_source(path)?;
+
+        Ok(Some((PathBuf::from(url), Placed::Uploaded)))
+    }
+
+    /// Adds `ctx.path` to the rule's zip archive and deletes it. Conflicts
+    /// with entries already in the archive are handled by
+    /// [`archive::append`].
+    fn add_to_archive(
+        &self,
+        rule: &CompiledRule,
+        ctx: &TemplateContext,
+    ) -> Result<Option<(PathBuf, Placed)>, AppError> {
+        let path = ctx.path;
+        let destination = expand_destination(rule, ctx);
+        let archive = PathBuf::from(archive::path(&destination).unwrap_or(&destination));
+        if let Some(parent) = archive.parent() {
+            std::fs::create_dir_all(long_path::extend(parent))?;
+        }
+        self.check_space(path, &archive)?;
+
+        let name = target_name(rule, ctx)?;
+        self.own_writes.record(&archive);
+        let ciphertext = rule
+            .encryptor
+            .as_ref()
+            .map(|encryptor| encryptor.encrypt_to_temp(path))
+            .transpose()?;
+        let source = ciphertext
+            .as_ref()
+            .map_or(path, Ciphertext::path);
+        let appended = archive::append(
+            &long_path::extend(&archive),
+            &long_path::extend(source),
+            &name,
+            rule.config,
+        )?;
+        let Some(entry) = appended else {
+            info!("'{name}' is already in '{}'; skipping.", archive.display());
+            return Ok(None);
+        };
+        if entry != name {
+            debug!("'{name}' is already in '{}'; using '{entry}'.", archive.display());
+        }
+
+        transfer::remove_source(path)?;
+        Ok(Some((archive, Placed::Archived)))
+    }
+
+    /// Applies the rule's conflict strategy when `dest` already exists.
+    ///
+    /// Returns the path to move to and whether it was reserved with a
+    /// placeholder file, or `None` if the move should be skipped.
+    fn prepare_destination(
+        &self,
+        rule: &CompiledRule,
+        dest: PathBuf,
+        is_dir: bool,
+    ) -> Result<Option<(PathBuf, bool)>, AppError> {
+        if !dest.exists() {
+            return Ok(Some((dest, false)));
+        }
+
+        match rule.config.on_conflict() {
+            ConflictStrategy::Rename => {
+                let renamed = reserve_suffixed(&dest, &rule.config.conflict_suffix, is_dir)?;
+                debug!("'{}' already exists; using '{}'.", dest.display(), renamed.display());
+                Ok(Some((renamed, true)))
+            },
+            ConflictStrategy::Skip => {
+                info!("'{}' already exists; skipping.", dest.display());
+                Ok(None)
+            },
+            ConflictStrategy::Overwrite => {
+                if rule.config.backup || rule.config.backup_dir.is_some() {
+                    let backup =
+                        backup_file(&dest, rule.config.backup_dir.as_deref(), &self.transfer)?;
+                    self.own_writes.record(&backup);
+                } else if dest.is_dir() {
+                    // A rename cannot replace a non-empty directory.
+                    std::fs::remove_dir_all(&dest)?;
+                }
+                Ok(Some((dest, false)))
+            },
+            ConflictStrategy::Versions => {
+                for version in rotate_versions(&dest, rule.config.versions_keep)? {
+                    self.own_writes.record(&version);
+                }
+                Ok(Some((dest, false)))
+            },
+        }
+    }
+
+    /// Fails with [`AppError::InsufficientSpace`] when moving `path` to
+    /// `dest` needs a copy that would not fit, leaving `min_free_space_mb`
+    /// free. Moves within one filesystem are renames and always fit.
+    fn check_space(&self, path: &Path, dest: &Path) -> Result<(), AppError> {
+        if space::same_filesystem(path, dest) {
+            return Ok(());
+        }
+
+        let available = match space::available(dest) {
+            Ok(available) => available,
+            Err(e) => {
+                debug!("Could not check free space for '{}': {e}", dest.display());
+                return Ok(());
+            },
+        };
+        let needed = total_size(path) + self.settings.min_free_space_mb * 1024 * 1024;
+        if needed > available {
+            return Err(AppError::InsufficientSpace {
+                dest: dest.to_path_buf(),
+                needed,
+                available,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if `path` lies inside a destination that is excluded
+    /// from matching.
+    fn is_excluded(&self, path: &Path) -> bool {
+        if self.excluded.is_empty() {
+            return false;
+        }
+
+        let path = absolute(path);
+        self.excluded
+            .iter()
+            .any(|dir| path.starts_with(dir))
+    }
+
+    /// Returns `true` if a directory between `path` and its watched root
+    /// matches a directory pattern, i.e. `path` is moved along with it.
+    fn in_matched_dir(&self, path: &Path) -> bool {
+        if !self.dir_rules {
+            return false;
+        }
+
+        let path = absolute(path);
+        path.ancestors()
+            .skip(1)
+            .take_while(|dir| {
+                !self
+                    .roots
+                    .iter()
+                    .any(|root| root == dir)
+            })
+            .any(|dir| {
+                let (full, filename) = match_subjects(dir);
+                self.rules.iter().any(|rule| {
+                    rule.match_pattern(&full, &filename, true)
+                        .is_some()
+                })
+            })
+    }
+
+    /// Returns `true` if `path` has one of the configured partial-download
+    /// extensions.
+    fn is_partial_download(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                self.settings
+                    .partial_download_extensions
+                    .iter()
+                    .any(|partial| partial.eq_ignore_ascii_case(ext))
+            })
+    }
+
+    /// Returns `true` if a partial-download sibling of `path` exists, i.e. a
+    /// browser is still writing the data that will replace it.
+    fn has_partial_sibling(&self, path: &Path) -> bool {
+        self.settings
+            .partial_download_extensions
+            .iter()
+            .any(|ext| {
+                let mut name = path.as_os_str().to_os_string();
+                name.push(".");
+                name.push(ext);
+                Path::new(&name).exists()
+            })
+    }
+}
+
+/// Dispatches filesystem events to the worker pool and keeps track of
+/// deferred matches.
+pub struct Engine<'a> {
+    ctx: &'a Context<'a>,
+    pool: WorkerPool<'a>,
+    done_tx: Sender<Completion>,
+    done_rx: Receiver<Completion>,
+    /// Matches deferred by a rule's rate limit, in arrival order.
+    throttled: VecDeque<Throttled>,
+    /// Matches deferred because another process had the file open.
+    open_files: VecDeque<OpenFile>,
+    /// Failed files waiting for another attempt.
+    retries: VecDeque<FailedMove>,
+    /// Matches waiting for their destination to regain space.
+    waiting_for_space: VecDeque<WaitingForSpace>,
+    /// Matches waiting for their destination to become reachable, mirrored
+    /// to `journal`.
+    waiting_for_destination: VecDeque<WaitingForDestination>,
+    journal: Journal,
+    /// Batches with files still being processed, by id.
+    batches: HashMap<u64, Batch>,
+    next_batch: u64,
+    /// Files moved since the engine started.
+    moved: u64,
+    /// When the last filesystem event arrived.
+    last_event: Option<SystemTime>,
+}
+
+impl<'a> Engine<'a> {
+    /// Creates an engine whose workers (`worker_threads` of them) run inside
+    /// `scope`.
+    pub fn new<'env>(scope: &'a Scope<'a, 'env>, ctx: &'a Context<'a>) -> Self {
+        let (done_tx, done_rx) = unbounded();
+
+        let journal = Journal::new(&ctx.settings.name);
+        let waiting_for_destination: VecDeque<WaitingForDestination> = journal
+            .load()
+            .into_iter()
+            .filter_map(|(destination, path)| {
+                let rule = ctx.rules.iter().position(|rule| {
+                    rule.config.wait_for_destination && rule.config.destination == destination
+                })?;
+                path.exists()
+                    .then(|| WaitingForDestination { path, rule, retry_at: Instant::now() })
+            })
+            .collect();
+        if !waiting_for_destination.is_empty() {
+            info!(
+                "Resuming {} matches queued for unreachable destinations.",
+                waiting_for_destination.len()
+            );
+        }
+
+        Self {
+            ctx,
+            pool: WorkerPool::new(scope, ctx.settings.worker_threads),
+            done_tx,
+            done_rx,
+            throttled: VecDeque::new(),
+            open_files: VecDeque::new(),
+            retries: VecDeque::new(),
+            waiting_for_space: VecDeque::new(),
+            waiting_for_destination,
+            journal,
+            batches: HashMap::new(),
+            next_batch: 0,
+            moved: 0,
+            last_event: None,
+        }
+    }
+
+    /// Returns the number of files moved and when the last event arrived.
+    pub fn activity(&self) -> (u64, Option<SystemTime>) {
+        (self.moved, self.last_event)
+    }
+
+    /// Returns the number of matches deferred for another attempt.
+    pub fn pending(&self) -> usize {
+        self.throttled.len()
+            + self.open_files.len()
+            + self.retries.len()
+            + self.waiting_for_space.len()
+            + self.waiting_for_destination.len()
+    }
+
+    /// Receives the results of finished jobs; pass them to
+    /// [`complete`](Self::complete).
+    pub fn completions(&self) -> &Receiver<Completion> {
+        &self.done_rx
+    }
+
+    /// Processes a burst of events as a unit and logs a single summary line
+    /// once all of them are done.
+    ///
+    /// Events are deduplicated per path (the latest event wins) while keeping
+    /// the order in which paths first appeared.
+    pub fn handle_batch(&mut self, events: Vec<FileEvent>) {
+        self.last_event = Some(SystemTime::now());
+        let events = dedup_events(events);
+        if events.len() <= 1 {
+            for event in &events {
+                self.handle_event(event);
+            }
+            return;
+        }
+
+        let id = self.next_batch;
+        self.next_batch += 1;
+
+        let mut batch = Batch::default();
+        for event in &events {
+            match self.ctx.filter(event) {
+                // Per-file lines would drown out the summary.
+                Some(path) => {
+                    self.submit(path, Some(id), log::Level::Debug, 0);
+                    batch.pending += 1;
+                },
+                None => batch.summary.record(Outcome::Ignored),
+            }
+        }
+
+        if batch.pending == 0 {
+            batch.summary.log();
+        } else {
+            self.batches.insert(id, batch);
+        }
+    }
+
+    /// Processes a single filesystem event.
+    pub fn handle_event(&mut self, event: &FileEvent) {
+        if let Some(path) = self.ctx.filter(event) {
+            self.submit(path, None, log::Level::Info, 0);
+        }
+    }
+
+    /// Queues `path` for processing on the worker responsible for it, so
+    /// events for the same file are handled in order.
+    fn submit(&self, path: PathBuf, batch: Option<u64>, log_level: log::Level, attempt: u32) {
+        let ctx = self.ctx;
+        let done = self.done_tx.clone();
+        let key = path.clone();
+
+        self.pool.submit(&key, move || {
+            let processed = ctx.process(path, log_level);
+            // The engine only goes away on shutdown.
+            let _ = done.send(Completion { batch, attempt, processed });
+        });
+    }
+
+    /// Records the result of a finished job: deferred matches are queued for
+    /// a retry, and a batch logs its summary once its last file is done.
+    pub fn complete(&mut self, done: Completion) {
+        let outcome = match done.processed {
+            Processed::Done(outcome) => outcome,
+            Processed::Throttled(item) => {
+                self.throttled.push_back(item);
+                Outcome::Queued
+            },
+            Processed::Open(path) => {
+                self.open_files.push_back(OpenFile {
+                    path,
+                    since: Instant::now(),
+                    retry_at: Instant::now() + self.open_file_retry(),
+                });
+                Outcome::Queued
+            },
+            Processed::NoSpace(item) => {
+                self.waiting_for_space
+                    .push_back(WaitingForSpace {
+                        path: item.path,
+                        rule: item.rule,
+                        retry_at: Instant::now() + SPACE_RECHECK_INTERVAL,
+                    });
+                Outcome::Queued
+            },
+            Processed::Offline(item) => {
+                self.waiting_for_destination
+                    .push_back(WaitingForDestination {
+                        path: item.path,
+                        rule: item.rule,
+                        retry_at: Instant::now() + OFFLINE_RECHECK_INTERVAL,
+                    });
+                self.save_journal();
+                Outcome::Queued
+            },
+            Processed::Failed { path, rule, error } => {
+                self.schedule_retry(path, rule, &error, done.attempt)
+            },
+        };
+        if outcome == Outcome::Moved {
+            self.moved += 1;
+        }
+
+        let Some(id) = done.batch else {
+            return;
+        };
+        let Some(batch) = self.batches.get_mut(&id) else {
+            return;
+        };
+
+        batch.summary.record(outcome);
+        batch.pending -= 1;
+        if batch.pending == 0 {
+            batch.summary.log();
+            self.batches.remove(&id);
+        }
+    }
+
+    /// Queues another attempt for a file that failed to move, or gives up
+    /// after `retry_attempts` failures.
+    fn schedule_retry(
+        &mut self,
+        path: PathBuf,
+        rule: usize,
+        error: &str,
+        failed_before: u32,
+    ) -> Outcome {
+        let failed = failed_before + 1;
+        let max = self.ctx.settings.retry_attempts;
+
+        let config = self.ctx.rules[rule].config;
+        let fields = logger::Fields {
+            rule: Some(&config.name),
+            source: Some(path.display().to_string()),
+            error: Some(error),
+            ..Default::default()
+        };
+        if failed > max || !path.exists() {
+            logger::log_fields(
+                log::Level::Error,
+                module_path!(),
+                &fields,
+                format_args!("Failed to move '{}': {error}", path.display()),
+            );
+            notify::failed(config, &path, error);
+            return Outcome::Failed;
+        }
+
+        let delay =
+            retry::backoff(Duration::from_millis(self.ctx.settings.retry_backoff_ms), failed);
+        logger::log_fields(
+            log::Level::Warn,
+            module_path!(),
+            &fields,
+            format_args!(
+                "Failed to move '{}': {error}; retrying in {:.1}s ({failed}/{max})",
+                path.display(),
+                delay.as_secs_f64()
+            ),
+        );
+        self.retries.push_back(FailedMove {
+            path,
+            failures: failed,
+            retry_at: Instant::now() + delay,
+        });
+        Outcome::Queued
+    }
+
+    /// Runs every deferred match that is due: throttled matches whose rule
+    /// regained budget, files that are no longer open elsewhere, failed
+    /// moves whose backoff has passed and matches waiting for space or for
+    /// their destination.
+    pub fn run_due(&mut self) {
+        self.drain_throttled();
+        self.retry_open_files();
+        self.retry_failed();
+        self.retry_waiting_for_space();
+        self.retry_waiting_for_destination();
+    }
+
+    /// Applies queued matches whose rule has regained rate-limit budget.
+    ///
+    /// Files that disappeared or were renamed away in the meantime are
+    /// dropped from the queue.
+    fn drain_throttled(&mut self) {
+        let ctx = self.ctx;
+        let pool = &self.pool;
+        let done_tx = &self.done_tx;
+        self.throttled.retain(|item| {
+            let rule = &ctx.rules[item.rule];
+            if !item.path.exists() {
+                return false;
+            }
+            if !acquire_budget(rule, &item.path) {
+                return true;
+            }
+
+            let item = Throttled { path: item.path.clone(), rule: item.rule };
+            let done = done_tx.clone();
+            pool.submit(&item.path.clone(), move || {
+                let processed = ctx.apply_throttled(item);
+                let _ = done.send(Completion { batch: None, attempt: 0, processed });
+            });
+            false
+        });
+    }
+
+    /// Re-runs deferred files that are no longer open in another process.
+    ///
+    /// Files that disappeared in the meantime are dropped, as are files still
+    /// open after [`OPEN_FILE_MAX_WAIT`].
+    fn retry_open_files(&mut self) {
+        let now = Instant::now();
+        let (due, waiting): (VecDeque<OpenFile>, VecDeque<OpenFile>) =
+            std::mem::take(&mut self.open_files)
+                .into_iter()
+                .partition(|item| item.retry_at <= now);
+        self.open_files = waiting;
+
+        for mut item in due {
+            if !item.path.exists() {
+                continue;
+            }
+
+            if busy::is_open(&item.path) {
+                if item.since.elapsed() >= OPEN_FILE_MAX_WAIT {
+                    warn!(
+                        "'{}' is still open in another process; giving up on it.",
+                        item.path.display()
+                    );
+                } else {
+                    item.retry_at = now + self.open_file_retry();
+                    self.open_files.push_back(item);
+                }
+                continue;
+            }
+
+            self.handle_event(&FileEvent::Created(item.path));
+        }
+    }
+
+    /// Re-runs failed files whose backoff has passed.
+    fn retry_failed(&mut self) {
+        let now = Instant::now();
+        let (due, waiting): (VecDeque<FailedMove>, VecDeque<FailedMove>) =
+            std::mem::take(&mut self.retries)
+                .into_iter()
+                .partition(|item| item.retry_at <= now);
+        self.retries = waiting;
+
+        for item in due {
+            if let Some(path) = self
+                .ctx
+                .filter(&FileEvent::Created(item.path))
+            {
+                self.submit(path, None, log::Level::Info, item.failures);
+            }
+        }
+    }
+
+    /// Re-applies matches waiting for space whose recheck is due. The move
+    /// itself checks the space again and re-queues the match if it still
+    /// does not fit. Files that disappeared in the meantime are dropped.
+    fn retry_waiting_for_space(&mut self) {
+        let now = Instant::now();
+        let (due, waiting): (VecDeque<WaitingForSpace>, VecDeque<WaitingForSpace>) =
+            std::mem::take(&mut self.waiting_for_space)
+                .into_iter()
+                .partition(|item| item.retry_at <= now);
+        self.waiting_for_space = waiting;
+
+        for item in due {
+            if !item.path.exists() {
+                continue;
+            }
+
+            let ctx = self.ctx;
+            let done = self.done_tx.clone();
+            let item = Throttled { path: item.path, rule: item.rule };
+            self.pool
+                .submit(&item.path.clone(), move || {
+                    let processed = ctx.apply_throttled(item);
+                    let _ = done.send(Completion { batch: None, attempt: 0, processed });
+                });
+        }
+    }
+
+    /// Re-applies matches whose destination has come back. Destinations
+    /// that are still missing are checked again later; whether a remote one
+    /// is back only shows when the move is tried. Files that disappeared in
+    /// the meantime are dropped.
+    fn retry_waiting_for_destination(&mut self) {
+        let now = Instant::now();
+        let (due, waiting): (VecDeque<WaitingForDestination>, VecDeque<WaitingForDestination>) =
+            std::mem::take(&mut self.waiting_for_destination)
+                .into_iter()
+                .partition(|item| item.retry_at <= now);
+        self.waiting_for_destination = waiting;
+        if due.is_empty() {
+            return;
+        }
+
+        for mut item in due {
+            if !item.path.exists() {
+                continue;
+            }
+            if mount_point(&self.ctx.rules[item.rule]).is_some_and(|base| !base.is_dir()) {
+                item.retry_at = now + OFFLINE_RECHECK_INTERVAL;
+                self.waiting_for_destination
+                    .push_back(item);
+                continue;
+            }
+
+            let ctx = self.ctx;
+            let done = self.done_tx.clone();
+            let item = Throttled { path: item.path, rule: item.rule };
+            self.pool
+                .submit(&item.path.clone(), move || {
+                    let processed = ctx.apply_throttled(item);
+                    let _ = done.send(Completion { batch: None, attempt: 0, processed });
+                });
+        }
+
+        self.save_journal();
+    }
+
+    /// Writes the matches waiting for their destination to the journal.
+    fn save_journal(&self) {
+        let rules = self.ctx.rules;
+        self.journal.save(
+            self.waiting_for_destination
+                .iter()
+                .map(|item| {
+                    (
+                        rules[item.rule]
+                            .config
+                            .destination
+                            .as_str(),
+                        item.path.as_path(),
+                    )
+                }),
+        );
+    }
+
+    fn open_file_retry(&self) -> Duration {
+        Duration::from_secs(
+            self.ctx
+                .settings
+                .open_file_retry_secs
+                .max(1),
+        )
+    }
+
+    /// Returns how long the event loop may block before queued matches need
+    /// another look, or `None` when nothing is queued.
+    pub fn next_wakeup(&self) -> Option<Duration> {
+        let open = self
+            .open_files
+            .iter()
+            .map(|item| {
+                item.retry_at
+                    .saturating_duration_since(Instant::now())
+            })
+            .min();
+
+        let failed = self
+            .retries
+            .iter()
+            .map(|item| {
+                item.retry_at
+                    .saturating_duration_since(Instant::now())
+            })
+            .min();
+
+        let space = self
+            .waiting_for_space
+            .iter()
+            .map(|item| {
+                item.retry_at
+                    .saturating_duration_since(Instant::now())
+            })
+            .min();
+
+        let destination = self
+            .waiting_for_destination
+            .iter()
+            .map(|item| {
+                item.retry_at
+                    .saturating_duration_since(Instant::now())
+            })
+            .min();
+
+        let throttled = self
+            .throttled
+            .iter()
+            .filter_map(|item| {
+                self.ctx.rules[item.rule]
+                    .rate_limit
+                    .as_ref()?
+                    .next_expiry()
+            })
+            .min()
+            .or_else(|| (!self.throttled.is_empty()).then_some(THROTTLE_RECHECK_INTERVAL));
+
+        [throttled, open, failed, space, destination]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+}
+
+/// Returns the rule destinations that lie strictly inside a watched
+/// directory, logging a warning for each.
+///
+/// Templated destinations are reduced to their static leading directory. A
+/// destination that resolves to the watched directory itself cannot be
+/// excluded without ignoring everything, so it is only warned about.
+fn nested_destinations(rules: &[CompiledRule], settings: &WatcherConfig) -> Vec<PathBuf> {
+    let roots: Vec<PathBuf> = settings
+        .effective_paths()
+        .into_iter()
+        .filter_map(|root| std::path::absolute(root).ok())
+        .collect();
+
+    let mut excluded: Vec<PathBuf> = Vec::new();
+    for rule in rules
+        .iter()
+        .filter(|rule| rule.remote.is_none() && !rule.archive)
+    {
+        let Ok(dest) = std::path::absolute(static_dir(&rule.config.destination)) else {
+            continue;
+        };
+
+        for root in &roots {
+            if dest == *root {
+                warn!(
+                    "Destination '{}' is the watched directory {}; moved files may be matched \
+                     again.",
+                    rule.config.destination,
+                    root.display()
+                );
+            } else if dest.starts_with(root) && !excluded.contains(&dest) {
+                warn!(
+                    "Destination '{}' is inside the watched directory {}; files in it will be \
+                     ignored.",
+                    rule.config.destination,
+                    root.display()
+                );
+                excluded.push(dest.clone());
+            }
+        }
+    }
+
+    excluded
+}
+
+/// Removes temporary copies left in rule destinations by moves that were
+/// interrupted, e.g. because rocas crashed mid-copy.
+///
+/// Templated destinations are searched from their static leading directory
+/// down to the depth the template can reach.
+pub fn remove_stale_copies(rules: &[CompiledRule]) {
+    for rule in rules
+        .iter()
+        .filter(|rule| rule.remote.is_none())
+    {
+        let destination = &rule.config.destination;
+        // A zip archive is written under a temporary name next to it.
+        let destination = archive::path(destination).unwrap_or(destination);
+        let dir = static_dir(destination);
+        let depth = Path::new(&destination[dir.len()..])
+            .components()
+            .count();
+
+        let dir = if dir.is_empty() { "." } else { dir };
+        transfer::remove_stale_temps(Path::new(dir), depth);
+    }
+}
+
+/// Returns the directory that must exist for a local destination to be
+/// reachable: the part before the first placeholder. `None` for WebDAV
+/// destinations and destinations that start with a placeholder.
+fn mount_point<'r>(rule: &'r CompiledRule) -> Option<&'r Path> {
+    if rule.remote.is_some() {
+        return None;
+    }
+
+    let destination = &rule.config.destination;
+    let destination = archive::path(destination).unwrap_or(destination);
+    let base = static_dir(destination);
+    if base.is_empty() {
+        return None;
+    }
+
+    let base = Path::new(base);
+    // The archive itself is created on first use.
+    if rule.archive && base.as_os_str().len() == destination.len() {
+        return base.parent();
+    }
+    Some(base)
+}
+
+/// Returns the leading part of a destination that contains no template
+/// syntax (`$` capture references or `{` placeholders), cut back to a whole
+/// path component.
+pub fn static_dir(destination: &str) -> &str {
+    let Some(idx) = destination.find(['$', '{']) else {
+        return destination;
+    };
+
+    let prefix = &destination[..idx];
+    match prefix.rfind(['/', '\\']) {
+        Some(sep) => &prefix[..=sep],
+        None => "",
+    }
+}
+
+/// Collapses events for the same path into the latest one, keeping the order
+/// in which paths first appeared.
+fn dedup_events(events: Vec<FileEvent>) -> Vec<FileEvent> {
+    let mut index: HashMap<PathBuf, usize> = HashMap::with_capacity(events.len());
+    let mut deduped: Vec<FileEvent> = Vec::with_capacity(events.len());
+
+    for event in events {
+        if let Some(&i) = index.get(event.path()) {
+            deduped[i] = event;
+        } else {
+            index.insert(event.path().to_path_buf(), deduped.len());
+            deduped.push(event);
+        }
+    }
+
+    deduped
+}
+
+/// Records the move of `path` against the rule's rate limit, if it has one.
+///
+/// Returns `false` if the rule is out of budget.
+fn acquire_budget(rule: &CompiledRule, path: &Path) -> bool {
+    let Some(limiter) = &rule.rate_limit else {
+        return true;
+    };
+
+    limiter.try_acquire(total_size(path))
+}
+
+/// Moves an existing destination file out of the way before it is overwritten.
+///
+/// The file goes into `backup_dir` when set, otherwise it is renamed to
+/// `<name>.bak` next to itself. An older backup with the same name is
+/// replaced. Returns the backup's path.
+fn backup_file(
+    dest: &Path,
+    backup_dir: Option<&str>,
+    opts: &transfer::Options,
+) -> Result<PathBuf, AppError> {
+    let filename = dest
+        .file_name()
+        .ok_or_else(|| AppError::Other(format!("invalid filename: {}", dest.display())))?;
+
+    let backup = if let Some(dir) = backup_dir {
+        std::fs::create_dir_all(long_path::extend(Path::new(dir)))?;
+        Path::new(dir).join(filename)
+    } else {
+        let mut name = filename.to_os_string();
+        name.push(".bak");
+        dest.with_file_name(name)
+    };
+
+    let long_backup = long_path::extend(&backup);
+    if long_backup.is_dir() {
+        std::fs::remove_dir_all(&long_backup)?;
+    }
+    transfer::move_path(dest, &backup, opts)?;
+
+    info!("Backed up {} → {}", dest.display(), backup.display());
+    Ok(backup)
+}
+
+/// Shifts the existing versions of `dest` up by one (`report.pdf` becomes
+/// `report.pdf.1`, `report.pdf.1` becomes `report.pdf.2`, …) so `dest` is
+/// free, deleting what would end up beyond `keep`. Returns the paths the
+/// versions were moved to.
+fn rotate_versions(dest: &Path, keep: u32) -> std::io::Result<Vec<PathBuf>> {
+    let version = |n: u32| {
+        let mut name = dest
+            .file_name()
+            .unwrap_or_default()
+            .to_os_string();
+        name.push(format!(".{n}"));
+        dest.with_file_name(name)
+    };
+    let exists = |path: &Path| std::fs::symlink_metadata(long_path::extend(path)).is_ok();
+    let rename =
+        |from: &Path, to: &Path| std::fs::rename(long_path::extend(from), long_path::extend(to));
+
+    // Versions beyond the limit, including ones left from a higher
+    // `versions_keep`.
+    let mut n = keep.max(1);
+    while exists(&version(n)) {
+        transfer::remove_source(&version(n))?;
+        n += 1;
+    }
+    if keep == 0 {
+        transfer::remove_source(dest)?;
+        return Ok(Vec::new());
+    }
+
+    let mut moved = Vec::new();
+    for n in (1..keep).rev() {
+        let from = version(n);
+        if exists(&from) {
+            rename(&from, &version(n + 1))?;
+            moved.push(version(n + 1));
+        }
+    }
+    rename(dest, &version(1))?;
+    moved.push(version(1));
+
+    debug!("Kept the previous '{}' as '{}'", dest.display(), version(1).display());
+    Ok(moved)
+}
+
+/// Reserves the first free name next to `dest` with `suffix` appended to its
+/// file name, `{n}` counting up from 1: `report.pdf` becomes
+/// `report (1).pdf`. A directory's name is suffixed as a whole.
+///
+/// The name is reserved by creating an empty placeholder file, so concurrent
+/// moves never pick the same one.
+fn reserve_suffixed(dest: &Path, suffix: &str, is_dir: bool) -> std::io::Result<PathBuf> {
+    let name = dest
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let (stem, ext) = match dest.extension() {
+        Some(ext) if !is_dir => {
+            let ext = ext.to_string_lossy();
+            (name[..name.len() - ext.len() - 1].to_string(), format!(".{ext}"))
+        },
+        _ => (name.to_string(), String::new()),
+    };
+
+    for n in 1_u64.. {
+        let suffix = suffix.replace("{n}", &n.to_string());
+        let candidate = dest.with_file_name(format!("{stem}{suffix}{ext}"));
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+        {
+            Ok(_) => return Ok(candidate),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {},
+            Err(e) => return Err(e),
+        }
+    }
+
+    unreachable!("ran out of conflict suffix numbers")
+}
+
+/// Returns `path` made absolute, or unchanged if that fails.
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Returns the full path and the filename that rule patterns are matched
+/// against.
+///
+/// The full path is normalized to forward slashes so glob patterns work on
+/// Windows (where `Path::to_str()` returns backslash-separated paths).
+pub fn match_subjects(path: &Path) -> (String, String) {
+    let full = path
+        .to_str()
+        .unwrap_or("")
+        .replace('\\', "/");
+
+    let filename = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("")
+        .to_string();
+
+    (full, filename)
+}
+
+/// Polls `path` until neither its size nor its modification time has changed
+/// for `quiet`, re-checking once the quiet period has passed. Returns an error
+/// if the file disappears. For a directory, the total size and newest
+/// modification time of everything inside it are compared.
+///
+/// This ensures a file is fully written before it is moved. Downloads that
+/// trigger a `Created`/`Modified` event early would otherwise be moved while
+/// the writer still has the file open, producing a truncated destination.
+/// The file is always probed at least twice, `quiet` apart: an old
+/// modification time proves nothing, since copies that preserve timestamps
+/// carry the source's while they are still being written.
+///
+/// Gives up and returns `Ok(())` after `STABLE_MAX_WAIT` to avoid blocking
+/// the event loop indefinitely on a stalled download.
+fn wait_until_stable(path: &Path, quiet: Duration) -> Result<(), AppError> {
+    let started = Instant::now();
+    let mut last: Option<(u64, Option<SystemTime>)> = None;
+    let mut unchanged_since = Instant::now();
+
+    loop {
+        if started.elapsed() >= STABLE_MAX_WAIT {
+            warn!(
+                "Timed out waiting for '{}' to finish writing; moving it anyway.",
+                path.display()
+            );
+            break;
+        }
+
+        match std::fs::metadata(path) {
+            Ok(meta) => {
+                let current = if meta.is_dir() {
+                    tree_state(path)
+                } else {
+                    (meta.len(), meta.modified().ok())
+                };
+
+                if last == Some(current) {
+                    // Unchanged since the previous probe — stable once the
+                    // quiet period has fully elapsed.
+                    if unchanged_since.elapsed() >= quiet {
+                        break;
+                    }
+                } else {
+                    last = Some(current);
+                    unchanged_since = Instant::now();
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(AppError::Other(format!("'{}' no longer exists", path.display())));
+            },
+            Err(e) => return Err(AppError::Io(e)),
+        }
+
+        std::thread::sleep(STABLE_POLL_INTERVAL.min(quiet));
+    }
+
+    Ok(())
+}
+
+/// Resolves the full destination path for a file matched by `rule`, creating
+/// the destination directory if needed.
+///
+/// Regex capture references in the destination are expanded. When the rule has
+/// a `rename` template the file name is rendered from it (reserving the name if
+/// it uses `{counter}`); otherwise the original file name is kept.
+fn resolve_destination(rule: &CompiledRule, ctx: &TemplateContext) -> Result<PathBuf, AppError> {
+    let dest_dir = PathBuf::from(expand_destination(rule, ctx));
+    std::fs::create_dir_all(long_path::extend(&dest_dir))?;
+
+    if let Some(rename) = &rule.rename {
+        return Ok(rename.reserve(&dest_dir, ctx, rule.sanitizer.as_ref())?);
+    }
+
+    let filename = ctx
+        .path
+        .file_name()
+        .ok_or_else(|| AppError::Other(format!("invalid filename: {}", ctx.path.display())))?;
+
+    let mut filename = filename.to_os_string();
+    if rule.encryptor.is_some() {
+        filename.push(".age");
+    }
+
+    if let Some(sanitizer) = &rule.sanitizer {
+        let original = filename.to_string_lossy();
+        let clean = sanitizer.clean(&original);
+        if clean != original {
+            debug!("Sanitized '{original}' to '{clean}'");
+        }
+        return Ok(dest_dir.join(clean));
+    }
+
+    Ok(dest_dir.join(filename))
+}
+
+/// Logs what `rule` would do with `path`, for `dry_run`. Nothing is created,
+/// moved or reserved.
+fn simulate(rule: &CompiledRule, path: &Path, log_level: log::Level) {
+    let (full, filename) = match_subjects(path);
+    let Some((pattern, subject)) = rule.match_pattern(&full, &filename, path.is_dir()) else {
+        return;
+    };
+    let ctx = TemplateContext { path, pattern, subject };
+
+    let destination = expand_destination(rule, &ctx);
+    let name = target_name(rule, &ctx).unwrap_or_default();
+    let target = if rule.remote.is_some() {
+        webdav::join(&destination, &name)
+    } else if rule.archive {
+        format!("{}/{name}", archive::path(&destination).unwrap_or(&destination))
+    } else {
+        Path::new(&destination)
+            .join(name)
+            .display()
+            .to_string()
+    };
+
+    log!(
+        log_level,
+        "[dry-run] would {} {} → {} ({})",
+        rule.action_name(),
+        path.display(),
+        target,
+        rule.config.name
+    );
+}
+
+/// Logs that `rule` placed `path` at `dest`, with the fields the JSON log
+/// format writes.
+fn log_placed(level: log::Level, rule: &CompiledRule, placed: Placed, path: &Path, dest: &Path) {
+    let name = &rule.config.name;
+    let fields = logger::Fields {
+        rule: Some(name),
+        source: Some(path.display().to_string()),
+        destination: Some(dest.display().to_string()),
+        // Uploads and archive entries have no local file to measure.
+        bytes: std::fs::metadata(dest)
+            .ok()
+            .filter(std::fs::Metadata::is_file)
+            .map(|meta| meta.len()),
+        error: None,
+    };
+    logger::log_fields(
+        level,
+        module_path!(),
+        &fields,
+        format_args!("{placed} {} → {} ({name})", path.display(), dest.display()),
+    );
+}
+
+/// Encrypts `path` to `dest` and deletes the plaintext.
+fn encrypt(encryptor: &Encryptor, path: &Path, dest: &Path) -> Result<Option<Placed>, AppError> {
+    encryptor.encrypt(path, dest)?;
+    transfer::remove_source(path)?;
+    Ok(Some(Placed::Encrypted))
+}
+
+/// Expands the rule's destination for `ctx`: regex capture references and
+/// the `{year}`, `{month}` and `{day}` of the current local date.
+pub fn expand_destination(rule: &CompiledRule, ctx: &TemplateContext) -> String {
+    // Regex rules may reference their capture groups in the destination.
+    let expanded = ctx
+        .pattern
+        .expand(ctx.subject, &rule.config.destination);
+    let expanded = if expanded.contains('{') {
+        let today = clock::now();
+        expanded
+            .replace("{year}", &format!("{:04}", today.year))
+            .replace("{month}", &format!("{:02}", today.month))
+            .replace("{day}", &format!("{:02}", today.day))
+    } else {
+        expanded
+    };
+
+    // A capture group may have put a `~` at the start.
+    tilde::expand_destination(&expanded).into_owned()
+}
+
+/// Returns the name a file is stored under at a destination that is not a
+/// local directory: the rendered `rename` template or the original filename,
+/// sanitized if the rule asks for it.
+pub fn target_name(rule: &CompiledRule, ctx: &TemplateContext) -> Result<String, AppError> {
+    let name = match &rule.rename {
+        Some(rename) => rename.render(ctx, 0),
+        None => {
+            let mut name = ctx
+                .path
+                .file_name()
+                .ok_or_else(|| {
+                    AppError::Other(format!("invalid filename: {}", ctx.path.display()))
+                })?
+                .to_string_lossy()
+                .into_owned();
+            if rule.encryptor.is_some() {
+                name.push_str(".age");
+            }
+            name
+        },
+    };
+
+    Ok(rule
+        .sanitizer
+        .as_ref()
+        .map_or_else(|| name.clone(), |s| s.clean(&name)))
+}
+
+/// Returns the size of a file, or the total size of the files below a
+/// directory.
+fn total_size(path: &Path) -> u64 {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.is_dir() => tree_state(path).0,
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Returns the total size and the newest modification time of everything
+/// below `dir`. Unreadable entries are skipped.
+fn tree_state(dir: &Path) -> (u64, Option<SystemTime>) {
+    let mut size = 0;
+    let mut newest = std::fs::metadata(dir)
+        .and_then(|meta| meta.modified())
+        .ok();
+
+    for entry in std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+    {
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+
+        let (entry_size, entry_mtime) = if meta.is_dir() {
+            tree_state(&entry.path())
+        } else {
+            (meta.len(), meta.modified().ok())
+        };
+        size += entry_size;
+        newest = newest.max(entry_mtime);
+    }
+
+    (size, newest)
+}
@@ -0,0 +1,252 @@
+//! `rocas explain`: shows why a file is or is not picked up.
+//!
+//! Like `rocas test`, the file is run through the rules of the watcher whose
+//! directory holds it. Instead of listing only the rules that apply, every
+//! pattern and condition of every rule is printed with its outcome, along
+//! with the reasons the watcher itself would ignore the file. Nothing is
+//! moved.
+
+use std::path::Path;
+
+use crate::config::{Config, WatcherConfig};
+use crate::pattern::Pattern;
+use crate::rule::CompiledRule;
+use crate::template::TemplateContext;
+use crate::{AppError, engine, tester, transfer};
+
+/// Prints how every rule evaluates `path`. Returns `true` if a rule would
+/// apply to it.
+///
+/// # Errors
+///
+/// Returns [`AppError`] if a rule does not compile.
+pub fn run(config: &Config, path: &Path) -> Result<bool, AppError> {
+    let watchers = config.watchers();
+    let found = watchers
+        .iter()
+        .enumerate()
+        .find_map(|(idx, watcher)| Some((idx, tester::root_of(watcher, path)?)));
+    let watcher = watchers[found
+        .as_ref()
+        .map_or(0, |(idx, _)| *idx)];
+
+    let used = config.rules_of(watcher)?;
+    let rules: Vec<CompiledRule> = used
+        .iter()
+        .copied()
+        .map(CompiledRule::new)
+        .collect::<Result<_, _>>()?;
+
+    println!("{}", path.display());
+    println!(
+        "  watcher '{}' ({}, match_mode = \"{}\")",
+        watcher.name,
+        watcher.effective_paths().join(", "),
+        watcher.match_mode
+    );
+    for note in ignored_because(
+        watcher,
+        &rules,
+        path,
+        found
+            .as_ref()
+            .map(|(_, root)| root.as_path()),
+    ) {
+        println!("  ! {note}");
+    }
+
+    let is_dir = path.is_dir();
+    let (full, filename) = engine::match_subjects(path);
+    let mut claimed_by: Option<&str> = None;
+    let mut applies = false;
+    let mut compiled = rules.iter();
+    for rule in &config.rules {
+        if !used
+            .iter()
+            .any(|used| std::ptr::eq(*used, rule))
+        {
+            println!("  rule '{}': not used by watcher '{}'", rule.name, watcher.name);
+            continue;
+        }
+        let Some(rule) = compiled.next() else {
+            break;
+        };
+
+        let mut lines = Vec::new();
+        let matched = trace_patterns(rule, &full, &filename, is_dir, &mut lines);
+        let content = trace_content(rule, path, is_dir, &mut lines);
+
+        let verdict = match (matched, content, claimed_by) {
+            (None, ..) => "does not apply: no pattern matches".to_string(),
+            (Some(_), Some(false), _) => "does not apply: the content condition fails".to_string(),
+            (Some(_), _, Some(earlier)) => {
+                format!("matches, but is not reached: rule '{earlier}' already handles the file")
+            },
+            (Some(_), None, None) => "applies if its content condition holds".to_string(),
+            (Some(_), Some(true), None) => "applies".to_string(),
+        };
+        println!("  rule '{}': {verdict}", rule.config.name);
+        for line in lines {
+            println!("    {line}");
+        }
+
+        let Some((pattern, subject)) = matched else {
+            continue;
+        };
+        if content == Some(false) || claimed_by.is_some() {
+            continue;
+        }
+        let ctx = TemplateContext { path, pattern, subject };
+        println!("    action:      {}", rule.action_name());
+        println!("    destination: {}", engine::expand_destination(rule, &ctx));
+        println!("    name:        {}", engine::target_name(rule, &ctx).unwrap_or_default());
+
+        applies = true;
+        // Without `match_all`, only the first matching rule applies.
+        if !watcher.match_all() || rule.config.stop {
+            claimed_by = Some(&rule.config.name);
+        }
+    }
+
+    if config.rules.is_empty() {
+        println!("  no rules are configured");
+    }
+    Ok(applies)
+}
+
+/// Returns why the watcher would ignore `path` before looking at any rule.
+/// `root` is the watched directory holding `path`, if any.
+fn ignored_because(
+    watcher: &WatcherConfig,
+    rules: &[CompiledRule],
+    path: &Path,
+    root: Option<&Path>,
+) -> Vec<String> {
+    let mut notes = Vec::new();
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+    match root {
+        None => notes.push(
+            "not inside any watched directory; checked against the rules of the first watcher"
+                .to_string(),
+        ),
+        Some(root) if !watcher.recursive && absolute.parent() != Some(root) => {
+            notes.push(format!(
+                "in a subdirectory of {}, which is not watched recursively",
+                root.display()
+            ));
+        },
+        Some(_) => {},
+    }
+    if !path.exists() {
+        notes.push("does not exist; content conditions cannot be checked".to_string());
+    }
+    if transfer::is_temp(path) {
+        notes.push("is a temporary copy written by rocas".to_string());
+    }
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str());
+    for partial in &watcher.partial_download_extensions {
+        if extension.is_some_and(|ext| ext.eq_ignore_ascii_case(partial)) {
+            notes.push(format!("is an in-progress download (.{partial})"));
+        }
+        let mut sibling = path.as_os_str().to_os_string();
+        sibling.push(".");
+        sibling.push(partial);
+        if Path::new(&sibling).exists() {
+            notes.push(format!(
+                "waits for its download to finish ({} exists)",
+                Path::new(&sibling).display()
+            ));
+        }
+    }
+
+    // Files in a destination inside the watched directory are not matched
+    // again.
+    if let Some(root) = root {
+        for rule in rules
+            .iter()
+            .filter(|rule| rule.remote.is_none() && !rule.archive)
+        {
+            let Ok(dest) = std::path::absolute(engine::static_dir(&rule.config.destination)) else {
+                continue;
+            };
+            if dest != *root && dest.starts_with(root) && absolute.starts_with(&dest) {
+                notes.push(format!(
+                    "is inside the destination of rule '{}', which is ignored",
+                    rule.config.name
+                ));
+            }
+        }
+    }
+
+    if watcher.dry_run {
+        notes.push("dry_run is on, so matches are only logged".to_string());
+    }
+    notes
+}
+
+/// Describes how each pattern of `rule` evaluates `path` and returns the
+/// first that matches, as the engine would use it.
+fn trace_patterns<'r, 'p>(
+    rule: &'r CompiledRule,
+    full: &'p str,
+    filename: &'p str,
+    is_dir: bool,
+    lines: &mut Vec<String>,
+) -> Option<(&'r Pattern, &'p str)> {
+    let mut matched = None;
+    for pattern in &rule.patterns {
+        let subject = pattern.subject(full, filename);
+        let outcome = if pattern.is_dir() != is_dir {
+            if pattern.is_dir() { "only matches directories" } else { "only matches files" }
+        } else if pattern.matches(subject) {
+            matched.get_or_insert((pattern, subject));
+            "matches"
+        } else {
+            "no match"
+        };
+        lines.push(format!("pattern '{}' against '{subject}': {outcome}", pattern.raw));
+    }
+    matched
+}
+
+/// Describes the content condition of `rule` for `path`. Returns whether it
+/// holds, or `None` if it cannot be checked.
+fn trace_content(
+    rule: &CompiledRule,
+    path: &Path,
+    is_dir: bool,
+    lines: &mut Vec<String>,
+) -> Option<bool> {
+    let (Some(contains), Some(raw)) = (&rule.contains, &rule.config.contains) else {
+        return Some(true);
+    };
+
+    // Content conditions only apply to files.
+    if is_dir {
+        lines.push(format!("contains '{raw}': fails, as directories have no content"));
+        return Some(false);
+    }
+    let within = format!("in the first {} KB", rule.config.contains_max_size_kb);
+    match contains.matches(path) {
+        Ok(true) => {
+            lines.push(format!("contains '{raw}': found {within}"));
+            Some(true)
+        },
+        Ok(false) => {
+            lines.push(format!("contains '{raw}': not found {within}"));
+            Some(false)
+        },
+        Err(_) if !path.exists() => {
+            lines.push(format!("contains '{raw}': not checked, as the file does not exist"));
+            None
+        },
+        Err(e) => {
+            lines.push(format!("contains '{raw}': fails, as the file cannot be read: {e}"));
+            Some(false)
+        },
+    }
+}
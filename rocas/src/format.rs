@@ -0,0 +1,100 @@
+//! Config file formats.
+//!
+//! The config is TOML, but the same settings can be written as YAML or JSON
+//! for tooling that generates them; the format follows the file extension.
+//! YAML and JSON documents are converted to the TOML document they describe,
+//! with `null` values dropped as TOML has none, so every format goes through
+//! the same loader.
+
+use std::path::Path;
+
+use toml::{Table, Value};
+
+/// A config file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+/// Error returned when a config file cannot be parsed.
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("invalid TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("invalid YAML: {0}")]
+    Yaml(#[from] serde_yaml_ng::Error),
+
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("cannot be represented as a config: {0}")]
+    Convert(#[from] toml::ser::Error),
+
+    #[error("the top level must be a mapping of sections")]
+    NotATable,
+}
+
+impl Format {
+    /// Detects the format of `path` from its extension. Anything other than
+    /// `.yaml`, `.yml` or `.json` is TOML.
+    pub fn of(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("json") => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+
+    /// Parses `text` in this format into a TOML document.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError`] if `text` is not valid in this format or does
+    /// not describe a table.
+    pub fn parse(self, text: &str) -> Result<Table, ParseError> {
+        let mut value: serde_json::Value = match self {
+            Self::Toml => return Ok(text.parse()?),
+            Self::Yaml => serde_yaml_ng::from_str(text)?,
+            Self::Json => serde_json::from_str(text)?,
+        };
+        remove_nulls(&mut value);
+
+        match Value::try_from(value)? {
+            Value::Table(table) => Ok(table),
+            _ => Err(ParseError::NotATable),
+        }
+    }
+
+    /// Writes `table` in this format. Used for files rocas generates, so
+    /// the document always has a representation.
+    pub fn render(self, table: &Table) -> String {
+        match self {
+            Self::Toml => table.to_string(),
+            Self::Yaml => serde_yaml_ng::to_string(table).unwrap_or_default(),
+            Self::Json => serde_json::to_string_pretty(table).unwrap_or_default() + "\n",
+        }
+    }
+}
+
+/// Removes `null` values, which mean "not set", from maps and arrays.
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(remove_nulls);
+        },
+        serde_json::Value::Array(items) => {
+            items.retain(|v| !v.is_null());
+            items.iter_mut().for_each(remove_nulls);
+        },
+        _ => {},
+    }
+}
@@ -0,0 +1,381 @@
+//! The history of moves, for `rocas undo`.
+//!
+//! Every file or directory rocas moves is appended to the `history` file in
+//! the rocas config directory as `<unix seconds>` TAB `<from>` TAB `<to>`.
+//! The paths are escaped so any name survives the round trip: `%`, tabs and
+//! line breaks are written as `%XX`, as are bytes that are not UTF-8 on Unix,
+//! and unpaired surrogates on Windows as `%uXXXX`.
+//! `rocas undo` moves the latest entries back, newest first, and removes them
+//! from the history. Links and copies keep their source, and uploaded,
+//! archived and encrypted files cannot be put back as they were, so only
+//! moves are recorded.
+
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{self, VerifyMode};
+use crate::{AppError, status, transfer};
+
+pub const HISTORY_NAME: &str = "history";
+
+/// How many moves the history keeps; older ones are dropped on startup.
+const MAX_ENTRIES: usize = 10_000;
+
+/// Serializes appends from the workers.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// A recorded move.
+struct Entry {
+    at: u64,
+    from: PathBuf,
+    to: PathBuf,
+}
+
+/// Which moves `rocas undo` puts back.
+#[derive(Debug, Clone, Copy)]
+pub enum Selection {
+    /// The latest `n` moves.
+    Last(usize),
+    /// The moves made within this long before now.
+    Since(Duration),
+}
+
+/// Records that `from` was moved to `to`.
+pub fn record(from: &Path, to: &Path) {
+    let at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let line = format!("{at}\t{}\t{}\n", encode(from), encode(to));
+
+    let _guard = LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let path = path();
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+        })
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+    if let Err(e) = result {
+        warn!("Could not record the move of '{}' in '{}': {e}", from.display(), path.display());
+    }
+}
+
+/// Returns the latest `n` moves as their source and destination, newest
+/// first.
+#[cfg(feature = "tray")]
+pub fn recent(n: usize) -> Vec<(PathBuf, PathBuf)> {
+    let entries = load();
+    entries[entries.len().saturating_sub(n)..]
+        .iter()
+        .rev()
+        .map(|entry| (entry.from.clone(), entry.to.clone()))
+        .collect()
+}
+
+/// Returns when the history was last written, or `None` if there is none.
+#[cfg(feature = "tray")]
+pub fn changed() -> Option<SystemTime> {
+    std::fs::metadata(path())
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Drops all but the latest [`MAX_ENTRIES`] moves.
+pub fn prune() {
+    let entries = load();
+    if entries.len() > MAX_ENTRIES
+        && let Err(e) = save(&entries[entries.len() - MAX_ENTRIES..])
+    {
+        warn!("Could not prune the history '{}': {e}", path().display());
+    }
+}
+
+/// Moves the files of the selected moves back to where they were, newest
+/// first. A file is left where it is when something else now has its
+/// original path. Returns `true` if every selected file was moved back.
+///
+/// # Errors
+///
+/// Returns [`AppError::Running`] while rocas is running, which would move the
+/// files again, or an I/O error if the history cannot be written.
+pub fn undo(selection: Selection) -> Result<bool, AppError> {
+    if status::is_running() {
+        return Err(AppError::Running("would move the files back again"));
+    }
+
+    let mut entries = load();
+    let selected = match selection {
+        Selection::Last(n) => entries.len().saturating_sub(n),
+        Selection::Since(age) => {
+            let cutoff = SystemTime::now()
+                .checked_sub(age)
+                .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |cutoff| cutoff.as_secs());
+            entries.partition_point(|entry| entry.at < cutoff)
+        },
+    };
+    if selected == entries.len() {
+        println!("Nothing to undo.");
+        return Ok(true);
+    }
+
+    let opts = transfer::Options {
+        verify: VerifyMode::Size,
+        preserve_metadata: true,
+        progress_threshold: None,
+        bandwidth: None,
+    };
+    let mut kept = Vec::new();
+    let mut all_restored = true;
+    for entry in entries.drain(selected..).rev() {
+        if entry.to.symlink_metadata().is_err() {
+            println!("'{}' is no longer there; skipping.", entry.to.display());
+            all_restored = false;
+            continue;
+        }
+        if entry.from.symlink_metadata().is_ok() {
+            println!(
+                "'{}' exists again; leaving '{}' where it is.",
+                entry.from.display(),
+                entry.to.display()
+            );
+            all_restored = false;
+            kept.push(entry);
+            continue;
+        }
+
+        let moved = entry
+            .from
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| transfer::move_path(&entry.to, &entry.from, &opts));
+        match moved {
+            Ok(()) => println!("{} → {}", entry.to.display(), entry.from.display()),
+            Err(e) => {
+                println!("Could not move '{}' back: {e}", entry.to.display());
+                all_restored = false;
+                kept.push(entry);
+            },
+        }
+    }
+
+    // Moves that could not be undone stay in the history, in their order.
+    kept.reverse();
+    entries.append(&mut kept);
+    save(&entries)?;
+
+    Ok(all_restored)
+}
+
+/// Parses an age like `30s`, `10m`, `2h` or `1d`.
+///
+/// # Errors
+///
+/// Returns a message for clap if `raw` is not a number followed by one of
+/// those units.
+pub fn parse_age(raw: &str) -> Result<Duration, String> {
+    let invalid = || format!("'{raw}' is not an age like 30s, 10m, 2h or 1d");
+    let unit = raw
+        .char_indices()
+        .last()
+        .ok_or_else(invalid)?;
+    let count: u64 = raw[..unit.0]
+        .parse()
+        .map_err(|_| invalid())?;
+    let secs = match unit.1 {
+        's' => 1,
+        'm' => 60,
+        'h' => 3_600,
+        'd' => 86_400,
+        _ => return Err(invalid()),
+    };
+
+    Ok(Duration::from_secs(count.saturating_mul(secs)))
+}
+
+fn path() -> PathBuf {
+    config::rocas_dir().join(HISTORY_NAME)
+}
+
+/// Returns the recorded moves, oldest first. Malformed lines are skipped; a
+/// missing history is empty.
+fn load() -> Vec<Entry> {
+    let contents = match std::fs::read_to_string(path()) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            warn!("Could not read the history '{}': {e}", path().display());
+            return Vec::new();
+        },
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            Some(Entry {
+                at: fields.next()?.parse().ok()?,
+                from: decode(fields.next()?)?,
+                to: decode(fields.next()?)?,
+            })
+        })
+        .collect()
+}
+
+/// Replaces the history with `entries`.
+fn save(entries: &[Entry]) -> io::Result<()> {
+    let mut contents = String::new();
+    for entry in entries {
+        let _ = writeln!(contents, "{}\t{}\t{}", entry.at, encode(&entry.from), encode(&entry.to));
+    }
+
+    let _guard = LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let path = path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, contents)?;
+    std::fs::rename(&temp, &path)
+}
+
+/// Writes `path` as a history field; see the module docs for the escapes.
+#[cfg(unix)]
+fn encode(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut encoded = String::new();
+    for chunk in path
+        .as_os_str()
+        .as_bytes()
+        .utf8_chunks()
+    {
+        push_escaped(&mut encoded, chunk.valid());
+        for byte in chunk.invalid() {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// Writes `path` as a history field; see the module docs for the escapes.
+#[cfg(windows)]
+fn encode(path: &Path) -> String {
+    use std::os::windows::ffi::OsStrExt;
+
+    let mut encoded = String::new();
+    for unit in char::decode_utf16(path.as_os_str().encode_wide()) {
+        match unit {
+            Ok(c) => push_escaped(&mut encoded, c.encode_utf8(&mut [0; 4])),
+            Err(e) => {
+                let _ = write!(encoded, "%u{:04X}", e.unpaired_surrogate());
+            },
+        }
+    }
+    encoded
+}
+
+/// Appends `text` to `encoded` with `%`, tabs and line breaks escaped.
+fn push_escaped(encoded: &mut String, text: &str) {
+    for c in text.chars() {
+        if matches!(c, '%' | '\t' | '\n' | '\r') {
+            let _ = write!(encoded, "%{:02X}", u32::from(c));
+        } else {
+            encoded.push(c);
+        }
+    }
+}
+
+/// Reads a history field written by [`encode`]. Returns `None` for a
+/// malformed escape.
+#[cfg(unix)]
+fn decode(field: &str) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(at) = rest.find('%') {
+        bytes.extend_from_slice(rest[..at].as_bytes());
+        bytes.push(u8::from_str_radix(rest.get(at + 1..at + 3)?, 16).ok()?);
+        rest = &rest[at + 3..];
+    }
+    bytes.extend_from_slice(rest.as_bytes());
+
+    Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+/// Reads a history field written by [`encode`]. Returns `None` for a
+/// malformed escape.
+#[cfg(windows)]
+fn decode(field: &str) -> Option<PathBuf> {
+    use std::os::windows::ffi::OsStringExt;
+
+    let mut wide = Vec::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(at) = rest.find('%') {
+        wide.extend(rest[..at].encode_utf16());
+        let digits = if rest[at + 1..].starts_with('u') { at + 2..at + 6 } else { at + 1..at + 3 };
+        wide.push(u16::from_str_radix(rest.get(digits.clone())?, 16).ok()?);
+        rest = &rest[digits.end..];
+    }
+    wide.extend(rest.encode_utf16());
+
+    Some(PathBuf::from(std::ffi::OsString::from_wide(&wide)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{decode, encode};
+
+    #[test]
+    fn escapes_separators_and_percent_signs() {
+        let path = Path::new("/home/me/a\tb\nc\rd 100%.txt");
+        assert_eq!(encode(path), "/home/me/a%09b%0Ac%0Dd 100%25.txt");
+        assert_eq!(decode(&encode(path)), Some(path.to_path_buf()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_names_that_are_not_utf8() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let path = PathBuf::from(std::ffi::OsString::from_vec(b"/tmp/caf\xE9.txt".to_vec()));
+        assert_eq!(encode(&path), "/tmp/caf%E9.txt");
+        assert_eq!(decode(&encode(&path)), Some(path));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn keeps_unpaired_surrogates() {
+        use std::os::windows::ffi::OsStringExt;
+
+        let wide: Vec<u16> = r"C:\caf"
+            .encode_utf16()
+            .chain([0xD800])
+            .collect();
+        let path = PathBuf::from(std::ffi::OsString::from_wide(&wide));
+        assert_eq!(encode(&path), r"C:\caf%uD800");
+        assert_eq!(decode(&encode(&path)), Some(path));
+    }
+
+    #[test]
+    fn rejects_malformed_escapes() {
+        assert_eq!(decode("/tmp/50%"), None);
+        assert_eq!(decode("/tmp/%zz"), None);
+    }
+}
@@ -0,0 +1,314 @@
+//! `include = ["rules/*.toml"]`: splitting the rules across several files.
+//!
+//! Included files may only contain `[[rules]]`, further includes and their
+//! `version`, in any [`Format`]. Paths
+//! are relative to the file that includes them, and a `*` or `?` in the file
+//! name picks every matching file in name order, so the result never
+//! depends on the order the filesystem lists them in. Included rules come
+//! before the including file's own, so a catch-all rule in the main config
+//! still runs last. A file that includes itself, directly or through others,
+//! is an error; a file included twice is only read once.
+//!
+//! forgeconf reads a single TOML file, so when the config has includes,
+//! [`aliases`], [`defaults`] or [`when`] conditions, several `[[watcher]]`
+//! entries, needs [`migrate`]ing or is written in another [`Format`], the
+//! merged TOML document is written to the rocas config directory and loaded
+//! from there. Its name holds a hash of the config's path, so rocas
+//! processes loading different configs never load each other's.
+
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use sha2::{Digest, Sha256};
+use toml::{Table, Value};
+
+use crate::aliases::{self, AliasError};
+use crate::defaults::{self, DefaultsError};
+use crate::format::{Format, ParseError};
+use crate::interpolate::{self, Unset, UnsetVariable};
+use crate::migrate::{self, MigrateError};
+use crate::pattern::{Pattern, PatternError};
+use crate::when::{self, WhenError};
+use crate::{config, tilde};
+
+const INCLUDE_KEY: &str = "include";
+/// The start of the names of merged configs, followed by the hash of the
+/// path of the config they were merged from.
+pub const MERGED_PREFIX: &str = "merged-config";
+
+/// The merged config written by the last [`prepare`], if the config needed
+/// one.
+static MERGED: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Error returned when the includes of a config cannot be resolved.
+#[derive(Debug, thiserror::Error)]
+pub enum IncludeError {
+    #[error("cannot read '{}': {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+
+    #[error("cannot parse '{}': {source}", path.display())]
+    Parse { path: PathBuf, source: ParseError },
+
+    #[error("'{}' sets '{key}'; included files may only contain rules", path.display())]
+    NotAllowed { path: PathBuf, key: String },
+
+    #[error("'include' in '{}' must be a path or a list of paths", .0.display())]
+    InvalidInclude(PathBuf),
+
+    #[error("include cycle: {}", format_cycle(.0))]
+    Cycle(Vec<PathBuf>),
+
+    #[error(transparent)]
+    Pattern(#[from] PatternError),
+
+    #[error(transparent)]
+    Interpolate(#[from] UnsetVariable),
+
+    #[error(transparent)]
+    Migrate(#[from] MigrateError),
+
+    #[error(transparent)]
+    Aliases(#[from] AliasError),
+
+    #[error(transparent)]
+    Defaults(#[from] DefaultsError),
+
+    #[error(transparent)]
+    When(#[from] WhenError),
+
+    #[error("cannot write the merged config '{}': {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+}
+
+fn format_cycle(cycle: &[PathBuf]) -> String {
+    cycle
+        .iter()
+        .map(|path| format!("'{}'", path.display()))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Returns the file forgeconf should load: the merged config if [`prepare`]
+/// wrote one, otherwise the config file itself.
+#[allow(dead_code, reason = "only called from the loader `#[forgeconf]` generates")]
+pub fn load_path() -> String {
+    MERGED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_deref()
+        .and_then(Path::to_str)
+        .map_or_else(config::config_path, str::to_string)
+}
+
+/// Resolves the includes, [`aliases`], [`defaults`] and [`when`] conditions
+/// of the config at `path`, if it has any, moves `[[watcher]]` entries to
+/// [`config::WATCHERS_KEY`], and writes the merged TOML document for
+/// [`load_path`]. A TOML config without any and a missing config are left to
+/// the loader.
+///
+/// # Errors
+///
+/// Returns [`IncludeError`] if a file cannot be read or parsed, includes
+/// something other than rules, or is part of an include cycle.
+pub fn prepare(path: &Path) -> Result<(), IncludeError> {
+    // A config loaded again, on a reload, may no longer need merging.
+    *MERGED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = None;
+    if !path.is_file() {
+        return Ok(());
+    }
+
+    let mut merged = resolve(path)?;
+    if merged
+        .get("watcher")
+        .is_some_and(Value::is_array)
+        && let Some(watchers) = merged.remove("watcher")
+    {
+        merged.insert(config::WATCHERS_KEY.to_string(), watchers);
+    }
+    if Format::of(path) == Format::Toml && merged == read(path)? {
+        return Ok(());
+    }
+    let target = merged_path(path);
+    write(&target, &Format::Toml.render(&merged))
+        .map_err(|source| IncludeError::Write { path: target.clone(), source })?;
+
+    *MERGED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(target);
+    Ok(())
+}
+
+/// Returns where the config merged from the config at `path` is written.
+fn merged_path(path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let digest = Sha256::new()
+        .chain_update(path.as_os_str().as_encoded_bytes())
+        .finalize();
+    config::rocas_dir().join(format!("{MERGED_PREFIX}-{}.toml", &format!("{digest:x}")[..16]))
+}
+
+/// Reads the config at `path` with every file [`migrate::upgrade`]d, the
+/// rules of included files merged into its `rules`, the `include` key
+/// removed, and [`aliases::apply`], [`defaults::apply`] and [`when::apply`]
+/// applied.
+///
+/// # Errors
+///
+/// See [`prepare`].
+pub fn resolve(path: &Path) -> Result<Table, IncludeError> {
+    let mut table = read(path)?;
+    migrate::upgrade(&mut table)?;
+    let canonical = canonicalize(path)?;
+    let mut resolver = Resolver {
+        stack: vec![canonical.clone()],
+        seen: HashSet::from([canonical]),
+        rules: Vec::new(),
+    };
+
+    resolver.include(path, &mut table)?;
+    let mut rules = resolver.rules;
+    match table.remove("rules") {
+        Some(Value::Array(own)) => rules.extend(own),
+        // Leave a malformed `rules` for the loader to report.
+        Some(other) if rules.is_empty() => {
+            table.insert("rules".to_string(), other);
+        },
+        _ => {},
+    }
+    if !rules.is_empty() {
+        table.insert("rules".to_string(), Value::Array(rules));
+    }
+
+    aliases::apply(&mut table)?;
+    defaults::apply(&mut table)?;
+    when::apply(&mut table)?;
+    Ok(table)
+}
+
+struct Resolver {
+    /// The files being included, outermost first.
+    stack: Vec<PathBuf>,
+    seen: HashSet<PathBuf>,
+    rules: Vec<Value>,
+}
+
+impl Resolver {
+    /// Appends the rules of the files `table` includes, removing its
+    /// `include` key. `path` is the file `table` was read from.
+    fn include(&mut self, path: &Path, table: &mut Table) -> Result<(), IncludeError> {
+        let patterns = match table.remove(INCLUDE_KEY) {
+            None => return Ok(()),
+            Some(Value::String(pattern)) => vec![pattern],
+            Some(Value::Array(items)) => items
+                .into_iter()
+                .map(|item| match item {
+                    Value::String(pattern) => Ok(pattern),
+                    _ => Err(IncludeError::InvalidInclude(path.to_path_buf())),
+                })
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err(IncludeError::InvalidInclude(path.to_path_buf())),
+        };
+        let base = path.parent().unwrap_or(Path::new("."));
+
+        for pattern in patterns {
+            let pattern = interpolate::expand(&pattern, Unset::Error)?;
+            let pattern = tilde::expand(&pattern);
+            for file in expand(base, &pattern)? {
+                let canonical = canonicalize(&file)?;
+                if let Some(start) = self
+                    .stack
+                    .iter()
+                    .position(|p| *p == canonical)
+                {
+                    let mut cycle = self.stack[start..].to_vec();
+                    cycle.push(canonical);
+                    return Err(IncludeError::Cycle(cycle));
+                }
+                if !self.seen.insert(canonical.clone()) {
+                    continue;
+                }
+
+                let mut included = read(&file)?;
+                migrate::upgrade(&mut included)?;
+                if let Some(key) = included.keys().find(|key| {
+                    !["rules", INCLUDE_KEY, migrate::VERSION_KEY].contains(&key.as_str())
+                }) {
+                    return Err(IncludeError::NotAllowed { path: file, key: key.clone() });
+                }
+
+                self.stack.push(canonical);
+                self.include(&file, &mut included)?;
+                self.stack.pop();
+
+                if let Some(Value::Array(rules)) = included.remove("rules") {
+                    self.rules.extend(rules);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the files `pattern` names, relative to `base`. Wildcards are only
+/// supported in the file name; a pattern without any names one file, which
+/// must exist.
+fn expand(base: &Path, pattern: &str) -> Result<Vec<PathBuf>, IncludeError> {
+    let path = base.join(pattern);
+    let Some(name) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| name.contains(['*', '?']))
+    else {
+        return Ok(vec![path]);
+    };
+
+    let glob = Pattern::new(name)?;
+    let dir = path.parent().unwrap_or(base);
+    let entries = std::fs::read_dir(dir)
+        .map_err(|source| IncludeError::Io { path: dir.to_path_buf(), source })?;
+
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|file| {
+            file.is_file()
+                && file
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| glob.matches(name))
+        })
+        .collect();
+    files.sort();
+
+    Ok(files)
+}
+
+fn read(path: &Path) -> Result<Table, IncludeError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|source| IncludeError::Io { path: path.to_path_buf(), source })?;
+    Format::of(path)
+        .parse(&text)
+        .map_err(|source| IncludeError::Parse { path: path.to_path_buf(), source })
+}
+
+fn canonicalize(path: &Path) -> Result<PathBuf, IncludeError> {
+    path.canonicalize()
+        .map_err(|source| IncludeError::Io { path: path.to_path_buf(), source })
+}
+
+/// Replaces `path` with `contents` through a temporary file, so a config
+/// loaded at the same time is never half-written.
+fn write(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Another rocas loading the same config may be writing it too.
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&temp, contents)?;
+    std::fs::rename(&temp, path)
+}
@@ -0,0 +1,209 @@
+//! `rocas init`: writes a commented starter config.
+//!
+//! The template in `assets/starter.toml` has example rules for images,
+//! documents, archives and installers. Its `@NAME@` placeholders are filled
+//! with this machine's folders, so the config works without edits. A YAML or
+//! JSON config path gets the same settings in that format, without the
+//! comments.
+//!
+//! With `--interactive`, rocas asks which folder to watch, which of the
+//! example rules to keep and whether to start on boot before writing it.
+
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::format::Format;
+use crate::{AppError, autostart, config, service, tilde};
+
+const TEMPLATE: &str = include_str!("../assets/starter.toml");
+
+/// What separates the rules of the template from each other and from the
+/// settings above them.
+const RULE_HEADER: &str = "\n[[rules]]\n";
+
+/// Writes the starter config to the platform config path and returns that
+/// path, or `None` if the interactive setup was cancelled.
+///
+/// # Errors
+///
+/// Returns [`AppError::ConfigExists`] if a config is already there and
+/// neither `force` nor `interactive` is set, an I/O error if the file cannot
+/// be written or stdin closes during the setup, or an auto-launch error if
+/// starting on boot cannot be enabled.
+pub fn run(force: bool, interactive: bool) -> Result<Option<PathBuf>, AppError> {
+    let path = PathBuf::from(config::config_path());
+    if path.exists() && !force {
+        if !interactive {
+            return Err(AppError::ConfigExists(path));
+        }
+        if !confirm(&format!("'{}' already exists. Replace it?", path.display()), false)? {
+            return Ok(None);
+        }
+    }
+
+    let downloads = folder(dirs::download_dir(), "Downloads");
+    let (text, autostart) =
+        if interactive { wizard(&downloads)? } else { (render(&downloads), false) };
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let contents = match Format::of(&path) {
+        Format::Toml => text,
+        format => format.render(
+            &Format::Toml
+                .parse(&text)
+                .expect("the starter config is valid TOML"),
+        ),
+    };
+    std::fs::write(&path, contents)?;
+
+    if autostart {
+        let done =
+            service::install(autostart::Mode::preferred(), false, &autostart::Launch::default())?;
+        println!("{done}");
+    }
+
+    Ok(Some(path))
+}
+
+/// Asks for the watched folder, the example rules to keep and autostart.
+/// Returns the config text and whether to start on boot.
+fn wizard(downloads: &Path) -> io::Result<(String, bool)> {
+    let watch_path = loop {
+        let answer = ask("Which folder should rocas watch?", &downloads.to_string_lossy())?;
+        let path = PathBuf::from(tilde::expand(&answer).as_ref());
+        if path.is_dir() {
+            break path;
+        }
+        println!("'{answer}' is not a folder.");
+    };
+
+    let text = render(&watch_path);
+    let mut parts = text.split(RULE_HEADER);
+    let mut config = parts
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    for rule in parts {
+        let (name, patterns, destination) = describe(rule);
+        if confirm(&format!("Move {name} ({patterns}) to {destination}?"), true)? {
+            config.push_str(RULE_HEADER);
+            config.push_str(rule);
+        }
+    }
+
+    let autostart = confirm("Start rocas when you log in?", false)?;
+    Ok((config, autostart))
+}
+
+/// Returns the name, patterns and destination of a rule of the template.
+fn describe(rule: &str) -> (String, String, String) {
+    let table = Format::Toml
+        .parse(rule)
+        .expect("the starter config is valid TOML");
+    let string = |key: &str| {
+        table
+            .get(key)
+            .and_then(|value| value.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let patterns = table
+        .get("patterns")
+        .and_then(|value| value.as_array())
+        .map(|patterns| {
+            patterns
+                .iter()
+                .filter_map(|pattern| pattern.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+
+    (string("name"), patterns, string("destination"))
+}
+
+/// Prints `question` with its default and returns the answer, or `default`
+/// for an empty answer.
+fn ask(question: &str, default: &str) -> io::Result<String> {
+    let answer = read_answer(&format!("{question} [{default}] "))?;
+    Ok(if answer.is_empty() { default.to_string() } else { answer })
+}
+
+/// Asks a yes/no question until the answer is one. An empty answer is
+/// `default`.
+pub fn confirm(question: &str, default: bool) -> io::Result<bool> {
+    let hint = if default { "Y/n" } else { "y/N" };
+    loop {
+        match read_answer(&format!("{question} [{hint}] "))?
+            .to_lowercase()
+            .as_str()
+        {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer yes or no."),
+        }
+    }
+}
+
+/// Prints `prompt` and reads a trimmed line from stdin. The end of input is
+/// an error, so a closed stdin cannot make a question repeat forever.
+fn read_answer(prompt: &str) -> io::Result<String> {
+    print!("{prompt}");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if io::stdin()
+        .lock()
+        .read_line(&mut answer)?
+        == 0
+    {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "cancelled"));
+    }
+    Ok(answer.trim().to_string())
+}
+
+fn folder(dir: Option<PathBuf>, name: &str) -> PathBuf {
+    dir.unwrap_or_else(|| {
+        dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(name)
+    })
+}
+
+/// Fills the template's placeholders with this machine's folders.
+fn render(watch_path: &Path) -> String {
+    let downloads = folder(dirs::download_dir(), "Downloads");
+
+    [
+        ("@WATCH_PATH@", watch_path.to_path_buf()),
+        ("@DOWNLOADS@", downloads.clone()),
+        ("@DESKTOP@", folder(dirs::desktop_dir(), "Desktop")),
+        ("@PICTURES@", folder(dirs::picture_dir(), "Pictures")),
+        ("@DOCUMENTS@", folder(dirs::document_dir(), "Documents")),
+        ("@ARCHIVES@", downloads.join("Archives")),
+        ("@INSTALLERS@", downloads.join("Installers")),
+        ("@LOG_FILE@", PathBuf::from(config::logs_path())),
+    ]
+    .iter()
+    .fold(TEMPLATE.to_owned(), |text, (placeholder, path)| {
+        text.replace(placeholder, &toml_string(path))
+    })
+}
+
+/// Quotes `path` as a TOML string. Literal strings keep Windows backslashes
+/// readable; a path containing `'` needs an escaped basic string.
+fn toml_string(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if path.contains('\'') {
+        format!(
+            "\"{}\"",
+            path.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+        )
+    } else {
+        format!("'{path}'")
+    }
+}
@@ -0,0 +1,125 @@
+//! Environment variable references in config values.
+//!
+//! `${VAR}` is replaced by the value of `VAR`, and `${VAR:-default}` falls
+//! back to `default` when `VAR` is unset or empty, so one config can be
+//! shared between machines (`watch_path = "${HOME}/Downloads"`).
+//!
+//! Destinations also use `${name}` for regex capture groups. There, a
+//! reference to an unset variable that names a capture group of the rule is
+//! kept for the capture expansion, and `${1}`-style numeric references are
+//! never looked up. `$$` is kept as it
+//! is, so `$${VAR}` always stays literal.
+
+/// Error returned when a config value references a missing variable.
+#[derive(Debug, thiserror::Error)]
+#[error("environment variable '{name}' used in '{value}' is not set")]
+pub struct UnsetVariable {
+    pub name: String,
+    pub value: String,
+}
+
+/// What to do with a reference to an unset variable without a default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unset<'a> {
+    Error,
+    /// Leave references to these names in place, e.g. the named regex
+    /// capture groups of a rule; other unset names are still an error.
+    Keep(&'a [String]),
+}
+
+/// Parses a `KEY=VALUE` assignment for `--env`.
+///
+/// # Errors
+///
+/// Returns a message for clap if `raw` has no `=` or an empty key.
+pub fn parse_assignment(raw: &str) -> Result<(String, String), String> {
+    match raw.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("'{raw}' is not a variable assignment like KEY=VALUE")),
+    }
+}
+
+/// Replaces the variable references in `value`.
+///
+/// # Errors
+///
+/// Returns [`UnsetVariable`] for a reference to an unset variable without a
+/// default, unless `unset` keeps its name.
+pub fn expand(value: &str, unset: Unset) -> Result<String, UnsetVariable> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(idx) = rest.find('$') {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+
+        if rest.starts_with("$$") {
+            out.push_str("$$");
+            rest = &rest[2..];
+            continue;
+        }
+
+        let reference = rest
+            .strip_prefix("${")
+            .and_then(|inner| inner.find('}').map(|end| &inner[..end]));
+        let Some(reference) = reference else {
+            out.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let len = reference.len() + 3;
+
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+        if !is_variable_name(name) {
+            out.push_str(&rest[..len]);
+            rest = &rest[len..];
+            continue;
+        }
+
+        let set = std::env::var_os(name)
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string_lossy().into_owned());
+        match (set, default, unset) {
+            (Some(set), ..) => out.push_str(&set),
+            (None, Some(default), _) => out.push_str(default),
+            (None, None, Unset::Keep(kept)) if kept.iter().any(|k| k == name) => {
+                out.push_str(&rest[..len]);
+            },
+            (None, None, _) => {
+                return Err(UnsetVariable {
+                    name: name.to_owned(),
+                    value: value.to_owned(),
+                });
+            },
+        }
+        rest = &rest[len..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Expands `value` in place.
+///
+/// # Errors
+///
+/// See [`expand`].
+pub fn expand_in_place(value: &mut String, unset: Unset) -> Result<(), UnsetVariable> {
+    if value.contains("${") {
+        *value = expand(value, unset)?;
+    }
+    Ok(())
+}
+
+/// Returns `true` for names like `HOME` or `my_var`; capture group numbers
+/// like `1` are not variable names.
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -9,6 +9,17 @@
 //! [2026-03-01T22:14:24Z INFO  rocas] message
 //! ```
 //!
+//! With `log_format = "json"`, every record is a JSON object on a line of
+//! its own instead, for log collectors like Loki:
+//!
+//! ```text
+//! {"timestamp":"2026-03-01T22:14:24Z","level":"INFO","target":"rocas::engine","message":"Moved …","rule":"pdfs","source":"/home/chiko/Downloads/a.pdf","destination":"/home/chiko/Documents/a.pdf","bytes":48213}
+//! ```
+//!
+//! Records about a file carry [`Fields`] — its rule, source, destination,
+//! size and error — which the JSON format writes as keys of their own. The
+//! text format leaves them out, as the message says the same.
+//!
 //! # Colors
 //!
 //! ANSI colors are applied to the level tag when stderr is a terminal.
@@ -26,12 +37,19 @@
 //! rocas.log    → rocas.log.1
 //! rocas.log    (new, empty)
 //! ```
+//!
+//! With `log_rotate` set to `daily` or `weekly`, it also rotates on the first
+//! record of a new day or week (UTC, weeks starting on Monday), whatever the
+//! size. `log_max_age_days` deletes rotated files last written longer ago
+//! than that, on startup and whenever the log rotates.
 
 use std::fs::{File, OpenOptions, rename};
 use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Map, Value};
 
 /// Error returned by [`Logger::init`].
 #[derive(Debug, thiserror::Error)]
@@ -44,6 +62,105 @@ pub enum LoggerInitError {
 
 const RESET: &str = "\x1b[0m";
 
+/// How log records are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `[timestamp LEVEL target] message` lines.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// When the log file is rotated regardless of its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Never,
+    Daily,
+    Weekly,
+}
+
+impl Rotation {
+    /// Returns the day or week `secs` (Unix seconds) falls in. The file is
+    /// rotated when this changes between two records.
+    fn period(self, secs: u64) -> Option<u64> {
+        let days = secs / 86_400;
+        match self {
+            Self::Never => None,
+            Self::Daily => Some(days),
+            // 1970-01-01 was a Thursday; weeks start on Monday.
+            Self::Weekly => Some((days + 3) / 7),
+        }
+    }
+}
+
+/// The details of a record about a file, which the JSON format writes as
+/// keys of their own. Passed as the key-values of the record.
+#[derive(Debug, Default)]
+pub struct Fields<'a> {
+    pub rule: Option<&'a str>,
+    pub source: Option<String>,
+    pub destination: Option<String>,
+    /// Size of the file, when it is one.
+    pub bytes: Option<u64>,
+    pub error: Option<&'a str>,
+}
+
+impl log::kv::Source for Fields<'_> {
+    fn visit<'kvs>(
+        &'kvs self,
+        visitor: &mut dyn log::kv::VisitSource<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        let strings = [
+            ("rule", self.rule),
+            ("source", self.source.as_deref()),
+            ("destination", self.destination.as_deref()),
+        ];
+        for (key, value) in strings {
+            if let Some(value) = value {
+                visitor.visit_pair(key.into(), value.into())?;
+            }
+        }
+        if let Some(bytes) = self.bytes {
+            visitor.visit_pair("bytes".into(), bytes.into())?;
+        }
+        if let Some(error) = self.error {
+            visitor.visit_pair("error".into(), error.into())?;
+        }
+        Ok(())
+    }
+}
+
+/// Logs `args` with `fields` as the key-values of the record.
+pub fn log_fields(level: log::Level, target: &str, fields: &Fields, args: std::fmt::Arguments) {
+    if level <= log::max_level() {
+        log::logger().log(
+            &log::Record::builder()
+                .level(level)
+                .target(target)
+                .key_values(fields)
+                .args(args)
+                .build(),
+        );
+    }
+}
+
+/// Collects the key-values of a record into a JSON object.
+struct JsonFields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        let value = value
+            .to_u64()
+            .map_or_else(|| Value::String(value.to_string()), Value::from);
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
 /// Format a single log line the same way the logger writes to stderr —
 /// including ANSI color on the level tag when stderr is a tty.
 ///
@@ -115,9 +232,10 @@ fn enable_ansi_on_windows() {
 }
 
 /// Logger that writes to stderr (with optional ANSI colors) and an optional
-/// rotating log file (always plain text).
+/// rotating log file (always without colors).
 pub struct Logger {
     level: log::LevelFilter,
+    format: Format,
     /// Cached at init time — does not change while the process is running.
     use_color: bool,
     file: Option<Mutex<FileLogger>>,
@@ -127,6 +245,7 @@ impl Logger {
     /// Build and globally register the logger.
     ///
     /// `log_path` — `None` disables file logging (stderr only).
+    /// `max_age_days` — `0` keeps rotated files however old they are.
     ///
     /// # Errors
     ///
@@ -135,23 +254,29 @@ impl Logger {
     /// or the parent directory cannot be created.
     pub fn init(
         level: log::LevelFilter,
+        format: Format,
         log_path: Option<PathBuf>,
         max_size_mb: u64,
         keep_files: u32,
+        rotation: Rotation,
+        max_age_days: u64,
     ) -> Result<(), LoggerInitError> {
         enable_ansi_on_windows();
-        let use_color = stderr_is_tty();
+        let use_color = format == Format::Text && stderr_is_tty();
 
         let file = log_path
             .map(|path| {
                 if let Some(parent) = path.parent() {
                     std::fs::create_dir_all(parent)?;
                 }
-                FileLogger::new(path, max_size_mb * 1024 * 1024, keep_files).map(Mutex::new)
+                let max_age = (max_age_days > 0)
+                    .then(|| Duration::from_hours(max_age_days.saturating_mul(24)));
+                FileLogger::new(path, max_size_mb * 1024 * 1024, keep_files, rotation, max_age)
+                    .map(Mutex::new)
             })
             .transpose()?;
 
-        let logger = Box::new(Logger { level, use_color, file });
+        let logger = Box::new(Logger { level, format, use_color, file });
         log::set_boxed_logger(logger)?;
         log::set_max_level(level);
         Ok(())
@@ -178,8 +303,21 @@ impl log::Log for Logger {
         let target = record.target();
         let args = record.args();
 
-        // Plain line written to the log file (no ANSI codes).
-        let plain = format!("[{ts} {level:<5} {target}] {args}\n");
+        // Line written to the log file (no ANSI codes).
+        let plain = match self.format {
+            Format::Text => format!("[{ts} {level:<5} {target}] {args}\n"),
+            Format::Json => {
+                let mut object = Map::new();
+                object.insert("timestamp".into(), ts.into());
+                object.insert("level".into(), level.as_str().into());
+                object.insert("target".into(), target.into());
+                object.insert("message".into(), args.to_string().into());
+                let _ = record
+                    .key_values()
+                    .visit(&mut JsonFields(&mut object));
+                format!("{}\n", Value::Object(object))
+            },
+        };
 
         // Stderr: color the level tag if we're on a tty.
         if self.use_color {
@@ -192,7 +330,7 @@ impl log::Log for Logger {
         // Write plain text to file.
         if let Some(mutex) = &self.file
             && let Ok(mut fl) = mutex.lock()
-            && let Err(e) = fl.write_line(&plain)
+            && let Err(e) = fl.write_line(&plain, secs)
         {
             eprintln!("[rocas logger] failed to write to log file: {e}");
         }
@@ -213,6 +351,11 @@ struct FileLogger {
     current_size: u64,
     max_size_bytes: u64,
     keep_files: u32,
+    rotation: Rotation,
+    /// The period of [`Rotation`] the active file was last written in.
+    period: Option<u64>,
+    /// How long rotated files are kept; `None` keeps them.
+    max_age: Option<Duration>,
 }
 
 impl FileLogger {
@@ -225,15 +368,37 @@ impl FileLogger {
         Ok((BufWriter::new(file), size))
     }
 
-    fn new(path: PathBuf, max_size_bytes: u64, keep_files: u32) -> std::io::Result<Self> {
+    fn new(
+        path: PathBuf,
+        max_size_bytes: u64,
+        keep_files: u32,
+        rotation: Rotation,
+        max_age: Option<Duration>,
+    ) -> std::io::Result<Self> {
         let (writer, current_size) = Self::open(&path)?;
-        Ok(Self {
+        // A file left by an earlier run belongs to the period it was last
+        // written in, so a new day rotates it on the first record.
+        let last_written = std::fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+        let period = rotation.period(
+            last_written
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        );
+        let logger = Self {
             writer,
             path,
             current_size,
             max_size_bytes,
             keep_files,
-        })
+            rotation,
+            period,
+            max_age,
+        };
+        logger.prune();
+        Ok(logger)
     }
 
     fn rotate(&mut self) -> std::io::Result<()> {
@@ -258,13 +423,41 @@ impl FileLogger {
         let (writer, _) = Self::open(&self.path)?;
         self.writer = writer;
         self.current_size = 0;
+        self.prune();
         Ok(())
     }
 
-    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
-        if self.max_size_bytes > 0 && self.current_size >= self.max_size_bytes {
+    /// Deletes the rotated files last written longer than `max_age` ago.
+    fn prune(&self) {
+        let Some(max_age) = self.max_age else {
+            return;
+        };
+        for i in 1..=self.keep_files {
+            let rotated = self
+                .path
+                .with_extension(format!("log.{i}"));
+            let expired = std::fs::metadata(&rotated)
+                .and_then(|meta| meta.modified())
+                .is_ok_and(|modified| {
+                    modified
+                        .elapsed()
+                        .is_ok_and(|age| age > max_age)
+                });
+            if expired {
+                let _ = std::fs::remove_file(&rotated);
+            }
+        }
+    }
+
+    /// Writes `line`, logged at `secs` (Unix seconds), rotating the file
+    /// first if it is too large or from an earlier day or week.
+    fn write_line(&mut self, line: &str, secs: u64) -> std::io::Result<()> {
+        let period = self.rotation.period(secs);
+        let too_large = self.max_size_bytes > 0 && self.current_size >= self.max_size_bytes;
+        if too_large || (self.current_size > 0 && period != self.period) {
             self.rotate()?;
         }
+        self.period = period;
         let bytes = line.as_bytes();
         self.writer.write_all(bytes)?;
         self.writer.flush()?;
@@ -0,0 +1,130 @@
+//! `rocas logs`: prints the log of rocas, wherever it goes on this machine.
+//!
+//! rocas logs to stderr and, unless disabled, to a log file. The file is the
+//! one the running rocas writes, or the one the config names when rocas is
+//! not running. Without a log file, the log of a rocas run as a systemd
+//! service (like the NixOS and Home Manager modules do) is in the journal,
+//! which `journalctl` shows instead.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::AppError;
+
+/// How often `--follow` checks the log file for new lines.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The name of the systemd unit the Nix modules install.
+#[cfg(target_os = "linux")]
+const UNIT: &str = "rocas";
+
+/// Prints the last `lines` lines of the log at `path`, then keeps printing
+/// new ones if `follow` is set.
+///
+/// # Errors
+///
+/// Returns [`AppError::NoLogs`] if there is no log file at `path` and rocas
+/// does not run as a systemd service, or an I/O error if the log cannot be
+/// read.
+pub fn run(path: &Path, lines: usize, follow: bool) -> Result<(), AppError> {
+    if path.is_file() {
+        return Ok(tail(path, lines, follow)?);
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Some(user) = systemd_unit() {
+        let mut command = Command::new("journalctl");
+        if user {
+            command.arg("--user");
+        }
+        command.args(["--unit", UNIT, "--lines", &lines.to_string()]);
+        if follow {
+            command.arg("--follow");
+        }
+        command.status()?;
+        return Ok(());
+    }
+
+    Err(AppError::NoLogs(path.to_path_buf()))
+}
+
+/// Prints the last `lines` lines of `path` and, with `follow`, whatever is
+/// appended to it afterwards. A rotated log is followed into the new file.
+fn tail(path: &Path, lines: usize, follow: bool) -> io::Result<()> {
+    let mut contents = Vec::new();
+    File::open(path)?.read_to_end(&mut contents)?;
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(last_lines(&contents, lines))?;
+    stdout.flush()?;
+    if !follow {
+        return Ok(());
+    }
+
+    let mut pos = contents.len() as u64;
+    loop {
+        std::thread::sleep(FOLLOW_INTERVAL);
+
+        // Between the rename and the next line, a rotated log is missing.
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let len = file.metadata()?.len();
+        if len < pos {
+            pos = 0;
+        }
+        if len == pos {
+            continue;
+        }
+
+        file.seek(SeekFrom::Start(pos))?;
+        let mut new = Vec::new();
+        file.read_to_end(&mut new)?;
+        pos += new.len() as u64;
+        stdout.write_all(&new)?;
+        stdout.flush()?;
+    }
+}
+
+/// Returns the end of `contents` that holds its last `lines` lines.
+fn last_lines(contents: &[u8], lines: usize) -> &[u8] {
+    let body = contents
+        .strip_suffix(b"\n")
+        .unwrap_or(contents);
+    let start = body
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|&(_, &byte)| byte == b'\n')
+        .nth(lines.saturating_sub(1))
+        .map_or(0, |(idx, _)| idx + 1);
+
+    if lines == 0 { &[] } else { &contents[start..] }
+}
+
+/// Returns whether rocas runs as a systemd service: `Some(true)` for a user
+/// service, `Some(false)` for a system one and `None` for neither.
+#[cfg(target_os = "linux")]
+fn systemd_unit() -> Option<bool> {
+    let active = |user: bool| {
+        let mut command = Command::new("systemctl");
+        if user {
+            command.arg("--user");
+        }
+        command
+            .args(["is-active", "--quiet", UNIT])
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    };
+
+    [true, false]
+        .into_iter()
+        .find(|&user| active(user))
+}
@@ -1,51 +1,175 @@
 #![windows_subsystem = "windows"]
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::thread::Scope;
 use std::time::{Duration, Instant};
 
-use clap::CommandFactory;
+use clap::{ArgMatches, CommandFactory, ValueEnum};
 use cli::Cli;
-use config::{Config, RuleConfig};
-use pattern::Pattern;
-use self_update::cargo_crate_version;
+use config::Config;
+use crossbeam_channel::{Receiver, after, never, select};
+use engine::{Context, Engine};
+use rule::CompiledRule;
 use watcher::{DirWatcher, FileEvent, WatcherConfig};
 
+mod adhoc;
+mod aliases;
+mod archive;
 mod art;
+mod autostart;
+mod bandwidth;
+mod busy;
+mod check;
 mod cli;
+mod clock;
+mod codesign;
+mod condition;
 mod config;
+mod control;
+mod daemon;
+mod defaults;
+mod destination;
+mod edit;
+mod encrypt;
+mod engine;
+mod explain;
+mod format;
+mod history;
+mod include;
+mod init;
+mod interpolate;
 mod logger;
+mod logs;
+mod metadata;
+mod migrate;
+mod notify;
+mod offline;
 mod pattern;
+mod pool;
+mod progress;
+mod prune;
+mod retry;
+mod rollout;
+mod rule;
+mod sanitize;
+mod schema;
+mod service;
+mod signals;
+mod space;
+mod sparse;
+mod status;
+mod template;
+mod tester;
+mod throttle;
+mod tilde;
+mod transfer;
+mod tray;
+mod uninstall;
+mod update;
+mod webdav;
+mod when;
 
 #[macro_use]
 extern crate log;
 
-/// How often to probe file size while waiting for a download to finish.
-const STABLE_POLL_INTERVAL: Duration = Duration::from_millis(500);
-
-/// Maximum time to wait for a file to stop growing before moving it anyway.
-const STABLE_MAX_WAIT: Duration = Duration::from_secs(300);
-
 /// All errors that can occur in the rocas binary.
 #[derive(Debug, thiserror::Error)]
-enum AppError {
+pub enum AppError {
     #[error("failed to load config: {0}")]
     Config(#[from] forgeconf::ConfigError),
 
+    #[error("failed to load config: {0}")]
+    Include(#[from] include::IncludeError),
+
+    #[error(transparent)]
+    Migrate(#[from] migrate::MigrateError),
+
+    #[error(transparent)]
+    Edit(#[from] edit::EditError),
+
+    #[error("failed to load config: {0}")]
+    Interpolate(#[from] interpolate::UnsetVariable),
+
+    #[error("invalid config: {0}")]
+    Destination(#[from] destination::UnwritableDestinations),
+
     #[error("logger initialisation failed: {0}")]
     Logger(#[from] logger::LoggerInitError),
 
     #[error("watcher error: {0}")]
     Watcher(#[from] watcher::Error),
 
+    #[cfg(not(windows))]
     #[error("auto-launch error: {0}")]
     AutoLaunch(#[from] auto_launch::Error),
 
+    #[error(transparent)]
+    Autostart(#[from] autostart::AutostartError),
+
     #[error("update check failed: {0}")]
     Update(#[from] self_update::errors::Error),
 
+    #[error("update failed: {0}")]
+    Install(#[from] update::UpdateError),
+
+    #[error("invalid bandwidth limit: {0}")]
+    Bandwidth(#[from] bandwidth::BandwidthError),
+
+    #[error(transparent)]
+    WebDav(#[from] webdav::WebDavError),
+
+    #[error(transparent)]
+    Archive(#[from] archive::ArchiveError),
+
+    #[error(transparent)]
+    Encrypt(#[from] encrypt::EncryptError),
+
+    #[error("invalid rule: {0}")]
+    Rule(#[from] rule::RuleError),
+
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error(
+        "not enough space in '{}': {} needed, {} available",
+        dest.display(),
+        progress::format_bytes(*needed),
+        progress::format_bytes(*available)
+    )]
+    InsufficientSpace {
+        dest: std::path::PathBuf,
+        needed: u64,
+        available: u64,
+    },
+
+    #[error("destination '{}' is not available", .0.display())]
+    DestinationOffline(std::path::PathBuf),
+
+    #[error("config file '{}' does not exist", .0.display())]
+    ConfigNotFound(std::path::PathBuf),
+
+    #[error("watcher '{watcher}' applies rule '{rule}', but there is no rule with that name")]
+    UnknownRule { watcher: String, rule: String },
+
+    #[error("there is more than one watcher named '{0}'")]
+    DuplicateWatcher(String),
+
+    #[error(
+        "no logs found: '{}' does not exist and rocas does not run as a systemd service",
+        .0.display()
+    )]
+    NoLogs(std::path::PathBuf),
+
+    #[error("rocas is running and {0}; stop it first")]
+    Running(&'static str),
+
+    #[error("rocas is already running")]
+    AlreadyRunning,
+
+    #[error("'{}' already exists; pass --force to overwrite it", .0.display())]
+    ConfigExists(std::path::PathBuf),
+
     #[error("failed to restart process: {0}")]
     Restart(String),
 
@@ -53,10 +177,132 @@ enum AppError {
     Other(String),
 }
 
-fn main() -> Result<(), AppError> {
-    let cmd = Config::augment_clap(Cli::command());
-    // let matches = cmd.get_matches();
-    let config = Config::load(cmd)?;
+fn main() -> ExitCode {
+    let result = try_main();
+    adhoc::stop();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn try_main() -> Result<(), AppError> {
+    let matches = match Config::augment_clap(Cli::command()).try_get_matches() {
+        Ok(matches) => matches,
+        Err(e) => {
+            attach_console();
+            e.exit();
+        },
+    };
+    // Only watching runs without a console on Windows; the other commands
+    // print to the terminal they were started from.
+    if !matches!(matches.subcommand(), None | Some(("run", _))) {
+        attach_console();
+    }
+    set_env(&matches);
+    select_config(&matches)?;
+
+    match matches.subcommand() {
+        Some(("setup", setup)) => return set_up_autostart(&matches, setup),
+        Some(("unsetup", _)) => {
+            service::uninstall()?;
+            println!("rocas will no longer start when you log in.");
+            return Ok(());
+        },
+        Some((name @ ("start" | "stop" | "restart"), _)) => {
+            let action = service::Action::from_str(name, false).map_err(AppError::Other)?;
+            println!("{}", service::control(action)?);
+            return Ok(());
+        },
+        Some(("status", _)) => {
+            std::process::exit(i32::from(!status::run(matches.get_flag("json"))));
+        },
+        Some(("undo", undo)) => undo_moves(undo)?,
+        Some(("uninstall", uninstall)) => remove_rocas(&matches, uninstall)?,
+        _ => {},
+    }
+
+    // `init` runs before any config is loaded, as there may be none yet.
+    if let Some(("init", init)) = matches.subcommand() {
+        if let Some(path) = init::run(init.get_flag("force"), init.get_flag("interactive"))? {
+            println!("Wrote a starter config to {}", path.display());
+        }
+        return Ok(());
+    }
+
+    if let Some(("migrate", _)) = matches.subcommand() {
+        let path = config::config_path();
+        match migrate::run(Path::new(&path))? {
+            Some((from, backup)) => println!(
+                "Upgraded {path} from version {from} to {}; the original is in {}",
+                migrate::CURRENT_VERSION,
+                backup.display()
+            ),
+            None => println!("{path} is already at version {}", migrate::CURRENT_VERSION),
+        }
+        return Ok(());
+    }
+
+    if let Some(("rule", rule)) = matches.subcommand()
+        && let Some(("add", add)) = rule.subcommand()
+    {
+        return add_rule(add);
+    }
+
+    if let Some(("schema", _)) = matches.subcommand() {
+        schema::run();
+        return Ok(());
+    }
+
+    if let Some(("check", _)) = matches.subcommand() {
+        let json = matches.get_flag("json");
+        let valid = match Config::load(&matches) {
+            Ok(config) => check::run(&config, json),
+            Err(e) => {
+                check::load_failed(&e, json);
+                false
+            },
+        };
+        std::process::exit(i32::from(!valid));
+    }
+
+    if let Some(("logs", logs)) = matches.subcommand() {
+        return show_logs(&matches, logs);
+    }
+
+    let config = Config::load(&matches)?;
+
+    if let Some(("prune-empty", prune)) = matches.subcommand() {
+        return prune_empty(&config, &matches, prune);
+    }
+
+    if let Some(("explain", explain)) = matches.subcommand()
+        && let Some(path) = explain.get_one::<PathBuf>("path")
+    {
+        let applies = explain::run(&config, path)?;
+        std::process::exit(i32::from(!applies));
+    }
+
+    if let Some(("test", test)) = matches.subcommand() {
+        return test_paths(&config, &matches, test);
+    }
+
+    // The config is checked before detaching, so its errors reach the
+    // terminal.
+    if let Some(("run", run)) = matches.subcommand()
+        && run.get_flag("daemon")
+    {
+        detach(run)?;
+    }
+    daemon::resume()?;
+    if let Some(("run", run)) = matches.subcommand()
+        && run.get_flag("service")
+    {
+        autostart::serve()?;
+    }
 
     // Resolve the log file path: explicit config value, or the OS data dir.
     let log_path = config
@@ -67,156 +313,463 @@ fn main() -> Result<(), AppError> {
 
     logger::Logger::init(
         config.misc.log_level(),
+        config.misc.log_format(),
         log_path,
         config.misc.log_max_size_mb,
         config.misc.log_keep_files,
+        config.misc.log_rotate(),
+        config.misc.log_max_age_days,
     )?;
 
-    run(&config)
+    let config_path = config::config_path();
+    if Path::new(&config_path).is_file() {
+        info!("Loaded config from '{config_path}'");
+    } else {
+        info!("No config file at '{config_path}'; using the defaults");
+    }
+    if config.version < migrate::CURRENT_VERSION {
+        info!(
+            "The config is for version {} of the format; run `rocas migrate` to upgrade it",
+            config.version
+        );
+    }
+
+    run(&config, &matches)
+}
+
+/// Attaches to the console of the terminal rocas was started from, if any.
+/// rocas is built as a GUI program on Windows, so that watching from
+/// autostart does not open a console window, and has none of its own.
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{ATTACH_PARENT_PROCESS, AttachConsole};
+
+    // SAFETY: AttachConsole takes no pointers. It fails harmlessly when
+    // there is no parent console or rocas already has one.
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
 }
 
-fn run(config: &Config) -> Result<(), AppError> {
+#[cfg(not(windows))]
+fn attach_console() {}
+
+fn run(config: &Config, matches: &ArgMatches) -> Result<(), AppError> {
     if config.misc.check_for_updates {
-        check_for_updates(config.misc.auto_update)?;
+        update::check(&config.misc)?;
     }
 
-    let compiled_rules: Vec<(Vec<Pattern>, &RuleConfig)> = config
+    let compiled_rules: Vec<CompiledRule> = config
         .rules
         .iter()
-        .map(|r| (r.compiled_patterns(), r))
+        .map(CompiledRule::new)
+        .collect::<Result<_, _>>()?;
+
+    // Each watcher has its own queue of unreachable destinations, kept under
+    // its name.
+    let watchers = config.watchers();
+    for (idx, watcher) in watchers.iter().enumerate() {
+        if watchers[..idx]
+            .iter()
+            .any(|earlier| earlier.name == watcher.name)
+        {
+            return Err(AppError::DuplicateWatcher(watcher.name.clone()));
+        }
+    }
+
+    let mut rule_sets = Vec::with_capacity(watchers.len());
+    let mut dir_watchers = Vec::with_capacity(watchers.len());
+    for settings in &watchers {
+        let rules: Vec<CompiledRule> = config
+            .rules_of(settings)?
+            .into_iter()
+            .map(CompiledRule::new)
+            .collect::<Result<_, _>>()?;
+        rule_sets.push(rules);
+
+        let mut watcher = DirWatcher::new(&WatcherConfig {
+            poll_interval_ms: settings.interval_millis,
+            debounce_ms: settings.debounce_ms,
+            rename_timeout_ms: settings.rename_timeout_ms,
+            ..Default::default()
+        })?;
+        for path in settings.effective_paths() {
+            watcher.watch(Path::new(path), settings.recursive, settings.max_depth)?;
+        }
+        dir_watchers.push(watcher);
+    }
+
+    config.print_startup_banner();
+
+    let status_ids: Vec<usize> = watchers
+        .iter()
+        .zip(&rule_sets)
+        .map(|(settings, rules)| {
+            status::register(status::WatcherStatus {
+                name: settings.name.clone(),
+                paths: settings
+                    .effective_paths()
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect(),
+                rules: rules.len(),
+                ..Default::default()
+            })
+        })
         .collect();
+    status::start(config.misc.log_file.clone());
+    control::start();
+    notify::start(config.misc.notify());
+    let reload_matches = matches.clone();
+    let stop = signals::install(move || Config::load(&reload_matches).map(drop));
+
+    engine::remove_stale_copies(&compiled_rules);
+    history::prune();
+
+    let contexts: Vec<Context> = rule_sets
+        .iter()
+        .zip(&watchers)
+        .map(|(rules, settings)| Context::new(rules, settings))
+        .collect::<Result<_, _>>()?;
 
-    let mut watcher = DirWatcher::new(&WatcherConfig {
-        poll_interval_ms: config.watcher.interval_millis,
-        debounce_ms: config.watcher.debounce_ms,
-        rename_timeout_ms: config.watcher.rename_timeout_ms,
-        ..Default::default()
-    })?;
+    std::thread::scope(|scope| {
+        let mut handles = Vec::with_capacity(contexts.len());
+        for (((ctx, settings), watcher), status_id) in contexts
+            .iter()
+            .zip(&watchers)
+            .zip(dir_watchers)
+            .zip(status_ids)
+        {
+            let stop = &stop;
+            handles
+                .push(scope.spawn(move || serve(scope, ctx, settings, &watcher, stop, status_id)));
+        }
 
-    let watch_paths = config.watcher.effective_paths();
-    for path in &watch_paths {
-        watcher.watch(Path::new(path), config.watcher.recursive, config.watcher.max_depth)?;
+        // The tray needs the main thread, which has nothing else to do.
+        if config.misc.tray {
+            tray::run(config.misc.log_file.as_deref(), &stop, || {
+                handles
+                    .iter()
+                    .all(std::thread::ScopedJoinHandle::is_finished)
+            });
+        }
+    });
+
+    status::stop();
+    control::stop();
+    daemon::stop();
+    match signals::requested() {
+        // Starting again loads the config anew, with the same arguments.
+        Some(signals::Signal::Reload) => {
+            signals::finished();
+            restart(&std::env::current_exe()?)
+        },
+        Some(signals::Signal::Stop) => {
+            info!("Stopped.");
+            signals::finished();
+            Ok(())
+        },
+        None => {
+            error!("Watcher channel closed unexpectedly — exiting.");
+            Ok(())
+        },
     }
+}
 
-    config.print_startup_banner(&watch_paths);
+/// Runs the engine of one watcher until its event channel or `stop` closes,
+/// keeping its entry `status_id` of the status snapshot up to date. The
+/// moves it started finish once the engine is gone.
+fn serve<'a>(
+    scope: &'a Scope<'a, '_>,
+    ctx: &'a Context<'a>,
+    settings: &config::WatcherConfig,
+    watcher: &DirWatcher,
+    stop: &Receiver<()>,
+    status_id: usize,
+) {
+    let mut engine = Engine::new(scope, ctx);
+    let batch_window = Duration::from_millis(settings.batch_window_ms);
+
+    // Sort out what is already there. The watcher is running by now, so
+    // files that arrive during the scan are not missed.
+    if settings.process_existing {
+        for path in settings.effective_paths() {
+            process_existing(&mut engine, settings, Path::new(path));
+        }
+    }
 
+    let no_events = never();
     loop {
-        if let Some(event) = watcher.next_event() {
-            dispatch_event(&event, &compiled_rules);
+        // While paused, events wait in the watcher's channel and deferred
+        // matches in their queues.
+        let paused = tray::paused();
+        let (events, wakeup) = if paused {
+            (&no_events, after(tray::PAUSE_RECHECK_INTERVAL))
         } else {
-            error!("Watcher channel closed unexpectedly — exiting.");
-            break Ok(());
+            // Wake up when a deferred match is due, even without events.
+            let wakeup = engine
+                .next_wakeup()
+                .map_or_else(never, after);
+            (watcher.receiver(), wakeup)
+        };
+
+        select! {
+            recv(events) -> event => match event {
+                Ok(event) => engine.handle_batch(collect_batch(watcher, event, batch_window)),
+                Err(_) => break,
+            },
+            recv(engine.completions()) -> done => {
+                if let Ok(done) = done {
+                    engine.complete(done);
+                }
+            },
+            recv(wakeup) -> _ => {},
+            recv(stop) -> _ => return,
         }
+
+        if !paused {
+            engine.run_due();
+        }
+
+        let (moved, last_event) = engine.activity();
+        let pending = engine.pending();
+        status::update(status_id, |status| {
+            status.moved = moved;
+            status.last_event = last_event;
+            status.pending = pending;
+        });
+    }
+
+    if !settings.name.is_empty() {
+        error!("Watcher '{}' stopped: its event channel closed unexpectedly.", settings.name);
     }
 }
 
-/// Applies the first matching rule to a filesystem event.
-fn dispatch_event(event: &FileEvent, compiled_rules: &[(Vec<Pattern>, &RuleConfig)]) {
-    let path = match event {
-        FileEvent::Created(p) | FileEvent::Modified(p) => p,
-        FileEvent::Deleted(_) => return,
-        FileEvent::Renamed { to, .. } => to,
+/// Sets the environment variables given with `--env`.
+fn set_env(matches: &ArgMatches) {
+    for (key, value) in matches
+        .get_many::<(String, String)>("env")
+        .into_iter()
+        .flatten()
+    {
+        // SAFETY: rocas has not started any threads yet.
+        unsafe { std::env::set_var(key, value) };
+    }
+}
+
+/// Points [`config::config_path`] at the `--config` file, or at the config
+/// `watch` builds from its arguments.
+fn select_config(matches: &ArgMatches) -> Result<(), AppError> {
+    if let Some(("watch", watch)) = matches.subcommand() {
+        config::set_config_path(adhoc::write(watch)?);
+    } else if let Some(path) = matches.get_one::<PathBuf>("config") {
+        config::set_config_path(path.clone());
+    }
+    Ok(())
+}
+
+/// `rocas rule add`: appends the rule described by the arguments to the
+/// config file.
+fn add_rule(add: &ArgMatches) -> Result<(), AppError> {
+    let path = PathBuf::from(config::config_path());
+    if !path.is_file() {
+        return Err(AppError::ConfigNotFound(path));
+    }
+    let strings = |id: &str| {
+        add.get_many::<String>(id)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    let rule = edit::NewRule {
+        name: add.get_one::<String>("name").cloned(),
+        patterns: strings("patterns"),
+        destination: add
+            .get_one::<String>("dest")
+            .cloned()
+            .unwrap_or_default(),
+        action: add.get_one::<String>("action").cloned(),
     };
+    edit::add_rule(&path, &rule)?;
+    println!("Added {} → {} to {}", rule.patterns.join(", "), rule.destination, path.display());
+    Ok(())
+}
 
-    let filename = path
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
-
-    // Normalize to forward slashes so glob patterns work on Windows
-    // (where Path::to_str() returns backslash-separated paths).
-    let full = path
-        .to_str()
-        .unwrap_or("")
-        .replace('\\', "/");
-
-    // Use the first matching rule only. Without `break`, a second matching
-    // rule would attempt to move an already-moved file and log a spurious error.
-    for (patterns, rule) in compiled_rules {
-        let matched = patterns
-            .iter()
-            .any(|p| if p.raw.contains('/') { p.matches(&full) } else { p.matches(filename) });
+/// `rocas setup`: makes rocas start with the system in the mode asked for.
+fn set_up_autostart(matches: &ArgMatches, setup: &ArgMatches) -> Result<(), AppError> {
+    if setup.get_flag("status") {
+        let healthy = service::print_status(matches.get_flag("json"))?;
+        std::process::exit(i32::from(!healthy));
+    }
 
-        if matched {
-            if let Err(e) = move_file(path, &rule.destination) {
-                error!("Failed to move '{}': {e}", path.display());
-            }
-            break;
-        }
+    let mode = setup
+        .get_one::<String>("mode")
+        .map_or_else(autostart::Mode::preferred, |mode| autostart::Mode::parse(mode));
+    let launch = autostart::Launch {
+        config: matches
+            .get_one::<PathBuf>("config")
+            .map(std::path::absolute)
+            .transpose()?,
+        env: matches
+            .get_many::<(String, String)>("env")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect(),
+        args: setup
+            .get_many::<String>("args")
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect(),
+    };
+    println!("{}", service::install(mode, setup.get_flag("highest"), &launch)?);
+    Ok(())
+}
+
+/// `rocas run --daemon`: continues in a background process, unless rocas is
+/// running already.
+fn detach(run: &ArgMatches) -> Result<(), AppError> {
+    if status::is_running() {
+        return Err(AppError::AlreadyRunning);
     }
+    let pid_file = run
+        .get_one::<PathBuf>("pid_file")
+        .cloned()
+        .unwrap_or_else(daemon::default_pid_file);
+    daemon::start(&pid_file)
 }
 
-/// Polls `path` until its size has been stable across two consecutive checks
-/// (`STABLE_POLL_INTERVAL` apart). Returns an error if the file disappears.
-///
-/// This ensures a file is fully written before it is moved. Downloads that
-/// trigger a `Created`/`Modified` event early would otherwise be moved while
-/// the writer still has the file open, producing a 0-byte destination.
-///
-/// Gives up and returns `Ok(())` after `STABLE_MAX_WAIT` to avoid blocking
-/// the event loop indefinitely on a stalled download.
-fn wait_until_stable(path: &Path) -> Result<(), AppError> {
-    let started = Instant::now();
-    let mut last_size: Option<u64> = None;
+fn test_paths(config: &Config, matches: &ArgMatches, test: &ArgMatches) -> Result<(), AppError> {
+    let paths: Vec<PathBuf> = test
+        .get_many::<PathBuf>("paths")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+    let claimed = tester::run(config, &paths, matches.get_flag("json"))?;
+    std::process::exit(i32::from(!claimed));
+}
 
-    loop {
-        if started.elapsed() >= STABLE_MAX_WAIT {
-            warn!(
-                "Timed out waiting for '{}' to finish writing; moving it anyway.",
-                path.display()
-            );
-            break;
-        }
+fn prune_empty(config: &Config, matches: &ArgMatches, prune: &ArgMatches) -> Result<(), AppError> {
+    let roots: Vec<PathBuf> = match prune.get_one::<PathBuf>("path") {
+        Some(path) => vec![path.clone()],
+        None => config
+            .watchers()
+            .iter()
+            .flat_map(|watcher| watcher.effective_paths())
+            .map(PathBuf::from)
+            .collect(),
+    };
+    let ignore: Vec<String> = prune
+        .get_many::<String>("ignore")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect();
+    let pruned = prune::run(&roots, &ignore, matches.get_flag("dry_run"))?;
+    std::process::exit(i32::from(!pruned));
+}
 
-        match std::fs::metadata(path) {
-            Ok(meta) => {
-                let current_size = meta.len();
-                if last_size == Some(current_size) {
-                    // Size unchanged across two consecutive probes — file is stable.
-                    break;
-                }
-                last_size = Some(current_size);
-            },
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                return Err(AppError::Other(format!("'{}' no longer exists", path.display())));
-            },
-            Err(e) => return Err(AppError::Io(e)),
-        }
+fn remove_rocas(matches: &ArgMatches, uninstall: &ArgMatches) -> Result<(), AppError> {
+    let removed = uninstall::run(matches, uninstall.get_flag("purge"))?;
+    std::process::exit(i32::from(!removed));
+}
 
-        std::thread::sleep(STABLE_POLL_INTERVAL);
+/// `rocas undo`: moves the files of the selected moves back and exits with
+/// a failure status if any could not be.
+fn undo_moves(undo: &ArgMatches) -> Result<(), AppError> {
+    let selection = match undo.get_one::<Duration>("since") {
+        Some(age) => history::Selection::Since(*age),
+        None => history::Selection::Last(
+            undo.get_one::<usize>("last")
+                .copied()
+                .unwrap_or(1),
+        ),
+    };
+    let restored = history::undo(selection)?;
+    std::process::exit(i32::from(!restored));
+}
+
+/// `rocas logs`: prints the log of the running rocas, or of the one the
+/// config describes when none is running.
+fn show_logs(matches: &ArgMatches, logs: &ArgMatches) -> Result<(), AppError> {
+    let path = status::log_file()
+        .or_else(|| {
+            Config::load(matches)
+                .ok()
+                .and_then(|config| config.misc.log_file)
+                .map(PathBuf::from)
+        })
+        .unwrap_or_else(|| PathBuf::from(config::logs_path()));
+    let lines = logs
+        .get_one::<usize>("lines")
+        .copied()
+        .unwrap_or_default();
+    logs::run(&path, lines, logs.get_flag("follow"))
+}
+
+/// Collects the events that arrive within `window` of `first`, so a burst
+/// (e.g. unpacking an archive) is processed as one batch.
+fn collect_batch(watcher: &DirWatcher, first: FileEvent, window: Duration) -> Vec<FileEvent> {
+    let deadline = Instant::now() + window;
+    let mut batch = vec![first];
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match watcher
+            .receiver()
+            .recv_timeout(remaining)
+        {
+            Ok(event) => batch.push(event),
+            Err(_) => break,
+        }
     }
 
-    Ok(())
+    batch
 }
 
-/// Moves a file to the specified destination directory, creating it if needed.
-///
-/// Waits for the source file to stop growing before moving to avoid moving
-/// partially-written downloads. Attempts an atomic rename first; falls back to
-/// copy + delete when source and destination are on different filesystems.
-fn move_file(from: &Path, to_dir: &str) -> Result<(), AppError> {
-    // Wait for the file to be fully written before moving it. Without this,
-    // a download that triggers a Created/Modified event early can be moved
-    // while the writer still has it open, resulting in a 0-byte destination.
-    wait_until_stable(from)?;
-
-    let dest_dir = Path::new(to_dir);
-    std::fs::create_dir_all(dest_dir)?;
-
-    let filename = from
-        .file_name()
-        .ok_or_else(|| AppError::Other(format!("invalid filename: {}", from.display())))?;
-    let dest = dest_dir.join(filename);
-
-    // Try to rename first (fast, same filesystem)
-    if std::fs::rename(from, &dest).is_err() {
-        // Fall back to copy + delete (cross-filesystem)
-        std::fs::copy(from, &dest)?;
-        std::fs::remove_file(from)?;
-    }
-
-    info!("Moved {} → {}", from.display(), dest.display());
-    Ok(())
+/// Runs every file already present under `root` through the rules, honouring
+/// the watcher's `recursive` and `max_depth` settings.
+fn process_existing(engine: &mut Engine, settings: &config::WatcherConfig, root: &Path) {
+    let max_depth = if settings.recursive { settings.max_depth } else { Some(0) };
+
+    let mut entries = Vec::new();
+    collect_entries(root, 0, max_depth, &mut entries);
+    // Sorting puts every directory before its contents, so a directory
+    // matched as a whole is moved before its files are looked at.
+    entries.sort();
+
+    info!("Processing {} existing entries in {}", entries.len(), root.display());
+    for entry in entries {
+        engine.handle_event(&FileEvent::Created(entry));
+    }
+}
+
+/// Collects the files and directories below `dir`, descending at most
+/// `max_depth` levels (`None` = unlimited). Unreadable directories are
+/// skipped with a warning.
+fn collect_entries(dir: &Path, depth: usize, max_depth: Option<usize>, entries: &mut Vec<PathBuf>) {
+    let listing = match std::fs::read_dir(dir) {
+        Ok(listing) => listing,
+        Err(e) => {
+            warn!("Could not read '{}': {e}", dir.display());
+            return;
+        },
+    };
+
+    for entry in listing.filter_map(Result::ok) {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+
+        if file_type.is_file() {
+            entries.push(entry.path());
+        } else if file_type.is_dir() {
+            entries.push(entry.path());
+            if max_depth.is_none_or(|max| depth < max) {
+                collect_entries(&entry.path(), depth + 1, max_depth, entries);
+            }
+        }
+    }
 }
 
 /// Replaces the current process image with a fresh instance of `exe`,
@@ -241,7 +794,16 @@ fn move_file(from: &Path, to_dir: &str) -> Result<(), AppError> {
 /// Returns [`AppError::Restart`] if (Unix) `execv` fails. The Windows spawn
 /// path calls `std::process::exit` on success and only returns on failure.
 fn restart(exe: &Path) -> Result<(), AppError> {
-    let args: Vec<String> = std::env::args().skip(1).collect();
+    // A service cannot start itself again; the service control manager
+    // restarts it, as it exits without saying it stopped.
+    if autostart::is_service() {
+        std::process::exit(1);
+    }
+
+    // Without `--daemon`, so the new process does not detach again.
+    let (args, env) = daemon::restart_args();
+    // The new process writes the config of `rocas watch` anew.
+    adhoc::stop();
 
     #[cfg(unix)]
     {
@@ -249,6 +811,7 @@ fn restart(exe: &Path) -> Result<(), AppError> {
         // exec replaces the current process image; it only returns on error.
         let err = std::process::Command::new(exe)
             .args(&args)
+            .envs(env)
             .exec();
         Err(AppError::Restart(format!("exec failed: {err}")))
     }
@@ -269,6 +832,7 @@ fn restart(exe: &Path) -> Result<(), AppError> {
         const DETACHED_PROCESS: u32 = 0x0000_0008;
         let child = std::process::Command::new(exe)
             .args(&args)
+            .envs(env)
             .creation_flags(DETACHED_PROCESS)
             .spawn()
             .map_err(|e| AppError::Restart(format!("spawn failed: {e}")))?;
@@ -276,48 +840,3 @@ fn restart(exe: &Path) -> Result<(), AppError> {
         std::process::exit(0);
     }
 }
-
-/// Checks GitHub for a newer release and optionally performs an in-place
-/// update.
-fn check_for_updates(auto_update: bool) -> Result<(), AppError> {
-    let releases = self_update::backends::github::ReleaseList::configure()
-        .repo_owner("chikof")
-        .repo_name("rocas")
-        .build()?
-        .fetch()?;
-
-    let latest = releases
-        .first()
-        .ok_or_else(|| AppError::Other("GitHub returned an empty release list".to_string()))?;
-    let current = cargo_crate_version!();
-
-    if self_update::version::bump_is_greater(current, &latest.version)? {
-        info!("New version available: {} → {}", current, latest.version);
-        warn!(
-            "To update manually update or set the 'misc.auto_update' option in the config to true."
-        );
-
-        if auto_update {
-            // Capture the exe path before the update so the path is resolved
-            // while the current file is still open (matters on Windows where
-            // self_replace renames the running exe before writing the new one).
-            let exe = std::env::current_exe()?;
-
-            self_update::backends::github::Update::configure()
-                .repo_owner("chikof")
-                .repo_name("rocas")
-                .bin_name("rocas")
-                .show_download_progress(true)
-                .current_version(current)
-                .build()?
-                .update()?;
-
-            info!("Updated to version {}. Restarting…", latest.version);
-            restart(&exe)?;
-        }
-    } else {
-        trace!("No update available (current: {}, latest: {})", current, latest.version);
-    }
-
-    Ok(())
-}
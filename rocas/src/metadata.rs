@@ -0,0 +1,183 @@
+//! Copying file metadata alongside file contents.
+//!
+//! A cross-filesystem move is a copy followed by a delete, and a plain copy
+//! gets fresh timestamps. This module carries over what a rename would have
+//! kept: modification and access times, permissions and, on Linux and macOS,
+//! extended attributes.
+
+use std::fs::{File, FileTimes};
+use std::io;
+use std::path::Path;
+
+/// Copies timestamps, permissions and extended attributes from `from` to
+/// `dest`. Works for files and directories.
+///
+/// Extended attributes the destination filesystem rejects are skipped.
+pub fn copy(from: &Path, dest: &Path) -> io::Result<()> {
+    let meta = std::fs::metadata(from)?;
+
+    // Extended attributes first: writing them needs write permission, which
+    // the copied permissions may take away.
+    copy_xattrs(from, dest);
+
+    let mut times = FileTimes::new().set_modified(meta.modified()?);
+    if let Ok(accessed) = meta.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    open_for_times(dest)?.set_times(times)?;
+
+    std::fs::set_permissions(dest, meta.permissions())
+}
+
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_WRITE_ATTRIBUTES: u32 = 0x0100;
+    // Needed to open a directory handle.
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+    std::fs::OpenOptions::new()
+        .access_mode(FILE_WRITE_ATTRIBUTES)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)
+}
+
+#[cfg(not(windows))]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+/// Copies every extended attribute of `from` to `dest`, logging the ones
+/// that cannot be read or written.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn copy_xattrs(from: &Path, dest: &Path) {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let (Ok(source), Ok(target)) =
+        (CString::new(from.as_os_str().as_bytes()), CString::new(dest.as_os_str().as_bytes()))
+    else {
+        return;
+    };
+
+    let names = match xattr::list(&source) {
+        Ok(names) => names,
+        Err(e) => {
+            debug!("Could not list extended attributes of '{}': {e}", from.display());
+            return;
+        },
+    };
+
+    for name in names
+        .split(|&b| b == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let Ok(name) = CString::new(name) else {
+            continue;
+        };
+
+        if let Err(e) =
+            xattr::get(&source, &name).and_then(|value| xattr::set(&target, &name, &value))
+        {
+            debug!(
+                "Could not copy extended attribute {} of '{}': {e}",
+                name.to_string_lossy(),
+                from.display()
+            );
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn copy_xattrs(_from: &Path, _dest: &Path) {}
+
+/// Thin wrappers around the platform's `*xattr` calls.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod xattr {
+    use std::ffi::CStr;
+    use std::io;
+
+    /// Returns the NUL-separated names of the attributes of `path`.
+    pub fn list(path: &CStr) -> io::Result<Vec<u8>> {
+        // SAFETY: a null buffer with size 0 only queries the required size.
+        let len = check(unsafe { raw_list(path, std::ptr::null_mut(), 0) })?;
+        let mut buf = vec![0_u8; len];
+        // SAFETY: `buf` is valid for `buf.len()` bytes.
+        let len = check(unsafe { raw_list(path, buf.as_mut_ptr().cast(), buf.len()) })?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Returns the value of attribute `name` of `path`.
+    pub fn get(path: &CStr, name: &CStr) -> io::Result<Vec<u8>> {
+        // SAFETY: a null buffer with size 0 only queries the required size.
+        let len = check(unsafe { raw_get(path, name, std::ptr::null_mut(), 0) })?;
+        let mut buf = vec![0_u8; len];
+        // SAFETY: `buf` is valid for `buf.len()` bytes.
+        let len = check(unsafe { raw_get(path, name, buf.as_mut_ptr().cast(), buf.len()) })?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Sets attribute `name` of `path` to `value`.
+    pub fn set(path: &CStr, name: &CStr, value: &[u8]) -> io::Result<()> {
+        // SAFETY: `value` is valid for `value.len()` bytes.
+        let rc = unsafe { raw_set(path, name, value.as_ptr().cast(), value.len()) };
+        if rc == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+    }
+
+    fn check(len: libc::ssize_t) -> io::Result<usize> {
+        usize::try_from(len).map_err(|_| io::Error::last_os_error())
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn raw_list(path: &CStr, buf: *mut libc::c_char, size: usize) -> libc::ssize_t {
+        unsafe { libc::listxattr(path.as_ptr(), buf, size) }
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn raw_get(
+        path: &CStr,
+        name: &CStr,
+        buf: *mut libc::c_void,
+        size: usize,
+    ) -> libc::ssize_t {
+        unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf, size) }
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn raw_set(
+        path: &CStr,
+        name: &CStr,
+        value: *const libc::c_void,
+        size: usize,
+    ) -> libc::c_int {
+        unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value, size, 0) }
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn raw_list(path: &CStr, buf: *mut libc::c_char, size: usize) -> libc::ssize_t {
+        unsafe { libc::listxattr(path.as_ptr(), buf, size, 0) }
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn raw_get(
+        path: &CStr,
+        name: &CStr,
+        buf: *mut libc::c_void,
+        size: usize,
+    ) -> libc::ssize_t {
+        unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf, size, 0, 0) }
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn raw_set(
+        path: &CStr,
+        name: &CStr,
+        value: *const libc::c_void,
+        size: usize,
+    ) -> libc::c_int {
+        unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value, size, 0, 0) }
+    }
+}
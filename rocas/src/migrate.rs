@@ -0,0 +1,154 @@
+//! Config format versions.
+//!
+//! A config states the format it was written for with a top-level
+//! `version`; files without one are version 0. Older configs are upgraded in
+//! memory when they are loaded, so they keep working as the format changes,
+//! and `rocas migrate` rewrites the file in the current format, keeping the
+//! original as a backup.
+//!
+//! Version 1 only accepts `snake_case` keys. Version 0 also took the
+//! `camelCase` spellings of the Nix module's options (`checkForUpdates`), which
+//! rocas otherwise ignores.
+
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+
+use crate::format::{Format, ParseError};
+
+pub const VERSION_KEY: &str = "version";
+
+/// The version of the format this build reads and writes.
+pub const CURRENT_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` upgrades a version `n` config to version `n + 1`.
+const MIGRATIONS: &[fn(&mut Table)] = &[snake_case_keys];
+
+/// Error returned when a config cannot be upgraded.
+#[derive(Debug, thiserror::Error)]
+pub enum MigrateError {
+    #[error(
+        "the config is for version {0} of the format, but this rocas only knows up to version \
+         {CURRENT_VERSION}; update rocas"
+    )]
+    Newer(u32),
+
+    #[error("'version' must be a non-negative integer")]
+    InvalidVersion,
+
+    #[error("cannot read '{}': {source}", path.display())]
+    Io { path: PathBuf, source: std::io::Error },
+
+    #[error("cannot parse '{}': {source}", path.display())]
+    Parse { path: PathBuf, source: ParseError },
+}
+
+/// Returns the version `config` was written for.
+///
+/// # Errors
+///
+/// Returns [`MigrateError::InvalidVersion`] if `version` is not a valid
+/// version number.
+pub fn version(config: &Table) -> Result<u32, MigrateError> {
+    match config.get(VERSION_KEY) {
+        None => Ok(0),
+        Some(Value::Integer(version)) => {
+            u32::try_from(*version).map_err(|_| MigrateError::InvalidVersion)
+        },
+        Some(_) => Err(MigrateError::InvalidVersion),
+    }
+}
+
+/// Upgrades `config` to the current format in memory. Its `version` is left
+/// as it was, so the file is still reported as outdated.
+///
+/// # Errors
+///
+/// Returns [`MigrateError`] if the version is invalid or newer than this
+/// build knows.
+pub fn upgrade(config: &mut Table) -> Result<(), MigrateError> {
+    let version = version(config)?;
+    if version > CURRENT_VERSION {
+        return Err(MigrateError::Newer(version));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(config);
+    }
+    Ok(())
+}
+
+/// Rewrites the config at `path` in the current format, keeping the
+/// original next to it. Returns the version the file had and where the
+/// original was kept, or `None` if it was already current.
+///
+/// # Errors
+///
+/// Returns [`MigrateError`] if the file cannot be read, parsed, upgraded or
+/// written.
+pub fn run(path: &Path) -> Result<Option<(u32, PathBuf)>, MigrateError> {
+    let io_error = |source| MigrateError::Io { path: path.to_path_buf(), source };
+
+    let text = std::fs::read_to_string(path).map_err(io_error)?;
+    let format = Format::of(path);
+    let mut config = format
+        .parse(&text)
+        .map_err(|source| MigrateError::Parse { path: path.to_path_buf(), source })?;
+
+    let from = version(&config)?;
+    if from == CURRENT_VERSION {
+        return Ok(None);
+    }
+    upgrade(&mut config)?;
+    config.insert(VERSION_KEY.to_string(), Value::Integer(CURRENT_VERSION.into()));
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{from}.bak"));
+    let backup = PathBuf::from(backup);
+
+    std::fs::copy(path, &backup).map_err(io_error)?;
+    std::fs::write(path, format.render(&config)).map_err(io_error)?;
+
+    Ok(Some((from, backup)))
+}
+
+/// Version 0 → 1: renames `camelCase` keys to `snake_case`, at any depth.
+fn snake_case_keys(table: &mut Table) {
+    let renames: Vec<(String, String)> = table
+        .keys()
+        .filter(|key| key.contains(|c: char| c.is_ascii_uppercase()))
+        .map(|key| (key.clone(), to_snake_case(key)))
+        .filter(|(_, snake)| !table.contains_key(snake))
+        .collect();
+    for (key, snake) in renames {
+        if let Some(value) = table.remove(&key) {
+            table.insert(snake, value);
+        }
+    }
+
+    for (_, value) in table.iter_mut() {
+        match value {
+            Value::Table(table) => snake_case_keys(table),
+            Value::Array(items) => items
+                .iter_mut()
+                .filter_map(Value::as_table_mut)
+                .for_each(snake_case_keys),
+            _ => {},
+        }
+    }
+}
+
+fn to_snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            if !snake.is_empty() {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
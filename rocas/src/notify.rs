@@ -0,0 +1,165 @@
+//! Desktop notifications for moved files and failed moves.
+//!
+//! `misc.notify` picks which ones rocas shows: none (`off`, the default),
+//! only files it could not move (`errors`), or also every file it moved
+//! (`all`). A rule's own `notify` picks for the files of that rule instead.
+//! notify-rust shows them through the notification server of the desktop on
+//! Linux and the BSDs, Notification Center on macOS and toasts on Windows.
+//!
+//! Notifications are shown from a thread of their own, so a slow
+//! notification server never holds up a move. At most [`BURST`] are shown
+//! per [`WINDOW`]; the ones after that are counted and shown as a single
+//! notification once the window has room again, so a folder of a thousand
+//! files makes a handful of notifications rather than a thousand.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, unbounded};
+
+use crate::config::{Notify, RuleConfig};
+use crate::transfer::Placed;
+
+/// How many notifications are shown within [`WINDOW`].
+const BURST: usize = 3;
+
+const WINDOW: Duration = Duration::from_secs(30);
+
+const APP_NAME: &str = "rocas";
+
+/// `misc.notify` and the channel to the thread showing the notifications,
+/// once rocas watches.
+static NOTIFIER: OnceLock<(Notify, Sender<Message>)> = OnceLock::new();
+
+struct Message {
+    failed: bool,
+    body: String,
+}
+
+/// Notifications held back by the rate limit.
+#[derive(Default)]
+struct Held {
+    moved: usize,
+    failed: usize,
+}
+
+impl Held {
+    fn is_empty(&self) -> bool {
+        self.moved + self.failed == 0
+    }
+
+    fn add(&mut self, message: &Message) {
+        if message.failed {
+            self.failed += 1;
+        } else {
+            self.moved += 1;
+        }
+    }
+
+    fn body(&self) -> String {
+        match (self.moved, self.failed) {
+            (moved, 0) => format!("{moved} more files moved"),
+            (0, failed) => format!("{failed} more files could not be moved"),
+            (moved, failed) => {
+                format!("{moved} more files moved, {failed} could not be moved")
+            },
+        }
+    }
+}
+
+/// Starts showing notifications, `notify` being `misc.notify`.
+pub fn start(notify: Notify) {
+    let (tx, rx) = unbounded();
+    if NOTIFIER.set((notify, tx)).is_ok() {
+        std::thread::spawn(move || run(&rx));
+    }
+}
+
+/// Shows that `rule` placed `path` at `dest`, if it notifies of that.
+pub fn placed(rule: &RuleConfig, placed: Placed, path: &Path, dest: &Path) {
+    let name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy();
+    let body = format!("{placed} {name} → {} ({})", dest.display(), rule.name);
+    send(rule, Notify::All, Message { failed: false, body });
+}
+
+/// Shows that `path` could not be moved by `rule`, if it notifies of that.
+pub fn failed(rule: &RuleConfig, path: &Path, error: &str) {
+    let body = format!("Could not move '{}': {error}", path.display());
+    send(rule, Notify::Errors, Message { failed: true, body });
+}
+
+/// Sends `message` if `rule` shows notifications of level `level`.
+fn send(rule: &RuleConfig, level: Notify, message: Message) {
+    let Some((global, tx)) = NOTIFIER.get() else {
+        return;
+    };
+    if rule.notify().unwrap_or(*global) >= level {
+        // The thread only goes away with the process.
+        let _ = tx.send(message);
+    }
+}
+
+/// Shows the notifications from `rx`, holding back the ones over the rate
+/// limit.
+fn run(rx: &Receiver<Message>) {
+    let mut shown: VecDeque<Instant> = VecDeque::new();
+    let mut held = Held::default();
+    let mut warned = false;
+
+    loop {
+        // Something is only held while the window is full, so wake up when
+        // its oldest notification leaves it.
+        let received = match shown.front() {
+            Some(oldest) if !held.is_empty() => rx.recv_deadline(*oldest + WINDOW),
+            _ => rx
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        while shown
+            .front()
+            .is_some_and(|at| at.elapsed() >= WINDOW)
+        {
+            shown.pop_front();
+        }
+
+        match received {
+            Ok(message) if held.is_empty() && shown.len() < BURST => {
+                show(message.failed, &message.body, &mut warned);
+                shown.push_back(Instant::now());
+            },
+            Ok(message) => held.add(&message),
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        if !held.is_empty() && shown.len() < BURST {
+            show(held.failed > 0, &held.body(), &mut warned);
+            shown.push_back(Instant::now());
+            held = Held::default();
+        }
+    }
+}
+
+/// Shows one notification. Only the first failure to show one is a warning:
+/// without a desktop session, all of them fail.
+fn show(failed: bool, body: &str, warned: &mut bool) {
+    let summary = if failed { "rocas: a move failed" } else { APP_NAME };
+    if let Err(e) = notify_rust::Notification::new()
+        .appname(APP_NAME)
+        .summary(summary)
+        .body(body)
+        .show()
+    {
+        if *warned {
+            debug!("Could not show a desktop notification: {e}");
+        } else {
+            warn!("Could not show a desktop notification: {e}");
+            *warned = true;
+        }
+    }
+}
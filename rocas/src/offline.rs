@@ -0,0 +1,117 @@
+//! Destinations that are temporarily unreachable, like an unmounted SMB or
+//! NFS share or a WebDAV server that is down.
+//!
+//! A rule with `wait_for_destination = true` does not fail matches while its
+//! destination is away: they are queued and moved once it is back. The queue
+//! is kept in a journal file so matches survive a restart of rocas.
+
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::webdav::WebDavError;
+use crate::{AppError, config};
+
+pub const JOURNAL_NAME: &str = "offline-queue";
+
+/// A match deferred because its destination was unreachable.
+pub struct WaitingForDestination {
+    pub path: PathBuf,
+    /// Index of the rule in the compiled rule list.
+    pub rule: usize,
+    /// When to check the destination again.
+    pub retry_at: Instant,
+}
+
+/// Returns `true` if `error` means the destination could not be reached, as
+/// opposed to the move itself failing.
+pub fn is_unreachable(error: &AppError) -> bool {
+    match error {
+        AppError::DestinationOffline(_) => true,
+        AppError::Io(e) | AppError::WebDav(WebDavError::Io(e)) => is_network_error(e),
+        AppError::WebDav(WebDavError::Http(e)) => e.is_connect() || e.is_timeout(),
+        _ => false,
+    }
+}
+
+fn is_network_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::NotConnected
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+            | ErrorKind::NetworkDown
+            | ErrorKind::StaleNetworkFileHandle
+            | ErrorKind::TimedOut
+    )
+}
+
+/// Persists the paths waiting for their destination, one `<destination>`
+/// TAB `<path>` line each. The destination identifies the rule across config
+/// changes better than its position does.
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    /// Returns the journal of the watcher named `watcher` in the rocas
+    /// config directory. The unnamed `[watcher]` keeps the journal of
+    /// versions that had only one watcher.
+    pub fn new(watcher: &str) -> Self {
+        let name = if watcher.is_empty() {
+            JOURNAL_NAME.to_string()
+        } else {
+            let watcher = watcher.replace(|c: char| !c.is_alphanumeric() && c != '-', "_");
+            format!("{JOURNAL_NAME}-{watcher}")
+        };
+        Self { path: config::rocas_dir().join(name) }
+    }
+
+    /// Returns the `(destination, path)` entries saved by a previous run.
+    /// Malformed lines are skipped; a missing journal is empty.
+    pub fn load(&self) -> Vec<(String, PathBuf)> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Vec::new(),
+            Err(e) => {
+                warn!("Could not read the offline queue '{}': {e}", self.path.display());
+                return Vec::new();
+            },
+        };
+
+        contents
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(destination, path)| (destination.to_owned(), PathBuf::from(path)))
+            .collect()
+    }
+
+    /// Replaces the journal with `entries`, removing it when there are none.
+    pub fn save<'e>(&self, entries: impl Iterator<Item = (&'e str, &'e Path)>) {
+        if let Err(e) = self.write(entries) {
+            warn!("Could not save the offline queue '{}': {e}", self.path.display());
+        }
+    }
+
+    fn write<'e>(&self, entries: impl Iterator<Item = (&'e str, &'e Path)>) -> io::Result<()> {
+        let mut contents = String::new();
+        for (destination, path) in entries {
+            let _ = writeln!(contents, "{destination}\t{}", path.display());
+        }
+
+        if contents.is_empty() {
+            return match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp = self.path.with_extension("tmp");
+        std::fs::write(&temp, contents)?;
+        std::fs::rename(&temp, &self.path)
+    }
+}
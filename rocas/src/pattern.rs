@@ -1,26 +1,110 @@
-/// A compiled glob pattern used to match file paths.
+use regex::Regex;
+
+/// Prefix that marks a pattern as a regular expression instead of a glob.
+pub const REGEX_PREFIX: &str = "re:";
+
+/// Error returned by [`Pattern::new`] when a pattern cannot be compiled.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid pattern '{raw}': {source}")]
+pub struct PatternError {
+    pub raw: String,
+    #[source]
+    pub source: regex::Error,
+}
+
+/// A compiled pattern used to match file paths.
+///
+/// Glob patterns support `*` (any characters except `/`), `**` (any characters
+/// including `/`), and `?` (any single character except `/`).
+///
+/// Patterns prefixed with `re:` are regular expressions. They must match the
+/// whole input, and their capture groups can be referenced from the rule
+/// destination as `$1`, `${2}` or `${name}`.
 ///
-/// Supports `*` (any characters except `/`), `**` (any characters including
-/// `/`), and `?` (any single character except `/`).
+/// A pattern ending in `/` (e.g. `Album-*/`) matches directories instead of
+/// files; the trailing slash is not part of what is matched.
 #[derive(Debug, Clone)]
 pub struct Pattern {
     pub raw: String,
+    regex: Option<Regex>,
+    dir: bool,
 }
 
 impl Pattern {
-    /// Creates a new `Pattern` from the given raw glob string.
-    pub fn new(raw: &str) -> Self {
-        Self { raw: raw.to_string() }
+    /// Creates a new `Pattern` from the given raw glob or `re:` string.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PatternError`] if a `re:` pattern is not a valid regular
+    /// expression.
+    pub fn new(raw: &str) -> Result<Self, PatternError> {
+        let dir = raw.len() > 1 && raw.ends_with('/');
+        let body = if dir { &raw[..raw.len() - 1] } else { raw };
+
+        let regex = body
+            .strip_prefix(REGEX_PREFIX)
+            .map(|expr| Regex::new(&format!("^(?:{expr})$")))
+            .transpose()
+            .map_err(|source| PatternError { raw: raw.to_string(), source })?;
+
+        Ok(Self { raw: raw.to_string(), regex, dir })
     }
 
-    /// Returns `true` if this pattern matches `path`.
+    /// Returns the names of this pattern's named capture groups. Glob
+    /// patterns have none.
+    pub fn capture_names(&self) -> impl Iterator<Item = &str> {
+        self.regex
+            .iter()
+            .flat_map(Regex::capture_names)
+            .flatten()
+    }
+
+    /// Returns `true` if this pattern matches directories rather than files.
+    pub fn is_dir(&self) -> bool {
+        self.dir
+    }
+
+    /// The pattern without the trailing `/` of a directory pattern.
+    fn body(&self) -> &str {
+        if self.dir { &self.raw[..self.raw.len() - 1] } else { &self.raw }
+    }
+
+    /// Picks the string this pattern should be matched against.
     ///
-    /// When the pattern contains a `/` it is matched against the full path;
-    /// otherwise it is matched against the filename component only.
+    /// When the pattern contains a `/` (other than a directory pattern's
+    /// trailing one) it is matched against the full path; otherwise it is
+    /// matched against the filename component only.
+    pub fn subject<'a>(&self, full: &'a str, filename: &'a str) -> &'a str {
+        if self.body().contains('/') { full } else { filename }
+    }
+
+    /// Returns `true` if this pattern matches `path`.
     pub fn matches(&self, path: &str) -> bool {
-        // Work with bytes: all glob meta-characters (`*`, `?`, `/`) are ASCII,
-        // so byte-level comparison is correct and avoids Vec<char> allocations.
-        glob_match(self.raw.as_bytes(), path.as_bytes())
+        match &self.regex {
+            Some(regex) => regex.is_match(path),
+            // Work with bytes: all glob meta-characters (`*`, `?`, `/`) are
+            // ASCII, so byte-level comparison is correct and avoids Vec<char>
+            // allocations.
+            None => glob_match(self.body().as_bytes(), path.as_bytes()),
+        }
+    }
+
+    /// Substitutes this pattern's capture groups for `path` into `template`.
+    ///
+    /// Glob patterns have no captures, so the template is returned unchanged.
+    /// The same goes for a regex that does not match `path`.
+    pub fn expand(&self, path: &str, template: &str) -> String {
+        let Some(caps) = self
+            .regex
+            .as_ref()
+            .and_then(|regex| regex.captures(path))
+        else {
+            return template.to_string();
+        };
+
+        let mut expanded = String::with_capacity(template.len());
+        caps.expand(template, &mut expanded);
+        expanded
     }
 }
 
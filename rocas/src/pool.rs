@@ -0,0 +1,62 @@
+//! A fixed-size pool of worker threads for file actions.
+//!
+//! Jobs are routed to a worker by the path they act on, so jobs for the same
+//! path run one after another in submission order while different paths are
+//! processed concurrently. A multi-gigabyte cross-filesystem copy therefore
+//! only holds up the files that happen to share its worker.
+
+use std::hash::{BuildHasher, RandomState};
+use std::path::Path;
+use std::thread::Scope;
+
+use crossbeam_channel::{Sender, unbounded};
+
+type Job<'scope> = Box<dyn FnOnce() + Send + 'scope>;
+
+/// Worker threads bound to a [`std::thread::scope`], so jobs may borrow the
+/// rules and configuration owned by the caller.
+pub struct WorkerPool<'scope> {
+    workers: Vec<Sender<Job<'scope>>>,
+    hasher: RandomState,
+}
+
+impl<'scope> WorkerPool<'scope> {
+    /// Spawns `size` worker threads (at least one) inside `scope`.
+    ///
+    /// The workers exit once the pool is dropped and their queues are empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a worker thread cannot be spawned.
+    pub fn new<'env>(scope: &'scope Scope<'scope, 'env>, size: usize) -> Self {
+        let workers = (0..size.max(1))
+            .map(|i| {
+                let (tx, rx) = unbounded::<Job<'scope>>();
+                std::thread::Builder::new()
+                    .name(format!("rocas-worker-{i}"))
+                    .spawn_scoped(scope, move || {
+                        for job in rx {
+                            job();
+                        }
+                    })
+                    .expect("failed to spawn worker thread");
+                tx
+            })
+            .collect();
+
+        Self { workers, hasher: RandomState::new() }
+    }
+
+    /// Queues `job` on the worker responsible for `key`.
+    pub fn submit(&self, key: &Path, job: impl FnOnce() + Send + 'scope) {
+        let hash = self.hasher.hash_one(key);
+        let idx = usize::try_from(hash % self.workers.len() as u64).unwrap_or_default();
+
+        if self.workers[idx]
+            .send(Box::new(job))
+            .is_err()
+        {
+            error!("Worker for '{}' is gone; dropping the job.", key.display());
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Progress of running file copies.
+//!
+//! Large cross-filesystem moves can take minutes. Copies above the configured
+//! size threshold register a [`Progress`] entry that is updated as data is
+//! written, logged periodically, and can be listed with [`active`].
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How often a running copy logs its progress.
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Copies currently in progress.
+static ACTIVE: Mutex<Vec<Progress>> = Mutex::new(Vec::new());
+
+/// A snapshot of one running copy.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub from: PathBuf,
+    pub dest: PathBuf,
+    pub total: u64,
+    pub copied: u64,
+    pub started: Instant,
+}
+
+impl Progress {
+    /// Percentage of the file copied so far.
+    #[expect(clippy::cast_precision_loss, reason = "only used for display")]
+    pub fn percent(&self) -> f64 {
+        if self.total == 0 { 100.0 } else { self.copied as f64 * 100.0 / self.total as f64 }
+    }
+
+    /// Average transfer speed since the copy started, in bytes per second.
+    #[expect(clippy::cast_precision_loss, reason = "only used for display")]
+    pub fn bytes_per_sec(&self) -> f64 {
+        let secs = self.started.elapsed().as_secs_f64();
+        if secs > 0.0 { self.copied as f64 / secs } else { 0.0 }
+    }
+}
+
+/// Returns the copies that are currently in progress.
+pub fn active() -> Vec<Progress> {
+    ACTIVE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Registers a copy for the duration of its lifetime and logs its progress.
+pub struct Tracker {
+    progress: Progress,
+    last_log: Instant,
+}
+
+impl Tracker {
+    pub fn start(from: &Path, dest: &Path, total: u64) -> Self {
+        let progress = Progress {
+            from: from.to_path_buf(),
+            dest: dest.to_path_buf(),
+            total,
+            copied: 0,
+            started: Instant::now(),
+        };
+
+        info!("Copying {} ({}) → {}", from.display(), format_bytes(total), dest.display());
+        registry().push(progress.clone());
+
+        Self { progress, last_log: Instant::now() }
+    }
+
+    /// Records `bytes` more copied data.
+    pub fn advance(&mut self, bytes: u64) {
+        self.progress.copied += bytes;
+
+        if let Some(entry) = registry()
+            .iter_mut()
+            .find(|p| p.from == self.progress.from)
+        {
+            entry.copied = self.progress.copied;
+        }
+
+        if self.last_log.elapsed() >= LOG_INTERVAL {
+            self.last_log = Instant::now();
+            info!(
+                "Copying {}: {:.0}% ({} of {}) at {}/s",
+                self.progress.from.display(),
+                self.progress.percent(),
+                format_bytes(self.progress.copied),
+                format_bytes(self.progress.total),
+                format_rate(self.progress.bytes_per_sec())
+            );
+        }
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        registry().retain(|p| p.from != self.progress.from);
+
+        debug!(
+            "Copied {} of {} to {} in {:.1}s ({}/s)",
+            format_bytes(self.progress.copied),
+            self.progress.from.display(),
+            self.progress.dest.display(),
+            self.progress
+                .started
+                .elapsed()
+                .as_secs_f64(),
+            format_rate(self.progress.bytes_per_sec())
+        );
+    }
+}
+
+fn registry() -> std::sync::MutexGuard<'static, Vec<Progress>> {
+    ACTIVE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 GiB`.
+#[expect(clippy::cast_precision_loss, reason = "only used for display")]
+pub fn format_bytes(bytes: u64) -> String {
+    format_rate(bytes as f64)
+}
+
+fn format_rate(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{value:.0} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
@@ -0,0 +1,111 @@
+//! `rocas prune-empty`: removes the empty directories that moves leave behind
+//! in the watched directories.
+//!
+//! Directories are removed bottom-up, so a directory holding nothing but
+//! empty directories goes too. The watched directories themselves are kept,
+//! symlinks are not followed, and directories matching an `--ignore` pattern
+//! are kept along with everything inside them.
+
+use std::path::{Path, PathBuf};
+
+use crate::pattern::Pattern;
+use crate::rule::RuleError;
+use crate::{AppError, engine};
+
+/// Removes the empty directories under each of `roots`, or only prints them
+/// if `dry_run` is set. Returns `false` if a directory could not be read or
+/// removed.
+///
+/// # Errors
+///
+/// Returns [`AppError::Rule`] if an `ignore` pattern is not a valid regular
+/// expression.
+pub fn run(roots: &[PathBuf], ignore: &[String], dry_run: bool) -> Result<bool, AppError> {
+    let ignore = ignore
+        .iter()
+        .map(|raw| Pattern::new(raw).map_err(RuleError::from))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut pruner = Pruner {
+        ignore,
+        dry_run,
+        removed: 0,
+        failed: false,
+    };
+    for root in roots {
+        if !root.is_dir() {
+            println!("'{}' is not a directory; skipping.", root.display());
+            pruner.failed = true;
+            continue;
+        }
+        pruner.prune(root);
+    }
+
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    match pruner.removed {
+        0 => println!("No empty directories found."),
+        1 => println!("{verb} 1 empty directory."),
+        n => println!("{verb} {n} empty directories."),
+    }
+    Ok(!pruner.failed)
+}
+
+struct Pruner {
+    ignore: Vec<Pattern>,
+    dry_run: bool,
+    removed: usize,
+    failed: bool,
+}
+
+impl Pruner {
+    /// Removes the empty directories below `dir`. Returns `true` if `dir` is
+    /// empty afterwards, counting directories a dry run would remove.
+    fn prune(&mut self, dir: &Path) -> bool {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("Could not read '{}': {e}", dir.display());
+                self.failed = true;
+                return false;
+            },
+        };
+
+        let mut empty = true;
+        for entry in entries {
+            let Ok(entry) = entry else {
+                empty = false;
+                continue;
+            };
+            let path = entry.path();
+            // `file_type` does not follow symlinks.
+            let is_dir = entry
+                .file_type()
+                .is_ok_and(|kind| kind.is_dir());
+            if !is_dir || self.ignored(&path) || !self.prune(&path) {
+                empty = false;
+                continue;
+            }
+
+            if self.dry_run {
+                println!("Would remove {}", path.display());
+            } else if let Err(e) = std::fs::remove_dir(&path) {
+                // Something may have been moved into it meanwhile.
+                println!("Could not remove '{}': {e}", path.display());
+                self.failed = true;
+                empty = false;
+                continue;
+            } else {
+                println!("Removed {}", path.display());
+            }
+            self.removed += 1;
+        }
+        empty
+    }
+
+    fn ignored(&self, dir: &Path) -> bool {
+        let (full, filename) = engine::match_subjects(dir);
+        self.ignore
+            .iter()
+            .any(|pattern| pattern.matches(pattern.subject(&full, &filename)))
+    }
+}
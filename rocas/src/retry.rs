@@ -0,0 +1,27 @@
+//! Retries for failed file actions.
+//!
+//! A move can fail for transient reasons, most often because an antivirus
+//! scanner or the browser briefly locks a fresh download on Windows. Failed
+//! files are retried with exponential backoff instead of being skipped.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Upper bound for the delay between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_mins(5);
+
+/// A file whose action failed and is waiting for another attempt.
+pub struct FailedMove {
+    pub path: PathBuf,
+    /// Number of attempts that already failed.
+    pub failures: u32,
+    pub retry_at: Instant,
+}
+
+/// Returns the delay before the attempt following `failed` failed attempts:
+/// `base`, doubled for every further failure, capped at [`MAX_BACKOFF`].
+pub fn backoff(base: Duration, failed: u32) -> Duration {
+    let factor = 1_u32 << failed.saturating_sub(1).min(16);
+    base.saturating_mul(factor)
+        .min(MAX_BACKOFF)
+}
@@ -0,0 +1,131 @@
+//! Minimum versions and staged rollouts of releases.
+//!
+//! A release can limit who updates to it with lines in its release notes,
+//! which may sit inside an HTML comment to keep them off the release page:
+//!
+//! ```text
+//! <!--
+//! minimum_version: 1.4.0
+//! rollout: 25%
+//! -->
+//! ```
+//!
+//! `minimum_version` makes rocas older than that skip the release, so they
+//! update to an earlier one first and reach this one from there. `rollout`
+//! offers the release to only that share of installations. Each
+//! installation draws a random id once, kept in `rollout_id` in the rocas
+//! config directory, and is in the rollout if the hash of its id and the
+//! release version falls in the share. Raising the share keeps the
+//! installations already in it, and which ones come first differs from
+//! release to release.
+
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::config;
+
+pub const ROLLOUT_ID_NAME: &str = "rollout_id";
+
+/// Who a release is offered to, from its release notes.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Gate {
+    /// The oldest version that may update to the release.
+    pub minimum_version: Option<String>,
+    /// The percentage of installations the release is offered to.
+    pub rollout: Option<u8>,
+}
+
+impl Gate {
+    /// Reads the gate from the release notes `notes`. Lines that are not
+    /// `key: value` or `key = value` with a known key are ignored.
+    pub fn parse(notes: &str) -> Self {
+        let mut gate = Self::default();
+        for line in notes.lines() {
+            let line = line
+                .trim()
+                .trim_start_matches("<!--")
+                .trim_end_matches("-->");
+            let Some((key, value)) = line.split_once([':', '=']) else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "minimum_version" => {
+                    gate.minimum_version = Some(
+                        value
+                            .trim_start_matches('v')
+                            .to_string(),
+                    );
+                },
+                "rollout" => {
+                    gate.rollout = value
+                        .trim_end_matches('%')
+                        .trim_end()
+                        .parse::<u8>()
+                        .ok()
+                        .map(|percent| percent.min(100));
+                },
+                _ => {},
+            }
+        }
+        gate
+    }
+}
+
+/// Returns `true` if the installation with `id` is among the `percent`
+/// percent that `version` is rolled out to.
+pub fn includes(id: &str, version: &str, percent: u8) -> bool {
+    let digest = Sha256::new()
+        .chain_update(id)
+        .chain_update([0])
+        .chain_update(version)
+        .finalize();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes) % 100 < u64::from(percent)
+}
+
+/// Returns the rollout id of this installation, drawing and saving one if
+/// there is none yet. If it cannot be saved, the id only lasts for this run.
+pub fn installation_id() -> String {
+    let path = id_path();
+    if let Ok(id) = std::fs::read_to_string(&path) {
+        let id = id.trim();
+        if !id.is_empty() {
+            return id.to_string();
+        }
+    }
+
+    let id = random_id();
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(&path, &id));
+    if let Err(e) = written {
+        warn!("Could not save the rollout id in '{}': {e}", path.display());
+    }
+    id
+}
+
+/// Returns 32 hex digits that differ between installations.
+fn random_id() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let digest = Sha256::new()
+        .chain_update(now.as_nanos().to_le_bytes())
+        .chain_update(std::process::id().to_le_bytes())
+        .chain_update(
+            config::rocas_dir()
+                .as_os_str()
+                .as_encoded_bytes(),
+        )
+        .finalize();
+    format!("{digest:x}")[..32].to_string()
+}
+
+fn id_path() -> PathBuf {
+    config::rocas_dir().join(ROLLOUT_ID_NAME)
+}
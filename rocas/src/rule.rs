@@ -0,0 +1,183 @@
+//! Rules compiled once at startup from [`RuleConfig`] entries.
+
+use std::time::Duration;
+
+use crate::archive::{self, ArchiveError};
+use crate::condition::Contains;
+use crate::config::{Action, RuleConfig};
+use crate::encrypt::{EncryptError, Encryptor};
+use crate::pattern::{Pattern, PatternError};
+use crate::sanitize::{SanitizeError, Sanitizer};
+use crate::template::{Template, TemplateError};
+use crate::throttle::RateLimiter;
+use crate::webdav::{WebDav, WebDavError};
+
+/// Error returned by [`CompiledRule::new`].
+#[derive(Debug, thiserror::Error)]
+pub enum RuleError {
+    #[error(transparent)]
+    Pattern(#[from] PatternError),
+
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+
+    #[error(transparent)]
+    Sanitize(#[from] SanitizeError),
+
+    #[error(transparent)]
+    WebDav(#[from] WebDavError),
+
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+
+    #[error(transparent)]
+    Encrypt(#[from] EncryptError),
+
+    #[error("conflict_suffix '{0}' must contain {{n}}")]
+    ConflictSuffix(String),
+}
+
+/// A [`RuleConfig`] with its patterns, conditions and templates compiled,
+/// ready to be evaluated against filesystem events.
+pub struct CompiledRule<'a> {
+    pub config: &'a RuleConfig,
+    pub patterns: Vec<Pattern>,
+    pub contains: Option<Contains>,
+    pub rename: Option<Template>,
+    pub rate_limit: Option<RateLimiter>,
+    pub sanitizer: Option<Sanitizer>,
+    /// The server to upload to, for a `webdav://` destination.
+    pub remote: Option<WebDav>,
+    /// Whether matches are added to a zip archive (a `zip:` destination).
+    pub archive: bool,
+    /// Encrypts matches with age, for a rule with `encrypt = true`.
+    pub encryptor: Option<Encryptor>,
+}
+
+impl<'a> CompiledRule<'a> {
+    /// Compiles all patterns, conditions and templates of `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RuleError`] if a pattern, a `contains` regex, the `rename`
+    /// template, the `conflict_suffix`, the sanitize settings, a WebDAV or
+    /// zip destination or the encryption settings are invalid.
+    pub fn new(config: &'a RuleConfig) -> Result<Self, RuleError> {
+        if !config.conflict_suffix.contains("{n}") {
+            return Err(RuleError::ConflictSuffix(config.conflict_suffix.clone()));
+        }
+
+        let contains = config
+            .contains
+            .as_deref()
+            .map(|raw| Contains::new(raw, config.contains_max_size_kb * 1024))
+            .transpose()?;
+
+        let rename = config
+            .rename
+            .as_deref()
+            .map(Template::new)
+            .transpose()?;
+
+        let rate_limit = RateLimiter::new(
+            config.rate_limit_files,
+            config
+                .rate_limit_mb
+                .map(|mb| mb * 1024 * 1024),
+            Duration::from_secs(config.rate_limit_window_secs),
+        );
+
+        let sanitizer = config
+            .sanitize
+            .then(|| Sanitizer::new(&config.sanitize_replacement, &config.sanitize_map))
+            .transpose()?;
+
+        let remote = WebDav::new(config)?;
+        // A counter needs to list the destination directory.
+        if remote.is_some()
+            && rename
+                .as_ref()
+                .is_some_and(Template::has_counter)
+        {
+            return Err(WebDavError::Unsupported("{counter} in rename").into());
+        }
+
+        let archive = archive::is_archive(&config.destination);
+        if archive {
+            archive::validate(
+                config,
+                rename
+                    .as_ref()
+                    .is_some_and(Template::has_counter),
+            )?;
+        }
+
+        let encryptor = Encryptor::new(config)?;
+        let patterns = config.compiled_patterns()?;
+        // age encrypts a single stream, not a directory tree.
+        if encryptor.is_some() && patterns.iter().any(Pattern::is_dir) {
+            return Err(EncryptError::Unsupported("a directory pattern").into());
+        }
+
+        Ok(Self {
+            config,
+            patterns,
+            contains,
+            rename,
+            rate_limit,
+            sanitizer,
+            remote,
+            archive,
+            encryptor,
+        })
+    }
+
+    /// Returns `true` if the rule's `rename` template reserves a fresh
+    /// destination name (it uses `{counter}`).
+    pub fn reserves_destination(&self) -> bool {
+        self.rename
+            .as_ref()
+            .is_some_and(Template::has_counter)
+    }
+
+    /// Describes what the rule does with a matched file.
+    pub fn action_name(&self) -> &'static str {
+        if self.remote.is_some() {
+            "upload"
+        } else if self.archive {
+            "add to archive"
+        } else if self.encryptor.is_some() {
+            "encrypt"
+        } else {
+            match self.config.action() {
+                Action::Move => "move",
+                Action::Hardlink => "hardlink",
+            }
+        }
+    }
+
+    /// Returns `true` if any of the rule's patterns matches directories.
+    pub fn matches_dirs(&self) -> bool {
+        self.patterns
+            .iter()
+            .any(Pattern::is_dir)
+    }
+
+    /// Returns the first pattern matching the event path, together with the
+    /// string it was matched against (the full path or just the filename).
+    ///
+    /// Directory patterns only match when `is_dir` is set, file patterns only
+    /// when it is not.
+    pub fn match_pattern<'p>(
+        &self,
+        full: &'p str,
+        filename: &'p str,
+        is_dir: bool,
+    ) -> Option<(&Pattern, &'p str)> {
+        self.patterns
+            .iter()
+            .filter(|p| p.is_dir() == is_dir)
+            .map(|p| (p, p.subject(full, filename)))
+            .find(|(p, subject)| p.matches(subject))
+    }
+}
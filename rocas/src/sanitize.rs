@@ -0,0 +1,110 @@
+//! Filename sanitization for restrictive destination filesystems.
+//!
+//! exFAT, FAT32, NTFS and SMB shares reject names that are fine on Linux and
+//! macOS: characters like `:` and `?`, names ending in a dot or space, and DOS
+//! device names like `CON`. A rule with `sanitize = true` rewrites the
+//! destination filename with a [`Sanitizer`] so the move does not fail.
+
+/// Characters Windows filesystems and SMB shares do not allow in names.
+const ILLEGAL: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// DOS device names, reserved on Windows with or without an extension.
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Error returned by [`Sanitizer::new`].
+#[derive(Debug, thiserror::Error)]
+pub enum SanitizeError {
+    #[error("sanitize_map entry '{0}' must look like '<char>=<replacement>'")]
+    MapEntry(String),
+
+    #[error("sanitize replacement '{0}' contains characters that are not allowed in filenames")]
+    Replacement(String),
+}
+
+/// Rewrites filenames so they are valid on Windows-compatible filesystems.
+pub struct Sanitizer {
+    /// Replacement for illegal characters without an entry in `map`.
+    replacement: String,
+    /// Per-character replacements, checked before the default one.
+    map: Vec<(char, String)>,
+}
+
+impl Sanitizer {
+    /// Creates a sanitizer from the rule's `sanitize_replacement` and
+    /// `sanitize_map` entries (`"<char>=<replacement>"`, e.g. `":= -"`).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SanitizeError`] if a map entry is malformed or a replacement
+    /// contains illegal characters itself.
+    pub fn new(replacement: &str, map: &[String]) -> Result<Self, SanitizeError> {
+        check_replacement(replacement)?;
+
+        let map = map
+            .iter()
+            .map(|entry| {
+                let mut chars = entry.chars();
+                let (Some(from), Some('=')) = (chars.next(), chars.next()) else {
+                    return Err(SanitizeError::MapEntry(entry.clone()));
+                };
+                let to = chars.as_str();
+                check_replacement(to)?;
+                Ok((from, to.to_owned()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { replacement: replacement.to_owned(), map })
+    }
+
+    /// Returns `name` with illegal characters replaced, trailing dots and
+    /// spaces removed and reserved device names suffixed with `_`.
+    pub fn clean(&self, name: &str) -> String {
+        let replaced = self.replace_chars(name);
+        let trimmed = replaced.trim_end_matches(['.', ' ']);
+        let mut clean = if trimmed.is_empty() { "_".to_owned() } else { trimmed.to_owned() };
+
+        // `CON` and `CON.txt` are both reserved; `CON_.txt` is not.
+        let stem_len = clean.find('.').unwrap_or(clean.len());
+        if RESERVED
+            .iter()
+            .any(|reserved| clean[..stem_len].eq_ignore_ascii_case(reserved))
+        {
+            clean.insert(stem_len, '_');
+        }
+
+        clean
+    }
+
+    /// Replaces illegal and mapped characters in `text`, which may be just a
+    /// part of a filename.
+    pub fn replace_chars(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            if let Some((_, to)) = self
+                .map
+                .iter()
+                .find(|(from, _)| *from == c)
+            {
+                out.push_str(to);
+            } else if ILLEGAL.contains(&c) || c.is_control() {
+                out.push_str(&self.replacement);
+            } else {
+                out.push(c);
+            }
+        }
+        out
+    }
+}
+
+fn check_replacement(replacement: &str) -> Result<(), SanitizeError> {
+    if replacement
+        .chars()
+        .any(|c| ILLEGAL.contains(&c) || c.is_control())
+    {
+        return Err(SanitizeError::Replacement(replacement.to_owned()));
+    }
+    Ok(())
+}
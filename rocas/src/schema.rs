@@ -0,0 +1,440 @@
+//! `rocas schema`: a JSON Schema of the config file.
+//!
+//! Editors use it to validate and complete the config, e.g. through taplo's
+//! `#:schema` directive or VS Code's `json.schemas` / `yaml.schemas`
+//! settings. The schema is built from the `FIELDS` tables in
+//! [`crate::config`], which `rocas check` also uses to report unknown keys.
+//! [`with_fields`] generates each table from the struct it describes, with
+//! the names, help and defaults of its `#[field]` attributes, so a table
+//! cannot miss a field or disagree with it.
+
+use serde_json::{Map, Value, json};
+
+use crate::config::{MiscConfig, RuleConfig, WatcherConfig};
+use crate::{migrate, when};
+
+/// The type of a config value, with its default where it is a constant.
+#[derive(Debug, Clone, Copy)]
+pub enum Kind {
+    Bool(bool),
+    /// A non-negative integer.
+    Integer(Option<u64>),
+    String(Option<&'static str>),
+    /// One of a fixed set of strings; the second value is the default.
+    OneOf(&'static [&'static str], &'static str),
+    Strings,
+}
+
+impl Kind {
+    /// The kind of a value of `shape` with the default `default`, or one of
+    /// `values` if it is limited to them.
+    pub const fn of(
+        shape: Shape,
+        default: DefaultValue,
+        values: Option<&'static [&'static str]>,
+    ) -> Self {
+        match (shape, default, values) {
+            (_, DefaultValue::String(default), Some(values)) => Self::OneOf(values, default),
+            (Shape::Bool, DefaultValue::Bool(default), _) => Self::Bool(default),
+            (Shape::Bool, ..) => Self::Bool(false),
+            (Shape::Integer, DefaultValue::Integer(default), _) => Self::Integer(Some(default)),
+            (Shape::Integer, ..) => Self::Integer(None),
+            (Shape::String, DefaultValue::String(default), _) => Self::String(Some(default)),
+            (Shape::String, ..) => Self::String(None),
+            (Shape::Strings, ..) => Self::Strings,
+        }
+    }
+}
+
+/// What a config value looks like, whatever its default.
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+    Bool,
+    Integer,
+    String,
+    Strings,
+}
+
+/// The Rust types of config fields and the [`Shape`] of their values.
+pub trait Shaped {
+    const SHAPE: Shape;
+}
+
+impl Shaped for bool {
+    const SHAPE: Shape = Shape::Bool;
+}
+
+impl Shaped for u32 {
+    const SHAPE: Shape = Shape::Integer;
+}
+
+impl Shaped for u64 {
+    const SHAPE: Shape = Shape::Integer;
+}
+
+impl Shaped for usize {
+    const SHAPE: Shape = Shape::Integer;
+}
+
+impl Shaped for String {
+    const SHAPE: Shape = Shape::String;
+}
+
+impl Shaped for Vec<String> {
+    const SHAPE: Shape = Shape::Strings;
+}
+
+impl<T: Shaped> Shaped for Option<T> {
+    const SHAPE: Shape = T::SHAPE;
+}
+
+/// The `default` of a `#[field]`, as far as it is a constant.
+#[derive(Debug, Clone, Copy)]
+pub enum DefaultValue {
+    /// No default: the key has to be set.
+    Required,
+    /// A default computed when the config is loaded, like a directory.
+    Computed,
+    Bool(bool),
+    Integer(u64),
+    String(&'static str),
+}
+
+/// A config key and what it accepts.
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub help: &'static str,
+    pub required: bool,
+}
+
+/// Declares a forgeconf config struct and its `FIELDS` table, built from
+/// the `#[field]` attribute of every field: its `name` or the field's own,
+/// its `help`, and the kind of value given by the field's type, its
+/// `default` and the values a `one_of` validator allows. A field without a
+/// `default` is required.
+macro_rules! with_fields {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[doc = $doc:literal])*
+                #[field($($args:tt)*)]
+                $field_vis:vis $field:ident: $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $(
+                $(#[doc = $doc])*
+                #[field($($args)*)]
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl $name {
+            /// Keys accepted in this table, used to report unknown keys and
+            /// to build the schema.
+            pub const FIELDS: &[$crate::schema::Field] =
+                &[$($crate::schema::field_entry!($field: $ty; $($args)*)),*];
+        }
+    };
+}
+
+/// Builds the [`Field`] of one field of [`with_fields`] from its type and
+/// the arguments of its `#[field]` attribute.
+macro_rules! field_entry {
+    ($field:ident: $ty:ty; $($args:tt)*) => {
+        $crate::schema::field_entry!(
+            @parse [$ty] [stringify!($field)] [""] [$crate::schema::DefaultValue::Required] [None];
+            $($args)*
+        )
+    };
+    (@parse [$ty:ty] [$name:expr] [$help:expr] [$default:expr] [$values:expr];) => {
+        $crate::schema::Field {
+            name: $name,
+            kind: $crate::schema::Kind::of(
+                <$ty as $crate::schema::Shaped>::SHAPE,
+                $default,
+                $values,
+            ),
+            help: $help,
+            required: matches!($default, $crate::schema::DefaultValue::Required),
+        }
+    };
+    (@parse $ty:tt $name:tt $help:tt $default:tt $values:tt; , $($rest:tt)*) => {
+        $crate::schema::field_entry!(@parse $ty $name $help $default $values; $($rest)*)
+    };
+    (
+        @parse $ty:tt $_name:tt $help:tt $default:tt $values:tt;
+        name = $name:literal $($rest:tt)*
+    ) => {
+        $crate::schema::field_entry!(@parse $ty [$name] $help $default $values; $($rest)*)
+    };
+    (
+        @parse $ty:tt $name:tt $_help:tt $default:tt $values:tt;
+        help = $help:literal $($rest:tt)*
+    ) => {
+        $crate::schema::field_entry!(@parse $ty $name [$help] $default $values; $($rest)*)
+    };
+    (@parse $ty:tt $name:tt $help:tt $_default:tt $values:tt; default = true $($rest:tt)*) => {
+        $crate::schema::field_entry!(
+            @parse $ty $name $help [$crate::schema::DefaultValue::Bool(true)] $values;
+            $($rest)*
+        )
+    };
+    (@parse $ty:tt $name:tt $help:tt $_default:tt $values:tt; default = false $($rest:tt)*) => {
+        $crate::schema::field_entry!(
+            @parse $ty $name $help [$crate::schema::DefaultValue::Bool(false)] $values;
+            $($rest)*
+        )
+    };
+    (
+        @parse $ty:tt $name:tt $help:tt $_default:tt $values:tt;
+        default = $default:literal.to_string() $($rest:tt)*
+    ) => {
+        $crate::schema::field_entry!(
+            @parse $ty $name $help [$crate::schema::DefaultValue::String($default)] $values;
+            $($rest)*
+        )
+    };
+    (
+        @parse $ty:tt $name:tt $help:tt $_default:tt $values:tt;
+        default = $default:literal $($rest:tt)*
+    ) => {
+        $crate::schema::field_entry!(
+            @parse $ty $name $help [$crate::schema::DefaultValue::Integer($default)] $values;
+            $($rest)*
+        )
+    };
+    (
+        @parse $ty:tt $name:tt $help:tt $_default:tt $values:tt;
+        default = $default:expr $(, $($rest:tt)*)?
+    ) => {
+        $crate::schema::field_entry!(
+            @parse $ty $name $help [$crate::schema::DefaultValue::Computed] $values;
+            $($($rest)*)?
+        )
+    };
+    (
+        @parse $ty:tt $name:tt $help:tt $default:tt $_values:tt;
+        validate = forgeconf::validators::one_of([$($value:literal.to_string()),* $(,)?])
+        $($rest:tt)*
+    ) => {
+        $crate::schema::field_entry!(@parse $ty $name $help $default [Some(&[$($value),*])]; $($rest)*)
+    };
+}
+
+pub(crate) use {field_entry, with_fields};
+
+/// Returns the names of `fields`.
+pub fn names(fields: &[Field]) -> Vec<&'static str> {
+    fields
+        .iter()
+        .map(|field| field.name)
+        .collect()
+}
+
+/// Prints the schema to stdout.
+pub fn run() {
+    let schema = generate();
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap_or_default());
+}
+
+/// Builds the schema of the whole config file.
+pub fn generate() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "rocas",
+        "description": "Configuration of the rocas file organizer",
+        "type": "object",
+        "properties": {
+            "version": {
+                "description": "Config format version; upgrade with rocas migrate",
+                "type": "integer",
+                "minimum": 0,
+                "maximum": migrate::CURRENT_VERSION,
+            },
+            "include": {
+                "description": "Files with more rules to load; * and ? match in the file name",
+                "type": "array",
+                "items": { "type": "string" },
+            },
+            "watcher": {
+                "anyOf": [
+                    section("Settings for the filesystem watcher", WatcherConfig::FIELDS),
+                    {
+                        "description": "Several watchers, each with its own settings and rules",
+                        "type": "array",
+                        "items": section("One watcher", WatcherConfig::FIELDS),
+                    },
+                ],
+            },
+            "misc": section("Logging and update settings", MiscConfig::FIELDS),
+            "patterns": {
+                "description": "Named pattern lists that rules reference as @name",
+                "type": "object",
+                "additionalProperties": { "type": "array", "items": { "type": "string" } },
+            },
+            "defaults": defaults(),
+            "rules": {
+                "description": "Rules tried in order against every new file",
+                "type": "array",
+                "items": rule(),
+            },
+        },
+        "additionalProperties": false,
+    })
+}
+
+/// Builds the schema of `[watcher]`, a `[[watcher]]` entry or `[misc]`,
+/// whose `when` entries replace some of its settings on matching machines.
+fn section(description: &str, fields: &[Field]) -> Value {
+    let mut entry = object("Settings that only apply where the conditions hold", fields);
+    if let Some(properties) = entry["properties"].as_object_mut() {
+        properties.extend(conditions());
+    }
+
+    let mut schema = object(description, fields);
+    schema["properties"][when::WHEN_KEY] = json!({ "type": "array", "items": entry });
+    schema
+}
+
+fn rule() -> Value {
+    let mut schema = object("Where files matching the patterns go", RuleConfig::FIELDS);
+    schema["properties"][when::WHEN_KEY] = json!({
+        "description": "Only use this rule where all conditions hold",
+        "type": "object",
+        "properties": conditions(),
+        "additionalProperties": false,
+    });
+    schema
+}
+
+/// Every rule key but `patterns`, none of them required.
+fn defaults() -> Value {
+    let fields: Vec<Field> = RuleConfig::FIELDS
+        .iter()
+        .filter(|field| field.name != "patterns")
+        .map(|field| Field { required: false, ..*field })
+        .collect();
+    object("Settings inherited by every rule that does not set them", &fields)
+}
+
+/// The schemas of the keys a `when` table tests.
+fn conditions() -> Map<String, Value> {
+    let one_or_more = |description: &str| {
+        json!({
+            "description": description,
+            "anyOf": [
+                { "type": "string" },
+                { "type": "array", "items": { "type": "string" } },
+            ],
+        })
+    };
+
+    Map::from_iter([
+        ("os".to_string(), one_or_more("Operating system: linux, macos, windows or unix")),
+        ("hostname".to_string(), one_or_more("Machine name, with or without the domain")),
+    ])
+}
+
+/// Builds the schema of a table with the keys in `fields`.
+fn object(description: &str, fields: &[Field]) -> Value {
+    let properties: Map<String, Value> = fields
+        .iter()
+        .map(|field| (field.name.to_string(), property(field)))
+        .collect();
+    let required: Vec<&str> = fields
+        .iter()
+        .filter(|field| field.required)
+        .map(|field| field.name)
+        .collect();
+
+    let mut schema = json!({
+        "description": description,
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    });
+    if !required.is_empty() {
+        schema["required"] = json!(required);
+    }
+    schema
+}
+
+fn property(field: &Field) -> Value {
+    let mut schema = match field.kind {
+        Kind::Bool(default) => json!({ "type": "boolean", "default": default }),
+        Kind::Integer(default) => json!({ "type": "integer", "minimum": 0, "default": default }),
+        Kind::String(default) => json!({ "type": "string", "default": default }),
+        Kind::OneOf(values, default) => json!({ "enum": values, "default": default }),
+        Kind::Strings => json!({ "type": "array", "items": { "type": "string" } }),
+    };
+
+    // An unknown default is left out rather than given as null.
+    if let Some(object) = schema.as_object_mut()
+        && object
+            .get("default")
+            .is_some_and(Value::is_null)
+    {
+        object.remove("default");
+    }
+    schema["description"] = json!(field.help);
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Field, Kind};
+    use crate::config::{MiscConfig, RuleConfig, WatcherConfig};
+
+    fn field(fields: &[Field], name: &str) -> Field {
+        *fields
+            .iter()
+            .find(|field| field.name == name)
+            .expect("the field is listed")
+    }
+
+    #[test]
+    fn tables_list_the_fields_with_their_defaults() {
+        let verify = field(WatcherConfig::FIELDS, "verify_copies");
+        assert!(matches!(verify.kind, Kind::OneOf(["none", "size", "hash"], "size")));
+        assert!(!verify.required);
+        assert!(matches!(field(WatcherConfig::FIELDS, "watch_path").kind, Kind::String(None)));
+        assert!(matches!(field(WatcherConfig::FIELDS, "max_depth").kind, Kind::Integer(None)));
+        assert!(matches!(field(MiscConfig::FIELDS, "tray").kind, Kind::Bool(true)));
+        assert!(matches!(field(MiscConfig::FIELDS, "log_file").kind, Kind::String(None)));
+        assert!(matches!(
+            field(RuleConfig::FIELDS, "conflict_suffix").kind,
+            Kind::String(Some(" ({n})"))
+        ));
+        assert!(matches!(
+            field(RuleConfig::FIELDS, "rate_limit_window_secs").kind,
+            Kind::Integer(Some(3600))
+        ));
+        assert!(matches!(field(RuleConfig::FIELDS, "encrypt_recipients").kind, Kind::Strings));
+    }
+
+    #[test]
+    fn fields_without_a_default_are_required() {
+        let required: Vec<&str> = RuleConfig::FIELDS
+            .iter()
+            .filter(|field| field.required)
+            .map(|field| field.name)
+            .collect();
+        assert_eq!(required, ["patterns", "destination"]);
+        assert!(
+            !WatcherConfig::FIELDS
+                .iter()
+                .any(|field| field.required)
+        );
+        assert!(
+            !MiscConfig::FIELDS
+                .iter()
+                .any(|field| field.required)
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Driving whatever starts rocas with the system, for `rocas setup`,
+//! `rocas unsetup`, `rocas start`, `rocas stop` and `rocas restart`.
+//!
+//! Every way rocas can start with the system is a [`ServiceManager`]: the
+//! login items, and on each platform its own tasks, services and autostart
+//! entries, which [`crate::autostart`] implements. Setting rocas up,
+//! removing it, reading back what is set up and starting or stopping it all
+//! go through the managers of the platform, the same way everywhere.
+
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::AppError;
+use crate::autostart::{self, AutostartError, Installed, Launch, Mode};
+
+/// One way rocas starts with the system.
+pub trait ServiceManager {
+    /// The mode of `rocas setup` it sets up.
+    fn mode(&self) -> Mode;
+
+    /// Sets rocas up to start as `launch` says. Returns what was set up,
+    /// for the user.
+    fn install(&self, launch: &Launch) -> Result<String, AppError>;
+
+    /// Stops it from starting rocas, if it is set up.
+    fn uninstall(&self) -> Result<(), AppError>;
+
+    /// Starts rocas.
+    fn start(&self) -> Result<(), AppError>;
+
+    /// Stops rocas.
+    fn stop(&self) -> Result<(), AppError>;
+
+    /// Restarts rocas. Managers that can restart rocas themselves do it
+    /// instead of stopping and starting it.
+    fn restart(&self) -> Result<(), AppError> {
+        self.stop()?;
+        self.start()
+    }
+
+    /// Returns what is set up, or `None` if it does not start rocas.
+    fn status(&self) -> Result<Option<Installed>, AppError>;
+}
+
+/// What to do to the rocas set up to start with the system, for `rocas
+/// start`, `rocas stop` and `rocas restart`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Action {
+    Start,
+    Stop,
+    Restart,
+}
+
+impl Action {
+    const fn done(self) -> &'static str {
+        match self {
+            Self::Start => "started",
+            Self::Stop => "stopped",
+            Self::Restart => "restarted",
+        }
+    }
+}
+
+/// Makes rocas start with the system in `mode` as `launch` says, and stops
+/// every other manager from starting it too. With `highest`, a task runs
+/// with the highest privileges available to the user. If a mode has more
+/// than one manager, the first that can be set up is used. Returns what was
+/// set up, for the user.
+///
+/// # Errors
+///
+/// Returns [`AppError`] if `mode` is not available on this platform or
+/// cannot be set up.
+pub fn install(mode: Mode, highest: bool, launch: &Launch) -> Result<String, AppError> {
+    set_up(&autostart::managers(highest), mode, launch)
+}
+
+/// Sets up the first of `managers` in `mode` that can be set up, and stops
+/// the others from starting rocas. See [`install`].
+///
+/// # Errors
+///
+/// Returns [`AppError`] if none of `managers` in `mode` can be set up.
+pub fn set_up(
+    managers: &[Box<dyn ServiceManager + '_>],
+    mode: Mode,
+    launch: &Launch,
+) -> Result<String, AppError> {
+    let mut failed: Option<AppError> = None;
+    for (idx, manager) in managers.iter().enumerate() {
+        if manager.mode() != mode {
+            continue;
+        }
+        match manager.install(launch) {
+            Ok(done) => {
+                for (other, manager) in managers.iter().enumerate() {
+                    if other != idx {
+                        manager.uninstall()?;
+                    }
+                }
+                return Ok(match failed {
+                    Some(e) => format!("{done} The preferred way could not be set up: {e}."),
+                    None => done,
+                });
+            },
+            Err(e) => {
+                failed.get_or_insert(e);
+            },
+        }
+    }
+    Err(failed.unwrap_or_else(|| AutostartError::Unsupported(mode.option()).into()))
+}
+
+/// Stops rocas from starting with the system, in any mode.
+///
+/// # Errors
+///
+/// Returns [`AppError`] if a manager that is set up cannot be removed.
+pub fn uninstall() -> Result<(), AppError> {
+    for manager in autostart::managers(false) {
+        manager.uninstall()?;
+    }
+    Ok(())
+}
+
+/// Starts, stops or restarts rocas through whatever starts it with the
+/// system. Returns what was done, for the user.
+///
+/// # Errors
+///
+/// Returns [`AppError`] if rocas is not set up to start exactly once, its
+/// manager cannot start or stop it, or the manager fails.
+pub fn control(action: Action) -> Result<String, AppError> {
+    let mut set_up = Vec::new();
+    for manager in autostart::managers(false) {
+        if let Some(entry) = manager.status()? {
+            set_up.push((manager, entry));
+        }
+    }
+    let (manager, entry) = match set_up.as_slice() {
+        [] => return Err(AutostartError::NotSetUp.into()),
+        [set_up] => set_up,
+        _ => return Err(AutostartError::SetUpTwice.into()),
+    };
+    match action {
+        Action::Start => manager.start()?,
+        Action::Stop => manager.stop()?,
+        Action::Restart => manager.restart()?,
+    }
+    Ok(format!("rocas {} ({}).", action.done(), entry.location))
+}
+
+/// Returns what rocas is set up to start from. More than one entry means
+/// rocas was set up by hand or by an older rocas, and starts twice.
+///
+/// # Errors
+///
+/// Returns [`AppError`] if what is set up cannot be read.
+pub fn installed() -> Result<Vec<Installed>, AppError> {
+    let mut found = Vec::new();
+    for manager in autostart::managers(false) {
+        found.extend(manager.status()?);
+    }
+    Ok(found)
+}
+
+/// Prints how rocas is set up to start, for `rocas setup --status`, as
+/// JSON if `json` is set. Returns `false` if it does not start with the
+/// system, or starts another rocas binary than this one.
+///
+/// # Errors
+///
+/// Returns [`AppError`] if what is set up cannot be read.
+pub fn print_status(json: bool) -> Result<bool, AppError> {
+    let exe = std::env::current_exe()?;
+    let installed = installed()?;
+    let current = |entry: &Installed| {
+        entry
+            .command
+            .as_deref()
+            .is_some_and(|command| runs(command, &exe))
+    };
+    let healthy = !installed.is_empty() && installed.iter().all(current);
+
+    if json {
+        let entries: Vec<_> = installed
+            .iter()
+            .map(|entry| {
+                json!({
+                    "mode": entry.mode.name(),
+                    "location": entry.location,
+                    "command": entry.command,
+                    "current_binary": current(entry),
+                    "running": entry.running,
+                })
+            })
+            .collect();
+        println!("{:#}", json!({ "binary": exe.display().to_string(), "installed": entries }));
+        return Ok(healthy);
+    }
+
+    if installed.is_empty() {
+        println!(
+            "rocas does not start with the system; `rocas setup` makes it start when you log in"
+        );
+        return Ok(false);
+    }
+    for entry in &installed {
+        println!("rocas {} ({})", entry.mode.describe(), entry.location);
+        println!(
+            "  command: {}",
+            entry
+                .command
+                .as_deref()
+                .unwrap_or("unknown")
+        );
+        let binary = match &entry.command {
+            None => "unknown".to_string(),
+            Some(_) if current(entry) => "this rocas".to_string(),
+            Some(_) => format!("not this rocas ({}); run `rocas setup` again", exe.display()),
+        };
+        println!("  binary:  {binary}");
+        if let Some(running) = entry.running {
+            println!("  running: {}", if running { "yes" } else { "no" });
+        }
+    }
+    if installed.len() > 1 {
+        println!();
+        println!("rocas is set up to start more than once; `rocas setup` keeps only one");
+    }
+    Ok(healthy)
+}
+
+/// Returns `true` if `command` starts the binary at `exe`.
+fn runs(command: &str, exe: &Path) -> bool {
+    let program = command
+        .trim_start()
+        .trim_start_matches('"');
+    let exe = exe.to_string_lossy();
+    if cfg!(windows) {
+        program
+            .to_lowercase()
+            .starts_with(&exe.to_lowercase())
+    } else {
+        program.starts_with(&*exe)
+    }
+}
@@ -0,0 +1,188 @@
+//! Signals that stop or reload rocas while it watches.
+//!
+//! SIGTERM and SIGINT, and on Windows the console's Ctrl+C, Ctrl+Break,
+//! close, logoff and shutdown events, make rocas stop taking new events,
+//! finish the moves it has started, remove its status file, control channel
+//! and PID file and flush its log before it exits. A second SIGTERM or
+//! SIGINT while it does exits at once.
+//!
+//! SIGHUP makes rocas do the same, then start again with the config as it
+//! is now. A config that does not load is logged, and rocas keeps watching
+//! with the one it has. In a terminal, SIGHUP means the terminal went away,
+//! so there it stops rocas instead.
+//!
+//! A signal handler may only touch atomics, so the handlers record the
+//! request and a thread acts on it.
+
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, bounded};
+
+use crate::AppError;
+
+const NONE: u8 = 0;
+const RELOAD: u8 = 1;
+/// Stopping wins over reloading.
+const STOP: u8 = 2;
+
+/// How often the requests of the signal handlers are looked at.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What the signal handlers asked for and not yet acted on.
+static REQUESTED: AtomicU8 = AtomicU8::new(NONE);
+
+/// What rocas is shutting down for, once it is.
+static SHUTDOWN: AtomicU8 = AtomicU8::new(NONE);
+
+/// Set once rocas has finished shutting down.
+static FINISHED: AtomicBool = AtomicBool::new(false);
+
+/// Why rocas stopped watching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Stop,
+    Reload,
+}
+
+/// Handles the signals that stop and reload rocas. The returned channel is
+/// closed once rocas should stop watching; [`requested`] then tells why.
+/// A reload only closes it if `check` accepts the config as it is now.
+pub fn install(check: impl Fn() -> Result<(), AppError> + Send + 'static) -> Receiver<()> {
+    platform::install();
+
+    let (tx, rx) = bounded::<()>(0);
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            match REQUESTED.swap(NONE, Ordering::SeqCst) {
+                STOP => {
+                    info!("Stopping: finishing the moves in progress");
+                    SHUTDOWN.store(STOP, Ordering::SeqCst);
+                    break;
+                },
+                RELOAD => match check() {
+                    Ok(()) => {
+                        info!("Reloading the config: finishing the moves in progress");
+                        SHUTDOWN.store(RELOAD, Ordering::SeqCst);
+                        break;
+                    },
+                    Err(e) => error!("Not reloading the config: {e}"),
+                },
+                _ => {},
+            }
+        }
+        drop(tx);
+    });
+    rx
+}
+
+/// Returns why rocas stopped watching, if a signal made it.
+pub fn requested() -> Option<Signal> {
+    match SHUTDOWN.load(Ordering::SeqCst) {
+        STOP => Some(Signal::Stop),
+        RELOAD => Some(Signal::Reload),
+        _ => None,
+    }
+}
+
+/// Tells a console handler waiting for rocas to shut down that it has.
+pub fn finished() {
+    log::logger().flush();
+    FINISHED.store(true, Ordering::SeqCst);
+}
+
+/// Stops rocas as SIGTERM does.
+#[cfg(feature = "tray")]
+pub fn stop() {
+    request(STOP);
+}
+
+/// Records `request`, or exits at once if rocas is already stopping.
+fn request(request: u8) {
+    if request == STOP && SHUTDOWN.load(Ordering::SeqCst) != NONE {
+        platform::exit_now();
+    }
+    REQUESTED.fetch_max(request, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::io::IsTerminal;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::{RELOAD, STOP, request};
+
+    /// Whether SIGHUP stops rocas rather than reloading its config.
+    static HANGUP_STOPS: AtomicBool = AtomicBool::new(false);
+
+    pub fn install() {
+        HANGUP_STOPS.store(std::io::stderr().is_terminal(), Ordering::SeqCst);
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+            // SAFETY: the handler only stores to atomics or calls `_exit`,
+            // both of which are async-signal-safe.
+            unsafe { libc::signal(signal, handler) };
+        }
+    }
+
+    pub fn exit_now() {
+        // SAFETY: `_exit` is async-signal-safe.
+        unsafe { libc::_exit(1) };
+    }
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        let reload = signal == libc::SIGHUP && !HANGUP_STOPS.load(Ordering::SeqCst);
+        request(if reload { RELOAD } else { STOP });
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    use windows_sys::Win32::System::Console::{
+        CTRL_BREAK_EVENT,
+        CTRL_C_EVENT,
+        CTRL_CLOSE_EVENT,
+        CTRL_LOGOFF_EVENT,
+        CTRL_SHUTDOWN_EVENT,
+        SetConsoleCtrlHandler,
+    };
+
+    use super::{FINISHED, STOP, request};
+    use crate::autostart;
+
+    /// Windows ends the process 5 seconds after a close event, and sooner
+    /// at logoff or shutdown; rocas gives up waiting a little earlier.
+    const CLOSE_TIMEOUT: Duration = Duration::from_millis(4500);
+
+    pub fn install() {
+        // SAFETY: the handler is a valid function for the whole process.
+        unsafe { SetConsoleCtrlHandler(Some(on_event), 1) };
+    }
+
+    pub fn exit_now() {
+        std::process::exit(1);
+    }
+
+    unsafe extern "system" fn on_event(event: u32) -> i32 {
+        match event {
+            CTRL_C_EVENT | CTRL_BREAK_EVENT => request(STOP),
+            // A service sees every user log off; it stops with the service.
+            CTRL_LOGOFF_EVENT if autostart::is_service() => return 0,
+            // The process ends when the handler returns, so it waits for
+            // rocas to shut down first.
+            CTRL_CLOSE_EVENT | CTRL_LOGOFF_EVENT | CTRL_SHUTDOWN_EVENT => {
+                request(STOP);
+                let start = Instant::now();
+                while !FINISHED.load(Ordering::SeqCst) && start.elapsed() < CLOSE_TIMEOUT {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+            },
+            _ => return 0,
+        }
+        1
+    }
+}
@@ -0,0 +1,125 @@
+//! Free-space checks for destinations on another filesystem.
+//!
+//! A move across filesystems is a copy followed by a delete, so it needs room
+//! for the whole file on the destination. Running out half-way leaves a
+//! partial copy behind; checking first lets rocas skip the file, or hold it
+//! until space frees up.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// A match deferred because its destination filesystem was full.
+pub struct WaitingForSpace {
+    pub path: PathBuf,
+    /// Index of the rule in the compiled rule list.
+    pub rule: usize,
+    /// When to check the destination again.
+    pub retry_at: Instant,
+}
+
+/// Returns the bytes available to unprivileged users on the filesystem that
+/// holds `path`, or would hold it once created.
+pub fn available(path: &Path) -> io::Result<u64> {
+    available_at(&existing_ancestor(path)?)
+}
+
+/// Returns `true` if moving `from` to `dest` can be done with a rename, i.e.
+/// both are on the same filesystem. When in doubt, assumes they are not.
+pub fn same_filesystem(from: &Path, dest: &Path) -> bool {
+    let Ok(dest) = existing_ancestor(dest) else {
+        return false;
+    };
+    same_device(from, &dest)
+}
+
+/// Returns the closest ancestor of `path` (or `path` itself) that exists.
+pub fn existing_ancestor(path: &Path) -> io::Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    path.ancestors()
+        .find(|p| p.exists())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no existing ancestor"))
+}
+
+#[cfg(unix)]
+fn available_at(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: `path` is NUL-terminated and `stat` is valid for writes.
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: statvfs succeeded, so `stat` is initialised.
+    let stat = unsafe { stat.assume_init() };
+
+    #[allow(clippy::useless_conversion, reason = "the field types differ between platforms")]
+    Ok(u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)))
+}
+
+#[cfg(windows)]
+fn available_at(path: &Path) -> io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = watcher::long_path::extend(path)
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut free: u64 = 0;
+
+    // SAFETY: `wide` is NUL-terminated; the null totals are optional.
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &raw mut free,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 { Err(io::Error::last_os_error()) } else { Ok(free) }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available_at(_path: &Path) -> io::Result<u64> {
+    Ok(u64::MAX)
+}
+
+#[cfg(unix)]
+fn same_device(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(windows)]
+fn same_device(a: &Path, b: &Path) -> bool {
+    use std::path::Component;
+
+    let prefix = |path: &Path| {
+        std::path::absolute(path)
+            .ok()?
+            .components()
+            .next()
+            .and_then(|c| match c {
+                Component::Prefix(prefix) => Some(prefix.as_os_str().to_ascii_lowercase()),
+                _ => None,
+            })
+    };
+
+    prefix(a).is_some_and(|p| Some(p) == prefix(b))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn same_device(_a: &Path, _b: &Path) -> bool {
+    false
+}
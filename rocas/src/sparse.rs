@@ -0,0 +1,184 @@
+//! Sparse file support for copy-based moves.
+//!
+//! Disk images and VM files are often sparse: ranges that were never written
+//! take no space on disk. A plain copy reads those holes as zeros and writes
+//! them out, so the copy takes the file's full size. [`data_ranges`] finds the
+//! ranges that hold data so the copy can skip the holes, and [`prepare`] sets
+//! up the destination so the skipped ranges stay holes.
+//!
+//! Supported on Linux (`SEEK_DATA`/`SEEK_HOLE`) and Windows (NTFS allocated
+//! ranges). Elsewhere sparse files are copied in full.
+
+use std::fs::{File, Metadata};
+use std::io;
+use std::ops::Range;
+
+/// Returns `true` if the file described by `meta` has holes.
+#[cfg(target_os = "linux")]
+pub fn is_sparse(meta: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    // `blocks` counts 512-byte units, whatever the filesystem block size.
+    meta.is_file() && meta.blocks().saturating_mul(512) < meta.len()
+}
+
+/// Returns `true` if the file described by `meta` has holes.
+#[cfg(windows)]
+pub fn is_sparse(meta: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_SPARSE_FILE: u32 = 0x200;
+
+    meta.is_file() && meta.file_attributes() & FILE_ATTRIBUTE_SPARSE_FILE != 0
+}
+
+/// Returns `true` if the file described by `meta` has holes.
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn is_sparse(_meta: &Metadata) -> bool {
+    false
+}
+
+/// Returns the ranges of the first `len` bytes of `file` that hold data, in
+/// order. Everything between them is a hole that reads as zeros.
+///
+/// Returns `None` when the ranges cannot be determined; the whole file should
+/// then be copied.
+#[cfg(target_os = "linux")]
+pub fn data_ranges(file: &File, len: u64) -> Option<Vec<Range<u64>>> {
+    use std::os::fd::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let end = libc::off_t::try_from(len).ok()?;
+    let mut ranges = Vec::new();
+    let mut pos: libc::off_t = 0;
+
+    while pos < end {
+        // SAFETY: `fd` is a valid descriptor for the lifetime of `file`.
+        let start = unsafe { libc::lseek(fd, pos, libc::SEEK_DATA) };
+        if start < 0 {
+            // ENXIO: no data after `pos`, only a trailing hole.
+            return (io::Error::last_os_error().raw_os_error() == Some(libc::ENXIO))
+                .then_some(ranges);
+        }
+        // SAFETY: as above.
+        let hole = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return None;
+        }
+
+        ranges.push(u64::try_from(start).ok()?..u64::try_from(hole.min(end)).ok()?);
+        pos = hole;
+    }
+
+    Some(ranges)
+}
+
+/// Returns the ranges of the first `len` bytes of `file` that hold data, in
+/// order. Everything between them is a hole that reads as zeros.
+///
+/// Returns `None` when the ranges cannot be determined; the whole file should
+/// then be copied.
+#[cfg(windows)]
+pub fn data_ranges(file: &File, len: u64) -> Option<Vec<Range<u64>>> {
+    use std::os::windows::io::AsRawHandle;
+
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+    use windows_sys::Win32::System::Ioctl::{
+        FILE_ALLOCATED_RANGE_BUFFER,
+        FSCTL_QUERY_ALLOCATED_RANGES,
+    };
+
+    const ERROR_MORE_DATA: i32 = 234;
+    const BATCH: usize = 512;
+
+    let mut ranges = Vec::new();
+    let mut query = FILE_ALLOCATED_RANGE_BUFFER {
+        FileOffset: 0,
+        Length: i64::try_from(len).ok()?,
+    };
+
+    loop {
+        // SAFETY: zeroed is a valid bit pattern for this plain C struct.
+        let mut out: Vec<FILE_ALLOCATED_RANGE_BUFFER> = vec![unsafe { std::mem::zeroed() }; BATCH];
+        let mut returned = 0_u32;
+
+        // SAFETY: the buffers are valid for the sizes passed, and the handle
+        // stays open for the lifetime of `file`.
+        let ok = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle(),
+                FSCTL_QUERY_ALLOCATED_RANGES,
+                (&raw const query).cast(),
+                u32::try_from(size_of::<FILE_ALLOCATED_RANGE_BUFFER>()).ok()?,
+                out.as_mut_ptr().cast(),
+                u32::try_from(size_of_val(out.as_slice())).ok()?,
+                &raw mut returned,
+                std::ptr::null_mut(),
+            )
+        };
+        let more = ok == 0 && io::Error::last_os_error().raw_os_error() == Some(ERROR_MORE_DATA);
+        if ok == 0 && !more {
+            return None;
+        }
+
+        let count = returned as usize / size_of::<FILE_ALLOCATED_RANGE_BUFFER>();
+        for range in &out[..count] {
+            let start = u64::try_from(range.FileOffset).ok()?;
+            let end = u64::try_from(range.FileOffset + range.Length).ok()?;
+            ranges.push(start..end.min(len));
+        }
+
+        if !more || count == 0 {
+            return Some(ranges);
+        }
+
+        // Continue after the last range returned.
+        let last = &out[count - 1];
+        let next = last.FileOffset + last.Length;
+        query.Length -= next - query.FileOffset;
+        query.FileOffset = next;
+    }
+}
+
+/// Returns the ranges of the first `len` bytes of `file` that hold data.
+/// Hole detection is not supported on this platform.
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn data_ranges(_file: &File, _len: u64) -> Option<Vec<Range<u64>>> {
+    None
+}
+
+/// Prepares a new destination file so ranges that are skipped while writing
+/// it stay holes. On Windows the file has to be marked sparse first; Linux
+/// filesystems leave skipped ranges unallocated on their own.
+#[cfg(windows)]
+pub fn prepare(file: &File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+    use windows_sys::Win32::System::Ioctl::FSCTL_SET_SPARSE;
+
+    let mut returned = 0_u32;
+    // SAFETY: no input or output buffers; the handle stays open for the
+    // lifetime of `file`.
+    let ok = unsafe {
+        DeviceIoControl(
+            file.as_raw_handle(),
+            FSCTL_SET_SPARSE,
+            std::ptr::null(),
+            0,
+            std::ptr::null_mut(),
+            0,
+            &raw mut returned,
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+}
+
+/// Prepares a new destination file so ranges that are skipped while writing
+/// it stay holes. Nothing to do on this platform.
+#[cfg(not(windows))]
+#[expect(clippy::unnecessary_wraps, reason = "fallible on Windows")]
+pub fn prepare(_file: &File) -> io::Result<()> {
+    Ok(())
+}
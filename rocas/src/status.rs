@@ -0,0 +1,349 @@
+//! `rocas status`: what the running rocas is doing.
+//!
+//! While watching, rocas keeps a snapshot of every watcher (what it watches,
+//! how many files it moved, when it last saw an event and how many matches
+//! wait for another attempt) in `status.json` in the rocas config directory,
+//! rewritten every few seconds. `rocas status` reads it. A snapshot that has
+//! not been rewritten for a while was left behind by a rocas that is no
+//! longer running. When the running rocas answers on its control channel,
+//! `rocas status` asks it instead, and gets a snapshot from that moment.
+//!
+//! The last update rocas installed is shown too, with the start of its
+//! release notes, whether or not rocas is running.
+
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Value, json};
+
+use crate::update::LastUpdate;
+use crate::{config, control, logger, progress};
+
+pub const STATUS_NAME: &str = "status.json";
+
+/// How often the running rocas rewrites its snapshot.
+const WRITE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How old a snapshot may be before its rocas is considered gone.
+const STALE_AFTER: Duration = WRITE_INTERVAL.saturating_mul(3);
+
+/// The watchers of this process, in the order they were registered.
+static WATCHERS: Mutex<Vec<WatcherStatus>> = Mutex::new(Vec::new());
+
+/// When this process started watching, the config it loaded and where it
+/// logs to.
+static STARTED: OnceLock<(SystemTime, String, Option<String>)> = OnceLock::new();
+
+/// What one watcher has done since rocas started.
+#[derive(Debug, Clone, Default)]
+pub struct WatcherStatus {
+    pub name: String,
+    pub paths: Vec<String>,
+    pub rules: usize,
+    pub moved: u64,
+    pub last_event: Option<SystemTime>,
+    /// Matches deferred for another attempt: rate-limited, open in another
+    /// process, failed, or waiting for space or for their destination.
+    pub pending: usize,
+}
+
+/// A snapshot written by a running rocas.
+struct Snapshot {
+    pid: u32,
+    started: SystemTime,
+    updated: SystemTime,
+    config: String,
+    log_file: Option<String>,
+    copying: usize,
+    watchers: Vec<WatcherStatus>,
+}
+
+impl Snapshot {
+    /// The snapshot as written to the status file and printed by `rocas
+    /// status --json`. Times are Unix timestamps in seconds.
+    fn to_json(&self) -> Value {
+        let watchers: Vec<Value> = self
+            .watchers
+            .iter()
+            .map(|watcher| {
+                json!({
+                    "name": watcher.name,
+                    "paths": watcher.paths,
+                    "rules": watcher.rules,
+                    "moved": watcher.moved,
+                    "last_event": watcher.last_event.map(unix_secs),
+                    "pending": watcher.pending,
+                })
+            })
+            .collect();
+        json!({
+            "running": true,
+            "pid": self.pid,
+            "started": unix_secs(self.started),
+            "updated": unix_secs(self.updated),
+            "config": self.config,
+            "log_file": self.log_file,
+            "copying": self.copying,
+            "watchers": watchers,
+        })
+    }
+}
+
+/// Adds a watcher to the snapshot and returns its index for [`update`].
+pub fn register(status: WatcherStatus) -> usize {
+    let mut watchers = WATCHERS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    watchers.push(status);
+    watchers.len() - 1
+}
+
+/// Changes the status of the watcher registered as `idx`. The change is
+/// written with the next snapshot.
+pub fn update(idx: usize, change: impl FnOnce(&mut WatcherStatus)) {
+    if let Some(status) = WATCHERS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get_mut(idx)
+    {
+        change(status);
+    }
+}
+
+/// Starts rewriting the snapshot every [`WRITE_INTERVAL`] until the process
+/// exits. `log_file` is where this rocas logs to, for `rocas logs`.
+pub fn start(log_file: Option<String>) {
+    let _ = STARTED.set((SystemTime::now(), config::config_path(), log_file));
+
+    std::thread::spawn(move || {
+        loop {
+            if let Err(e) = write(&snapshot()) {
+                warn!("Could not write the status file '{}': {e}", path().display());
+            }
+            std::thread::sleep(WRITE_INTERVAL);
+        }
+    });
+}
+
+/// Returns the snapshot of this process as it is now, for the control
+/// channel.
+pub fn current() -> Value {
+    snapshot().to_json()
+}
+
+fn snapshot() -> Snapshot {
+    let (started, config, log_file) = STARTED
+        .get()
+        .cloned()
+        .unwrap_or_else(|| (SystemTime::now(), config::config_path(), None));
+    Snapshot {
+        pid: std::process::id(),
+        started,
+        updated: SystemTime::now(),
+        config,
+        log_file,
+        copying: progress::active().len(),
+        watchers: WATCHERS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone(),
+    }
+}
+
+/// Removes the snapshot, for when rocas stops watching.
+pub fn stop() {
+    match std::fs::remove_file(path()) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            warn!("Could not remove the status file '{}': {e}", path().display());
+        },
+        _ => {},
+    }
+}
+
+/// Prints the status of the running rocas, as JSON if `json` is set.
+/// Returns `false` if none is running.
+pub fn run(json: bool) -> bool {
+    let snapshot = read_running();
+    let update = LastUpdate::read();
+    if json {
+        let mut value = snapshot
+            .as_ref()
+            .map_or_else(|| json!({ "running": false }), Snapshot::to_json);
+        value["last_update"] = update
+            .as_ref()
+            .map_or(Value::Null, LastUpdate::to_json);
+        println!("{value:#}");
+        return snapshot.is_some();
+    }
+    let Some(snapshot) = snapshot else {
+        println!("rocas is not running");
+        if let Some(update) = &update {
+            print_update(update);
+        }
+        return false;
+    };
+
+    let uptime = snapshot
+        .started
+        .elapsed()
+        .unwrap_or_default();
+    println!("rocas is running (pid {}, up {})", snapshot.pid, format_duration(uptime));
+    println!("config: {}", snapshot.config);
+    if snapshot.copying > 0 {
+        println!("copying {} files", snapshot.copying);
+    }
+
+    for watcher in &snapshot.watchers {
+        println!();
+        if watcher.name.is_empty() {
+            println!("watcher");
+        } else {
+            println!("watcher '{}'", watcher.name);
+        }
+        println!("  watching:        {}", watcher.paths.join(", "));
+        println!("  rules:           {}", watcher.rules);
+        println!("  files moved:     {}", watcher.moved);
+        println!("  last event:      {}", format_last_event(watcher.last_event));
+        println!("  pending retries: {}", watcher.pending);
+    }
+
+    if let Some(update) = &update {
+        println!();
+        print_update(update);
+    }
+    true
+}
+
+fn print_update(update: &LastUpdate) {
+    println!("last update: {} → {} on {}", update.from, update.to, update.installed_at());
+    for line in update.summary() {
+        println!("  {line}");
+    }
+}
+
+/// Returns `true` if rocas is running.
+pub fn is_running() -> bool {
+    read_running().is_some()
+}
+
+/// Returns the log file of the running rocas, if it is running and logs to
+/// a file.
+pub fn log_file() -> Option<PathBuf> {
+    read_running()?
+        .log_file
+        .map(PathBuf::from)
+}
+
+fn path() -> PathBuf {
+    config::rocas_dir().join(STATUS_NAME)
+}
+
+fn write(snapshot: &Snapshot) -> io::Result<()> {
+    let contents = snapshot.to_json();
+    let path = path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, contents.to_string())?;
+    std::fs::rename(&temp, &path)
+}
+
+/// Asks the running rocas for its snapshot, or reads the one it last wrote.
+/// Returns `None` if there is none, it is malformed or its rocas is gone.
+fn read_running() -> Option<Snapshot> {
+    if let Ok(value) = control::request("status")
+        && let Some(snapshot) = parse(&value)
+    {
+        return Some(snapshot);
+    }
+    let contents = std::fs::read_to_string(path()).ok()?;
+    let value: Value = serde_json::from_str(&contents).ok()?;
+    parse(&value).filter(|snapshot| {
+        snapshot
+            .updated
+            .elapsed()
+            .unwrap_or_default()
+            < STALE_AFTER
+    })
+}
+
+fn parse(value: &Value) -> Option<Snapshot> {
+    let watchers = value
+        .get("watchers")?
+        .as_array()?
+        .iter()
+        .map(|watcher| {
+            Some(WatcherStatus {
+                name: watcher
+                    .get("name")?
+                    .as_str()?
+                    .to_string(),
+                paths: watcher
+                    .get("paths")?
+                    .as_array()?
+                    .iter()
+                    .filter_map(|path| path.as_str().map(str::to_string))
+                    .collect(),
+                rules: usize::try_from(watcher.get("rules")?.as_u64()?).ok()?,
+                moved: watcher.get("moved")?.as_u64()?,
+                last_event: watcher
+                    .get("last_event")
+                    .and_then(Value::as_u64)
+                    .map(from_unix_secs),
+                pending: usize::try_from(watcher.get("pending")?.as_u64()?).ok()?,
+            })
+        })
+        .collect::<Option<_>>()?;
+
+    Some(Snapshot {
+        pid: u32::try_from(value.get("pid")?.as_u64()?).ok()?,
+        started: from_unix_secs(value.get("started")?.as_u64()?),
+        updated: from_unix_secs(value.get("updated")?.as_u64()?),
+        config: value
+            .get("config")?
+            .as_str()?
+            .to_string(),
+        log_file: value
+            .get("log_file")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        copying: usize::try_from(value.get("copying")?.as_u64()?).ok()?,
+        watchers,
+    })
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn from_unix_secs(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn format_last_event(time: Option<SystemTime>) -> String {
+    let Some(time) = time else {
+        return "none yet".to_string();
+    };
+    let ago = time.elapsed().unwrap_or_default();
+    format!("{} ({} ago)", logger::format_timestamp(unix_secs(time)), format_duration(ago))
+}
+
+/// Formats `duration` with its two largest units, like `2h 13m`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, mins) = (secs / 86_400, secs / 3_600 % 24, secs / 60 % 60);
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {mins}m")
+    } else if mins > 0 {
+        format!("{mins}m {}s", secs % 60)
+    } else {
+        format!("{secs}s")
+    }
+}
@@ -0,0 +1,281 @@
+//! Filename templates used by the rule `rename` option.
+//!
+//! # Placeholders
+//!
+//! | Placeholder    | Value                                                   |
+//! | -------------- | ------------------------------------------------------- |
+//! | `{name}`       | original filename without its extension                 |
+//! | `{ext}`        | original extension, without the dot                     |
+//! | `{counter}`    | next free sequence number in the destination directory  |
+//! | `{counter:N}`  | same, zero-padded to `N` digits                         |
+//!
+//! Literal braces are written as `{{` and `}}`. Regex capture references
+//! (`$1`, `${name}`) in the literal parts are substituted as well.
+
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use watcher::long_path;
+
+use crate::pattern::Pattern;
+use crate::sanitize::Sanitizer;
+
+/// Error returned by [`Template::new`] when a template cannot be parsed.
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    #[error("unknown placeholder '{{{placeholder}}}' in template '{raw}'")]
+    UnknownPlaceholder { raw: String, placeholder: String },
+
+    #[error("unclosed '{{' in template '{raw}'")]
+    Unclosed { raw: String },
+
+    #[error("template '{raw}' may contain at most one {{counter}} placeholder")]
+    MultipleCounters { raw: String },
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Name,
+    Ext,
+    Counter { width: usize },
+}
+
+/// Values substituted into a [`Template`] for a single file.
+pub struct TemplateContext<'a> {
+    /// The path of the file being processed.
+    pub path: &'a Path,
+    /// The pattern that matched, used to expand regex capture references.
+    pub pattern: &'a Pattern,
+    /// The string `pattern` was matched against.
+    pub subject: &'a str,
+}
+
+impl TemplateContext<'_> {
+    fn name(&self) -> &str {
+        self.path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+    }
+
+    fn ext(&self) -> &str {
+        self.path
+            .extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+    }
+}
+
+/// A parsed filename template.
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    /// Parses a template string.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TemplateError`] for unknown or unclosed placeholders, or when
+    /// `{counter}` appears more than once.
+    pub fn new(raw: &str) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = raw.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                },
+                '{' => {
+                    let mut placeholder = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        placeholder.push(c);
+                    }
+                    if !closed {
+                        return Err(TemplateError::Unclosed { raw: raw.to_string() });
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_placeholder(raw, &placeholder)?);
+                },
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        let counters = segments
+            .iter()
+            .filter(|s| matches!(s, Segment::Counter { .. }))
+            .count();
+        if counters > 1 {
+            return Err(TemplateError::MultipleCounters { raw: raw.to_string() });
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Returns `true` if the template contains a `{counter}` placeholder.
+    pub fn has_counter(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Counter { .. }))
+    }
+
+    /// Renders the template for `ctx`, using `counter` for `{counter}`.
+    pub fn render(&self, ctx: &TemplateContext, counter: u64) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => out.push_str(&ctx.pattern.expand(ctx.subject, s)),
+                Segment::Name => out.push_str(ctx.name()),
+                Segment::Ext => out.push_str(ctx.ext()),
+                Segment::Counter { width } => {
+                    let _ = write!(out, "{counter:0width$}");
+                },
+            }
+        }
+        out
+    }
+
+    /// Renders the template into a path inside `dir`.
+    ///
+    /// Without a `{counter}` placeholder this is a plain render. With one, the
+    /// counter starts after the highest number already used by a matching file
+    /// in `dir`, and the chosen path is reserved by creating an empty file
+    /// there (`create_new`), so two moves can never pick the same name. The
+    /// caller is expected to replace the placeholder with the real file.
+    ///
+    /// With a `sanitizer`, the rendered name is cleaned before it is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`std::io::Error`] if `dir` cannot be read or the placeholder
+    /// file cannot be created.
+    pub fn reserve(
+        &self,
+        dir: &Path,
+        ctx: &TemplateContext,
+        sanitizer: Option<&Sanitizer>,
+    ) -> std::io::Result<PathBuf> {
+        let name = |counter| {
+            let name = self.render(ctx, counter);
+            sanitizer.map_or(name.clone(), |s| s.clean(&name))
+        };
+
+        if !self.has_counter() {
+            return Ok(dir.join(name(0)));
+        }
+
+        let mut counter = self.next_counter(dir, ctx, sanitizer)?;
+        loop {
+            let candidate = dir.join(name(counter));
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(long_path::extend(&candidate))
+            {
+                Ok(_) => return Ok(candidate),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => counter += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns one more than the highest counter value used by an existing
+    /// file in `dir` that matches this template, or `1` if there is none.
+    fn next_counter(
+        &self,
+        dir: &Path,
+        ctx: &TemplateContext,
+        sanitizer: Option<&Sanitizer>,
+    ) -> std::io::Result<u64> {
+        let Some(idx) = self
+            .segments
+            .iter()
+            .position(|s| matches!(s, Segment::Counter { .. }))
+        else {
+            return Ok(1);
+        };
+
+        let prefix = Self { segments: self.segments[..idx].to_vec() }.render(ctx, 0);
+        let suffix = Self {
+            segments: self.segments[idx + 1..].to_vec(),
+        }
+        .render(ctx, 0);
+        let (prefix, suffix) = match sanitizer {
+            Some(s) => (s.replace_chars(&prefix), s.replace_chars(&suffix)),
+            None => (prefix, suffix),
+        };
+
+        let entries = match std::fs::read_dir(long_path::extend(dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(1),
+            Err(e) => return Err(e),
+        };
+
+        let highest = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name();
+                let digits = name
+                    .to_str()?
+                    .strip_prefix(prefix.as_str())?
+                    .strip_suffix(suffix.as_str())?
+                    .to_string();
+                if digits.is_empty()
+                    || !digits
+                        .bytes()
+                        .all(|b| b.is_ascii_digit())
+                {
+                    return None;
+                }
+                digits.parse::<u64>().ok()
+            })
+            .max()
+            .unwrap_or(0);
+
+        Ok(highest + 1)
+    }
+}
+
+fn parse_placeholder(raw: &str, placeholder: &str) -> Result<Segment, TemplateError> {
+    let unknown = || TemplateError::UnknownPlaceholder {
+        raw: raw.to_string(),
+        placeholder: placeholder.to_string(),
+    };
+
+    match placeholder.split_once(':') {
+        None => match placeholder {
+            "name" => Ok(Segment::Name),
+            "ext" => Ok(Segment::Ext),
+            "counter" => Ok(Segment::Counter { width: 0 }),
+            _ => Err(unknown()),
+        },
+        Some(("counter", width)) => {
+            let width = width.parse().map_err(|_| unknown())?;
+            Ok(Segment::Counter { width })
+        },
+        Some(_) => Err(unknown()),
+    }
+}
@@ -0,0 +1,171 @@
+//! `rocas test`: shows which rule would claim a path, without touching it.
+//!
+//! Each path is matched against the rules of the watcher whose directory
+//! holds it, or of the first watcher for paths outside every watched
+//! directory. For every rule that would apply, the pattern that matched, the
+//! action and the expanded destination are printed. `contains` conditions
+//! are only checked for files that exist.
+
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+use serde_json::{Value, json};
+
+use crate::config::{Config, WatcherConfig};
+use crate::rule::CompiledRule;
+use crate::template::TemplateContext;
+use crate::{AppError, engine};
+
+/// Tests every path in `paths`, or every line of stdin when there are none,
+/// and prints the results, as JSON if `json` is set. Returns `true` if each
+/// path matched a rule.
+///
+/// # Errors
+///
+/// Returns [`AppError`] if a rule does not compile or stdin cannot be read.
+pub fn run(config: &Config, paths: &[PathBuf], json: bool) -> Result<bool, AppError> {
+    let paths = if paths.is_empty() {
+        io::stdin()
+            .lock()
+            .lines()
+            .map(|line| line.map(|line| PathBuf::from(line.trim())))
+            .filter(|path| {
+                path.as_ref()
+                    .map_or(true, |path| !path.as_os_str().is_empty())
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        paths.to_vec()
+    };
+
+    let watchers = config.watchers();
+    let mut rule_sets = Vec::with_capacity(watchers.len());
+    for watcher in &watchers {
+        let rules: Vec<CompiledRule> = config
+            .rules_of(watcher)?
+            .into_iter()
+            .map(CompiledRule::new)
+            .collect::<Result<_, _>>()?;
+        rule_sets.push(rules);
+    }
+
+    let mut all_matched = true;
+    let mut results = Vec::new();
+    for path in &paths {
+        let idx = watchers
+            .iter()
+            .position(|watcher| root_of(watcher, path).is_some())
+            .unwrap_or(0);
+
+        let claims = test(&rule_sets[idx], watchers[idx], path);
+        all_matched &= !claims.is_empty();
+        if json {
+            results.push(json!({
+                "path": path,
+                "matches": claims.iter().map(Claim::to_json).collect::<Vec<_>>(),
+            }));
+        } else {
+            print(path, &claims);
+        }
+    }
+
+    if json {
+        println!("{:#}", Value::Array(results));
+    }
+    Ok(all_matched)
+}
+
+/// A rule that would apply to a path.
+struct Claim {
+    rule: String,
+    pattern: String,
+    action: &'static str,
+    destination: String,
+    name: String,
+    /// Why the rule's content condition could not be checked, if it could
+    /// not.
+    unchecked: Option<String>,
+}
+
+impl Claim {
+    fn to_json(&self) -> Value {
+        json!({
+            "rule": self.rule,
+            "pattern": self.pattern,
+            "action": self.action,
+            "destination": self.destination,
+            "name": self.name,
+            "content_unchecked": self.unchecked,
+        })
+    }
+}
+
+/// Returns the rules that would apply to `path`, in the order they would.
+fn test(rules: &[CompiledRule], watcher: &WatcherConfig, path: &Path) -> Vec<Claim> {
+    let is_dir = path.is_dir();
+    let (full, filename) = engine::match_subjects(path);
+    let mut claims = Vec::new();
+
+    for rule in rules {
+        let Some((pattern, subject)) = rule.match_pattern(&full, &filename, is_dir) else {
+            continue;
+        };
+
+        let mut unchecked = None;
+        if let Some(contains) = &rule.contains {
+            match contains.matches(path) {
+                Ok(true) => {},
+                Ok(false) => continue,
+                Err(e) => unchecked = Some(e.to_string()),
+            }
+        }
+
+        let ctx = TemplateContext { path, pattern, subject };
+        claims.push(Claim {
+            rule: rule.config.name.clone(),
+            pattern: pattern.raw.clone(),
+            action: rule.action_name(),
+            destination: engine::expand_destination(rule, &ctx),
+            name: engine::target_name(rule, &ctx).unwrap_or_default(),
+            unchecked,
+        });
+
+        // Without `match_all`, only the first matching rule applies.
+        if !watcher.match_all() || rule.config.stop {
+            break;
+        }
+    }
+
+    claims
+}
+
+/// Prints the rules that would apply to `path`.
+fn print(path: &Path, claims: &[Claim]) {
+    println!("{}", path.display());
+    for claim in claims {
+        if let Some(e) = &claim.unchecked {
+            println!(
+                "  rule '{}' matches '{}', but its content condition cannot be checked: {e}",
+                claim.rule, claim.pattern
+            );
+        }
+        println!("  rule '{}' (pattern '{}')", claim.rule, claim.pattern);
+        println!("    action:      {}", claim.action);
+        println!("    destination: {}", claim.destination);
+        println!("    name:        {}", claim.name);
+    }
+
+    if claims.is_empty() {
+        println!("  no rule matches");
+    }
+}
+
+/// Returns the directory `watcher` watches that holds `path`, if any.
+pub fn root_of(watcher: &WatcherConfig, path: &Path) -> Option<PathBuf> {
+    let path = std::path::absolute(path).ok()?;
+    watcher
+        .effective_paths()
+        .into_iter()
+        .filter_map(|root| std::path::absolute(root).ok())
+        .find(|root| path.starts_with(root))
+}
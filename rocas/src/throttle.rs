@@ -0,0 +1,95 @@
+//! Per-rule throughput limits.
+//!
+//! A [`RateLimiter`] tracks the files (and bytes) a rule moved within a
+//! sliding window. Matches that exceed the budget are queued by the event loop
+//! and retried once older moves fall out of the window.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A match deferred because its rule ran out of budget.
+pub struct Throttled {
+    pub path: PathBuf,
+    /// Index of the rule in the compiled rule list.
+    pub rule: usize,
+}
+
+/// Sliding-window limit on files and/or bytes per interval.
+pub struct RateLimiter {
+    max_files: Option<u64>,
+    max_bytes: Option<u64>,
+    window: Duration,
+    /// Moves within the current window: when they happened and their size.
+    history: Mutex<VecDeque<(Instant, u64)>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter, or returns `None` when neither limit is set.
+    pub fn new(max_files: Option<u64>, max_bytes: Option<u64>, window: Duration) -> Option<Self> {
+        if max_files.is_none() && max_bytes.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            max_files,
+            max_bytes,
+            window,
+            history: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Records a move of `bytes` if it fits in the remaining budget.
+    ///
+    /// Returns `false` when the move has to wait. A single file larger than the
+    /// byte budget is let through once the window is empty, so it cannot be
+    /// queued forever.
+    pub fn try_acquire(&self, bytes: u64) -> bool {
+        let mut history = self
+            .history
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        self.expire(&mut history);
+
+        let files = history.len() as u64;
+        let used: u64 = history.iter().map(|(_, b)| b).sum();
+
+        let files_ok = self
+            .max_files
+            .is_none_or(|max| files < max);
+        let bytes_ok = self
+            .max_bytes
+            .is_none_or(|max| history.is_empty() || used + bytes <= max);
+
+        if files_ok && bytes_ok {
+            history.push_back((Instant::now(), bytes));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns how long until the oldest recorded move leaves the window and
+    /// frees up budget, or `None` if nothing is recorded.
+    pub fn next_expiry(&self) -> Option<Duration> {
+        let mut history = self
+            .history
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        self.expire(&mut history);
+
+        history
+            .front()
+            .map(|(at, _)| self.window.saturating_sub(at.elapsed()))
+    }
+
+    fn expire(&self, history: &mut VecDeque<(Instant, u64)>) {
+        while history
+            .front()
+            .is_some_and(|(at, _)| at.elapsed() >= self.window)
+        {
+            history.pop_front();
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! `~` and `~user` at the start of configured paths.
+//!
+//! Shells expand these, but a path read from the config reaches the
+//! filesystem as written, so `~/Documents` would otherwise become a
+//! directory literally named `~` under the working directory. Paths where
+//! the user cannot be found are left as they are.
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use crate::archive;
+
+/// Replaces a leading `~` with the current user's home directory and a
+/// leading `~user` with that user's.
+pub fn expand(path: &str) -> Cow<'_, str> {
+    let Some(rest) = path.strip_prefix('~') else {
+        return Cow::Borrowed(path);
+    };
+    let end = rest
+        .find(std::path::is_separator)
+        .unwrap_or(rest.len());
+    let (user, tail) = rest.split_at(end);
+
+    let home = if user.is_empty() { dirs::home_dir() } else { user_home(user) };
+    match home {
+        Some(home) => Cow::Owned(format!("{}{tail}", home.display())),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// Expands a destination, which may name a path inside a `zip:` archive.
+/// Remote destinations never start with `~` and are returned as they are.
+pub fn expand_destination(destination: &str) -> Cow<'_, str> {
+    let Some(path) = archive::path(destination) else {
+        return expand(destination);
+    };
+    match expand(path) {
+        Cow::Owned(path) => Cow::Owned(format!("{}{path}", archive::PREFIX)),
+        Cow::Borrowed(_) => Cow::Borrowed(destination),
+    }
+}
+
+/// Expands `value` in place.
+pub fn expand_in_place(value: &mut String) {
+    if let Cow::Owned(expanded) = expand(value) {
+        *value = expanded;
+    }
+}
+
+/// Looks up the home directory of `user` in the user database.
+#[cfg(unix)]
+fn user_home(user: &str) -> Option<PathBuf> {
+    use std::ffi::{CStr, CString, OsStr};
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+
+    let name = CString::new(user).ok()?;
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+
+    loop {
+        let mut pwd = MaybeUninit::<libc::passwd>::uninit();
+        let mut result = std::ptr::null_mut();
+        // SAFETY: `getpwnam_r` writes the entry to `pwd`, with its strings in
+        // `buf`, and points `result` at `pwd` only if the user exists.
+        let rc = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                pwd.as_mut_ptr(),
+                buf.as_mut_ptr(),
+                buf.len(),
+                &raw mut result,
+            )
+        };
+
+        if rc == libc::ERANGE && buf.len() < 1 << 20 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if rc != 0 || result.is_null() {
+            return None;
+        }
+
+        // SAFETY: `result` points to the filled-in entry, whose `pw_dir` is
+        // null or a NUL-terminated string in `buf`.
+        let dir = unsafe {
+            (*result)
+                .pw_dir
+                .as_ref()
+                .map(|dir| CStr::from_ptr(dir))
+        }?;
+        return Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())));
+    }
+}
+
+/// Windows has no user database to ask; profiles live side by side, so
+/// another user's home is a sibling of the current one.
+#[cfg(not(unix))]
+fn user_home(user: &str) -> Option<PathBuf> {
+    let home = dirs::home_dir()?;
+    let dir = home.parent()?.join(user);
+    dir.is_dir().then_some(dir)
+}
@@ -0,0 +1,444 @@
+//! Moving files and directories between locations.
+//!
+//! A move is a plain rename whenever possible. Across filesystems it falls
+//! back to copy + delete. The copy is written under a hidden temporary name
+//! next to the destination and only renamed into place once it is complete
+//! and verified, so other tools never see a partial file; the source is
+//! removed last, and a partial copy is cleaned up when anything goes wrong.
+//! Holes in sparse files are kept as holes.
+//! Timestamps, permissions and extended attributes are carried over unless
+//! disabled, large copies report their progress and copies can be held to a
+//! bandwidth limit.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use watcher::long_path;
+
+use crate::bandwidth::{BandwidthError, BandwidthLimit};
+use crate::config::{LinkFallback, VerifyMode, WatcherConfig};
+use crate::progress::Tracker;
+use crate::{metadata, sparse};
+
+/// Suffix of the temporary names copies are written to, as
+/// `.<name>.rocas-tmp`.
+const TEMP_SUFFIX: &str = ".rocas-tmp";
+
+/// Buffer size for chunked copies.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How copy-based moves are carried out.
+pub struct Options {
+    pub verify: VerifyMode,
+    pub preserve_metadata: bool,
+    /// Files at least this large (in bytes) report copy progress.
+    pub progress_threshold: Option<u64>,
+    /// Shared cap on the copy rate.
+    pub bandwidth: Option<BandwidthLimit>,
+}
+
+impl Options {
+    pub fn new(settings: &WatcherConfig) -> Result<Self, BandwidthError> {
+        Ok(Self {
+            verify: settings.verify_copies(),
+            preserve_metadata: settings.preserve_metadata,
+            progress_threshold: (settings.progress_threshold_mb > 0)
+                .then(|| settings.progress_threshold_mb * 1024 * 1024),
+            bandwidth: BandwidthLimit::new(settings)?,
+        })
+    }
+}
+
+/// How a file or directory got to its destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placed {
+    Moved,
+    Linked,
+    /// Copied because a hard link was impossible; the source is kept.
+    Copied,
+    /// Uploaded to a WebDAV destination.
+    Uploaded,
+    /// Added to a zip archive.
+    Archived,
+    /// Encrypted into the destination; the plaintext is deleted.
+    Encrypted,
+}
+
+impl std::fmt::Display for Placed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Placed::Moved => "Moved",
+            Placed::Linked => "Linked",
+            Placed::Copied => "Copied",
+            Placed::Uploaded => "Uploaded",
+            Placed::Archived => "Archived",
+            Placed::Encrypted => "Encrypted",
+        })
+    }
+}
+
+/// Moves a file or directory to `dest`.
+///
+/// The caller must have waited for the file to stop growing. Attempts an
+/// atomic rename first; falls back to copy + delete when source and
+/// destination are on different filesystems.
+///
+/// Both paths are used in their long-path form, so deep destination trees
+/// work on Windows.
+pub fn move_path(from: &Path, dest: &Path, opts: &Options) -> io::Result<()> {
+    let (from, dest) = (&long_path::extend(from), &long_path::extend(dest));
+    let is_dir = from.is_dir();
+
+    // Drop the placeholder file reserved for a `{counter}` rename; a
+    // directory cannot be renamed onto it.
+    if is_dir && dest.is_file() {
+        std::fs::remove_file(dest)?;
+    }
+
+    // Try to rename first (fast, same filesystem)
+    if std::fs::rename(from, dest).is_ok() {
+        return Ok(());
+    }
+
+    // Fall back to copy + delete (cross-filesystem). The source is only
+    // removed once the copy is in place.
+    copy_into_place(from, dest, is_dir, opts)?;
+
+    if is_dir { std::fs::remove_dir_all(from) } else { std::fs::remove_file(from) }
+}
+
+/// Hard-links a file to `dest`, or recreates a directory at `dest` with its
+/// files hard-linked. The source stays in place.
+///
+/// Hard links cannot cross filesystems; then the source is copied or, with
+/// [`LinkFallback::Skip`], left alone and `None` is returned. An existing
+/// file at `dest` (a reserved name or a file being overwritten) is replaced.
+pub fn link_path(
+    from: &Path,
+    dest: &Path,
+    fallback: LinkFallback,
+    opts: &Options,
+) -> io::Result<Option<Placed>> {
+    let (from, dest) = (&long_path::extend(from), &long_path::extend(dest));
+    let is_dir = from.is_dir();
+
+    if dest.is_file() {
+        std::fs::remove_file(dest)?;
+    }
+
+    let linked = if is_dir { link_tree(from, dest) } else { std::fs::hard_link(from, dest) };
+    match linked {
+        Ok(()) => return Ok(Some(Placed::Linked)),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            if is_dir {
+                let _ = std::fs::remove_dir_all(dest);
+            }
+        },
+        Err(e) => {
+            if is_dir {
+                let _ = std::fs::remove_dir_all(dest);
+            }
+            return Err(e);
+        },
+    }
+
+    match fallback {
+        LinkFallback::Copy => {
+            debug!("Cannot hard-link '{}' across filesystems; copying it.", from.display());
+            copy_into_place(from, dest, is_dir, opts)?;
+            Ok(Some(Placed::Copied))
+        },
+        LinkFallback::Skip => {
+            info!("Cannot hard-link '{}' across filesystems; skipping.", from.display());
+            Ok(None)
+        },
+    }
+}
+
+/// Recreates the directory `from` at `dest` with every file hard-linked.
+fn link_tree(from: &Path, dest: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dest)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            link_tree(&entry.path(), &target)?;
+        } else {
+            std::fs::hard_link(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies `from` to `dest`. The copy is written under a temporary name and
+/// only appears under its real name once it is complete and verified.
+fn copy_into_place(from: &Path, dest: &Path, is_dir: bool, opts: &Options) -> io::Result<()> {
+    // Drop a reserved placeholder; a directory cannot be renamed onto it.
+    if is_dir && dest.is_file() {
+        std::fs::remove_file(dest)?;
+    }
+
+    let temp = temp_path(dest);
+    remove_entry(&temp)?;
+
+    let copied = if is_dir { copy_tree(from, &temp, opts) } else { copy_file(from, &temp, opts) };
+    if let Err(e) = copied.and_then(|()| std::fs::rename(&temp, dest)) {
+        let _ = remove_entry(&temp);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if `dest` already holds `from`: the same file, or a copy
+/// with the same size and modification time.
+pub fn already_placed(from: &Path, dest: &Path) -> bool {
+    let (Ok(source), Ok(target)) = (std::fs::metadata(from), std::fs::metadata(dest)) else {
+        return false;
+    };
+
+    source.is_file()
+        && target.is_file()
+        && source.len() == target.len()
+        && source.modified().ok() == target.modified().ok()
+}
+
+/// Removes the file or directory at `path` once it has been uploaded.
+pub fn remove_source(path: &Path) -> io::Result<()> {
+    let path = &long_path::extend(path);
+    if path.is_dir() { std::fs::remove_dir_all(path) } else { std::fs::remove_file(path) }
+}
+
+/// Returns `true` if `path` is a temporary copy written by [`move_path`].
+pub fn is_temp(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.') && name.ends_with(TEMP_SUFFIX))
+}
+
+/// Removes temporary copies left behind in `dir`, and in its subdirectories
+/// up to `depth` levels down, by a move that was interrupted (e.g. by a
+/// crash or power loss).
+pub fn remove_stale_temps(dir: &Path, depth: usize) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if is_temp(&path) {
+            match remove_entry(&path) {
+                Ok(()) => info!("Removed stale temporary copy '{}'", path.display()),
+                Err(e) => warn!("Could not remove stale temporary copy '{}': {e}", path.display()),
+            }
+        } else if depth > 0
+            && entry
+                .file_type()
+                .is_ok_and(|t| t.is_dir())
+        {
+            remove_stale_temps(&path, depth - 1);
+        }
+    }
+}
+
+/// Returns the hidden temporary name a copy to `dest` is written under.
+pub fn temp_path(dest: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(dest.file_name().unwrap_or_default());
+    name.push(TEMP_SUFFIX);
+    dest.with_file_name(name)
+}
+
+/// Removes the file or directory at `path`, if there is one.
+fn remove_entry(path: &Path) -> io::Result<()> {
+    let removed = match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+
+    match removed {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        other => other,
+    }
+}
+
+/// Recursively copies the directory `from` to `dest`.
+fn copy_tree(from: &Path, dest: &Path, opts: &Options) -> io::Result<()> {
+    std::fs::create_dir_all(dest)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_tree(&entry.path(), &target, opts)?;
+        } else {
+            copy_file(&entry.path(), &target, opts)?;
+        }
+    }
+
+    // After the contents, which would otherwise bump the directory's mtime.
+    preserve_metadata(from, dest, opts);
+    Ok(())
+}
+
+/// Copies the metadata of `from` to `dest` if configured. Failures are only
+/// logged: the data itself has been copied.
+fn preserve_metadata(from: &Path, dest: &Path, opts: &Options) {
+    if !opts.preserve_metadata {
+        return;
+    }
+
+    if let Err(e) = metadata::copy(from, dest) {
+        warn!("Could not preserve the metadata of '{}': {e}", from.display());
+    }
+}
+
+/// Copies the file `from` to `dest` and checks the copy as configured.
+fn copy_file(from: &Path, dest: &Path, opts: &Options) -> io::Result<()> {
+    let meta = std::fs::metadata(from)?;
+    let hash = opts.verify == VerifyMode::Hash;
+    let report = opts
+        .progress_threshold
+        .is_some_and(|threshold| meta.len() >= threshold);
+
+    let limit = opts.bandwidth.as_ref();
+
+    let source_hash = if hash || report || limit.is_some() || sparse::is_sparse(&meta) {
+        copy_chunked(from, dest, hash, report, limit)?
+    } else {
+        std::fs::copy(from, dest)?;
+        None
+    };
+    preserve_metadata(from, dest, opts);
+
+    if opts.verify == VerifyMode::None {
+        return Ok(());
+    }
+
+    // Make sure the data reached the disk before the source goes away.
+    File::open(dest)?.sync_all()?;
+
+    let expected = std::fs::metadata(from)?.len();
+    let actual = std::fs::metadata(dest)?.len();
+    if actual != expected {
+        return Err(io::Error::other(format!(
+            "copy of '{}' is {actual} bytes, expected {expected}",
+            from.display()
+        )));
+    }
+
+    if let Some(source_hash) = source_hash
+        && hash_file(dest)? != source_hash
+    {
+        return Err(io::Error::other(format!(
+            "checksum of the copy of '{}' does not match",
+            from.display()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Copies `from` to `dest` in chunks, returning the SHA-256 of the data
+/// read when `hash` is set. With `report`, progress is tracked and logged;
+/// with a `limit`, the copy is paced to stay under it.
+///
+/// Holes in a sparse source are skipped rather than written as zeros, so
+/// they stay holes in the copy.
+fn copy_chunked(
+    from: &Path,
+    dest: &Path,
+    hash: bool,
+    report: bool,
+    limit: Option<&BandwidthLimit>,
+) -> io::Result<Option<[u8; 32]>> {
+    let mut reader = File::open(from)?;
+    let source = reader.metadata()?;
+    let len = source.len();
+    let mut writer = File::create(dest)?;
+
+    let ranges = if sparse::is_sparse(&source) { sparse::data_ranges(&reader, len) } else { None };
+    if ranges.is_some() {
+        sparse::prepare(&writer)?;
+    }
+
+    let mut hasher = hash.then(Sha256::new);
+    let mut tracker = report.then(|| Tracker::start(from, dest, len));
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut pos = 0;
+
+    let whole = std::iter::once(0..len).collect();
+    for range in ranges.unwrap_or(whole) {
+        // The hole before this range reads as zeros.
+        if let Some(hasher) = &mut hasher {
+            hash_zeros(hasher, &mut buf, range.start - pos);
+        }
+        if let Some(tracker) = &mut tracker {
+            tracker.advance(range.start - pos);
+        }
+
+        reader.seek(SeekFrom::Start(range.start))?;
+        writer.seek(SeekFrom::Start(range.start))?;
+
+        let mut remaining = range.end - range.start;
+        while remaining > 0 {
+            let want = usize::try_from(remaining).map_or(CHUNK_SIZE, |r| r.min(CHUNK_SIZE));
+            let n = reader.read(&mut buf[..want])?;
+            if n == 0 {
+                break;
+            }
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&buf[..n]);
+            }
+            writer.write_all(&buf[..n])?;
+            if let Some(tracker) = &mut tracker {
+                tracker.advance(n as u64);
+            }
+            if let Some(limit) = limit {
+                limit.consume(n as u64);
+            }
+            remaining -= n as u64;
+        }
+
+        pos = range.end;
+    }
+
+    // A trailing hole.
+    if let Some(hasher) = &mut hasher {
+        hash_zeros(hasher, &mut buf, len - pos);
+    }
+    if let Some(tracker) = &mut tracker {
+        tracker.advance(len - pos);
+    }
+    writer.set_len(len)?;
+
+    writer.flush()?;
+    Ok(hasher.map(|hasher| hasher.finalize().into()))
+}
+
+/// Feeds `len` zero bytes into `hasher`, using `buf` as scratch space.
+fn hash_zeros(hasher: &mut Sha256, buf: &mut [u8], mut len: u64) {
+    if len == 0 {
+        return;
+    }
+
+    buf.fill(0);
+    while len > 0 {
+        let n = usize::try_from(len).map_or(buf.len(), |l| l.min(buf.len()));
+        hasher.update(&buf[..n]);
+        len -= n as u64;
+    }
+}
+
+/// Returns the SHA-256 of the file at `path`.
+fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
@@ -0,0 +1,268 @@
+//! The tray icon, or menu bar icon on macOS, shown while rocas watches.
+//!
+//! Only builds with the `tray` feature have it, so headless builds do not
+//! pull in a GUI toolkit. Its menu pauses and resumes watching, opens the
+//! config and the log file, lists the latest moves (clicking one opens the
+//! folder the file went to) and quits rocas the way SIGTERM does.
+//!
+//! A paused rocas finishes the moves it has started but takes no new
+//! events; they wait in the watcher's queue until it resumes.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crossbeam_channel::Receiver;
+
+/// How often a paused watcher looks whether it was resumed.
+pub const PAUSE_RECHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Whether watching is paused from the tray.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Returns `true` while watching is paused.
+pub fn paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+/// Shows the tray icon until `stop` closes or `finished` returns `true`.
+/// Must run on the main thread, which macOS needs for the menu bar. Returns
+/// at once without a desktop to show it on, or without the `tray` feature.
+pub fn run(log_file: Option<&str>, stop: &Receiver<()>, finished: impl Fn() -> bool) {
+    platform::run(log_file, stop, finished);
+}
+
+#[cfg(feature = "tray")]
+mod platform {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant, SystemTime};
+
+    use crossbeam_channel::{Receiver, TryRecvError};
+    use tao::event::{Event, StartCause};
+    use tao::event_loop::{ControlFlow, EventLoopBuilder};
+    use tao::platform::run_return::EventLoopExtRunReturn;
+    use tray_icon::menu::{Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu};
+    use tray_icon::{Icon, TrayIcon, TrayIconBuilder};
+
+    use super::PAUSED;
+    use crate::{autostart, config, history, signals};
+
+    const ICON: &[u8] = include_bytes!("../assets/roquita.png");
+
+    /// How many moves "Recent moves" lists.
+    const RECENT: usize = 10;
+
+    /// How often the menu is updated and `stop` is looked at.
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    struct Tray {
+        icon: TrayIcon,
+        pause: MenuItem,
+        open_config: MenuItem,
+        open_log: MenuItem,
+        quit: MenuItem,
+        recent: Submenu,
+        /// The items of `recent` and the folders they open.
+        moves: HashMap<MenuId, PathBuf>,
+        recent_items: Vec<MenuItem>,
+        /// When the history was last changed, as of the last update of
+        /// `recent`.
+        history_changed: Option<SystemTime>,
+        log_file: Option<PathBuf>,
+    }
+
+    pub fn run(log_file: Option<&str>, stop: &Receiver<()>, finished: impl Fn() -> bool) {
+        if !has_desktop() {
+            debug!("No desktop to show the tray icon on.");
+            return;
+        }
+
+        let mut event_loop = EventLoopBuilder::new().build();
+        let mut tray: Option<Tray> = None;
+        let log_file = log_file.map(PathBuf::from);
+        event_loop.run_return(|event, _, control_flow| {
+            *control_flow = ControlFlow::WaitUntil(Instant::now() + POLL_INTERVAL);
+
+            // macOS only shows icons created once the event loop runs.
+            if let Event::NewEvents(StartCause::Init) = event {
+                match Tray::new(log_file.clone()) {
+                    Ok(new) => tray = Some(new),
+                    Err(e) => {
+                        warn!("Could not show the tray icon: {e}");
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    },
+                }
+            }
+
+            if matches!(stop.try_recv(), Err(TryRecvError::Disconnected)) || finished() {
+                *control_flow = ControlFlow::Exit;
+                return;
+            }
+            if let Some(tray) = &mut tray {
+                while let Ok(event) = MenuEvent::receiver().try_recv() {
+                    tray.clicked(event.id());
+                }
+                tray.update_recent();
+            }
+        });
+    }
+
+    impl Tray {
+        fn new(log_file: Option<PathBuf>) -> Result<Self, String> {
+            let pause = MenuItem::new("Pause", true, None);
+            let open_config = MenuItem::new("Open config", true, None);
+            let open_log = MenuItem::new("Open log", log_file.is_some(), None);
+            let recent = Submenu::new("Recent moves", true);
+            let quit = MenuItem::new("Quit", true, None);
+            let version = format!("rocas {}", env!("CARGO_PKG_VERSION"));
+
+            let menu = Menu::new();
+            menu.append_items(&[
+                &MenuItem::new(version, false, None),
+                &PredefinedMenuItem::separator(),
+                &pause,
+                &open_config,
+                &open_log,
+                &recent,
+                &PredefinedMenuItem::separator(),
+                &quit,
+            ])
+            .map_err(|e| e.to_string())?;
+
+            let image = image::load_from_memory(ICON)
+                .map_err(|e| e.to_string())?
+                .into_rgba8();
+            let (width, height) = image.dimensions();
+            let icon =
+                Icon::from_rgba(image.into_raw(), width, height).map_err(|e| e.to_string())?;
+            let tray_icon = TrayIconBuilder::new()
+                .with_icon(icon)
+                .with_tooltip("rocas is watching")
+                .with_menu(Box::new(menu))
+                .build()
+                .map_err(|e| e.to_string())?;
+
+            let mut tray = Self {
+                icon: tray_icon,
+                pause,
+                open_config,
+                open_log,
+                quit,
+                recent,
+                moves: HashMap::new(),
+                recent_items: Vec::new(),
+                history_changed: None,
+                log_file,
+            };
+            tray.update_recent();
+            Ok(tray)
+        }
+
+        fn clicked(&self, id: &MenuId) {
+            if id == self.pause.id() {
+                let paused = !PAUSED.fetch_xor(true, Ordering::SeqCst);
+                self.pause
+                    .set_text(if paused { "Resume" } else { "Pause" });
+                let tooltip = if paused { "rocas is paused" } else { "rocas is watching" };
+                let _ = self.icon.set_tooltip(Some(tooltip));
+                info!("{} watching from the tray.", if paused { "Paused" } else { "Resumed" });
+            } else if id == self.open_config.id() {
+                open(Path::new(&config::config_path()));
+            } else if id == self.open_log.id() {
+                if let Some(log_file) = &self.log_file {
+                    open(log_file);
+                }
+            } else if id == self.quit.id() {
+                signals::stop();
+            } else if let Some(folder) = self.moves.get(id) {
+                open(folder);
+            }
+        }
+
+        /// Lists the latest moves in "Recent moves", if the history changed
+        /// since the last time.
+        fn update_recent(&mut self) {
+            let changed = history::changed();
+            if changed == self.history_changed && !self.recent_items.is_empty() {
+                return;
+            }
+            self.history_changed = changed;
+
+            for item in self.recent_items.drain(..) {
+                let _ = self.recent.remove(&item);
+            }
+            self.moves.clear();
+
+            let moves = history::recent(RECENT);
+            if moves.is_empty() {
+                let item = MenuItem::new("No moves yet", false, None);
+                let _ = self.recent.append(&item);
+                self.recent_items.push(item);
+                return;
+            }
+            for (from, to) in moves {
+                let name = from
+                    .file_name()
+                    .unwrap_or(from.as_os_str())
+                    .to_string_lossy()
+                    .into_owned();
+                let item = MenuItem::new(format!("{name} → {}", to.display()), true, None);
+                if self.recent.append(&item).is_ok() {
+                    let folder = to.parent().unwrap_or(&to).to_path_buf();
+                    self.moves
+                        .insert(item.id().clone(), folder);
+                    self.recent_items.push(item);
+                }
+            }
+        }
+    }
+
+    /// Whether there is a desktop session to show the icon in. On Linux and
+    /// the BSDs, the toolkit aborts without a display, and a Windows service
+    /// has no desktop.
+    fn has_desktop() -> bool {
+        if autostart::is_service() {
+            return false;
+        }
+        if cfg!(any(target_os = "windows", target_os = "macos")) {
+            return true;
+        }
+        ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|var| std::env::var_os(var).is_some_and(|value| !value.is_empty()))
+    }
+
+    /// Opens `path` with the application the desktop picks for it.
+    fn open(path: &Path) {
+        let opener = if cfg!(target_os = "windows") {
+            "explorer"
+        } else if cfg!(target_os = "macos") {
+            "open"
+        } else {
+            "xdg-open"
+        };
+        match Command::new(opener)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            // Waited for on the side, so it does not linger as a zombie.
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            },
+            Err(e) => warn!("Could not open '{}': {e}", path.display()),
+        }
+    }
+}
+
+#[cfg(not(feature = "tray"))]
+mod platform {
+    use crossbeam_channel::Receiver;
+
+    pub fn run(_log_file: Option<&str>, _stop: &Receiver<()>, _finished: impl Fn() -> bool) {}
+}
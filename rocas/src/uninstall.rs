@@ -0,0 +1,198 @@
+//! `rocas uninstall`: removes what rocas set up and wrote.
+//!
+//! Starting on boot is turned off, and the files rocas keeps next to its
+//! config (the queues of matches waiting for their destination, the history
+//! of moves, the status snapshot, the PID file and the merged config) are
+//! removed along with the log files. rocas then asks whether to remove the
+//! config, with the backups `rocas migrate` made, and the rocas binary too.
+//! `--purge` removes all of it without asking.
+
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+
+use crate::config::{self, Config};
+use crate::{
+    AppError,
+    daemon,
+    history,
+    include,
+    init,
+    offline,
+    rollout,
+    service,
+    status,
+    update,
+};
+
+/// Removes rocas after confirming, or without asking if `purge` is set.
+/// Returns `false` if anything could not be removed.
+///
+/// # Errors
+///
+/// Returns [`AppError::Running`] while rocas is running, or an I/O error if
+/// stdin closes before the questions are answered.
+pub fn run(matches: &ArgMatches, purge: bool) -> Result<bool, AppError> {
+    if status::is_running() {
+        return Err(AppError::Running("would keep running"));
+    }
+
+    let config_path = PathBuf::from(config::config_path());
+    let exe = std::env::current_exe()?;
+    if !purge
+        && !init::confirm("Stop starting rocas on boot and remove its state and log files?", false)?
+    {
+        println!("Nothing was removed.");
+        return Ok(true);
+    }
+    let remove_config = purge
+        || (config_path.exists()
+            && init::confirm(
+                &format!("Remove the config '{}' too?", config_path.display()),
+                false,
+            )?);
+    let remove_binary = purge
+        || init::confirm(&format!("Remove the rocas binary '{}' too?", exe.display()), false)?;
+
+    // The log may be configured elsewhere than the default.
+    let log_file = Config::load(matches)
+        .ok()
+        .and_then(|config| config.misc.log_file)
+        .unwrap_or_else(config::logs_path);
+
+    let mut removed_all = true;
+    if let Err(e) = service::uninstall() {
+        println!("Could not stop starting rocas on boot: {e}");
+        removed_all = false;
+    }
+
+    let dir = config::rocas_dir();
+    let mut files = matching(&dir, is_state);
+    files.push(daemon::default_pid_file());
+    let log_file = PathBuf::from(log_file);
+    if let (Some(log_dir), Some(log_name)) = (log_file.parent(), log_file.file_name()) {
+        let log_name = log_name.to_string_lossy().into_owned();
+        files.extend(matching(log_dir, |name| is_log(name, &log_name)));
+    }
+    if remove_config {
+        files.push(config_path.clone());
+        if let (Some(config_dir), Some(config_name)) =
+            (config_path.parent(), config_path.file_name())
+        {
+            let backup = format!("{}.v", config_name.to_string_lossy());
+            files.extend(matching(config_dir, |name| {
+                name.strip_prefix(&backup)
+                    .and_then(|rest| rest.strip_suffix(".bak"))
+                    .is_some_and(|from| from.bytes().all(|b| b.is_ascii_digit()))
+            }));
+        }
+    }
+    for file in files {
+        removed_all &= remove(&file);
+    }
+    // Only goes away once nothing else is left in it.
+    let _ = std::fs::remove_dir(&dir);
+
+    if remove_binary {
+        removed_all &= remove_exe(&exe);
+    }
+
+    Ok(removed_all)
+}
+
+/// Returns `true` for the name of a file rocas keeps in its config
+/// directory. Each is replaced by renaming a sibling with the same stem.
+fn is_state(name: &str) -> bool {
+    let stem = |name: &'static str| name.split('.').next();
+    let own_stem = name.split('.').next().unwrap_or(name);
+
+    own_stem.starts_with(offline::JOURNAL_NAME)
+        || own_stem.starts_with(include::MERGED_PREFIX)
+        || [
+            history::HISTORY_NAME,
+            status::STATUS_NAME,
+            update::LAST_UPDATE_NAME,
+            update::RELEASES_NAME,
+            rollout::ROLLOUT_ID_NAME,
+        ]
+        .into_iter()
+        .any(|state| stem(state) == Some(own_stem))
+}
+
+/// Returns `true` for `log_name` and the files it is rotated to, like
+/// `rocas.log.1`.
+fn is_log(name: &str, log_name: &str) -> bool {
+    name.strip_prefix(log_name)
+        .is_some_and(|rest| {
+            rest.is_empty()
+                || rest
+                    .strip_prefix('.')
+                    .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        })
+}
+
+/// Returns the files in `dir` whose name `wanted` accepts.
+fn matching(dir: &Path, wanted: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(&wanted)
+        })
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// Removes `path`, if it exists. Returns `false` if it cannot be removed.
+fn remove(path: &Path) -> bool {
+    match std::fs::remove_file(path) {
+        Ok(()) => {
+            println!("Removed {}", path.display());
+            true
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => true,
+        Err(e) => {
+            println!("Could not remove {}: {e}", path.display());
+            false
+        },
+    }
+}
+
+/// Removes the running binary. Unix lets it go while it runs.
+#[cfg(not(windows))]
+fn remove_exe(exe: &Path) -> bool {
+    remove(exe)
+}
+
+/// Removes the running binary. Windows keeps it while it runs, so a detached
+/// shell deletes it once rocas has exited.
+#[cfg(windows)]
+fn remove_exe(exe: &Path) -> bool {
+    use std::os::windows::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let spawned = Command::new("cmd")
+        .arg("/C")
+        .raw_arg(format!("ping -n 3 127.0.0.1 >NUL & del /F /Q \"{}\"", exe.display()))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags(CREATE_NO_WINDOW)
+        .spawn();
+    match spawned {
+        Ok(_) => {
+            println!("{} is removed once rocas exits", exe.display());
+            true
+        },
+        Err(e) => {
+            println!("Could not remove {}: {e}", exe.display());
+            false
+        },
+    }
+}
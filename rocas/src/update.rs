@@ -0,0 +1,1151 @@
+//! Looking for and installing new releases of rocas from GitHub.
+//!
+//! Requests go through the proxy in `misc.update_proxy`, or else the one in
+//! the `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` environment variables.
+//! They carry the token in `misc.update_token` or `GITHUB_TOKEN`, if set,
+//! which raises GitHub's rate limit from 60 requests an hour per IP address.
+//! When the limit is reached, rocas waits for it to reset if that is a
+//! matter of seconds, and otherwise skips the check until the next start.
+//!
+//! The release list is kept in `releases.json` in the rocas config
+//! directory with its `ETag` and `Last-Modified`, and the next check only
+//! asks GitHub whether it changed. An unchanged list is not downloaded
+//! again, and with a token the check does not count against the limit.
+//!
+//! The newest release is picked by comparing versions as semver, with any
+//! `v` prefix of the tag removed, so only a strictly newer version is
+//! installed. Only releases of the configured channel or a more stable one
+//! are considered: a version without a pre-release part is stable, one like
+//! `1.5.0-nightly.20260101` is nightly, and any other pre-release, like
+//! `1.5.0-beta.1` or `1.5.0-rc.1`, is beta. A release can also require a
+//! minimum version to update from, or be offered to only some installations
+//! at first; see [`crate::rollout`].
+//!
+//! A new build is only installed once its archive matches the SHA-256
+//! checksum published with the release: the `<archive>.sha256` asset next to
+//! it, or a `<checksum>  <archive>` line in the release notes, as written by
+//! `sha256sum`. A release without a checksum for the archive, or with a
+//! different one, is not installed.
+//!
+//! The archive must also carry an ed25519 signature, the `<archive>.sig`
+//! asset, made with the key whose public half is built into the official
+//! binaries. A checksum only shows the download is intact; the signature
+//! shows it was made by whoever holds the release key, not merely uploaded
+//! to the releases. Builds made without the public key cannot check
+//! signatures, so they only update with `verify_update_signature = false`.
+//!
+//! An interrupted download is kept in `update` in the rocas config
+//! directory, which only the user running rocas can enter, and the next
+//! check resumes it where it stopped. The complete archive is checked
+//! against its checksum and signature where it is installed from, after it
+//! was moved there, so nothing can swap it between the check and the
+//! install; one that does not match is deleted.
+//!
+//! With `misc.update_signer` set, the new binary must also carry a valid
+//! code signature by that signer on Windows and macOS; see
+//! [`crate::codesign`].
+//!
+//! Before it replaces the running binary, the new one is run with
+//! `--version` and has to print the version of the release. A build for
+//! another architecture or C library, or one that is damaged in a way the
+//! checks above cannot see, is not installed and rocas keeps running as it
+//! is.
+//!
+//! Once installed, the start of the release notes is logged and the update
+//! is recorded in `update.json` in the rocas config directory, where
+//! `rocas status` finds it.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use reqwest::{Proxy, StatusCode};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use self_update::{ArchiveKind, Compression, Extract, Release, ReleaseAsset, cargo_crate_version};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::codesign::{self, CodesignError};
+use crate::config::{self, Channel, MiscConfig};
+use crate::rollout::{self, Gate};
+use crate::{AppError, logger};
+
+pub const LAST_UPDATE_NAME: &str = "update.json";
+pub const RELEASES_NAME: &str = "releases.json";
+
+const REPO_OWNER: &str = "chikof";
+const REPO_NAME: &str = "rocas";
+
+/// The public key release archives are signed with, as 64 hex digits. The
+/// release workflow sets it when it builds the official binaries.
+const PUBLIC_KEY: Option<&str> = option_env!("ROCAS_UPDATE_PUBLIC_KEY");
+
+/// How many releases to ask for; the newest is among the latest ones.
+const PER_PAGE: usize = 30;
+
+/// The longest rocas waits on startup for the rate limit to reset.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// How long downloading an archive may take before it is resumed on the
+/// next check instead.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long the new binary gets to print its version.
+const VERSION_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many lines of the release notes to log and show in `rocas status`.
+const SUMMARY_LINES: usize = 8;
+
+/// The name of the binary inside the release archives.
+const BIN_NAME: &str = if cfg!(windows) { "rocas.exe" } else { "rocas" };
+
+/// Error returned when a release cannot be installed.
+#[derive(Debug, thiserror::Error)]
+pub enum UpdateError {
+    #[error("GitHub request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("GitHub answered {status} to {url}")]
+    Status { status: StatusCode, url: String },
+
+    #[error("GitHub's rate limit is reached for another {} min", .0.as_secs().div_ceil(60))]
+    RateLimited(Duration),
+
+    #[error("GitHub returned an unreadable release list: {0}")]
+    BadReleases(#[from] serde_json::Error),
+
+    #[error("release {version} has no build for {target}; it has {available}")]
+    NoAsset {
+        version: String,
+        target: &'static str,
+        available: String,
+    },
+
+    #[error("no SHA-256 checksum is published for '{0}'; not installing it")]
+    NoChecksum(String),
+
+    #[error(
+        "'{asset}' does not match its published checksum (expected {expected}, got {actual}); not \
+         installing it"
+    )]
+    ChecksumMismatch {
+        asset: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error(
+        "this build of rocas has no valid key to check update signatures with; set \
+         misc.verify_update_signature = false to update without checking them"
+    )]
+    NoPublicKey,
+
+    #[error("no signature is published for '{0}'; not installing it")]
+    NoSignature(String),
+
+    #[error("the signature of '{0}' is not valid; not installing it")]
+    BadSignature(String),
+
+    #[error(
+        "the download of '{asset}' stopped after {got} of {expected} bytes; the next check \
+         resumes it"
+    )]
+    Incomplete { asset: String, got: u64, expected: u64 },
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("the new rocas {version} is not installed: {source}")]
+    CodeSignature { version: String, source: CodesignError },
+
+    #[error("the new rocas {version} does not work here ({reason}); keeping this one")]
+    BrokenBinary { version: String, reason: String },
+}
+
+/// Checks GitHub for a newer release on the configured channel and, with
+/// `auto_update`, installs it and restarts rocas.
+///
+/// # Errors
+///
+/// Returns [`AppError`] if the releases cannot be fetched, or the new
+/// release cannot be downloaded, verified or installed.
+pub fn check(misc: &MiscConfig) -> Result<(), AppError> {
+    let github = Github::new(misc)?;
+    let releases = match github.releases() {
+        Err(UpdateError::RateLimited(wait)) => {
+            warn!(
+                "Skipping the update check: {}{}",
+                UpdateError::RateLimited(wait),
+                if github.token.is_none() {
+                    "; set 'misc.update_token' to raise the limit"
+                } else {
+                    ""
+                }
+            );
+            return Ok(());
+        },
+        result => result?,
+    };
+
+    if releases.is_empty() {
+        return Err(AppError::Other("GitHub returned an empty release list".to_string()));
+    }
+    let current = cargo_crate_version!();
+
+    let installation = rollout::installation_id();
+    if let Some(latest) = newest(&releases, current, misc.update_channel(), &installation) {
+        info!("New version available: {} → {}", current, version_of(latest));
+        warn!(
+            "To update manually update or set the 'misc.auto_update' option in the config to true."
+        );
+
+        if misc.auto_update {
+            // Capture the exe path before the update so the path is resolved
+            // while the current file is still open (matters on Windows where
+            // self_replace renames the running exe before writing the new one).
+            let exe = std::env::current_exe()?;
+
+            install(&github, latest, misc)?;
+
+            info!("Updated to version {}", version_of(latest));
+            let update = LastUpdate {
+                from: current.to_string(),
+                to: version_of(latest).to_string(),
+                installed: SystemTime::now(),
+                notes: latest.body.clone(),
+            };
+            for line in update.summary() {
+                info!("  {line}");
+            }
+            if let Err(e) = update.write() {
+                warn!("Could not record the update in '{}': {e}", last_update_path().display());
+            }
+
+            info!("Restarting…");
+            crate::restart(&exe)?;
+        }
+    } else {
+        trace!(
+            "No update available (current: {}, latest: {})",
+            current,
+            releases.first().map_or("", version_of)
+        );
+    }
+
+    Ok(())
+}
+
+/// The update rocas last installed, as recorded in [`LAST_UPDATE_NAME`].
+pub struct LastUpdate {
+    pub from: String,
+    pub to: String,
+    pub installed: SystemTime,
+    pub notes: Option<String>,
+}
+
+impl LastUpdate {
+    /// Reads the recorded update, or `None` if there is none or it is
+    /// malformed.
+    pub fn read() -> Option<Self> {
+        let contents = std::fs::read_to_string(last_update_path()).ok()?;
+        let value: Value = serde_json::from_str(&contents).ok()?;
+        Some(Self {
+            from: value.get("from")?.as_str()?.to_string(),
+            to: value.get("to")?.as_str()?.to_string(),
+            installed: UNIX_EPOCH + Duration::from_secs(value.get("installed")?.as_u64()?),
+            notes: value
+                .get("notes")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+    }
+
+    /// The update as written to its file and printed by `rocas status
+    /// --json`. The time is a Unix timestamp in seconds.
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "from": self.from,
+            "to": self.to,
+            "installed": self.installed_secs(),
+            "notes": self.notes,
+        })
+    }
+
+    /// When the update was installed, formatted like the log.
+    pub fn installed_at(&self) -> String {
+        logger::format_timestamp(self.installed_secs())
+    }
+
+    /// Returns the first [`SUMMARY_LINES`] lines of the release notes that
+    /// are not blank, with a last line saying how many more there are.
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .notes
+            .as_deref()
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect();
+        if lines.len() > SUMMARY_LINES {
+            let more = lines.len() - SUMMARY_LINES;
+            lines.truncate(SUMMARY_LINES);
+            lines.push(format!("… and {more} more line{}", if more == 1 { "" } else { "s" }));
+        }
+        lines
+    }
+
+    fn installed_secs(&self) -> u64 {
+        self.installed
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    fn write(&self) -> io::Result<()> {
+        write_state(&last_update_path(), &self.to_json())
+    }
+}
+
+fn last_update_path() -> PathBuf {
+    config::rocas_dir().join(LAST_UPDATE_NAME)
+}
+
+/// The GitHub API, with the token to authenticate to it, if any.
+struct Github {
+    client: Client,
+    token: Option<String>,
+}
+
+impl Github {
+    fn new(misc: &MiscConfig) -> Result<Self, UpdateError> {
+        let token = misc
+            .update_token
+            .clone()
+            .or_else(|| std::env::var("GITHUB_TOKEN").ok())
+            .filter(|token| !token.trim().is_empty());
+        let mut builder =
+            Client::builder().user_agent(concat!("rocas/", env!("CARGO_PKG_VERSION")));
+        if let Some(url) = &misc.update_proxy {
+            debug!("Looking for updates through the proxy {url}");
+            builder = builder.proxy(Proxy::all(url.as_str())?);
+        }
+        let client = builder.build()?;
+        Ok(Self { client, token })
+    }
+
+    /// Fetches the latest releases, newest first, or takes them from the
+    /// cache if they have not changed since. If the rate limit is reached
+    /// and resets within [`MAX_RATE_LIMIT_WAIT`], waits for it once.
+    fn releases(&self) -> Result<Vec<Release>, UpdateError> {
+        let url = format!(
+            "https://api.github.com/repos/{REPO_OWNER}/{REPO_NAME}/releases?per_page={PER_PAGE}"
+        );
+        let cached = ReleaseCache::read().filter(|cache| cache.url == url);
+        let mut waited = false;
+        loop {
+            let mut request = self
+                .client
+                .get(&url)
+                .header("Accept", "application/vnd.github+json");
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            if let Some(cache) = &cached {
+                if let Some(etag) = &cache.etag {
+                    request = request.header("If-None-Match", etag.as_str());
+                }
+                if let Some(modified) = &cache.last_modified {
+                    request = request.header("If-Modified-Since", modified.as_str());
+                }
+            }
+            let response = request.send()?;
+            let status = response.status();
+            if status == StatusCode::NOT_MODIFIED
+                && let Some(cache) = &cached
+            {
+                debug!("The release list has not changed since the last check");
+                return parse_releases(&cache.body);
+            }
+            if status.is_success() {
+                let etag = header(&response, "etag").map(str::to_string);
+                let last_modified = header(&response, "last-modified").map(str::to_string);
+                let body = response.text()?;
+                let releases = parse_releases(&body)?;
+
+                let cache = ReleaseCache { url, etag, last_modified, body };
+                if let Err(e) = cache.write() {
+                    warn!(
+                        "Could not cache the release list in '{}': {e}",
+                        releases_path().display()
+                    );
+                }
+                return Ok(releases);
+            }
+
+            match rate_limit_wait(&response) {
+                Some(wait) if !waited && wait <= MAX_RATE_LIMIT_WAIT => {
+                    info!("GitHub's rate limit is reached; asking again in {} s", wait.as_secs());
+                    std::thread::sleep(wait);
+                    waited = true;
+                },
+                Some(wait) => return Err(UpdateError::RateLimited(wait)),
+                None => return Err(UpdateError::Status { status, url }),
+            }
+        }
+    }
+
+    /// Downloads a small release asset, like a checksum, into memory.
+    fn fetch(&self, asset: &ReleaseAsset) -> Result<Vec<u8>, UpdateError> {
+        let response = self.asset_request(asset).send()?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(UpdateError::Status { status, url: asset.download_url.clone() });
+        }
+        Ok(response.bytes()?.to_vec())
+    }
+
+    /// Downloads `asset` to `path`. If `path` holds the start of it from an
+    /// earlier attempt, only the rest is downloaded.
+    fn download(&self, asset: &ReleaseAsset, path: &Path) -> Result<(), UpdateError> {
+        let have = std::fs::metadata(path).map_or(0, |meta| meta.len());
+        let mut request = self
+            .asset_request(asset)
+            .timeout(DOWNLOAD_TIMEOUT);
+        if have > 0 {
+            request = request.header("Range", format!("bytes={have}-").as_str());
+        }
+        let mut response = request.send()?;
+
+        let status = response.status();
+        let (mut file, offset) = match status {
+            StatusCode::PARTIAL_CONTENT => {
+                info!("Resuming the download of '{}' after {have} bytes", asset.name);
+                (
+                    OpenOptions::new()
+                        .append(true)
+                        .open(path)?,
+                    have,
+                )
+            },
+            // The earlier attempt got all of it; the checksum tells whether
+            // it is really the archive.
+            StatusCode::RANGE_NOT_SATISFIABLE if have > 0 => return Ok(()),
+            status if status.is_success() => (File::create(path)?, 0),
+            status => {
+                return Err(UpdateError::Status { status, url: asset.download_url.clone() });
+            },
+        };
+        let total = response
+            .content_length()
+            .map(|len| offset + len);
+        if offset == 0 {
+            info!(
+                "Downloading '{}'{}",
+                asset.name,
+                total.map_or(String::new(), |total| format!(" ({} KB)", total.div_ceil(1024)))
+            );
+        }
+
+        let mut got = offset;
+        let mut logged = 0;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let read = response.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            file.write_all(&buf[..read])?;
+            got += read as u64;
+            if let Some(total) = total.filter(|&total| total > 0) {
+                let percent = got * 100 / total;
+                if percent / 10 > logged / 10 {
+                    logged = percent;
+                    debug!("Downloaded {percent}% of '{}'", asset.name);
+                }
+            }
+        }
+        file.sync_all()?;
+
+        match total {
+            Some(expected) if got != expected => {
+                Err(UpdateError::Incomplete { asset: asset.name.clone(), got, expected })
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn asset_request(&self, asset: &ReleaseAsset) -> RequestBuilder {
+        // The API URL of an asset returns its content only when asked for it.
+        let request = self
+            .client
+            .get(&asset.download_url)
+            .header("Accept", "application/octet-stream");
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+/// Reads the releases in a response of the releases API. Drafts, which
+/// only show with a token of someone who can publish them, are skipped.
+fn parse_releases(text: &str) -> Result<Vec<Release>, UpdateError> {
+    let list: Vec<Value> = serde_json::from_str(text)?;
+    let field = |value: &Value, key: &str| {
+        value
+            .get(key)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+
+    Ok(list
+        .iter()
+        .filter(|release| {
+            release
+                .get("draft")
+                .and_then(Value::as_bool)
+                != Some(true)
+        })
+        .filter_map(|release| {
+            let tag = field(release, "tag_name")?;
+            let assets = release
+                .get("assets")
+                .and_then(Value::as_array)
+                .map(|assets| {
+                    assets
+                        .iter()
+                        .filter_map(|asset| {
+                            Some(ReleaseAsset {
+                                download_url: field(asset, "url")?,
+                                name: field(asset, "name")?,
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(Release {
+                name: field(release, "name").unwrap_or_else(|| tag.clone()),
+                version: tag.trim_start_matches('v').to_string(),
+                date: field(release, "created_at").unwrap_or_default(),
+                body: field(release, "body"),
+                assets,
+            })
+        })
+        .collect())
+}
+
+/// Returns how long to wait before asking again if `response` says a rate
+/// limit is reached, from its `Retry-After` header or else from when the
+/// hourly limit resets.
+fn rate_limit_wait(response: &Response) -> Option<Duration> {
+    let status = response.status();
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let header = |name: &str| header(response, name);
+
+    if let Some(secs) = header("retry-after").and_then(|value| value.parse().ok()) {
+        return Some(Duration::from_secs(secs));
+    }
+    // Other 403s mean the request is not allowed at all.
+    if header("x-ratelimit-remaining") != Some("0") {
+        return None;
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    let reset = header("x-ratelimit-reset")
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(now + 60);
+    Some(Duration::from_secs(reset.saturating_sub(now)))
+}
+
+fn header<'r>(response: &'r Response, name: &str) -> Option<&'r str> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+}
+
+/// The release list as last fetched, kept in [`RELEASES_NAME`].
+struct ReleaseCache {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+impl ReleaseCache {
+    fn read() -> Option<Self> {
+        let contents = std::fs::read_to_string(releases_path()).ok()?;
+        let value: Value = serde_json::from_str(&contents).ok()?;
+        let field = |key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        Some(Self {
+            url: field("url")?,
+            etag: field("etag"),
+            last_modified: field("last_modified"),
+            body: field("body")?,
+        })
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let contents = serde_json::json!({
+            "url": self.url,
+            "etag": self.etag,
+            "last_modified": self.last_modified,
+            "body": self.body,
+        });
+        write_state(&releases_path(), &contents)
+    }
+}
+
+fn releases_path() -> PathBuf {
+    config::rocas_dir().join(RELEASES_NAME)
+}
+
+/// Writes `contents` to `path` through a temporary file, so a reader never
+/// sees half of it.
+fn write_state(path: &Path, contents: &Value) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, contents.to_string())?;
+    std::fs::rename(&temp, path)
+}
+
+/// Returns the newest release on `channel` that is strictly newer than
+/// `current` and offered to `installation`, if any. Releases whose tag is
+/// not a version are skipped.
+fn newest<'r>(
+    releases: &'r [Release],
+    current: &str,
+    channel: Channel,
+    installation: &str,
+) -> Option<&'r Release> {
+    let mut newest: Option<&Release> = None;
+    for release in releases {
+        let version = version_of(release);
+        if channel_of(version) > channel || !offered(release, current, installation) {
+            continue;
+        }
+        let newer =
+            self_update::version::bump_is_greater(newest.map_or(current, version_of), version);
+        if matches!(newer, Ok(true)) {
+            newest = Some(release);
+        }
+    }
+    newest
+}
+
+/// Returns `true` unless the gate in the notes of `release` keeps it from
+/// `installation`, which runs `current`.
+fn offered(release: &Release, current: &str, installation: &str) -> bool {
+    let version = version_of(release);
+    let gate = Gate::parse(
+        release
+            .body
+            .as_deref()
+            .unwrap_or_default(),
+    );
+    if let Some(minimum) = &gate.minimum_version
+        && matches!(self_update::version::bump_is_greater(current, minimum), Ok(true))
+    {
+        debug!("Release {version} needs rocas {minimum} or later to update from; skipping it");
+        return false;
+    }
+    if let Some(percent) = gate.rollout
+        && !rollout::includes(installation, version, percent)
+    {
+        debug!("Release {version} is rolled out to {percent}% of installations, not yet this one");
+        return false;
+    }
+    true
+}
+
+/// Returns the version of `release`, without a `v` prefix from its tag.
+fn version_of(release: &Release) -> &str {
+    release.version.trim_start_matches('v')
+}
+
+/// Returns the channel a release with `version` belongs to.
+fn channel_of(version: &str) -> Channel {
+    // Build metadata after `+` may contain `-` too.
+    let core = version
+        .split('+')
+        .next()
+        .unwrap_or(version);
+    match core.split_once('-') {
+        None => Channel::Stable,
+        Some((_, pre))
+            if pre
+                .to_ascii_lowercase()
+                .starts_with("nightly") =>
+        {
+            Channel::Nightly
+        },
+        Some(_) => Channel::Beta,
+    }
+}
+
+/// Downloads the build of `release` for this platform, checks it against
+/// its published checksum and, as configured in `misc`, its signatures, and
+/// replaces the running binary with it.
+fn install(github: &Github, release: &Release, misc: &MiscConfig) -> Result<(), AppError> {
+    // The target this binary was built for, architecture included, like
+    // `aarch64-apple-darwin`.
+    let target = self_update::get_target();
+    let asset = archive_for(release, target).ok_or_else(|| {
+        let archives: Vec<&str> = release
+            .assets
+            .iter()
+            .filter(|asset| !is_sidecar(&asset.name))
+            .map(|asset| asset.name.as_str())
+            .collect();
+        UpdateError::NoAsset {
+            version: version_of(release).to_string(),
+            target,
+            available: if archives.is_empty() {
+                "no builds at all".to_string()
+            } else {
+                archives.join(", ")
+            },
+        }
+    })?;
+    let expected = published_checksum(github, release, asset)?;
+    let signature = if misc.verify_update_signature {
+        Some((public_key()?, published_signature(github, release, asset)?))
+    } else {
+        warn!("Not checking the signature of '{}', as configured", asset.name);
+        None
+    };
+
+    // Partial downloads are kept between attempts, named after the archive,
+    // whose name holds the version.
+    let downloads = config::rocas_dir().join("update");
+    config::create_private_dir(&downloads)?;
+    let partial = downloads.join(format!("{}.part", asset.name));
+    github.download(asset, &partial)?;
+
+    // Checked where it is installed from, so it cannot change in between.
+    let archive = downloads.join(&asset.name);
+    std::fs::rename(&partial, &archive)?;
+    if let Err(e) = verify(&archive, asset, &expected, signature.as_ref()) {
+        let _ = std::fs::remove_file(&archive);
+        return Err(e.into());
+    }
+
+    // A directory with a random name that only this user can enter, so the
+    // binary cannot be swapped between its checks and the install. It is
+    // deleted when dropped.
+    let dir = tempfile::Builder::new()
+        .prefix("rocas-update-")
+        .tempdir()?;
+    let result = install_from(
+        &archive,
+        dir.path(),
+        asset,
+        version_of(release),
+        misc.update_signer.as_deref(),
+    );
+    if result.is_ok() {
+        // Anything left is from releases that were not installed.
+        let _ = std::fs::remove_dir_all(&downloads);
+    } else {
+        let _ = std::fs::remove_file(&archive);
+    }
+    result
+}
+
+/// Returns the archive of `release` built for `target`, like
+/// `rocas-v1.4.0-x86_64-unknown-linux-gnu.tar.gz`. The checksum and
+/// signature assets next to it name the target too, so they are skipped.
+fn archive_for<'r>(release: &'r Release, target: &str) -> Option<&'r ReleaseAsset> {
+    release
+        .assets
+        .iter()
+        .find(|asset| asset.name.contains(target) && !is_sidecar(&asset.name))
+}
+
+/// Returns the asset of `release` named like `asset` with `extension`
+/// added, like its `.sha256` checksum or `.sig` signature.
+fn sidecar<'r>(
+    release: &'r Release,
+    asset: &ReleaseAsset,
+    extension: &str,
+) -> Option<&'r ReleaseAsset> {
+    let name = format!("{}.{extension}", asset.name);
+    release
+        .assets
+        .iter()
+        .find(|other| other.name == name)
+}
+
+/// Returns `true` for the name of a checksum or signature asset.
+fn is_sidecar(name: &str) -> bool {
+    name.rsplit_once('.')
+        .is_some_and(|(_, ext)| {
+            ext.eq_ignore_ascii_case("sha256") || ext.eq_ignore_ascii_case("sig")
+        })
+}
+
+/// Checks the download of `asset` at `path` against its checksum and, if
+/// given, its signature.
+fn verify(
+    path: &Path,
+    asset: &ReleaseAsset,
+    expected: &str,
+    signature: Option<&(VerifyingKey, Signature)>,
+) -> Result<(), UpdateError> {
+    let actual = sha256(path)?;
+    if actual != expected {
+        return Err(UpdateError::ChecksumMismatch {
+            asset: asset.name.clone(),
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    debug!("'{}' matches its published checksum {expected}", asset.name);
+
+    if let Some((key, signature)) = signature {
+        key.verify(&std::fs::read(path)?, signature)
+            .map_err(|_| UpdateError::BadSignature(asset.name.clone()))?;
+        debug!("'{}' is signed with the release key", asset.name);
+    }
+    Ok(())
+}
+
+/// Installs the binary of `version` from `archive`, the download of
+/// `asset`, provided it is code signed by `signer`, if given. The binary is
+/// extracted into `dir`.
+fn install_from(
+    archive: &Path,
+    dir: &Path,
+    asset: &ReleaseAsset,
+    version: &str,
+    signer: Option<&str>,
+) -> Result<(), AppError> {
+    Extract::from_source(archive)
+        .archive(archive_kind(&asset.name))
+        .extract_file(dir, BIN_NAME)?;
+    let binary = dir.join(BIN_NAME);
+    match signer {
+        Some(signer) if codesign::supported() => {
+            codesign::verify(&binary, signer).map_err(|source| UpdateError::CodeSignature {
+                version: version.to_string(),
+                source,
+            })?;
+            debug!("The new binary is code signed by {signer}");
+        },
+        Some(_) => debug!("Binaries carry no code signature on this platform; not checking it"),
+        None => {},
+    }
+    check_runs(&binary, version)?;
+    self_update::self_replace::self_replace(binary)?;
+    Ok(())
+}
+
+/// Runs the binary at `path` with `--version` and checks that it prints
+/// `version`.
+fn check_runs(path: &Path, version: &str) -> Result<(), UpdateError> {
+    let broken =
+        |reason: String| UpdateError::BrokenBinary { version: version.to_string(), reason };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| broken(format!("cannot make it executable: {e}")))?;
+    }
+    let mut child = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| broken(format!("cannot start it: {e}")))?;
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() < VERSION_CHECK_TIMEOUT => {
+                std::thread::sleep(Duration::from_millis(50));
+            },
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(broken(format!(
+                    "`--version` did not finish within {} s",
+                    VERSION_CHECK_TIMEOUT.as_secs()
+                )));
+            },
+            Err(e) => return Err(broken(e.to_string())),
+        }
+    };
+    if !status.success() {
+        return Err(broken(format!("`--version` failed with {status}")));
+    }
+
+    let mut output = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        let _ = stdout.read_to_string(&mut output);
+    }
+    let words: Vec<&str> = output.split_whitespace().collect();
+    if words != ["rocas", version] {
+        return Err(broken(format!("`--version` printed '{}'", output.trim())));
+    }
+    debug!("The new binary runs and is rocas {version}");
+    Ok(())
+}
+
+/// Returns the checksum published for `asset`, from its `.sha256` asset or
+/// else from the release notes, in lowercase.
+fn published_checksum(
+    github: &Github,
+    release: &Release,
+    asset: &ReleaseAsset,
+) -> Result<String, AppError> {
+    if let Some(sums) = sidecar(release, asset, "sha256") {
+        let contents = github.fetch(sums)?;
+        if let Some(sum) = String::from_utf8_lossy(&contents)
+            .split_whitespace()
+            .find(|word| is_sha256(word))
+        {
+            return Ok(sum.to_ascii_lowercase());
+        }
+    }
+
+    release
+        .body
+        .as_deref()
+        .and_then(|notes| listed_checksum(notes, &asset.name))
+        .ok_or_else(|| UpdateError::NoChecksum(asset.name.clone()).into())
+}
+
+/// Returns the key built into this binary to check signatures with.
+fn public_key() -> Result<VerifyingKey, UpdateError> {
+    let hex = PUBLIC_KEY.ok_or(UpdateError::NoPublicKey)?;
+    let bytes: [u8; 32] = decode_hex(hex.trim())
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(UpdateError::NoPublicKey)?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| UpdateError::NoPublicKey)
+}
+
+/// Downloads the signature published for `asset` as its `.sig` asset.
+fn published_signature(
+    github: &Github,
+    release: &Release,
+    asset: &ReleaseAsset,
+) -> Result<Signature, AppError> {
+    let sig = sidecar(release, asset, "sig")
+        .ok_or_else(|| UpdateError::NoSignature(asset.name.clone()))?;
+
+    let contents = github.fetch(sig)?;
+    Ok(Signature::from_slice(&contents)
+        .map_err(|_| UpdateError::BadSignature(asset.name.clone()))?)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Finds the checksum listed for `name` in `text`, on a line holding both,
+/// like the output of `sha256sum` or a Markdown table row.
+fn listed_checksum(text: &str, name: &str) -> Option<String> {
+    text.lines()
+        .find_map(|line| {
+            let words: Vec<&str> = line
+                .split(|c: char| c.is_whitespace() || c == '|' || c == '`')
+                .filter(|word| !word.is_empty())
+                .collect();
+            // `sha256sum --binary` marks the name with a `*`.
+            if !words
+                .iter()
+                .any(|word| word.trim_start_matches('*') == name)
+            {
+                return None;
+            }
+            words
+                .into_iter()
+                .find(|word| is_sha256(word))
+        })
+        .map(str::to_ascii_lowercase)
+}
+
+fn is_sha256(word: &str) -> bool {
+    word.len() == 64
+        && word
+            .bytes()
+            .all(|b| b.is_ascii_hexdigit())
+}
+
+/// Returns the SHA-256 of the file at `path` in lowercase hex.
+fn sha256(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn archive_kind(name: &str) -> ArchiveKind {
+    let name = name.to_ascii_lowercase();
+    match name.rsplit_once('.') {
+        Some((_, "zip")) => ArchiveKind::Zip,
+        Some((_, "tgz")) => ArchiveKind::Tar(Some(Compression::Gz)),
+        Some((stem, "gz")) if Path::new(stem).extension() == Some("tar".as_ref()) => {
+            ArchiveKind::Tar(Some(Compression::Gz))
+        },
+        Some((_, "gz")) => ArchiveKind::Plain(Some(Compression::Gz)),
+        _ => ArchiveKind::Plain(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use self_update::Release;
+
+    use super::{archive_for, listed_checksum, newest, parse_releases, sidecar, version_of};
+    use crate::config::Channel;
+
+    /// A response of the releases API, with its keys in another order and
+    /// spaced differently than GitHub sends them.
+    const RELEASES: &str = include_str!("../tests/fixtures/releases.json");
+
+    const LINUX: &str = "x86_64-unknown-linux-gnu";
+
+    fn releases() -> Vec<Release> {
+        parse_releases(RELEASES).expect("the fixture is a release list")
+    }
+
+    fn release<'r>(releases: &'r [Release], version: &str) -> &'r Release {
+        releases
+            .iter()
+            .find(|release| version_of(release) == version)
+            .expect("the fixture has the release")
+    }
+
+    fn newest_version(current: &str, channel: Channel) -> Option<String> {
+        let releases = releases();
+        newest(&releases, current, channel, "test-installation")
+            .map(|release| version_of(release).to_string())
+    }
+
+    #[test]
+    fn parses_releases_and_skips_drafts() {
+        let releases = releases();
+        let versions: Vec<&str> = releases
+            .iter()
+            .map(version_of)
+            .collect();
+        assert_eq!(
+            versions,
+            ["1.6.0-nightly.20260901", "1.5.0-beta.2", "1.4.2", "1.4.1", "docs-2026-08"]
+        );
+
+        let beta = release(&releases, "1.5.0-beta.2");
+        assert_eq!(beta.name, "v1.5.0-beta.2", "a release without a name is named after its tag");
+        let docs = release(&releases, "docs-2026-08");
+        assert_eq!(docs.body, None);
+        assert!(docs.assets.is_empty());
+
+        let stable = release(&releases, "1.4.2");
+        assert_eq!(stable.assets.len(), 6);
+        assert_eq!(
+            stable.assets[0].download_url,
+            "https://api.github.com/repos/chikof/rocas/releases/assets/401",
+            "assets are downloaded from their API URL"
+        );
+    }
+
+    #[test]
+    fn picks_the_archive_for_the_target() {
+        let releases = releases();
+        let stable = release(&releases, "1.4.2");
+
+        let linux = archive_for(stable, LINUX).expect("there is a Linux build");
+        assert_eq!(linux.name, "rocas-v1.4.2-x86_64-unknown-linux-gnu.tar.gz");
+        let windows =
+            archive_for(stable, "x86_64-pc-windows-msvc").expect("there is a Windows build");
+        assert_eq!(windows.name, "rocas-v1.4.2-x86_64-pc-windows-msvc.zip");
+        assert!(archive_for(stable, "riscv64gc-unknown-linux-gnu").is_none());
+    }
+
+    #[test]
+    fn finds_the_checksum_and_signature_of_an_archive() {
+        let releases = releases();
+        let stable = release(&releases, "1.4.2");
+
+        let linux = archive_for(stable, LINUX).expect("there is a Linux build");
+        assert_eq!(
+            sidecar(stable, linux, "sha256").map(|asset| asset.name.as_str()),
+            Some("rocas-v1.4.2-x86_64-unknown-linux-gnu.tar.gz.sha256")
+        );
+        assert_eq!(
+            sidecar(stable, linux, "sig").map(|asset| asset.name.as_str()),
+            Some("rocas-v1.4.2-x86_64-unknown-linux-gnu.tar.gz.sig")
+        );
+
+        let windows =
+            archive_for(stable, "x86_64-pc-windows-msvc").expect("there is a Windows build");
+        assert!(sidecar(stable, windows, "sha256").is_some());
+        assert!(sidecar(stable, windows, "sig").is_none());
+    }
+
+    #[test]
+    fn reads_a_checksum_from_the_release_notes() {
+        let releases = releases();
+        let older = release(&releases, "1.4.1");
+        let archive = archive_for(older, LINUX).expect("there is a Linux build");
+        assert!(sidecar(older, archive, "sha256").is_none());
+
+        let notes = older
+            .body
+            .as_deref()
+            .unwrap_or_default();
+        assert_eq!(
+            listed_checksum(notes, &archive.name).as_deref(),
+            Some("ab12cd34ef56ab12cd34ef56ab12cd34ef56ab12cd34ef56ab12cd34ef56ab12")
+        );
+        assert_eq!(listed_checksum(notes, "rocas-v1.4.1-aarch64-apple-darwin.tar.gz"), None);
+    }
+
+    #[test]
+    fn newest_keeps_to_the_channel() {
+        assert_eq!(newest_version("1.4.0", Channel::Stable).as_deref(), Some("1.4.2"));
+        assert_eq!(newest_version("1.4.0", Channel::Beta).as_deref(), Some("1.5.0-beta.2"));
+        assert_eq!(
+            newest_version("1.4.0", Channel::Nightly).as_deref(),
+            Some("1.6.0-nightly.20260901")
+        );
+    }
+
+    #[test]
+    fn newest_is_strictly_newer() {
+        assert_eq!(newest_version("1.4.2", Channel::Stable), None);
+        assert_eq!(newest_version("1.6.0", Channel::Nightly), None);
+    }
+
+    #[test]
+    fn newest_skips_releases_gated_on_a_minimum_version() {
+        // The beta needs 1.3.0 or later to update from.
+        assert_eq!(newest_version("1.2.0", Channel::Beta).as_deref(), Some("1.4.2"));
+    }
+}
@@ -0,0 +1,293 @@
+//! WebDAV destinations.
+//!
+//! A rule whose `destination` starts with `webdav://` uploads matched files
+//! to a WebDAV server (e.g. Nextcloud) over HTTPS instead of moving them on
+//! disk; `webdav+http://` uses plain HTTP for servers on a trusted network.
+//! The local file is deleted once the upload has succeeded. Conflicts are
+//! resolved by the engine with the rule's `on_conflict`, as for local
+//! destinations.
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::path::Path;
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::{Method, StatusCode};
+
+use crate::config::RuleConfig;
+
+const SCHEME: &str = "webdav://";
+const PLAIN_SCHEME: &str = "webdav+http://";
+
+/// Error returned by WebDAV destinations.
+#[derive(Debug, thiserror::Error)]
+pub enum WebDavError {
+    #[error("invalid WebDAV destination '{0}'")]
+    InvalidUrl(String),
+
+    #[error("{0} is not supported for WebDAV destinations")]
+    Unsupported(&'static str),
+
+    #[error("WebDAV request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("WebDAV server answered {status} to {method} {url}")]
+    Status {
+        method: &'static str,
+        url: String,
+        status: StatusCode,
+    },
+
+    #[error("upload of '{url}' is incomplete: sent {sent} bytes, server has {stored}")]
+    Incomplete { url: String, sent: u64, stored: u64 },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Returns `true` if `destination` points at a WebDAV server.
+pub fn is_remote(destination: &str) -> bool {
+    destination.starts_with(SCHEME) || destination.starts_with(PLAIN_SCHEME)
+}
+
+/// Returns the HTTP(S) URL of the collection an expanded `destination`
+/// names, without a trailing slash.
+pub fn collection_url(destination: &str) -> Result<String, WebDavError> {
+    let (scheme, rest) = if let Some(rest) = destination.strip_prefix(SCHEME) {
+        ("https://", rest)
+    } else if let Some(rest) = destination.strip_prefix(PLAIN_SCHEME) {
+        ("http://", rest)
+    } else {
+        return Err(WebDavError::InvalidUrl(destination.to_owned()));
+    };
+
+    let rest = rest.trim_end_matches('/');
+    if rest.is_empty() || rest.starts_with('/') {
+        return Err(WebDavError::InvalidUrl(destination.to_owned()));
+    }
+
+    Ok(format!("{scheme}{rest}"))
+}
+
+/// Returns the URL of `name` inside the collection at `collection`.
+pub fn join(collection: &str, name: &str) -> String {
+    format!("{collection}/{}", encode(name))
+}
+
+/// A WebDAV server a rule uploads to, with the rule's credentials.
+pub struct WebDav {
+    client: Client,
+    auth: Auth,
+}
+
+enum Auth {
+    None,
+    Basic { user: String, password: Option<String> },
+    Bearer(String),
+}
+
+impl WebDav {
+    /// Creates the client for `config`, or returns `None` when its
+    /// destination is local.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`WebDavError`] if the destination URL is malformed, the rule
+    /// uses an option WebDAV destinations do not support, or the HTTP client
+    /// cannot be created.
+    pub fn new(config: &RuleConfig) -> Result<Option<Self>, WebDavError> {
+        if !is_remote(&config.destination) {
+            return Ok(None);
+        }
+        collection_url(&config.destination)?;
+
+        if config
+            .action
+            .eq_ignore_ascii_case("hardlink")
+        {
+            return Err(WebDavError::Unsupported("action = \"hardlink\""));
+        }
+        if config.backup_dir.is_some() {
+            return Err(WebDavError::Unsupported("backup_dir"));
+        }
+
+        let auth = match (&config.webdav_token, &config.webdav_user) {
+            (Some(token), _) => Auth::Bearer(token.clone()),
+            (None, Some(user)) => Auth::Basic {
+                user: user.clone(),
+                password: config.webdav_password.clone(),
+            },
+            (None, None) => Auth::None,
+        };
+
+        let client = Client::builder()
+            .user_agent(concat!("rocas/", env!("CARGO_PKG_VERSION")))
+            // Uploads of large files take as long as they take.
+            .timeout(None)
+            .build()?;
+
+        Ok(Some(Self { client, auth }))
+    }
+
+    /// Returns `true` if a file or collection exists at `url`.
+    pub fn exists(&self, url: &str) -> Result<bool, WebDavError> {
+        let response = self.request(Method::HEAD, url).send()?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(status_error("HEAD", url, status)),
+        }
+    }
+
+    /// Creates the collection at `url` and any missing parents. Existing
+    /// collections are left alone.
+    pub fn ensure_collection(&self, url: &str) -> Result<(), WebDavError> {
+        let response = self.request(mkcol(), url).send()?;
+        match response.status() {
+            // 405: the collection already exists.
+            status if status.is_success() || status == StatusCode::METHOD_NOT_ALLOWED => Ok(()),
+            // 409: a parent collection is missing.
+            StatusCode::CONFLICT => {
+                let parent = url
+                    .rsplit_once('/')
+                    .map(|(parent, _)| parent)
+                    .filter(|parent| !parent.ends_with('/'))
+                    .ok_or_else(|| WebDavError::InvalidUrl(url.to_owned()))?;
+                self.ensure_collection(parent)?;
+                self.ensure_collection(url)
+            },
+            status => Err(status_error("MKCOL", url, status)),
+        }
+    }
+
+    /// Returns the URL of the first free name in `collection` with `suffix`
+    /// appended to `name`, `{n}` counting up from 1: `report.pdf` becomes
+    /// `report (1).pdf`. A directory's name is suffixed as a whole.
+    pub fn free_name(
+        &self,
+        collection: &str,
+        name: &str,
+        suffix: &str,
+        is_dir: bool,
+    ) -> Result<String, WebDavError> {
+        let (stem, ext) = match Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+        {
+            Some(ext) if !is_dir => (&name[..name.len() - ext.len() - 1], format!(".{ext}")),
+            _ => (name, String::new()),
+        };
+
+        for n in 1_u64.. {
+            let suffix = suffix.replace("{n}", &n.to_string());
+            let candidate = join(collection, &format!("{stem}{suffix}{ext}"));
+            if !self.exists(&candidate)? {
+                return Ok(candidate);
+            }
+        }
+
+        unreachable!("ran out of conflict suffix numbers")
+    }
+
+    /// Moves the resource at `from` to `to` on the server, replacing what is
+    /// there.
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), WebDavError> {
+        let response = self
+            .request(Method::from_bytes(b"MOVE").expect("MOVE is a valid method"), from)
+            .header("Destination", to)
+            .header("Overwrite", "T")
+            .send()?;
+        check(&response, "MOVE", from)
+    }
+
+    /// Deletes the file or collection at `url`.
+    pub fn delete(&self, url: &str) -> Result<(), WebDavError> {
+        let response = self
+            .request(Method::DELETE, url)
+            .send()?;
+        check(&response, "DELETE", url)
+    }
+
+    /// Uploads the file or directory at `path` to `url`, replacing a file
+    /// that is already there. With `verify`, the size the server reports for
+    /// each uploaded file is compared with the local one.
+    pub fn upload(&self, path: &Path, url: &str, verify: bool) -> Result<(), WebDavError> {
+        if path.is_dir() {
+            self.ensure_collection(url)?;
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                let child = join(url, &entry.file_name().to_string_lossy());
+                self.upload(&entry.path(), &child, verify)?;
+            }
+            return Ok(());
+        }
+
+        let file = File::open(path)?;
+        let sent = file.metadata()?.len();
+        let response = self
+            .request(Method::PUT, url)
+            .body(file)
+            .send()?;
+        check(&response, "PUT", url)?;
+
+        if verify {
+            self.verify_size(url, sent)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the server stored `sent` bytes at `url`. Servers that do
+    /// not report a size are trusted.
+    fn verify_size(&self, url: &str, sent: u64) -> Result<(), WebDavError> {
+        let response = self.request(Method::HEAD, url).send()?;
+        check(&response, "HEAD", url)?;
+
+        let stored = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok());
+        match stored {
+            Some(stored) if stored != sent => {
+                Err(WebDavError::Incomplete { url: url.to_owned(), sent, stored })
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.auth {
+            Auth::None => request,
+            Auth::Basic { user, password } => request.basic_auth(user, password.as_ref()),
+            Auth::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+}
+
+fn mkcol() -> Method {
+    Method::from_bytes(b"MKCOL").expect("MKCOL is a valid method")
+}
+
+fn check(response: &Response, method: &'static str, url: &str) -> Result<(), WebDavError> {
+    let status = response.status();
+    if status.is_success() { Ok(()) } else { Err(status_error(method, url, status)) }
+}
+
+fn status_error(method: &'static str, url: &str, status: StatusCode) -> WebDavError {
+    WebDavError::Status { method, url: url.to_owned(), status }
+}
+
+/// Percent-encodes a single path segment.
+fn encode(segment: &str) -> String {
+    let mut out = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&byte) {
+            out.push(char::from(byte));
+        } else {
+            let _ = write!(out, "%{byte:02X}");
+        }
+    }
+    out
+}
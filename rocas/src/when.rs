@@ -0,0 +1,204 @@
+//! Config that only applies on some platforms or machines.
+//!
+//! One config can be shared between computers that need different paths.
+//! A rule with a `when` table is only used where all its conditions hold:
+//!
+//! ```toml
+//! [[rules]]
+//! patterns = ["*.exe"]
+//! destination = 'D:\Installers'
+//! [rules.when]
+//! os = "windows"
+//! ```
+//!
+//! `[watcher]`, every `[[watcher]]` entry and `[misc]` take a list of `when`
+//! tables instead, each with conditions and the settings it replaces; later
+//! matching entries win:
+//!
+//! ```toml
+//! [[watcher.when]]
+//! hostname = "work-laptop"
+//! watch_path = "/mnt/data/Downloads"
+//! ```
+//!
+//! `os` is `linux`, `macos`, `windows`, `unix` or another Rust target OS
+//! name; `hostname` is compared case-insensitively, with or without the
+//! domain. Both accept a list, which matches if any entry does.
+
+use toml::{Table, Value};
+
+pub const WHEN_KEY: &str = "when";
+
+/// The keys a `when` table can test.
+const CONDITIONS: &[&str] = &["os", "hostname"];
+
+/// Error returned for a malformed `when` table.
+#[derive(Debug, thiserror::Error)]
+pub enum WhenError {
+    #[error("unknown condition '{0}' in a rule's 'when'; use os or hostname")]
+    UnknownCondition(String),
+
+    #[error("'{0}' in 'when' must be a string or a list of strings")]
+    InvalidValue(String),
+
+    #[error("'when' in {0} must be a table")]
+    NotATable(String),
+}
+
+/// Drops the rules of `config` whose conditions do not hold here and
+/// applies the matching `when` entries of its sections, removing every
+/// `when` key.
+///
+/// # Errors
+///
+/// Returns [`WhenError`] if a `when` table is malformed.
+pub fn apply(config: &mut Table) -> Result<(), WhenError> {
+    let host = Host::current();
+
+    if let Some(Value::Array(rules)) = config.get_mut("rules") {
+        let mut kept = Vec::with_capacity(rules.len());
+        for mut rule in rules.drain(..) {
+            let conditions = match &mut rule {
+                Value::Table(rule) => rule.remove(WHEN_KEY),
+                _ => None,
+            };
+            let holds = match conditions {
+                None => true,
+                Some(Value::Table(conditions)) => {
+                    if let Some(key) = conditions
+                        .keys()
+                        .find(|key| !CONDITIONS.contains(&key.as_str()))
+                    {
+                        return Err(WhenError::UnknownCondition(key.clone()));
+                    }
+                    host.matches(&conditions)?
+                },
+                Some(_) => return Err(WhenError::NotATable("a rule".to_string())),
+            };
+            if holds {
+                kept.push(rule);
+            }
+        }
+        *rules = kept;
+    }
+
+    for (name, value) in config.iter_mut() {
+        match value {
+            Value::Table(section) => override_settings(&format!("[{name}]"), section, &host)?,
+            Value::Array(sections) if name == "watcher" => {
+                for section in sections
+                    .iter_mut()
+                    .filter_map(Value::as_table_mut)
+                {
+                    override_settings("[[watcher]]", section, &host)?;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies the `when` entries of `section` whose conditions hold and removes
+/// them. `location` names the section in errors.
+fn override_settings(location: &str, section: &mut Table, host: &Host) -> Result<(), WhenError> {
+    let entries = match section.remove(WHEN_KEY) {
+        None => return Ok(()),
+        Some(Value::Array(entries)) => entries,
+        Some(Value::Table(entry)) => vec![Value::Table(entry)],
+        Some(_) => return Err(WhenError::NotATable(location.to_string())),
+    };
+
+    for entry in entries {
+        let Value::Table(mut entry) = entry else {
+            return Err(WhenError::NotATable(location.to_string()));
+        };
+        let conditions: Table = CONDITIONS
+            .iter()
+            .filter_map(|key| entry.remove_entry(*key))
+            .collect();
+        if host.matches(&conditions)? {
+            section.extend(entry);
+        }
+    }
+
+    Ok(())
+}
+
+/// What conditions are tested against.
+struct Host {
+    hostname: Option<String>,
+}
+
+impl Host {
+    fn current() -> Self {
+        Self {
+            hostname: hostname().map(|name| name.to_lowercase()),
+        }
+    }
+
+    /// Returns `true` if every condition in `conditions` holds.
+    fn matches(&self, conditions: &Table) -> Result<bool, WhenError> {
+        for (key, value) in conditions {
+            let values = strings(key, value)?;
+            let holds = match key.as_str() {
+                "os" => values.iter().any(|os| os_matches(os)),
+                "hostname" => values
+                    .iter()
+                    .any(|name| self.hostname_matches(name)),
+                _ => return Err(WhenError::UnknownCondition(key.clone())),
+            };
+            if !holds {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn hostname_matches(&self, name: &str) -> bool {
+        let Some(hostname) = &self.hostname else {
+            return false;
+        };
+        let name = name.to_lowercase();
+        *hostname == name || hostname.split('.').next() == Some(name.as_str())
+    }
+}
+
+fn os_matches(os: &str) -> bool {
+    let os = os.to_lowercase();
+    os == std::env::consts::OS || (os == "unix" && cfg!(unix))
+}
+
+fn strings<'v>(key: &str, value: &'v Value) -> Result<Vec<&'v str>, WhenError> {
+    let invalid = || WhenError::InvalidValue(key.to_string());
+    match value {
+        Value::String(value) => Ok(vec![value]),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| value.as_str().ok_or_else(invalid))
+            .collect(),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: `gethostname` writes at most `buf.len()` bytes to `buf`.
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if rc != 0 {
+        return None;
+    }
+
+    let len = buf
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+#[cfg(not(unix))]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
@@ -13,6 +13,12 @@
 //!     println!("{:?}", event);
 //! }
 //! ```
+//!
+//! Paths longer than Windows' 260-character limit are watched through their
+//! verbatim (`\\?\`) form; see [`long_path`]. Events always carry ordinary
+//! paths.
+
+pub mod long_path;
 
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -172,7 +178,7 @@ impl DirWatcher {
                 loop {
                     select! {
                         recv(raw_rx) -> msg => {
-                            let event = match msg {
+                            let mut event = match msg {
                                 Ok(Ok(e)) => e,
                                 Ok(Err(e)) => { log::error!("notify error: {e}"); continue; }
                                 Err(_) => break, // channel closed, shut down
@@ -180,6 +186,14 @@ impl DirWatcher {
 
                             let roots = roots_for_thread.read().unwrap();
 
+                            // Paths under a root watched in verbatim form come
+                            // back verbatim.
+                            event.paths = event
+                                .paths
+                                .into_iter()
+                                .map(long_path::strip)
+                                .collect();
+
                             // Check if a stale pending rename should be emitted as a delete.
                             if let Some(ref r) = pending_rename
                                 && r.is_expired(rename_timeout)
@@ -326,11 +340,13 @@ impl DirWatcher {
         max_depth: Option<usize>,
     ) -> NotifyResult<()> {
         let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
-        self.watcher.watch(path, mode)?;
+        let extended = long_path::extend(path);
+        self.watcher.watch(&extended, mode)?;
+        // Keep the root in the form events are reported in.
         self.watched_roots
             .write()
             .unwrap()
-            .push((path.to_path_buf(), max_depth));
+            .push((long_path::strip(extended), max_depth));
         Ok(())
     }
 
@@ -340,7 +356,8 @@ impl DirWatcher {
     ///
     /// Returns [`notify::Error`] if the path is not currently being watched.
     pub fn unwatch(&mut self, path: &Path) -> NotifyResult<()> {
-        self.watcher.unwatch(path)
+        self.watcher
+            .unwatch(&long_path::extend(path))
     }
 
     /// Blocks until the next debounced event is available.
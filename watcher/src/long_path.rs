@@ -0,0 +1,177 @@
+//! Windows long-path handling.
+//!
+//! Ordinary Windows paths are limited to 260 characters (`MAX_PATH`). The
+//! verbatim form (`\\?\C:\…` or `\\?\UNC\server\share\…`) lifts the limit,
+//! but the OS takes it literally: no `/` separators, `.` or `..`. [`extend`]
+//! builds it from an absolute, normalized path before a path is handed to an
+//! OS API directly, and [`strip`] turns paths the OS reports back into the
+//! ordinary form so they can be shown, compared and matched.
+//!
+//! On other platforms both functions return the path unchanged.
+
+use std::path::{Path, PathBuf};
+
+/// Returns the verbatim (`\\?\`) form of `path`, made absolute.
+///
+/// Paths that are already verbatim, device paths and paths that cannot be
+/// made absolute are returned unchanged.
+#[cfg(windows)]
+#[must_use]
+pub fn extend(path: &Path) -> PathBuf {
+    use std::path::{Component, Prefix};
+
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    let mut components = absolute.components();
+
+    let prefix = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(_) => {
+                let mut verbatim = std::ffi::OsString::from(r"\\?\");
+                verbatim.push(prefix.as_os_str());
+                verbatim
+            },
+            Prefix::UNC(server, share) => {
+                let mut verbatim = std::ffi::OsString::from(r"\\?\UNC\");
+                verbatim.push(server);
+                verbatim.push(r"\");
+                verbatim.push(share);
+                verbatim
+            },
+            _ => return absolute,
+        },
+        _ => return absolute,
+    };
+
+    rebuild(prefix, components).unwrap_or(absolute)
+}
+
+/// Returns the ordinary form of a verbatim (`\\?\`) path.
+///
+/// Other paths are returned unchanged.
+#[cfg(windows)]
+#[must_use]
+pub fn strip(path: PathBuf) -> PathBuf {
+    use std::path::{Component, Prefix};
+
+    let mut components = path.components();
+
+    let prefix = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::VerbatimDisk(drive) => {
+                std::ffi::OsString::from(format!("{}:", char::from(drive)))
+            },
+            Prefix::VerbatimUNC(server, share) => {
+                let mut plain = std::ffi::OsString::from(r"\\");
+                plain.push(server);
+                plain.push(r"\");
+                plain.push(share);
+                plain
+            },
+            _ => return path,
+        },
+        _ => return path,
+    };
+
+    rebuild(prefix, components).unwrap_or(path)
+}
+
+/// Joins `prefix` and the remaining components with `\`. Returns `None` for
+/// paths with `.` or `..` components, which a verbatim path cannot express.
+#[cfg(windows)]
+fn rebuild(prefix: std::ffi::OsString, components: std::path::Components) -> Option<PathBuf> {
+    use std::path::Component;
+
+    let mut rebuilt = prefix;
+    let mut at_separator = false;
+
+    for component in components {
+        match component {
+            Component::RootDir => {
+                rebuilt.push(r"\");
+                at_separator = true;
+            },
+            Component::Normal(name) => {
+                if !at_separator {
+                    rebuilt.push(r"\");
+                }
+                rebuilt.push(name);
+                at_separator = false;
+            },
+            Component::CurDir | Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(PathBuf::from(rebuilt))
+}
+
+/// Returns `path` unchanged; long paths need no special form here.
+#[cfg(not(windows))]
+#[must_use]
+pub fn extend(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Returns `path` unchanged; long paths need no special form here.
+#[cfg(not(windows))]
+#[must_use]
+pub fn strip(path: PathBuf) -> PathBuf {
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{extend, strip};
+
+    /// A destination a template like `Sorted/{year}/{month}/…` can expand
+    /// to, well past `MAX_PATH`.
+    fn deep_destination(root: &str, separator: &str) -> String {
+        let mut path = root.to_string();
+        for level in 0..30 {
+            path.push_str(separator);
+            path.push_str(&format!("Invoices 2026-{level:02}"));
+        }
+        path.push_str(separator);
+        path.push_str("report.pdf");
+        path
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn extends_a_deep_disk_destination() {
+        let plain = deep_destination(r"C:\Sorted", r"\");
+        assert!(plain.len() > 260);
+
+        let extended = extend(Path::new(&deep_destination("C:/Sorted", "/")));
+        assert_eq!(extended, PathBuf::from(format!(r"\\?\{plain}")));
+        assert_eq!(strip(extended), PathBuf::from(plain));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn extends_a_deep_unc_destination() {
+        let plain = deep_destination(r"\\nas\share\Sorted", r"\");
+        let extended = extend(Path::new(&plain));
+        assert_eq!(extended, PathBuf::from(deep_destination(r"\\?\UNC\nas\share\Sorted", r"\")));
+        assert_eq!(strip(extended), PathBuf::from(plain));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn keeps_verbatim_paths() {
+        let verbatim = PathBuf::from(deep_destination(r"\\?\C:\Sorted", r"\"));
+        assert_eq!(extend(&verbatim), verbatim);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn keeps_deep_destinations_unchanged() {
+        let deep = deep_destination("/home/me/Sorted", "/");
+        assert!(deep.len() > 260);
+        assert_eq!(extend(Path::new(&deep)), PathBuf::from(&deep));
+        assert_eq!(strip(PathBuf::from(&deep)), PathBuf::from(deep));
+    }
+}